@@ -0,0 +1,8 @@
+-- Your SQL goes here
+CREATE TABLE contract_abis (
+    address TEXT PRIMARY KEY,
+    abi JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE logs ADD COLUMN decoded JSONB;
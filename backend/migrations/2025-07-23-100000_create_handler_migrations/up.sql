@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE handler_migrations (
+    handler TEXT NOT NULL,
+    version TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (handler, version)
+);
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+CREATE TABLE indexer_state (
+    key TEXT PRIMARY KEY,
+    value BIGINT NOT NULL
+);
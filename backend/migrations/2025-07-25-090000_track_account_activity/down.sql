@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE accounts ADD COLUMN created_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+ALTER TABLE accounts
+    DROP COLUMN first_seen_block,
+    DROP COLUMN first_seen_tx,
+    DROP COLUMN first_seen_at,
+    DROP COLUMN last_active_block,
+    DROP COLUMN tx_sent_count,
+    DROP COLUMN tx_received_count,
+    DROP COLUMN is_contract;
@@ -0,0 +1,41 @@
+-- Your SQL goes here
+ALTER TABLE accounts
+    ADD COLUMN first_seen_block BIGINT,
+    ADD COLUMN first_seen_tx TEXT,
+    ADD COLUMN first_seen_at TIMESTAMPTZ,
+    ADD COLUMN last_active_block BIGINT,
+    ADD COLUMN tx_sent_count BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN tx_received_count BIGINT NOT NULL DEFAULT 0,
+    ADD COLUMN is_contract BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Backfill from the transactions indexed so far.
+WITH activity AS (
+    SELECT t.from_address AS address, t.block_number, t.position, t.hash, b.timestamp, 1 AS sent, 0 AS received
+    FROM transactions t JOIN blocks b ON b.hash = t.block_hash
+    UNION ALL
+    SELECT t.to_address, t.block_number, t.position, t.hash, b.timestamp, 0, 1
+    FROM transactions t JOIN blocks b ON b.hash = t.block_hash
+    WHERE t.to_address IS NOT NULL
+),
+first_seen AS (
+    SELECT DISTINCT ON (address) address, block_number, hash, timestamp
+    FROM activity
+    ORDER BY address, block_number, position
+),
+totals AS (
+    SELECT address, MAX(block_number) AS last_active_block, SUM(sent) AS sent, SUM(received) AS received
+    FROM activity
+    GROUP BY address
+)
+UPDATE accounts a SET
+    first_seen_block = f.block_number,
+    first_seen_tx = f.hash,
+    first_seen_at = f.timestamp,
+    last_active_block = t.last_active_block,
+    tx_sent_count = t.sent,
+    tx_received_count = t.received
+FROM first_seen f JOIN totals t ON t.address = f.address
+WHERE a.address = f.address;
+
+-- `created_at` recorded when the indexer saw the account, not when it appeared on chain.
+ALTER TABLE accounts DROP COLUMN created_at;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE proxy_upgrades;
+DROP TABLE contracts;
+DROP TABLE bytecodes;
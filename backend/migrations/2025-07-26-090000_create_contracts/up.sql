@@ -0,0 +1,30 @@
+-- Your SQL goes here
+-- Deployed code, shared by every contract with the same bytecode.
+CREATE TABLE bytecodes (
+    hash TEXT PRIMARY KEY,
+    code TEXT NOT NULL,
+    size INTEGER NOT NULL
+);
+
+CREATE TABLE contracts (
+    address TEXT PRIMARY KEY,
+    bytecode_hash TEXT REFERENCES bytecodes(hash),
+    -- Only known for contracts deployed by an indexed transaction.
+    creation_tx TEXT REFERENCES transactions(hash) ON DELETE CASCADE,
+    creation_block BIGINT,
+    proxy_kind TEXT,
+    implementation TEXT,
+    beacon TEXT
+);
+
+CREATE INDEX contracts_beacon_idx ON contracts (beacon) WHERE beacon IS NOT NULL;
+
+CREATE TABLE proxy_upgrades (
+    id SERIAL PRIMARY KEY,
+    tx_hash TEXT NOT NULL REFERENCES transactions(hash) ON DELETE CASCADE,
+    proxy_address TEXT NOT NULL,
+    implementation TEXT NOT NULL,
+    previous_implementation TEXT
+);
+
+CREATE INDEX proxy_upgrades_proxy_address_idx ON proxy_upgrades (proxy_address);
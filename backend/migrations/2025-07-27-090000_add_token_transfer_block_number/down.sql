@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX token_transfers_from_history_idx;
+DROP INDEX token_transfers_to_history_idx;
+
+ALTER TABLE token_transfers DROP COLUMN block_number;
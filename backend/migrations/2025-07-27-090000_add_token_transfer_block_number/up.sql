@@ -0,0 +1,14 @@
+-- Your SQL goes here
+ALTER TABLE token_transfers ADD COLUMN block_number BIGINT;
+
+UPDATE token_transfers tt SET block_number = t.block_number
+FROM transactions t
+WHERE t.hash = tt.tx_hash;
+
+ALTER TABLE token_transfers ALTER COLUMN block_number SET NOT NULL;
+
+-- Historical balances sum an owner's transfers up to a block.
+CREATE INDEX token_transfers_to_history_idx
+    ON token_transfers (to_address, token_address, block_number);
+CREATE INDEX token_transfers_from_history_idx
+    ON token_transfers (from_address, token_address, block_number);
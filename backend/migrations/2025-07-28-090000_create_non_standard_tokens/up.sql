@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- Tokens whose on-chain balances drifted from their Transfer events, e.g. rebasing or
+-- fee-on-transfer tokens.
+CREATE TABLE non_standard_tokens (
+    token_address TEXT PRIMARY KEY,
+    reason TEXT NOT NULL,
+    flagged_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
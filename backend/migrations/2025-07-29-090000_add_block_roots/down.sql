@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE blocks
+    DROP COLUMN transactions_root,
+    DROP COLUMN receipts_root,
+    DROP COLUMN logs_bloom;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+-- Header commitments, used to re-verify stored blocks. NULL for blocks indexed before this.
+ALTER TABLE blocks
+    ADD COLUMN transactions_root TEXT,
+    ADD COLUMN receipts_root TEXT,
+    ADD COLUMN logs_bloom TEXT;
@@ -0,0 +1,78 @@
+-- This file should undo anything in `up.sql`
+-- Foreign keys can't span the type change, so they are recreated around it.
+ALTER TABLE transactions DROP CONSTRAINT transactions_block_hash_fkey;
+ALTER TABLE logs DROP CONSTRAINT logs_tx_hash_fkey;
+ALTER TABLE token_transfers DROP CONSTRAINT token_transfers_tx_hash_fkey;
+ALTER TABLE contracts DROP CONSTRAINT contracts_bytecode_hash_fkey;
+ALTER TABLE contracts DROP CONSTRAINT contracts_creation_tx_fkey;
+ALTER TABLE proxy_upgrades DROP CONSTRAINT proxy_upgrades_tx_hash_fkey;
+
+ALTER TABLE blocks
+    ALTER COLUMN hash TYPE TEXT USING '0x' || encode(hash, 'hex'),
+    ALTER COLUMN parent_hash TYPE TEXT USING '0x' || encode(parent_hash, 'hex'),
+    ALTER COLUMN miner TYPE TEXT USING '0x' || encode(miner, 'hex'),
+    ALTER COLUMN transactions_root TYPE TEXT USING '0x' || encode(transactions_root, 'hex'),
+    ALTER COLUMN receipts_root TYPE TEXT USING '0x' || encode(receipts_root, 'hex');
+
+ALTER TABLE transactions
+    ALTER COLUMN hash TYPE TEXT USING '0x' || encode(hash, 'hex'),
+    ALTER COLUMN block_hash TYPE TEXT USING '0x' || encode(block_hash, 'hex'),
+    ALTER COLUMN from_address TYPE TEXT USING '0x' || encode(from_address, 'hex'),
+    ALTER COLUMN to_address TYPE TEXT USING '0x' || encode(to_address, 'hex');
+
+ALTER TABLE logs
+    ALTER COLUMN tx_hash TYPE TEXT USING '0x' || encode(tx_hash, 'hex'),
+    ALTER COLUMN address TYPE TEXT USING '0x' || encode(address, 'hex'),
+    ALTER COLUMN topic0 TYPE TEXT USING '0x' || encode(topic0, 'hex'),
+    ALTER COLUMN topic1 TYPE TEXT USING '0x' || encode(topic1, 'hex'),
+    ALTER COLUMN topic2 TYPE TEXT USING '0x' || encode(topic2, 'hex'),
+    ALTER COLUMN topic3 TYPE TEXT USING '0x' || encode(topic3, 'hex');
+
+ALTER TABLE accounts
+    ALTER COLUMN address TYPE TEXT USING '0x' || encode(address, 'hex'),
+    ALTER COLUMN first_seen_tx TYPE TEXT USING '0x' || encode(first_seen_tx, 'hex');
+
+ALTER TABLE token_transfers
+    ALTER COLUMN tx_hash TYPE TEXT USING '0x' || encode(tx_hash, 'hex'),
+    ALTER COLUMN token_address TYPE TEXT USING '0x' || encode(token_address, 'hex'),
+    ALTER COLUMN from_address TYPE TEXT USING '0x' || encode(from_address, 'hex'),
+    ALTER COLUMN to_address TYPE TEXT USING '0x' || encode(to_address, 'hex');
+
+ALTER TABLE token_balances
+    ALTER COLUMN owner_address TYPE TEXT USING '0x' || encode(owner_address, 'hex'),
+    ALTER COLUMN token_address TYPE TEXT USING '0x' || encode(token_address, 'hex');
+
+ALTER TABLE contract_abis
+    ALTER COLUMN address TYPE TEXT USING '0x' || encode(address, 'hex');
+
+ALTER TABLE bytecodes
+    ALTER COLUMN hash TYPE TEXT USING '0x' || encode(hash, 'hex');
+
+ALTER TABLE contracts
+    ALTER COLUMN address TYPE TEXT USING '0x' || encode(address, 'hex'),
+    ALTER COLUMN bytecode_hash TYPE TEXT USING '0x' || encode(bytecode_hash, 'hex'),
+    ALTER COLUMN creation_tx TYPE TEXT USING '0x' || encode(creation_tx, 'hex'),
+    ALTER COLUMN implementation TYPE TEXT USING '0x' || encode(implementation, 'hex'),
+    ALTER COLUMN beacon TYPE TEXT USING '0x' || encode(beacon, 'hex');
+
+ALTER TABLE proxy_upgrades
+    ALTER COLUMN tx_hash TYPE TEXT USING '0x' || encode(tx_hash, 'hex'),
+    ALTER COLUMN proxy_address TYPE TEXT USING '0x' || encode(proxy_address, 'hex'),
+    ALTER COLUMN implementation TYPE TEXT USING '0x' || encode(implementation, 'hex'),
+    ALTER COLUMN previous_implementation TYPE TEXT USING '0x' || encode(previous_implementation, 'hex');
+
+ALTER TABLE non_standard_tokens
+    ALTER COLUMN token_address TYPE TEXT USING '0x' || encode(token_address, 'hex');
+
+ALTER TABLE transactions ADD CONSTRAINT transactions_block_hash_fkey
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE;
+ALTER TABLE logs ADD CONSTRAINT logs_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE token_transfers ADD CONSTRAINT token_transfers_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE contracts ADD CONSTRAINT contracts_bytecode_hash_fkey
+    FOREIGN KEY (bytecode_hash) REFERENCES bytecodes(hash);
+ALTER TABLE contracts ADD CONSTRAINT contracts_creation_tx_fkey
+    FOREIGN KEY (creation_tx) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE proxy_upgrades ADD CONSTRAINT proxy_upgrades_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
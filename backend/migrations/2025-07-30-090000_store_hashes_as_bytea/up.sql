@@ -0,0 +1,79 @@
+-- Your SQL goes here
+-- Hashes and addresses were stored as `0x`-prefixed hex; store their raw bytes instead.
+-- Foreign keys can't span the type change, so they are recreated around it.
+ALTER TABLE transactions DROP CONSTRAINT transactions_block_hash_fkey;
+ALTER TABLE logs DROP CONSTRAINT logs_tx_hash_fkey;
+ALTER TABLE token_transfers DROP CONSTRAINT token_transfers_tx_hash_fkey;
+ALTER TABLE contracts DROP CONSTRAINT contracts_bytecode_hash_fkey;
+ALTER TABLE contracts DROP CONSTRAINT contracts_creation_tx_fkey;
+ALTER TABLE proxy_upgrades DROP CONSTRAINT proxy_upgrades_tx_hash_fkey;
+
+ALTER TABLE blocks
+    ALTER COLUMN hash TYPE BYTEA USING decode(substring(hash from 3), 'hex'),
+    ALTER COLUMN parent_hash TYPE BYTEA USING decode(substring(parent_hash from 3), 'hex'),
+    ALTER COLUMN miner TYPE BYTEA USING decode(substring(miner from 3), 'hex'),
+    ALTER COLUMN transactions_root TYPE BYTEA USING decode(substring(transactions_root from 3), 'hex'),
+    ALTER COLUMN receipts_root TYPE BYTEA USING decode(substring(receipts_root from 3), 'hex');
+
+ALTER TABLE transactions
+    ALTER COLUMN hash TYPE BYTEA USING decode(substring(hash from 3), 'hex'),
+    ALTER COLUMN block_hash TYPE BYTEA USING decode(substring(block_hash from 3), 'hex'),
+    ALTER COLUMN from_address TYPE BYTEA USING decode(substring(from_address from 3), 'hex'),
+    ALTER COLUMN to_address TYPE BYTEA USING decode(substring(to_address from 3), 'hex');
+
+ALTER TABLE logs
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substring(tx_hash from 3), 'hex'),
+    ALTER COLUMN address TYPE BYTEA USING decode(substring(address from 3), 'hex'),
+    ALTER COLUMN topic0 TYPE BYTEA USING decode(substring(topic0 from 3), 'hex'),
+    ALTER COLUMN topic1 TYPE BYTEA USING decode(substring(topic1 from 3), 'hex'),
+    ALTER COLUMN topic2 TYPE BYTEA USING decode(substring(topic2 from 3), 'hex'),
+    ALTER COLUMN topic3 TYPE BYTEA USING decode(substring(topic3 from 3), 'hex');
+
+ALTER TABLE accounts
+    ALTER COLUMN address TYPE BYTEA USING decode(substring(address from 3), 'hex'),
+    ALTER COLUMN first_seen_tx TYPE BYTEA USING decode(substring(first_seen_tx from 3), 'hex');
+
+ALTER TABLE token_transfers
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substring(tx_hash from 3), 'hex'),
+    ALTER COLUMN token_address TYPE BYTEA USING decode(substring(token_address from 3), 'hex'),
+    ALTER COLUMN from_address TYPE BYTEA USING decode(substring(from_address from 3), 'hex'),
+    ALTER COLUMN to_address TYPE BYTEA USING decode(substring(to_address from 3), 'hex');
+
+ALTER TABLE token_balances
+    ALTER COLUMN owner_address TYPE BYTEA USING decode(substring(owner_address from 3), 'hex'),
+    ALTER COLUMN token_address TYPE BYTEA USING decode(substring(token_address from 3), 'hex');
+
+ALTER TABLE contract_abis
+    ALTER COLUMN address TYPE BYTEA USING decode(substring(address from 3), 'hex');
+
+ALTER TABLE bytecodes
+    ALTER COLUMN hash TYPE BYTEA USING decode(substring(hash from 3), 'hex');
+
+ALTER TABLE contracts
+    ALTER COLUMN address TYPE BYTEA USING decode(substring(address from 3), 'hex'),
+    ALTER COLUMN bytecode_hash TYPE BYTEA USING decode(substring(bytecode_hash from 3), 'hex'),
+    ALTER COLUMN creation_tx TYPE BYTEA USING decode(substring(creation_tx from 3), 'hex'),
+    ALTER COLUMN implementation TYPE BYTEA USING decode(substring(implementation from 3), 'hex'),
+    ALTER COLUMN beacon TYPE BYTEA USING decode(substring(beacon from 3), 'hex');
+
+ALTER TABLE proxy_upgrades
+    ALTER COLUMN tx_hash TYPE BYTEA USING decode(substring(tx_hash from 3), 'hex'),
+    ALTER COLUMN proxy_address TYPE BYTEA USING decode(substring(proxy_address from 3), 'hex'),
+    ALTER COLUMN implementation TYPE BYTEA USING decode(substring(implementation from 3), 'hex'),
+    ALTER COLUMN previous_implementation TYPE BYTEA USING decode(substring(previous_implementation from 3), 'hex');
+
+ALTER TABLE non_standard_tokens
+    ALTER COLUMN token_address TYPE BYTEA USING decode(substring(token_address from 3), 'hex');
+
+ALTER TABLE transactions ADD CONSTRAINT transactions_block_hash_fkey
+    FOREIGN KEY (block_hash) REFERENCES blocks(hash) ON DELETE CASCADE;
+ALTER TABLE logs ADD CONSTRAINT logs_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE token_transfers ADD CONSTRAINT token_transfers_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE contracts ADD CONSTRAINT contracts_bytecode_hash_fkey
+    FOREIGN KEY (bytecode_hash) REFERENCES bytecodes(hash);
+ALTER TABLE contracts ADD CONSTRAINT contracts_creation_tx_fkey
+    FOREIGN KEY (creation_tx) REFERENCES transactions(hash) ON DELETE CASCADE;
+ALTER TABLE proxy_upgrades ADD CONSTRAINT proxy_upgrades_tx_hash_fkey
+    FOREIGN KEY (tx_hash) REFERENCES transactions(hash) ON DELETE CASCADE;
@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS token_transfers_tx_hash_idx;
+DROP INDEX IF EXISTS logs_topic0_idx;
+DROP INDEX IF EXISTS logs_address_topic0_idx;
+DROP INDEX IF EXISTS logs_tx_hash_idx;
+DROP INDEX IF EXISTS transactions_to_address_idx;
+DROP INDEX IF EXISTS transactions_from_address_idx;
+DROP INDEX IF EXISTS transactions_block_hash_idx;
+DROP INDEX IF EXISTS blocks_number_idx;
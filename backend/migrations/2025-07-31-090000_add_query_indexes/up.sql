@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- Indexes behind the API's lookups and reorg rollbacks. They're skipped when the session sets
+-- `cw_indexer.defer_indexes` (`--defer-indexes`), so large databases can build them with
+-- `CREATE INDEX CONCURRENTLY` once the backfill is done. Keep in sync with `indexes::QUERY_INDEXES`.
+DO $$
+BEGIN
+    IF current_setting('cw_indexer.defer_indexes', true) IS DISTINCT FROM 'on' THEN
+        CREATE INDEX IF NOT EXISTS blocks_number_idx ON blocks (number);
+        CREATE INDEX IF NOT EXISTS transactions_block_hash_idx ON transactions (block_hash);
+        CREATE INDEX IF NOT EXISTS transactions_from_address_idx ON transactions (from_address);
+        CREATE INDEX IF NOT EXISTS transactions_to_address_idx ON transactions (to_address);
+        CREATE INDEX IF NOT EXISTS logs_tx_hash_idx ON logs (tx_hash);
+        CREATE INDEX IF NOT EXISTS logs_address_topic0_idx ON logs (address, topic0);
+        CREATE INDEX IF NOT EXISTS logs_topic0_idx ON logs (topic0);
+        CREATE INDEX IF NOT EXISTS token_transfers_tx_hash_idx ON token_transfers (tx_hash);
+    END IF;
+END
+$$;
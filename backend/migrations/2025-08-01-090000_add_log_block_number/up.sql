@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- Logs carry their block number so they can be partitioned and pruned by block range.
+ALTER TABLE logs ADD COLUMN block_number BIGINT;
+
+UPDATE logs l SET block_number = t.block_number
+FROM transactions t
+WHERE t.hash = l.tx_hash;
+
+ALTER TABLE logs ALTER COLUMN block_number SET NOT NULL;
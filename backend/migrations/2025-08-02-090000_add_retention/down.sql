@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS token_transfers_block_number_idx;
+DROP INDEX IF EXISTS logs_block_number_idx;
+DROP INDEX IF EXISTS transactions_block_number_idx;
+
+DROP TABLE pruned_balances;
+DROP TABLE daily_stats;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+-- Per-day totals of pruned blocks; days still indexed are summed from `blocks`.
+CREATE TABLE daily_stats (
+    day DATE PRIMARY KEY,
+    blocks BIGINT NOT NULL,
+    transactions BIGINT NOT NULL,
+    token_transfers BIGINT NOT NULL,
+    gas_used NUMERIC NOT NULL
+);
+
+-- Balance changes of pruned token transfers, the starting point of historical balances.
+CREATE TABLE pruned_balances (
+    owner_address BYTEA NOT NULL,
+    token_address BYTEA NOT NULL,
+    token_id NUMERIC,
+    amount NUMERIC NOT NULL
+);
+CREATE INDEX pruned_balances_owner_idx ON pruned_balances (owner_address, token_address);
+
+-- Pruning deletes by block range. Deferred like the indexes of `add_query_indexes`; keep in sync
+-- with `indexes::QUERY_INDEXES`.
+DO $$
+BEGIN
+    IF current_setting('cw_indexer.defer_indexes', true) IS DISTINCT FROM 'on' THEN
+        CREATE INDEX IF NOT EXISTS transactions_block_number_idx ON transactions (block_number);
+        CREATE INDEX IF NOT EXISTS logs_block_number_idx ON logs (block_number);
+        CREATE INDEX IF NOT EXISTS token_transfers_block_number_idx ON token_transfers (block_number);
+    END IF;
+END
+$$;
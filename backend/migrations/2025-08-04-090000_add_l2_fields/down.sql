@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE transactions
+    DROP COLUMN tx_type,
+    DROP COLUMN l1_fee,
+    DROP COLUMN l1_gas_used,
+    DROP COLUMN l1_gas_price,
+    DROP COLUMN gas_used_for_l1,
+    DROP COLUMN l1_block_number,
+    DROP COLUMN deposit_source_hash,
+    DROP COLUMN deposit_mint;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+-- Fields of L2 transactions and receipts beyond Ethereum's, NULL on L1 chains.
+ALTER TABLE transactions
+    ADD COLUMN tx_type SMALLINT,
+    -- OP-stack: the L1 data fee, charged on top of the L2 gas, and what it was computed from
+    ADD COLUMN l1_fee NUMERIC,
+    ADD COLUMN l1_gas_used NUMERIC,
+    ADD COLUMN l1_gas_price NUMERIC,
+    -- Arbitrum: the part of `gas_used` paying for L1 calldata, and the L1 block it was based on
+    ADD COLUMN gas_used_for_l1 NUMERIC,
+    ADD COLUMN l1_block_number BIGINT,
+    -- OP-stack deposits (type 0x7e): the L1 event they come from and the ETH minted on L2
+    ADD COLUMN deposit_source_hash BYTEA,
+    ADD COLUMN deposit_mint NUMERIC;
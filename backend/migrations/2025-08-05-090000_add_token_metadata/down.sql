@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX contracts_token_name_idx;
+DROP INDEX contracts_token_symbol_idx;
+ALTER TABLE contracts
+    DROP COLUMN token_name,
+    DROP COLUMN token_symbol,
+    DROP COLUMN token_decimals;
@@ -0,0 +1,11 @@
+-- Your SQL goes here
+-- ERC20/ERC721 metadata read from `name()`, `symbol()` and `decimals()` when a contract is
+-- inspected, NULL for contracts that don't implement them.
+ALTER TABLE contracts
+    ADD COLUMN token_name TEXT,
+    ADD COLUMN token_symbol TEXT,
+    ADD COLUMN token_decimals SMALLINT;
+
+-- Searching tokens by case-insensitive prefix
+CREATE INDEX contracts_token_symbol_idx ON contracts (lower(token_symbol) text_pattern_ops);
+CREATE INDEX contracts_token_name_idx ON contracts (lower(token_name) text_pattern_ops);
@@ -0,0 +1,15 @@
+-- This file should undo anything in `up.sql`
+DO $$
+DECLARE
+    tbl TEXT;
+BEGIN
+    FOREACH tbl IN ARRAY ARRAY['logs', 'token_transfers', 'token_balances', 'proxy_upgrades'] LOOP
+        EXECUTE format('ALTER SEQUENCE %s AS INTEGER', pg_get_serial_sequence(tbl, 'id'));
+    END LOOP;
+END
+$$;
+
+ALTER TABLE logs ALTER COLUMN id TYPE INTEGER;
+ALTER TABLE token_transfers ALTER COLUMN id TYPE INTEGER;
+ALTER TABLE token_balances ALTER COLUMN id TYPE INTEGER;
+ALTER TABLE proxy_upgrades ALTER COLUMN id TYPE INTEGER;
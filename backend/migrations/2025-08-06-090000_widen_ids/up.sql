@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- The row ids of the busiest tables, which page cursors are keyed on, outgrow INTEGER.
+ALTER TABLE logs ALTER COLUMN id TYPE BIGINT;
+ALTER TABLE token_transfers ALTER COLUMN id TYPE BIGINT;
+ALTER TABLE token_balances ALTER COLUMN id TYPE BIGINT;
+ALTER TABLE proxy_upgrades ALTER COLUMN id TYPE BIGINT;
+
+DO $$
+DECLARE
+    tbl TEXT;
+BEGIN
+    FOREACH tbl IN ARRAY ARRAY['logs', 'token_transfers', 'token_balances', 'proxy_upgrades'] LOOP
+        EXECUTE format('ALTER SEQUENCE %s AS BIGINT', pg_get_serial_sequence(tbl, 'id'));
+    END LOOP;
+END
+$$;
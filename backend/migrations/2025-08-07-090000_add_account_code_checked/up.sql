@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- Set once the indexer has looked up an account's code with `eth_getCode`. Accounts without a
+-- `contracts` row had none then, and aren't looked up again when they receive a plain transfer.
+-- Accounts indexed before this column are looked up once more.
+ALTER TABLE accounts ADD COLUMN code_checked BOOLEAN NOT NULL DEFAULT FALSE;
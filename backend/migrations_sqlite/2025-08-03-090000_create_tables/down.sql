@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE proxy_upgrades;
+DROP TABLE contracts;
+DROP TABLE bytecodes;
+DROP TABLE contract_abis;
+DROP TABLE token_balances;
+DROP TABLE token_transfers;
+DROP TABLE accounts;
+DROP TABLE logs;
+DROP TABLE transactions;
+DROP TABLE blocks;
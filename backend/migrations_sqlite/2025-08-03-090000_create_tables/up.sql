@@ -0,0 +1,123 @@
+-- The PostgreSQL schema as of `add_retention`, for single-node SQLite databases. Hashes and
+-- addresses are raw bytes; amounts are decimal strings, since SQLite numbers can't hold a uint256,
+-- and are added up by the indexer rather than in SQL.
+CREATE TABLE blocks (
+    hash BLOB PRIMARY KEY NOT NULL,
+    parent_hash BLOB NOT NULL,
+    number BIGINT NOT NULL,
+    timestamp TEXT NOT NULL,
+    miner BLOB NOT NULL,
+    gas_used TEXT NOT NULL,
+    gas_limit TEXT NOT NULL,
+    base_fee_per_gas TEXT,
+    extra_data TEXT NOT NULL,
+    tx_count INTEGER NOT NULL,
+    transactions_root BLOB,
+    receipts_root BLOB,
+    logs_bloom TEXT
+);
+
+CREATE TABLE transactions (
+    hash BLOB PRIMARY KEY NOT NULL,
+    block_hash BLOB NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,
+    block_number BIGINT NOT NULL,
+    from_address BLOB NOT NULL,
+    to_address BLOB,
+    value TEXT NOT NULL,
+    gas_price TEXT,
+    gas_used TEXT,
+    nonce BIGINT NOT NULL,
+    position INTEGER NOT NULL,
+    method_id TEXT
+);
+
+CREATE TABLE logs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tx_hash BLOB NOT NULL REFERENCES transactions(hash) ON DELETE CASCADE,
+    address BLOB NOT NULL,
+    topic0 BLOB,
+    topic1 BLOB,
+    topic2 BLOB,
+    topic3 BLOB,
+    data TEXT NOT NULL,
+    decoded TEXT,
+    block_number BIGINT NOT NULL
+);
+
+CREATE TABLE accounts (
+    address BLOB PRIMARY KEY NOT NULL,
+    first_seen_block BIGINT,
+    first_seen_tx BLOB,
+    first_seen_at TEXT,
+    last_active_block BIGINT,
+    tx_sent_count BIGINT NOT NULL DEFAULT 0,
+    tx_received_count BIGINT NOT NULL DEFAULT 0,
+    is_contract BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE TABLE token_transfers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tx_hash BLOB NOT NULL REFERENCES transactions(hash) ON DELETE CASCADE,
+    token_address BLOB NOT NULL,
+    from_address BLOB NOT NULL,
+    to_address BLOB NOT NULL,
+    value TEXT, -- For ERC20
+    token_id TEXT, -- For ERC721
+    block_number BIGINT NOT NULL
+);
+
+CREATE TABLE token_balances (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    owner_address BLOB NOT NULL,
+    token_address BLOB NOT NULL,
+    amount TEXT NOT NULL,
+    token_id TEXT -- Distinguishes NFTs
+);
+-- Unlike the PostgreSQL constraint, this also covers the NULL `token_id` of ERC20 balances.
+CREATE UNIQUE INDEX token_balances_owner_token_idx
+    ON token_balances (owner_address, token_address, IFNULL(token_id, ''));
+
+CREATE TABLE contract_abis (
+    address BLOB PRIMARY KEY NOT NULL,
+    abi TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE bytecodes (
+    hash BLOB PRIMARY KEY NOT NULL,
+    code TEXT NOT NULL,
+    size INTEGER NOT NULL
+);
+
+CREATE TABLE contracts (
+    address BLOB PRIMARY KEY NOT NULL,
+    bytecode_hash BLOB REFERENCES bytecodes(hash),
+    creation_tx BLOB REFERENCES transactions(hash) ON DELETE CASCADE,
+    creation_block BIGINT,
+    proxy_kind TEXT,
+    implementation BLOB,
+    beacon BLOB
+);
+
+CREATE TABLE proxy_upgrades (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    tx_hash BLOB NOT NULL REFERENCES transactions(hash) ON DELETE CASCADE,
+    proxy_address BLOB NOT NULL,
+    implementation BLOB NOT NULL,
+    previous_implementation BLOB
+);
+
+CREATE INDEX blocks_number_idx ON blocks (number);
+CREATE INDEX transactions_block_hash_idx ON transactions (block_hash);
+CREATE INDEX transactions_from_address_idx ON transactions (from_address);
+CREATE INDEX transactions_to_address_idx ON transactions (to_address);
+CREATE INDEX logs_tx_hash_idx ON logs (tx_hash);
+CREATE INDEX logs_address_topic0_idx ON logs (address, topic0);
+CREATE INDEX logs_topic0_idx ON logs (topic0);
+CREATE INDEX token_transfers_tx_hash_idx ON token_transfers (tx_hash);
+CREATE INDEX token_transfers_to_history_idx
+    ON token_transfers (to_address, token_address, block_number);
+CREATE INDEX token_transfers_from_history_idx
+    ON token_transfers (from_address, token_address, block_number);
+CREATE INDEX contracts_beacon_idx ON contracts (beacon) WHERE beacon IS NOT NULL;
+CREATE INDEX proxy_upgrades_proxy_address_idx ON proxy_upgrades (proxy_address);
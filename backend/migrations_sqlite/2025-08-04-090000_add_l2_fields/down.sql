@@ -0,0 +1,8 @@
+ALTER TABLE transactions DROP COLUMN deposit_mint;
+ALTER TABLE transactions DROP COLUMN deposit_source_hash;
+ALTER TABLE transactions DROP COLUMN l1_block_number;
+ALTER TABLE transactions DROP COLUMN gas_used_for_l1;
+ALTER TABLE transactions DROP COLUMN l1_gas_price;
+ALTER TABLE transactions DROP COLUMN l1_gas_used;
+ALTER TABLE transactions DROP COLUMN l1_fee;
+ALTER TABLE transactions DROP COLUMN tx_type;
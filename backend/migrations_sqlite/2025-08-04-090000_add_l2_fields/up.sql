@@ -0,0 +1,9 @@
+-- Fields of L2 transactions and receipts, see the PostgreSQL migration of the same name.
+ALTER TABLE transactions ADD COLUMN tx_type INTEGER;
+ALTER TABLE transactions ADD COLUMN l1_fee TEXT;
+ALTER TABLE transactions ADD COLUMN l1_gas_used TEXT;
+ALTER TABLE transactions ADD COLUMN l1_gas_price TEXT;
+ALTER TABLE transactions ADD COLUMN gas_used_for_l1 TEXT;
+ALTER TABLE transactions ADD COLUMN l1_block_number INTEGER;
+ALTER TABLE transactions ADD COLUMN deposit_source_hash BLOB;
+ALTER TABLE transactions ADD COLUMN deposit_mint TEXT;
@@ -0,0 +1,5 @@
+DROP INDEX contracts_token_name_idx;
+DROP INDEX contracts_token_symbol_idx;
+ALTER TABLE contracts DROP COLUMN token_decimals;
+ALTER TABLE contracts DROP COLUMN token_symbol;
+ALTER TABLE contracts DROP COLUMN token_name;
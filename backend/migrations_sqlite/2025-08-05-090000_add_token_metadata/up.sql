@@ -0,0 +1,6 @@
+-- Token metadata of contracts, see the PostgreSQL migration of the same name.
+ALTER TABLE contracts ADD COLUMN token_name TEXT;
+ALTER TABLE contracts ADD COLUMN token_symbol TEXT;
+ALTER TABLE contracts ADD COLUMN token_decimals INTEGER;
+CREATE INDEX contracts_token_symbol_idx ON contracts (lower(token_symbol));
+CREATE INDEX contracts_token_name_idx ON contracts (lower(token_name));
@@ -0,0 +1,2 @@
+-- Accounts whose code was looked up, see the PostgreSQL migration of the same name.
+ALTER TABLE accounts ADD COLUMN code_checked BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,169 @@
+use crate::store::Store;
+use anyhow::{Context, Result};
+use common::{DecodedLog, DecodedParam};
+use ethers::{
+    abi::{Abi, Event, EventExt, RawLog, Token},
+    types::{Address, Bytes, H256, I256},
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, RwLock},
+};
+
+/// A registry shared between the indexer and the API, which may register new ABIs at runtime.
+pub type SharedAbiRegistry = Arc<RwLock<AbiRegistry>>;
+
+/// Known contract ABIs, used to decode raw logs into named event parameters.
+///
+/// ABIs registered for a specific address take precedence. Every event is also indexed by its
+/// signature hash, so logs emitted by contracts without a registered ABI are still decoded when
+/// their `topic0` matches a known event.
+#[derive(Default)]
+pub struct AbiRegistry {
+    by_address: HashMap<Address, Abi>,
+    events: HashMap<H256, Vec<Event>>,
+}
+
+impl AbiRegistry {
+    /// Registers an ABI, optionally scoped to a contract address.
+    pub fn register(&mut self, address: Option<Address>, abi: Abi) {
+        for event in abi.events().filter(|e| !e.anonymous) {
+            let candidates = self.events.entry(event.signature()).or_default();
+            if !candidates.contains(event) {
+                candidates.push(event.clone());
+            }
+        }
+        if let Some(address) = address {
+            self.by_address.insert(address, abi);
+        }
+    }
+
+    /// Loads every `*.json` file in a directory.
+    ///
+    /// Files named after a contract address (`0x….json`) are registered for that address, any
+    /// other file only contributes its event signatures. Both plain ABI arrays and compiler
+    /// artifacts with an `abi` field are accepted.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<usize> {
+        let mut loaded = 0;
+        for entry in fs::read_dir(dir).with_context(|| format!("reading {}", dir.display()))? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)?;
+            let abi = parse_abi(&serde_json::from_str(&contents)?)
+                .with_context(|| format!("parsing ABI in {}", path.display()))?;
+            let address = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| Address::from_str(s).ok());
+            self.register(address, abi);
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Loads the ABIs previously uploaded through the admin API.
+    pub fn load_from_store(&mut self, store: &dyn Store) -> Result<usize> {
+        let stored = store.uploaded_abis()?;
+        let count = stored.len();
+        for (address, abi) in stored {
+            self.register(Some(address), parse_abi(&abi)?);
+        }
+        Ok(count)
+    }
+
+    /// Decodes a raw log, returning `None` if no known event matches it.
+    pub fn decode_log(
+        &self,
+        address: Address,
+        topics: &[H256],
+        data: &Bytes,
+    ) -> Option<DecodedLog> {
+        let topic0 = topics.first()?;
+        let raw = RawLog {
+            topics: topics.to_vec(),
+            data: data.to_vec(),
+        };
+
+        let scoped = self
+            .by_address
+            .get(&address)
+            .into_iter()
+            .flat_map(|abi| abi.events().filter(|e| e.signature() == *topic0));
+        let global = self.events.get(topic0).into_iter().flatten();
+
+        scoped
+            .chain(global)
+            .find_map(|event| decode_with(event, raw.clone()))
+    }
+}
+
+/// Parses a JSON ABI, accepting either the bare array or an artifact object with an `abi` field.
+pub fn parse_abi(value: &serde_json::Value) -> Result<Abi> {
+    let abi = value.get("abi").unwrap_or(value);
+    serde_json::from_value(abi.clone()).map_err(Into::into)
+}
+
+/// Persists an uploaded ABI and registers it for its address.
+pub fn store_abi(
+    store: &dyn Store,
+    registry: &SharedAbiRegistry,
+    address: Address,
+    value: serde_json::Value,
+) -> Result<()> {
+    let abi = parse_abi(&value)?;
+    store.save_abi(address, &value)?;
+
+    registry
+        .write()
+        .map_err(|_| anyhow::anyhow!("ABI registry lock poisoned"))?
+        .register(Some(address), abi);
+    Ok(())
+}
+
+fn decode_with(event: &Event, raw: RawLog) -> Option<DecodedLog> {
+    let parsed = event.parse_log_whole(raw).ok()?;
+    let params = event
+        .inputs
+        .iter()
+        .zip(parsed.params)
+        .map(|(input, param)| DecodedParam {
+            name: param.name,
+            kind: input.kind.to_string(),
+            indexed: input.indexed,
+            value: format_token(&param.value),
+        })
+        .collect();
+
+    Some(DecodedLog {
+        name: event.name.clone(),
+        signature: event.abi_signature(),
+        params,
+    })
+}
+
+/// Formats a decoded ABI value the way the explorer displays it.
+fn format_token(token: &Token) -> String {
+    let join = |tokens: &[Token]| {
+        tokens
+            .iter()
+            .map(format_token)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match token {
+        Token::Address(a) => format!("{:#x}", a),
+        Token::FixedBytes(b) | Token::Bytes(b) => format!("0x{}", hex::encode(b)),
+        Token::Int(i) => I256::from_raw(*i).to_string(),
+        Token::Uint(u) => u.to_string(),
+        Token::Bool(b) => b.to_string(),
+        Token::String(s) => s.clone(),
+        Token::FixedArray(t) | Token::Array(t) => format!("[{}]", join(t)),
+        Token::Tuple(t) => format!("({})", join(t)),
+    }
+}
@@ -1,148 +1,976 @@
 use crate::{
-    db::DbPool,
-    models,
-    schema::{self, blocks, logs, token_balances, token_transfers, transactions},
+    abi::{self, SharedAbiRegistry},
+    models::{self, DbAddress, DbHash},
+    signatures::{SharedSignatureDb, SignatureDb},
+    store::{blocking, SharedStore, Store, StoredUpgrade},
 };
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{FromRef, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
-use common::{AccountDetail, Block, TransactionDetail};
-use diesel::prelude::*;
-
-/// Handler to get the 20 most recent blocks.
-pub async fn get_recent_blocks(State(pool): State<DbPool>) -> Result<Json<Vec<Block>>, StatusCode> {
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Fetch data using Diesel's model
-    let results = blocks::table
-        .order(blocks::number.desc())
-        .limit(20)
-        .load::<models::Block>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+use chrono::Utc;
+use common::{
+    AccountBalances, AccountDetail, BalancePoint, Block, ContractDetail, DailyStats, DecodedLog,
+    ErrorResponse, Page, ProxyInfo, SearchResult, TokenInfo, TransactionDetail,
+};
+use ethers::{
+    types::{Address, Bytes, H256},
+    utils::to_checksum,
+};
+use serde::Deserialize;
+use std::{str::FromStr, sync::Arc};
+
+/// State shared by all API handlers.
+#[derive(Clone)]
+pub struct AppState {
+    pub store: SharedStore,
+    pub abi_registry: SharedAbiRegistry,
+    pub signatures: SharedSignatureDb,
+    /// Token required in the `x-admin-token` header by admin endpoints, which are disabled if unset.
+    pub admin_token: Option<String>,
+}
+
+impl FromRef<AppState> for SharedStore {
+    fn from_ref(state: &AppState) -> Self {
+        state.store.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedAbiRegistry {
+    fn from_ref(state: &AppState) -> Self {
+        state.abi_registry.clone()
+    }
+}
+
+impl FromRef<AppState> for SharedSignatureDb {
+    fn from_ref(state: &AppState) -> Self {
+        state.signatures.clone()
+    }
+}
+
+/// An API error: a bare status, or a malformed input reported with an `ErrorResponse` body.
+#[derive(Debug, PartialEq)]
+pub enum ApiError {
+    Status(StatusCode),
+    InvalidInput {
+        /// Machine-readable kind, such as `invalid_address`.
+        kind: &'static str,
+        message: String,
+    },
+    /// The requested block is older than the retention horizon.
+    Pruned {
+        block: i64,
+        pruned_before: i64,
+    },
+}
+
+impl ApiError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Status(status) => *status,
+            ApiError::InvalidInput { .. } => StatusCode::BAD_REQUEST,
+            ApiError::Pruned { .. } => StatusCode::GONE,
+        }
+    }
+}
 
-    // Convert from Diesel model to common API model
-    let blocks_api: Vec<common::Block> = results.into_iter().map(Into::into).collect();
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::Status(status)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        match self {
+            ApiError::Status(status) => status.into_response(),
+            ApiError::InvalidInput { kind, message } => {
+                let body = ErrorResponse {
+                    error: kind.to_string(),
+                    message,
+                };
+                (StatusCode::BAD_REQUEST, Json(body)).into_response()
+            }
+            ApiError::Pruned {
+                block,
+                pruned_before,
+            } => {
+                let body = ErrorResponse {
+                    error: "pruned".to_string(),
+                    message: format!(
+                        "Block {} is older than the retention horizon, blocks before {} have been pruned",
+                        block, pruned_before
+                    ),
+                };
+                (StatusCode::GONE, Json(body)).into_response()
+            }
+        }
+    }
+}
+
+/// Decodes `N` bytes of hex from user input, accepting either case, surrounding whitespace and an
+/// optional `0x` prefix.
+fn parse_hex<const N: usize>(input: &str, kind: &'static str) -> Result<[u8; N], ApiError> {
+    let trimmed = input.trim();
+    let digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"))
+        .unwrap_or(trimmed);
+    let problem = if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        format!("invalid hex character {:?}", c)
+    } else if digits.len() != N * 2 {
+        format!("expected {} hex digits, got {}", N * 2, digits.len())
+    } else {
+        let mut bytes = [0u8; N];
+        hex::decode_to_slice(digits, &mut bytes).expect("digits were validated");
+        return Ok(bytes);
+    };
+    Err(ApiError::InvalidInput {
+        kind,
+        message: format!("{:?} is not valid: {}", input, problem),
+    })
+}
+
+/// Parses a block or transaction hash taken from a request.
+pub fn parse_hash(hash: &str) -> Result<DbHash, ApiError> {
+    parse_hex(hash, "invalid_hash").map(|bytes| DbHash(H256(bytes)))
+}
+
+/// Parses an address taken from a request. Checksummed (EIP-55), lowercase and uppercase forms are
+/// all accepted, since only the bytes are stored.
+pub fn parse_address(address: &str) -> Result<DbAddress, ApiError> {
+    parse_hex(address, "invalid_address").map(|bytes| DbAddress(Address::from(bytes)))
+}
+
+/// Blocks below the latest indexed one for a block to count as `finalized`, two epochs on
+/// Ethereum.
+pub const FINALIZED_DEPTH: i64 = 64;
+
+/// A block as named in a request path.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlockId {
+    Hash(DbHash),
+    Number(i64),
+    /// The latest indexed block.
+    Latest,
+    /// The latest indexed block at least `FINALIZED_DEPTH` blocks deep.
+    Finalized,
+}
+
+/// Parses a block taken from a request: a hash, a decimal or `0x`-prefixed hex number, or one of
+/// the tags `latest` and `finalized`.
+pub fn parse_block_id(id: &str) -> Result<BlockId, ApiError> {
+    let trimmed = id.trim();
+    let hex_digits = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix("0X"));
+    let invalid = || ApiError::InvalidInput {
+        kind: "invalid_block_id",
+        message: format!(
+            "{:?} is not a block hash, number, \"latest\" or \"finalized\"",
+            id
+        ),
+    };
+
+    if trimmed.eq_ignore_ascii_case("latest") {
+        Ok(BlockId::Latest)
+    } else if trimmed.eq_ignore_ascii_case("finalized") {
+        Ok(BlockId::Finalized)
+    } else if hex_digits.unwrap_or(trimmed).len() == 64 {
+        parse_hex(id, "invalid_hash").map(|bytes| BlockId::Hash(DbHash(H256(bytes))))
+    } else if let Some(digits) = hex_digits {
+        i64::from_str_radix(digits, 16)
+            .map(BlockId::Number)
+            .map_err(|_| invalid())
+    } else if trimmed.starts_with(|c: char| c.is_ascii_digit()) {
+        trimmed.parse().map(BlockId::Number).map_err(|_| invalid())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// The hash of the stored block a `BlockId` names. Hashes are taken as is, without checking that
+/// the block is stored.
+fn resolve_block_hash(store: &dyn Store, id: BlockId) -> anyhow::Result<Option<DbHash>> {
+    let number = match id {
+        BlockId::Hash(hash) => return Ok(Some(hash)),
+        BlockId::Number(number) => return Ok(store.block_hash(number)?.map(DbHash)),
+        BlockId::Latest => None,
+        BlockId::Finalized => match store.latest_block_number()? {
+            Some(latest) => Some(latest - FINALIZED_DEPTH),
+            None => return Ok(None),
+        },
+    };
+    // Log mode leaves gaps, so tags resolve to the highest stored block at or below the number.
+    let block = store.recent_blocks(number.map(|number| number + 1), 1)?;
+    Ok(block.into_iter().next().map(|block| block.hash))
+}
+
+/// The first block that hasn't been pruned, if pruning has run.
+async fn pruned_before(store: &SharedStore) -> Result<Option<i64>, ApiError> {
+    blocking(store, |store| store.pruned_before())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into())
+}
+
+/// Rejects requests about `block` once it has been pruned.
+fn check_retained(block: i64, pruned_before: Option<i64>) -> Result<(), ApiError> {
+    match pruned_before {
+        Some(pruned_before) if block < pruned_before => Err(ApiError::Pruned {
+            block,
+            pruned_before,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Items per page when a request doesn't set `limit`.
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+/// The largest `limit` served; larger ones are capped to it.
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+/// The `?cursor=&limit=` parameters of list endpoints.
+#[derive(Default, Deserialize)]
+pub struct PageQuery {
+    /// The `next_cursor` of the previous page, starting from the first page if unset.
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl PageQuery {
+    fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn cursor(&self) -> Result<Option<Cursor>, ApiError> {
+        self.cursor.as_deref().map(Cursor::decode).transpose()
+    }
+}
+
+/// The last item of a page: its block and its position there, which is the transaction index
+/// for transactions, the ID for logs, token transfers, token balances and upgrades, and the
+/// count of balances already listed for balances as of a block. Clients get it hex-encoded and
+/// treat it as opaque.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cursor {
+    pub block_number: i64,
+    pub position: i64,
+}
+
+impl Cursor {
+    pub fn new(block_number: i64, position: i64) -> Self {
+        Self {
+            block_number,
+            position,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.block_number.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.position.to_be_bytes());
+        hex::encode(bytes)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, ApiError> {
+        let bytes: [u8; 16] = parse_hex(cursor, "invalid_cursor")?;
+        let (block_number, position) = bytes.split_at(8);
+        Ok(Self::new(
+            i64::from_be_bytes(block_number.try_into().expect("8 bytes")),
+            i64::from_be_bytes(position.try_into().expect("8 bytes")),
+        ))
+    }
+
+    /// The position as a transaction index.
+    fn position_i32(&self) -> Result<i32, ApiError> {
+        i32::try_from(self.position).map_err(|_| ApiError::InvalidInput {
+            kind: "invalid_cursor",
+            message: format!("{} is not a valid position", self.position),
+        })
+    }
+}
+
+/// Makes a page of `items` fetched with `limit + 1` rows, the extra row only telling that there
+/// is a next page.
+fn page<T, U>(
+    mut items: Vec<T>,
+    limit: i64,
+    cursor: impl Fn(&T) -> Cursor,
+    convert: impl FnMut(T) -> U,
+) -> Page<U> {
+    let next_cursor = if items.len() as i64 > limit {
+        items.truncate(limit as usize);
+        items.last().map(|item| cursor(item).encode())
+    } else {
+        None
+    };
+    Page {
+        items: items.into_iter().map(convert).collect(),
+        next_cursor,
+    }
+}
+
+/// The chains served by the API, in configuration order.
+pub type SharedChains = Arc<Vec<common::Chain>>;
+
+/// Handler to list the chains, for clients to pick the one whose routes they use.
+pub async fn get_chains(State(chains): State<SharedChains>) -> Json<Vec<common::Chain>> {
+    Json(chains.as_ref().clone())
+}
+
+/// Handler to get a page of the most recent blocks, newest first.
+pub async fn get_recent_blocks(
+    State(store): State<SharedStore>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<Block>>, ApiError> {
+    let (before, limit) = (query.cursor()?, query.limit());
+
+    let results = blocking(&store, move |store| {
+        store.recent_blocks(before.map(|cursor| cursor.block_number), limit + 1)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Convert from the stored model to common API model
+    let blocks_api = page(
+        results,
+        limit,
+        |block| Cursor::new(block.number, 0),
+        Block::from,
+    );
 
     Ok(Json(blocks_api))
 }
 
-/// Handler to get the details of a single block by its hash.
+/// Handler to get the details of a single block by its hash, number or tag.
 pub async fn get_block_details(
-    State(pool): State<DbPool>,
-    Path(hash): Path<String>,
-) -> Result<Json<Block>, StatusCode> {
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    State(store): State<SharedStore>,
+    Path(id): Path<String>,
+) -> Result<Json<Block>, ApiError> {
+    let id = parse_block_id(&id)?;
+    if let BlockId::Number(number) = id {
+        check_retained(number, pruned_before(&store).await?)?;
+    }
 
-    let block_result = blocks::table
-        .filter(blocks::hash.eq(hash))
-        .first::<models::Block>(&mut conn)
-        .optional()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let block_result = blocking(&store, move |store| match resolve_block_hash(store, id)? {
+        Some(hash) => store.block(hash),
+        None => Ok(None),
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     match block_result {
         Some(block) => Ok(Json(block.into())),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(StatusCode::NOT_FOUND.into()),
     }
 }
 
-/// Handler to get the details of a single transaction by its hash.
+/// Handler to get the details of a single transaction by its hash, with the first page of its
+/// logs and token transfers.
 pub async fn get_transaction_details(
-    State(pool): State<DbPool>,
+    State(store): State<SharedStore>,
+    State(abi_registry): State<SharedAbiRegistry>,
+    State(signatures): State<SharedSignatureDb>,
     Path(hash): Path<String>,
-) -> Result<Json<TransactionDetail>, StatusCode> {
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Find the transaction
-    let tx_result = transactions::table
-        .filter(transactions::hash.eq(&hash))
-        .first::<models::Transaction>(&mut conn)
-        .optional()
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<Json<TransactionDetail>, ApiError> {
+    let hash = parse_hash(&hash)?;
+    let limit = DEFAULT_PAGE_SIZE;
 
-    if tx_result.is_none() {
-        return Err(StatusCode::NOT_FOUND);
-    }
-    let tx = tx_result.unwrap();
+    // Find the transaction with its logs and token transfers
+    let found = blocking(&store, move |store| {
+        let Some(tx) = store.transaction(hash)? else {
+            return Ok(None);
+        };
+        let logs = store.transaction_logs(hash, None, limit + 1)?;
+        let transfers = store.transaction_token_transfers(hash, None, limit + 1)?;
+        Ok(Some((tx, logs, transfers)))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some((tx, log_results, transfer_results)) = found else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
 
-    // Find associated logs
-    let log_results = logs::table
-        .filter(logs::tx_hash.eq(&hash))
-        .load::<models::Log>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let logs = log_page(&abi_registry, &signatures, log_results, limit)?;
 
-    // Find associated token transfers
-    let transfer_results = token_transfers::table
-        .filter(token_transfers::tx_hash.eq(&hash))
-        .load::<models::TokenTransfer>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let mut transaction = common::Transaction::from(tx);
+    label_transaction(&signatures, &mut transaction);
 
     let response = TransactionDetail {
-        transaction: tx.into(),
-        logs: log_results.into_iter().map(Into::into).collect(),
-        token_transfers: transfer_results.into_iter().map(Into::into).collect(),
+        transaction,
+        logs,
+        token_transfers: transfer_page(transfer_results, limit),
     };
 
     Ok(Json(response))
 }
 
-/// Handler to get the details of an account, including token balances.
+/// Handler to get a page of the logs of a transaction.
+pub async fn get_transaction_logs(
+    State(store): State<SharedStore>,
+    State(abi_registry): State<SharedAbiRegistry>,
+    State(signatures): State<SharedSignatureDb>,
+    Path(hash): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<common::Log>>, ApiError> {
+    let hash = parse_hash(&hash)?;
+    let (limit, after) = (query.limit(), query.cursor()?);
+    let after = after.map(|cursor| cursor.position);
+
+    let results = blocking(&store, move |store| {
+        store.transaction_logs(hash, after, limit + 1)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(log_page(&abi_registry, &signatures, results, limit)?))
+}
+
+/// Handler to get a page of the token transfers of a transaction.
+pub async fn get_transaction_transfers(
+    State(store): State<SharedStore>,
+    Path(hash): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<common::TokenTransfer>>, ApiError> {
+    let hash = parse_hash(&hash)?;
+    let (limit, after) = (query.limit(), query.cursor()?);
+    let after = after.map(|cursor| cursor.position);
+
+    let results = blocking(&store, move |store| {
+        store.transaction_token_transfers(hash, after, limit + 1)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(transfer_page(results, limit)))
+}
+
+/// Makes a page of stored logs, decoding and labelling them.
+fn log_page(
+    abi_registry: &SharedAbiRegistry,
+    signatures: &SignatureDb,
+    logs: Vec<models::Log>,
+    limit: i64,
+) -> Result<Page<common::Log>, ApiError> {
+    // Logs indexed before their ABI was registered are decoded on the fly.
+    let registry = abi_registry
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(page(
+        logs,
+        limit,
+        |log| Cursor::new(log.block_number, log.id),
+        |log| {
+            let mut log = common::Log::from(log);
+            if log.decoded.is_none() {
+                log.decoded = decode_stored_log(&registry, &log);
+            }
+            label_log(signatures, &mut log);
+            log
+        },
+    ))
+}
+
+fn transfer_page(transfers: Vec<models::TokenTransfer>, limit: i64) -> Page<common::TokenTransfer> {
+    page(
+        transfers,
+        limit,
+        |transfer| Cursor::new(transfer.block_number, transfer.id),
+        Into::into,
+    )
+}
+
+/// Handler to get the details of an account, including the first page of its token balances.
 pub async fn get_account_details(
-    State(pool): State<DbPool>,
+    State(store): State<SharedStore>,
     Path(address): Path<String>,
-) -> Result<Json<AccountDetail>, StatusCode> {
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<Json<AccountDetail>, ApiError> {
+    let address = parse_address(&address)?;
+    let limit = DEFAULT_PAGE_SIZE;
 
-    // Check if account exists
-    let account_exists: bool = diesel::select(diesel::dsl::exists(
-        schema::accounts::table.filter(schema::accounts::address.eq(&address)),
-    ))
-    .get_result(&mut conn)
+    // Find the account with its token balances
+    let (account, balances) = blocking(&store, move |store| {
+        let Some(account) = store.account(address)? else {
+            return Ok(None);
+        };
+        Ok(Some((
+            account,
+            store.token_balances(address, None, limit + 1)?,
+        )))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let response = AccountDetail {
+        address: address.to_string(),
+        checksum_address: to_checksum(&address.0, None),
+        first_seen_block: account.first_seen_block,
+        first_seen_tx: account.first_seen_tx.map(|tx| tx.to_string()),
+        first_seen_at: account.first_seen_at,
+        last_active_block: account.last_active_block,
+        tx_sent_count: account.tx_sent_count,
+        tx_received_count: account.tx_received_count,
+        is_contract: account.is_contract,
+        token_balances: token_balance_page(balances, limit),
+    };
+
+    Ok(Json(response))
+}
+
+/// Handler to get a page of an account's current token balances.
+pub async fn get_account_tokens(
+    State(store): State<SharedStore>,
+    Path(address): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<common::TokenBalance>>, ApiError> {
+    let address = parse_address(&address)?;
+    let (limit, after) = (query.limit(), query.cursor()?);
+    let after = after.map(|cursor| cursor.position);
+
+    let balances = blocking(&store, move |store| {
+        store.token_balances(address, after, limit + 1)
+    })
+    .await
     .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    if !account_exists {
-        return Err(StatusCode::NOT_FOUND);
+    Ok(Json(token_balance_page(balances, limit)))
+}
+
+/// Current balances aren't tied to a block, so their cursors only hold the ID.
+fn token_balance_page(
+    balances: Vec<models::TokenBalance>,
+    limit: i64,
+) -> Page<common::TokenBalance> {
+    page(
+        balances,
+        limit,
+        |balance| Cursor::new(0, balance.id),
+        Into::into,
+    )
+}
+
+#[derive(Deserialize)]
+pub struct BalancesQuery {
+    /// Defaults to the latest indexed block.
+    pub block: Option<i64>,
+}
+
+/// Handler to get a page of an account's token balances as of a block, summed from its
+/// transfers. Later pages stay on the block of the first one.
+pub async fn get_account_balances(
+    State(store): State<SharedStore>,
+    Path(address): Path<String>,
+    Query(query): Query<BalancesQuery>,
+    Query(page_query): Query<PageQuery>,
+) -> Result<Json<AccountBalances>, ApiError> {
+    let address = parse_address(&address)?;
+    let (limit, cursor) = (page_query.limit(), page_query.cursor()?);
+
+    let block_number = match (cursor, query.block) {
+        (Some(cursor), _) => cursor.block_number,
+        (None, Some(block)) => block,
+        (None, None) => blocking(&store, |store| store.latest_block_number())
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .unwrap_or(0),
+    };
+    check_retained(block_number, pruned_before(&store).await?)?;
+    let offset = cursor.map_or(0, |cursor| cursor.position.max(0));
+
+    let mut balances = blocking(&store, move |store| {
+        store.balances_at(address, block_number, offset, limit + 1)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    // Balances are summed on the fly, so the cursor counts those already listed.
+    let next_cursor =
+        (balances.len() as i64 > limit).then(|| Cursor::new(block_number, offset + limit).encode());
+    balances.truncate(limit as usize);
+    let response = AccountBalances {
+        address: address.to_string(),
+        block_number,
+        balances: Page {
+            items: balances,
+            next_cursor,
+        },
+    };
+
+    Ok(Json(response))
+}
+
+#[derive(Deserialize)]
+pub struct BalanceSeriesQuery {
+    pub from: Option<i64>,
+    pub to: Option<i64>,
+}
+
+/// Handler to get a page of an account's balance of a token over time, with a point for every
+/// block in which it changed. ERC721 balances count the tokens held.
+pub async fn get_balance_series(
+    State(store): State<SharedStore>,
+    Path((address, token)): Path<(String, String)>,
+    Query(query): Query<BalanceSeriesQuery>,
+    Query(page_query): Query<PageQuery>,
+) -> Result<Json<Page<BalancePoint>>, ApiError> {
+    let (address, token) = (parse_address(&address)?, parse_address(&token)?);
+    let pruned_before = pruned_before(&store).await?;
+    for block in query.from.into_iter().chain(query.to) {
+        check_retained(block, pruned_before)?;
+    }
+    let mut from = query.from.or(pruned_before).unwrap_or(0);
+    if let Some(cursor) = page_query.cursor()? {
+        from = from.max(cursor.block_number + 1);
     }
 
-    // Find token balances
-    let balances = token_balances::table
-        .filter(token_balances::owner_address.eq(&address))
-        .load::<models::TokenBalance>(&mut conn)
+    let (to, limit) = (query.to.unwrap_or(i64::MAX), page_query.limit());
+    let points = blocking(&store, move |store| {
+        store.balance_series(address, token, from, to, limit + 1)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(page(
+        points,
+        limit,
+        |point| Cursor::new(point.block_number, 0),
+        |point| point,
+    )))
+}
+
+#[derive(Deserialize)]
+pub struct DailyStatsQuery {
+    /// Days to return, up to today; defaults to 30.
+    pub days: Option<i64>,
+}
+
+/// Handler to get per-day block, transaction and token transfer counts and gas used, combining
+/// the totals kept for pruned blocks with the blocks still indexed.
+pub async fn get_daily_stats(
+    State(store): State<SharedStore>,
+    Query(query): Query<DailyStatsQuery>,
+) -> Result<Json<Vec<DailyStats>>, ApiError> {
+    let days = query.days.unwrap_or(30).clamp(1, 366);
+    let first_day = Utc::now().date_naive() - chrono::Duration::days(days - 1);
+
+    let stats = blocking(&store, move |store| store.daily_stats(first_day))
+        .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let response = AccountDetail {
-        address,
-        token_balances: balances.into_iter().map(Into::into).collect(),
+    Ok(Json(stats))
+}
+
+/// Handler to get a contract's bytecode, proxy details and the first page of its upgrade
+/// history.
+pub async fn get_contract_details(
+    State(store): State<SharedStore>,
+    Path(address): Path<String>,
+) -> Result<Json<ContractDetail>, ApiError> {
+    let address = parse_address(&address)?;
+    let limit = DEFAULT_PAGE_SIZE;
+
+    let found = blocking(&store, move |store| {
+        let Some((contract, bytecode)) = store.contract(address)? else {
+            return Ok(None);
+        };
+        let upgrades = store.proxy_upgrades(&upgrade_sources(&contract), None, limit + 1)?;
+        Ok(Some((contract, bytecode, upgrades)))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let Some((contract, bytecode, upgrades)) = found else {
+        return Err(StatusCode::NOT_FOUND.into());
+    };
+
+    let (bytecode, bytecode_size) = bytecode.unzip();
+    let token = token_info(&contract);
+    let response = ContractDetail {
+        address: contract.address.to_string(),
+        checksum_address: to_checksum(&contract.address.0, None),
+        bytecode_hash: contract.bytecode_hash.map(|hash| hash.to_string()),
+        bytecode,
+        bytecode_size,
+        creation_tx: contract.creation_tx.map(|tx| tx.to_string()),
+        creation_block: contract.creation_block,
+        proxy: contract.proxy_kind.map(|kind| ProxyInfo {
+            kind,
+            implementation: contract.implementation.map(|a| a.to_string()),
+            beacon: contract.beacon.map(|a| a.to_string()),
+        }),
+        upgrades: upgrade_page(upgrades, limit),
+        token,
     };
 
     Ok(Json(response))
 }
 
+/// Handler to get a page of a contract's upgrade history, oldest first.
+pub async fn get_contract_upgrades(
+    State(store): State<SharedStore>,
+    Path(address): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<common::ProxyUpgrade>>, ApiError> {
+    let address = parse_address(&address)?;
+    let (limit, after) = (query.limit(), query.cursor()?);
+    let after = after.map(|cursor| cursor.position);
+
+    let upgrades = blocking(&store, move |store| {
+        let Some((contract, _)) = store.contract(address)? else {
+            return Ok(None);
+        };
+        let upgrades = store.proxy_upgrades(&upgrade_sources(&contract), after, limit + 1)?;
+        Ok(Some(upgrades))
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(upgrade_page(upgrades, limit)))
+}
+
+/// The contracts whose upgrades apply to `contract`: itself and, as beacon proxies follow the
+/// upgrades of their beacon, its beacon.
+fn upgrade_sources(contract: &models::Contract) -> Vec<DbAddress> {
+    std::iter::once(contract.address)
+        .chain(contract.beacon)
+        .collect()
+}
+
+fn upgrade_page(upgrades: Vec<StoredUpgrade>, limit: i64) -> Page<common::ProxyUpgrade> {
+    page(
+        upgrades,
+        limit,
+        |(id, _, block_number, _)| Cursor::new(*block_number, *id),
+        |(_, tx_hash, block_number, implementation)| common::ProxyUpgrade {
+            tx_hash: tx_hash.to_string(),
+            block_number,
+            implementation: implementation.to_string(),
+        },
+    )
+}
+
+/// The token metadata of a contract, if it reported a name or symbol.
+fn token_info(contract: &models::Contract) -> Option<TokenInfo> {
+    (contract.token_name.is_some() || contract.token_symbol.is_some()).then(|| TokenInfo {
+        name: contract.token_name.clone(),
+        symbol: contract.token_symbol.clone(),
+        decimals: contract.token_decimals,
+    })
+}
+
+/// Most tokens returned for a search by symbol or name.
+pub const MAX_TOKEN_RESULTS: i64 = 10;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+}
+
+/// Handler to find what a query names. Addresses, block numbers and tags, and hashes are looked
+/// up directly; anything else is taken as the start of a token symbol or name.
+pub async fn search(
+    State(store): State<SharedStore>,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let query = query.q.trim().to_string();
+    if query.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+
+    let results = blocking(&store, move |store| search_store(store, &query))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)??;
+
+    Ok(Json(results))
+}
+
+/// Classifies a search query by its shape and looks up what it names, then falls back to token
+/// names and symbols, which can look like hex or numbers too. Fails with `ApiError::Pruned` for
+/// the number of a pruned block that no token matches either.
+fn search_store(
+    store: &dyn Store,
+    query: &str,
+) -> anyhow::Result<Result<Vec<SearchResult>, ApiError>> {
+    let mut pruned = None;
+    let results: Vec<SearchResult> = if let Ok(address) = parse_address(query) {
+        let result = match store.contract(address)? {
+            Some((contract, _)) => Some(SearchResult::Contract {
+                address: address.to_string(),
+                token: token_info(&contract),
+            }),
+            None => store.account(address)?.map(|_| SearchResult::Account {
+                address: address.to_string(),
+            }),
+        };
+        result.into_iter().collect()
+    } else {
+        match parse_block_id(query) {
+            // A hash names a block or a transaction.
+            Ok(BlockId::Hash(hash)) => {
+                let block = store.block(hash)?.map(|block| SearchResult::Block {
+                    hash: block.hash.to_string(),
+                    number: block.number,
+                });
+                let transaction = store
+                    .transaction(hash)?
+                    .map(|tx| SearchResult::Transaction {
+                        hash: tx.hash.to_string(),
+                        block_number: tx.block_number,
+                    });
+                block.into_iter().chain(transaction).collect()
+            }
+            Ok(id) => {
+                if let BlockId::Number(number) = id {
+                    pruned = check_retained(number, store.pruned_before()?).err();
+                }
+                match resolve_block_hash(store, id)? {
+                    Some(hash) if pruned.is_none() => store
+                        .block(hash)?
+                        .map(|block| SearchResult::Block {
+                            hash: block.hash.to_string(),
+                            number: block.number,
+                        })
+                        .into_iter()
+                        .collect(),
+                    _ => Vec::new(),
+                }
+            }
+            Err(_) => Vec::new(),
+        }
+    };
+    if !results.is_empty() {
+        return Ok(Ok(results));
+    }
+
+    let tokens: Vec<SearchResult> = store
+        .search_tokens(query, MAX_TOKEN_RESULTS)?
+        .into_iter()
+        .filter_map(|contract| {
+            Some(SearchResult::Token {
+                address: contract.address.to_string(),
+                token: token_info(&contract)?,
+            })
+        })
+        .collect();
+    match pruned {
+        Some(error) if tokens.is_empty() => Ok(Err(error)),
+        _ => Ok(Ok(tokens)),
+    }
+}
+
+/// Admin handler to register the ABI of a contract, used to decode its logs.
+pub async fn upload_abi(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(address): Path<String>,
+    Json(abi_json): Json<serde_json::Value>,
+) -> Result<StatusCode, ApiError> {
+    let Some(admin_token) = &state.admin_token else {
+        return Err(StatusCode::FORBIDDEN.into());
+    };
+    let provided = headers.get("x-admin-token").and_then(|v| v.to_str().ok());
+    if provided != Some(admin_token.as_str()) {
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+
+    let address = parse_address(&address)?.0;
+    abi::parse_abi(&abi_json).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let registry = state.abi_registry.clone();
+    blocking(&state.store, move |store| {
+        abi::store_abi(store, &registry, address, abi_json)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler to get a page of the transactions of a block, named as in `get_block_details`, in
+/// block order.
 pub async fn get_transactions_for_block(
-    State(pool): State<DbPool>,
-    Path(hash): Path<String>,
-) -> Result<Json<Vec<common::Transaction>>, StatusCode> {
-    let mut conn = pool.get().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    State(store): State<SharedStore>,
+    State(signatures): State<SharedSignatureDb>,
+    Path(id): Path<String>,
+    Query(query): Query<PageQuery>,
+) -> Result<Json<Page<common::Transaction>>, ApiError> {
+    let mut id = parse_block_id(&id)?;
+    let (limit, cursor) = (query.limit(), query.cursor()?);
+    let after = cursor.map(|cursor| cursor.position_i32()).transpose()?;
+    // Later pages of `latest` and `finalized` stay on the block of the first one.
+    if let (BlockId::Latest | BlockId::Finalized, Some(cursor)) = (id, cursor) {
+        id = BlockId::Number(cursor.block_number);
+    }
+    if let BlockId::Number(number) = id {
+        check_retained(number, pruned_before(&store).await?)?;
+    }
 
-    let results = schema::transactions::table
-        .filter(schema::transactions::block_hash.eq(hash))
-        .load::<models::Transaction>(&mut conn)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let results = blocking(&store, move |store| match resolve_block_hash(store, id)? {
+        Some(hash) => store.block_transactions(hash, after, limit + 1),
+        None => Ok(Vec::new()),
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    let transactions_api: Vec<common::Transaction> = results.into_iter().map(Into::into).collect();
+    let transactions_api = page(
+        results,
+        limit,
+        |tx| Cursor::new(tx.block_number, tx.position.into()),
+        |tx| {
+            let mut tx = common::Transaction::from(tx);
+            label_transaction(&signatures, &mut tx);
+            tx
+        },
+    );
 
     Ok(Json(transactions_api))
 }
 
+/// Decodes a log read back from the database.
+fn decode_stored_log(registry: &abi::AbiRegistry, log: &common::Log) -> Option<DecodedLog> {
+    let address = Address::from_str(&log.address).ok()?;
+    let topics = [&log.topic0, &log.topic1, &log.topic2, &log.topic3]
+        .into_iter()
+        .map_while(|t| t.as_deref())
+        .map(H256::from_str)
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    let data = Bytes::from_str(&log.data).ok()?;
+    registry.decode_log(address, &topics, &data)
+}
+
+/// Labels a transaction with the known signatures of its input selector.
+fn label_transaction(signatures: &SignatureDb, tx: &mut common::Transaction) {
+    let selector = tx
+        .method_id
+        .as_deref()
+        .and_then(|id| hex::decode(id.trim_start_matches("0x")).ok())
+        .and_then(|bytes| <[u8; 4]>::try_from(bytes).ok());
+    if let Some(selector) = selector {
+        tx.method_name = signatures.method_names(&selector).to_vec();
+    }
+}
+
+/// Labels a log with the known event signatures of its `topic0`.
+fn label_log(signatures: &SignatureDb, log: &mut common::Log) {
+    if let Some(topic0) = log.topic0.as_deref().and_then(|t| H256::from_str(t).ok()) {
+        log.event_name = signatures.event_names(&topic0).to_vec();
+    }
+}
+
 impl From<models::Block> for common::Block {
     fn from(b: models::Block) -> Self {
         Self {
-            hash: b.hash,
-            parent_hash: b.parent_hash,
+            hash: b.hash.to_string(),
+            parent_hash: b.parent_hash.to_string(),
             number: b.number,
             timestamp: b.timestamp,
-            miner: b.miner,
+            miner: b.miner.to_string(),
             gas_used: b.gas_used,
             gas_limit: b.gas_limit,
             tx_count: b.tx_count,
@@ -152,15 +980,33 @@ impl From<models::Block> for common::Block {
 
 impl From<models::Transaction> for common::Transaction {
     fn from(t: models::Transaction) -> Self {
+        // The L1 data fee is charged on top of the L2 gas; Arbitrum's is part of `gas_used`.
+        let fee = match (&t.gas_used, &t.gas_price) {
+            (Some(gas_used), Some(gas_price)) => {
+                Some(gas_used * gas_price + t.l2.l1_fee.clone().unwrap_or_default())
+            }
+            _ => None,
+        };
         Self {
-            hash: t.hash,
-            block_hash: t.block_hash,
+            hash: t.hash.to_string(),
+            block_hash: t.block_hash.to_string(),
             block_number: t.block_number,
-            from_address: t.from_address,
-            to_address: t.to_address,
+            from_address: t.from_address.to_string(),
+            to_address: t.to_address.map(|a| a.to_string()),
             value: t.value,
             gas_price: t.gas_price,
             gas_used: t.gas_used,
+            method_id: t.method_id,
+            method_name: Vec::new(),
+            tx_type: t.tx_type,
+            fee,
+            l1_fee: t.l2.l1_fee,
+            l1_gas_used: t.l2.l1_gas_used,
+            l1_gas_price: t.l2.l1_gas_price,
+            gas_used_for_l1: t.l2.gas_used_for_l1,
+            l1_block_number: t.l2.l1_block_number,
+            deposit_source_hash: t.l2.deposit_source_hash.map(|h| h.to_string()),
+            deposit_mint: t.l2.deposit_mint,
         }
     }
 }
@@ -169,13 +1015,15 @@ impl From<models::Log> for common::Log {
     fn from(l: models::Log) -> Self {
         Self {
             id: l.id,
-            tx_hash: l.tx_hash,
-            address: l.address,
-            topic0: l.topic0,
-            topic1: l.topic1,
-            topic2: l.topic2,
-            topic3: l.topic3,
+            tx_hash: l.tx_hash.to_string(),
+            address: l.address.to_string(),
+            topic0: l.topic0.map(|t| t.to_string()),
+            topic1: l.topic1.map(|t| t.to_string()),
+            topic2: l.topic2.map(|t| t.to_string()),
+            topic3: l.topic3.map(|t| t.to_string()),
             data: l.data,
+            event_name: Vec::new(),
+            decoded: l.decoded.and_then(|d| serde_json::from_value(d).ok()),
         }
     }
 }
@@ -184,12 +1032,13 @@ impl From<models::TokenTransfer> for common::TokenTransfer {
     fn from(t: models::TokenTransfer) -> Self {
         Self {
             id: t.id,
-            tx_hash: t.tx_hash,
-            token_address: t.token_address,
-            from_address: t.from_address,
-            to_address: t.to_address,
+            tx_hash: t.tx_hash.to_string(),
+            token_address: t.token_address.to_string(),
+            from_address: t.from_address.to_string(),
+            to_address: t.to_address.to_string(),
             value: t.value,
             token_id: t.token_id,
+            block_number: t.block_number,
         }
     }
 }
@@ -198,8 +1047,8 @@ impl From<models::TokenBalance> for common::TokenBalance {
     fn from(b: models::TokenBalance) -> Self {
         Self {
             id: b.id,
-            owner_address: b.owner_address,
-            token_address: b.token_address,
+            owner_address: b.owner_address.to_string(),
+            token_address: b.token_address.to_string(),
             amount: b.amount,
             token_id: b.token_id,
         }
@@ -0,0 +1,288 @@
+use crate::{
+    chains::ChainConfig,
+    db::DbPool,
+    indexer::{get_latest_indexed_block, u256_to_bigdecimal, DbConnection, EthProvider},
+    models::{DbAddress, TokenBalance},
+    schema::{non_standard_tokens, token_balances},
+};
+use anyhow::{bail, Result};
+use bigdecimal::BigDecimal;
+use diesel::{dsl::sql, prelude::*, sql_types::Double};
+use ethers::{
+    abi::{encode, Token},
+    prelude::*,
+    types::transaction::eip2718::TypedTransaction,
+};
+use std::collections::HashSet;
+
+/// Selector of ERC20 `balanceOf(address)`.
+const BALANCE_OF_SELECTOR: [u8; 4] = [0x70, 0xa0, 0x82, 0x31];
+/// Selector of ERC721 `ownerOf(uint256)`.
+const OWNER_OF_SELECTOR: [u8; 4] = [0x63, 0x52, 0x21, 0x1e];
+
+/// Balances checked per round of concurrent `eth_call`s.
+const BATCH_SIZE: i64 = 200;
+
+/// Which balances `verify-balances` checks and what it does with mismatches.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VerifyOptions {
+    /// Check this many random balances instead of all of them.
+    pub sample: Option<i64>,
+    /// Overwrite mismatching balances with their on-chain value and flag their tokens.
+    pub fix: bool,
+}
+
+/// An indexed balance that differs from the token contract's answer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BalanceMismatch {
+    pub balance_id: i64,
+    pub token_address: DbAddress,
+    pub owner_address: DbAddress,
+    pub token_id: Option<BigDecimal>,
+    pub indexed: BigDecimal,
+    pub on_chain: BigDecimal,
+}
+
+impl BalanceMismatch {
+    /// Why the token is flagged as non-standard when the mismatch is corrected.
+    pub fn reason(&self, block_number: i64) -> String {
+        let drift = if self.on_chain > self.indexed {
+            "grew without a Transfer event (rebasing?)"
+        } else {
+            "shrank without a Transfer event (fee-on-transfer or rebasing?)"
+        };
+        format!(
+            "balance of {} {} at block {}: indexed {}, on-chain {}",
+            self.owner_address, drift, block_number, self.indexed, self.on_chain
+        )
+    }
+}
+
+/// The outcome of a `verify-balances` run.
+#[derive(Debug, Default)]
+pub struct BalanceReport {
+    /// The indexed head the balances were compared at.
+    pub block_number: i64,
+    pub checked: usize,
+    pub mismatches: Vec<BalanceMismatch>,
+    /// Negative balances, which are reported whether or not they were sampled.
+    pub negative: Vec<TokenBalance>,
+    /// Balances whose `eth_call` failed, e.g. because the token isn't ERC20 or ERC721.
+    pub unverifiable: usize,
+    pub corrected: usize,
+}
+
+impl BalanceReport {
+    /// Whether every mismatch and negative balance found was corrected.
+    pub fn is_consistent(&self) -> bool {
+        let all_corrected = self.corrected == self.mismatches.len();
+        all_corrected
+            && self.negative.iter().all(|balance| {
+                self.corrected > 0 && self.mismatches.iter().any(|m| m.balance_id == balance.id)
+            })
+    }
+}
+
+/// Compares indexed token balances with `balanceOf` (ERC20) or `ownerOf` (ERC721) called at the
+/// latest indexed block, reporting each mismatch as it's found.
+pub async fn run_balance_verifier(
+    pool: DbPool,
+    chain: &ChainConfig,
+    options: VerifyOptions,
+) -> Result<BalanceReport> {
+    let provider = chain.connect().await?;
+    println!("✅ Verifier connected to Ethereum RPC");
+
+    let mut conn = pool.get()?;
+    let Some(block_number) = get_latest_indexed_block(&mut conn)? else {
+        bail!("No blocks have been indexed yet");
+    };
+    let mut report = BalanceReport {
+        block_number,
+        negative: negative_balances(&mut conn)?,
+        ..Default::default()
+    };
+    for balance in &report.negative {
+        println!(
+            "❗ Negative balance: token {} owner {} token id {:?}: {}",
+            balance.token_address, balance.owner_address, balance.token_id, balance.amount
+        );
+    }
+    drop(conn);
+
+    println!("🔎 Verifying token balances at block {}", block_number);
+
+    let mut last_id = 0;
+    let mut sampled = false;
+    loop {
+        let mut conn = pool.get()?;
+        let batch = match options.sample {
+            Some(_) if sampled => break,
+            Some(size) => {
+                sampled = true;
+                let mut batch = random_balances(&mut conn, size)?;
+                // Negative balances are always checked, so they can be corrected too.
+                let sampled_ids: HashSet<i64> = batch.iter().map(|b| b.id).collect();
+                batch.extend(
+                    report
+                        .negative
+                        .iter()
+                        .filter(|b| !sampled_ids.contains(&b.id))
+                        .cloned(),
+                );
+                batch
+            }
+            None => balances_after(&mut conn, last_id, BATCH_SIZE)?,
+        };
+        drop(conn);
+        if batch.is_empty() {
+            break;
+        }
+        last_id = batch.last().map_or(last_id, |b| b.id);
+
+        for chunk in batch.chunks(BATCH_SIZE as usize) {
+            let calls = chunk
+                .iter()
+                .map(|balance| on_chain_amount(&provider, balance, block_number));
+            let results = futures::future::join_all(calls).await;
+            for (balance, result) in chunk.iter().zip(results) {
+                report.checked += 1;
+                match result {
+                    Ok(on_chain) if on_chain != balance.amount => {
+                        let mismatch = BalanceMismatch {
+                            balance_id: balance.id,
+                            token_address: balance.token_address,
+                            owner_address: balance.owner_address,
+                            token_id: balance.token_id.clone(),
+                            indexed: balance.amount.clone(),
+                            on_chain,
+                        };
+                        println!(
+                            "⚠️ Mismatch: token {} owner {} token id {:?}: indexed {}, on-chain {}",
+                            mismatch.token_address,
+                            mismatch.owner_address,
+                            mismatch.token_id,
+                            mismatch.indexed,
+                            mismatch.on_chain
+                        );
+                        report.mismatches.push(mismatch);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        report.unverifiable += 1;
+                        eprintln!(
+                            "Could not verify token {} owner {}: {}",
+                            balance.token_address, balance.owner_address, e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if options.fix && !report.mismatches.is_empty() {
+        let mut conn = pool.get()?;
+        report.corrected = apply_corrections(&mut conn, &report.mismatches, block_number)?;
+        println!("🔧 Corrected {} balances", report.corrected);
+    }
+
+    println!(
+        "✅ Checked {} balances: {} mismatches, {} negative, {} unverifiable",
+        report.checked,
+        report.mismatches.len(),
+        report.negative.len(),
+        report.unverifiable
+    );
+    Ok(report)
+}
+
+/// Loads every negative balance, which transfers alone can never produce.
+pub fn negative_balances(connection: &mut DbConnection) -> Result<Vec<TokenBalance>> {
+    token_balances::table
+        .filter(token_balances::amount.lt(BigDecimal::from(0)))
+        .order(token_balances::id)
+        .load(connection)
+        .map_err(Into::into)
+}
+
+/// Loads a page of balances in id order, for a full scan.
+pub fn balances_after(
+    connection: &mut DbConnection,
+    after_id: i64,
+    limit: i64,
+) -> Result<Vec<TokenBalance>> {
+    token_balances::table
+        .filter(token_balances::id.gt(after_id))
+        .order(token_balances::id)
+        .limit(limit)
+        .load(connection)
+        .map_err(Into::into)
+}
+
+fn random_balances(connection: &mut DbConnection, size: i64) -> Result<Vec<TokenBalance>> {
+    token_balances::table
+        .order(sql::<Double>("RANDOM()"))
+        .limit(size)
+        .load(connection)
+        .map_err(Into::into)
+}
+
+/// Overwrites mismatching balances with their on-chain value and flags their tokens as
+/// non-standard, returning the number of balances corrected.
+pub fn apply_corrections(
+    connection: &mut DbConnection,
+    mismatches: &[BalanceMismatch],
+    block_number: i64,
+) -> Result<usize> {
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        for mismatch in mismatches {
+            diesel::update(token_balances::table.find(mismatch.balance_id))
+                .set(token_balances::amount.eq(&mismatch.on_chain))
+                .execute(conn)?;
+            diesel::insert_into(non_standard_tokens::table)
+                .values((
+                    non_standard_tokens::token_address.eq(mismatch.token_address),
+                    non_standard_tokens::reason.eq(mismatch.reason(block_number)),
+                ))
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        Ok(mismatches.len())
+    })
+}
+
+/// Reads the amount the token contract reports for an indexed balance: the ERC20 balance, or 1
+/// or 0 depending on whether the owner holds the ERC721 token.
+async fn on_chain_amount(
+    provider: &EthProvider,
+    balance: &TokenBalance,
+    block_number: i64,
+) -> Result<BigDecimal> {
+    let (token, owner) = (balance.token_address.0, balance.owner_address.0);
+    let data = match &balance.token_id {
+        Some(token_id) => {
+            let token_id = U256::from_dec_str(&token_id.with_scale(0).to_string())?;
+            [OWNER_OF_SELECTOR.to_vec(), encode(&[Token::Uint(token_id)])].concat()
+        }
+        None => [
+            BALANCE_OF_SELECTOR.to_vec(),
+            encode(&[Token::Address(owner)]),
+        ]
+        .concat(),
+    };
+    let call: TypedTransaction = TransactionRequest::new().to(token).data(data).into();
+    let output = provider
+        .call(&call, Some(BlockId::Number((block_number as u64).into())))
+        .await?;
+    if output.len() < 32 {
+        bail!("unexpected return data {}", output);
+    }
+
+    let word = &output[..32];
+    if balance.token_id.is_some() {
+        let held = Address::from_slice(&word[12..]) == owner;
+        Ok(BigDecimal::from(i32::from(held)))
+    } else {
+        u256_to_bigdecimal(U256::from_big_endian(word))
+    }
+}
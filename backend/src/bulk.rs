@@ -0,0 +1,415 @@
+use crate::{
+    contracts::{
+        called_contracts, record_upgrade, store_contract, DeployedContract,
+        UPGRADED_EVENT_SIGNATURE,
+    },
+    indexer::{
+        block_row, ensure_partitions, fetch_block_data, stored_logs, transaction_activity,
+        transaction_row, transfer_amount, AccountActivity, DbConnection, EthProvider, FetchedBlock,
+        IndexerContext, TRANSFER_EVENT_SIGNATURE,
+    },
+    models::{DbAddress, DbHash, NewLog, NewTokenTransfer},
+    schema::{blocks, logs, token_transfers, transactions},
+    store::SharedStore,
+};
+use anyhow::{anyhow, bail, Result};
+use bigdecimal::BigDecimal;
+use common::DecodedLog;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, BigInt, Bool, Bytea, Nullable, Numeric, Timestamptz},
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, Log as EthersLog, H256},
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    ops::RangeInclusive,
+};
+
+/// Rows per multi-row `INSERT`, well below PostgreSQL's limit of 65535 bind parameters.
+const INSERT_CHUNK_SIZE: usize = 1_000;
+
+/// Fetches consecutive blocks with their receipts and contracts concurrently, checking that each
+/// one extends the previous.
+pub async fn fetch_block_range(
+    store: &SharedStore,
+    provider: &EthProvider,
+    context: &IndexerContext,
+    range: RangeInclusive<i64>,
+) -> Result<Vec<FetchedBlock>> {
+    let fetches = range.map(|number| async move {
+        let block = provider
+            .get_block_with_txs(number as u64)
+            .await?
+            .ok_or_else(|| anyhow!("Block {} not found", number))?;
+        fetch_block_data(store, provider, context, block).await
+    });
+    let batch = futures::future::try_join_all(fetches).await?;
+
+    for pair in batch.windows(2) {
+        if pair[1].block.parent_hash != pair[0].block.hash.unwrap() {
+            bail!(
+                "Block {} doesn't extend the fetched block {}, the chain reorganized",
+                pair[1].block.number.unwrap(),
+                pair[0].block.number.unwrap()
+            );
+        }
+    }
+    Ok(batch)
+}
+
+/// Net change of one balance over a batch.
+///
+/// Balances are debited only if they already exist, so debits applied before the first credit
+/// of a balance that isn't stored yet are dropped, as they would be block by block.
+#[derive(Default)]
+struct BalanceDelta {
+    total: BigDecimal,
+    /// Sum of the changes from the first credit on, `None` if the batch never credits it.
+    since_credit: Option<BigDecimal>,
+}
+
+impl BalanceDelta {
+    fn credit(&mut self, value: &BigDecimal) {
+        self.total += value;
+        *self.since_credit.get_or_insert_with(BigDecimal::default) += value;
+    }
+
+    fn debit(&mut self, value: &BigDecimal) {
+        self.total -= value;
+        if let Some(since_credit) = &mut self.since_credit {
+            *since_credit -= value;
+        }
+    }
+}
+
+/// An account's activity summed over a batch.
+struct AccountTotals {
+    /// The earliest activity, which sets the first-seen position.
+    first: AccountActivity,
+    last_active_block: i64,
+    sent: i64,
+    received: i64,
+    is_contract: bool,
+    code_checked: bool,
+}
+
+/// A write that depends on earlier state, replayed row by row in chain order.
+enum OrderedWrite<'a> {
+    Contract(Address, &'a DeployedContract, Option<(H256, i64)>),
+    Upgrade(H256, &'a EthersLog),
+    Handlers(&'a EthersLog, Option<DecodedLog>),
+}
+
+/// Writes a batch of consecutive blocks in a single database transaction, for backfilling.
+///
+/// Blocks and transactions use multi-row inserts, logs and token transfers are streamed with
+/// `COPY ... FROM STDIN (FORMAT binary)`, and account activity and token balance changes are
+/// summed per account and balance and applied with one upsert each. Contracts, proxy upgrades and
+/// event handlers are then replayed in chain order, so handlers see the whole batch's rows. The
+/// stored result is the same as indexing each block with `process_block_data`.
+pub fn write_block_batch(
+    connection: &mut DbConnection,
+    context: &IndexerContext,
+    batch: &[FetchedBlock],
+) -> Result<()> {
+    let registry = context
+        .abi_registry
+        .read()
+        .map_err(|_| anyhow!("ABI registry lock poisoned"))?;
+
+    let mut block_rows = Vec::with_capacity(batch.len());
+    let mut tx_rows = Vec::new();
+    let mut log_rows = Vec::new();
+    let mut transfers = Vec::new();
+    let mut accounts: BTreeMap<Address, AccountTotals> = BTreeMap::new();
+    let mut balances: BTreeMap<(DbAddress, DbAddress, Option<BigDecimal>), BalanceDelta> =
+        BTreeMap::new();
+    let mut ordered = Vec::new();
+    let mut inspected = HashSet::new();
+
+    for fetched in batch {
+        let block = block_row(&fetched.block)?;
+        for (pos, (tx, receipt)) in fetched.transactions_with_receipts.iter().enumerate() {
+            let Some(tx_logs) = stored_logs(context, tx, receipt) else {
+                continue;
+            };
+
+            for (address, activity) in transaction_activity(&block, tx, receipt, &fetched.contracts)
+            {
+                let totals = accounts.entry(address).or_insert(AccountTotals {
+                    first: activity,
+                    last_active_block: activity.block_number,
+                    sent: 0,
+                    received: 0,
+                    is_contract: false,
+                    code_checked: false,
+                });
+                totals.last_active_block = totals.last_active_block.max(activity.block_number);
+                totals.sent += i64::from(activity.sent);
+                totals.received += i64::from(activity.received);
+                totals.is_contract |= activity.is_contract;
+                totals.code_checked |= activity.code_checked;
+            }
+
+            tx_rows.push(transaction_row(&block, pos, tx, receipt)?);
+
+            // Later blocks of the batch may inspect a contract again, since it wasn't stored yet
+            // when they were fetched; only its first inspection is kept, as block by block.
+            for address in called_contracts(tx, receipt) {
+                if let Some(contract) = fetched.contracts.get(&address) {
+                    if inspected.insert(address) {
+                        ordered.push(OrderedWrite::Contract(address, contract, None));
+                    }
+                }
+            }
+            if let Some((created, contract)) = receipt
+                .contract_address
+                .and_then(|created| Some((created, fetched.contracts.get(&created)?)))
+            {
+                inspected.insert(created);
+                ordered.push(OrderedWrite::Contract(
+                    created,
+                    contract,
+                    Some((tx.hash, block.number)),
+                ));
+            }
+
+            for log in tx_logs {
+                let tx_hash = log.transaction_hash.unwrap();
+                let decoded_log = registry.decode_log(log.address, &log.topics, &log.data);
+                let decoded = decoded_log.as_ref().map(serde_json::to_value).transpose()?;
+                log_rows.push((tx_hash, log, log.data.to_string(), decoded, block.number));
+
+                match log.topics.first() {
+                    Some(&topic) if topic == TRANSFER_EVENT_SIGNATURE => {
+                        if let Some((value, token_id)) = transfer_amount(log)? {
+                            let token = DbAddress(log.address);
+                            let from = DbAddress(Address::from(log.topics[1]));
+                            let to = DbAddress(Address::from(log.topics[2]));
+                            if !from.0.is_zero() {
+                                balances
+                                    .entry((from, token, token_id.clone()))
+                                    .or_default()
+                                    .debit(&value);
+                            }
+                            balances
+                                .entry((to, token, token_id.clone()))
+                                .or_default()
+                                .credit(&value);
+                            transfers.push((
+                                tx_hash,
+                                token,
+                                from,
+                                to,
+                                value,
+                                token_id,
+                                block.number,
+                            ));
+                        }
+                    }
+                    Some(&topic) if topic == UPGRADED_EVENT_SIGNATURE => {
+                        ordered.push(OrderedWrite::Upgrade(tx_hash, log));
+                    }
+                    _ => {}
+                }
+                if !context.handlers.is_empty() {
+                    ordered.push(OrderedWrite::Handlers(log, decoded_log));
+                }
+            }
+        }
+        block_rows.push(block);
+    }
+    drop(registry);
+
+    if let (Some(first), Some(last)) = (block_rows.first(), block_rows.last()) {
+        ensure_partitions(connection, context, first.number..=last.number)?;
+    }
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        for chunk in block_rows.chunks(INSERT_CHUNK_SIZE) {
+            diesel::insert_into(blocks::table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+        for chunk in tx_rows.chunks(INSERT_CHUNK_SIZE) {
+            diesel::insert_into(transactions::table)
+                .values(chunk)
+                .on_conflict_do_nothing()
+                .execute(conn)?;
+        }
+
+        let new_logs: Vec<NewLog> = log_rows
+            .iter()
+            .map(|(tx_hash, log, data, decoded, block_number)| {
+                let topic = |i: usize| log.topics.get(i).copied().map(DbHash);
+                NewLog {
+                    tx_hash: DbHash(*tx_hash),
+                    address: DbAddress(log.address),
+                    topic0: topic(0),
+                    topic1: topic(1),
+                    topic2: topic(2),
+                    topic3: topic(3),
+                    data,
+                    decoded: decoded.as_ref(),
+                    block_number: *block_number,
+                }
+            })
+            .collect();
+        diesel::copy_from(logs::table)
+            .from_insertable(&new_logs)
+            .execute(conn)?;
+
+        let new_transfers: Vec<NewTokenTransfer> = transfers
+            .iter()
+            .map(
+                |(
+                    tx_hash,
+                    token_address,
+                    from_address,
+                    to_address,
+                    value,
+                    token_id,
+                    block_number,
+                )| {
+                    NewTokenTransfer {
+                        tx_hash: DbHash(*tx_hash),
+                        token_address: *token_address,
+                        from_address: *from_address,
+                        to_address: *to_address,
+                        value: Some(value),
+                        token_id: token_id.as_ref(),
+                        block_number: *block_number,
+                    }
+                },
+            )
+            .collect();
+        diesel::copy_from(token_transfers::table)
+            .from_insertable(&new_transfers)
+            .execute(conn)?;
+
+        upsert_accounts(conn, &accounts)?;
+        apply_balance_deltas(conn, balances)?;
+
+        for write in &ordered {
+            match write {
+                OrderedWrite::Contract(address, contract, creation) => {
+                    store_contract(conn, *address, contract, *creation)?;
+                }
+                OrderedWrite::Upgrade(tx_hash, log) => record_upgrade(conn, *tx_hash, log)?,
+                OrderedWrite::Handlers(log, decoded) => {
+                    context.handlers.dispatch(conn, log, decoded.as_ref())?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Applies the summed activity of a batch with the same merge rules as `record_account_activity`.
+fn upsert_accounts(
+    connection: &mut DbConnection,
+    accounts: &BTreeMap<Address, AccountTotals>,
+) -> Result<()> {
+    if accounts.is_empty() {
+        return Ok(());
+    }
+    let addresses: Vec<DbAddress> = accounts.keys().copied().map(DbAddress).collect();
+    let totals: Vec<&AccountTotals> = accounts.values().collect();
+    diesel::sql_query(
+        "INSERT INTO accounts (address, first_seen_block, first_seen_tx, first_seen_at, \
+             last_active_block, tx_sent_count, tx_received_count, is_contract, code_checked) \
+         SELECT * FROM UNNEST($1, $2, $3, $4, $5, $6, $7, $8, $9) \
+         ON CONFLICT (address) DO UPDATE SET \
+             first_seen_block = CASE WHEN accounts.first_seen_block IS NULL \
+                 OR EXCLUDED.first_seen_block < accounts.first_seen_block \
+                 THEN EXCLUDED.first_seen_block ELSE accounts.first_seen_block END, \
+             first_seen_tx = CASE WHEN accounts.first_seen_block IS NULL \
+                 OR EXCLUDED.first_seen_block < accounts.first_seen_block \
+                 THEN EXCLUDED.first_seen_tx ELSE accounts.first_seen_tx END, \
+             first_seen_at = CASE WHEN accounts.first_seen_block IS NULL \
+                 OR EXCLUDED.first_seen_block < accounts.first_seen_block \
+                 THEN EXCLUDED.first_seen_at ELSE accounts.first_seen_at END, \
+             last_active_block = GREATEST(accounts.last_active_block, EXCLUDED.last_active_block), \
+             tx_sent_count = accounts.tx_sent_count + EXCLUDED.tx_sent_count, \
+             tx_received_count = accounts.tx_received_count + EXCLUDED.tx_received_count, \
+             is_contract = accounts.is_contract OR EXCLUDED.is_contract, \
+             code_checked = accounts.code_checked OR EXCLUDED.code_checked",
+    )
+    .bind::<Array<Bytea>, _>(addresses)
+    .bind::<Array<BigInt>, _>(
+        totals
+            .iter()
+            .map(|t| t.first.block_number)
+            .collect::<Vec<_>>(),
+    )
+    .bind::<Array<Bytea>, _>(
+        totals
+            .iter()
+            .map(|t| DbHash(t.first.tx_hash))
+            .collect::<Vec<_>>(),
+    )
+    .bind::<Array<Timestamptz>, _>(totals.iter().map(|t| t.first.timestamp).collect::<Vec<_>>())
+    .bind::<Array<BigInt>, _>(
+        totals
+            .iter()
+            .map(|t| t.last_active_block)
+            .collect::<Vec<_>>(),
+    )
+    .bind::<Array<BigInt>, _>(totals.iter().map(|t| t.sent).collect::<Vec<_>>())
+    .bind::<Array<BigInt>, _>(totals.iter().map(|t| t.received).collect::<Vec<_>>())
+    .bind::<Array<Bool>, _>(totals.iter().map(|t| t.is_contract).collect::<Vec<_>>())
+    .bind::<Array<Bool>, _>(totals.iter().map(|t| t.code_checked).collect::<Vec<_>>())
+    .execute(connection)?;
+    Ok(())
+}
+
+/// Adds the net change of each balance to its stored row, and creates the rows of balances that
+/// the batch credits but that aren't stored yet, as `indexer::credit_token_balance` does.
+fn apply_balance_deltas(
+    connection: &mut DbConnection,
+    balances: BTreeMap<(DbAddress, DbAddress, Option<BigDecimal>), BalanceDelta>,
+) -> Result<()> {
+    if balances.is_empty() {
+        return Ok(());
+    }
+    let mut owners = Vec::with_capacity(balances.len());
+    let mut tokens = Vec::with_capacity(balances.len());
+    let mut token_ids = Vec::with_capacity(balances.len());
+    let mut totals = Vec::with_capacity(balances.len());
+    let mut since_credits = Vec::with_capacity(balances.len());
+    for ((owner, token, token_id), delta) in balances {
+        owners.push(owner);
+        tokens.push(token);
+        token_ids.push(token_id);
+        totals.push(delta.total);
+        since_credits.push(delta.since_credit);
+    }
+
+    diesel::sql_query(
+        "WITH deltas AS ( \
+             SELECT * FROM UNNEST($1, $2, $3, $4, $5) \
+                 AS d(owner_address, token_address, token_id, total, since_credit) \
+         ), updated AS ( \
+             UPDATE token_balances b SET amount = b.amount + d.total FROM deltas d \
+             WHERE b.owner_address = d.owner_address AND b.token_address = d.token_address \
+                 AND b.token_id IS NOT DISTINCT FROM d.token_id \
+             RETURNING b.owner_address, b.token_address, b.token_id \
+         ) \
+         INSERT INTO token_balances (owner_address, token_address, token_id, amount) \
+         SELECT d.owner_address, d.token_address, d.token_id, d.since_credit FROM deltas d \
+         WHERE d.since_credit IS NOT NULL AND NOT EXISTS ( \
+             SELECT 1 FROM updated u \
+             WHERE u.owner_address = d.owner_address AND u.token_address = d.token_address \
+                 AND u.token_id IS NOT DISTINCT FROM d.token_id)",
+    )
+    .bind::<Array<Bytea>, _>(owners)
+    .bind::<Array<Bytea>, _>(tokens)
+    .bind::<Array<Nullable<Numeric>>, _>(token_ids)
+    .bind::<Array<Numeric>, _>(totals)
+    .bind::<Array<Nullable<Numeric>>, _>(since_credits)
+    .execute(connection)?;
+    Ok(())
+}
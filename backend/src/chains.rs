@@ -0,0 +1,114 @@
+use crate::indexer::EthProvider;
+use anyhow::{bail, Context, Result};
+use ethers::providers::Middleware;
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path};
+
+/// The name of the chain indexed when no chains file is given, into the database's default
+/// schema.
+pub const DEFAULT_CHAIN: &str = "default";
+
+/// A chain indexed by the process, with its own RPC, PostgreSQL schema and API routes under
+/// `/api/<name>`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Identifies the chain in API paths, e.g. `mainnet` or `base_sepolia`.
+    pub name: String,
+    /// Checked against the RPC's `eth_chainId` before indexing.
+    #[serde(default)]
+    pub chain_id: Option<u64>,
+    /// Websocket URL of the chain's RPC.
+    pub rpc_url: String,
+    /// The block to start from on the first run, 0 by default.
+    #[serde(default)]
+    pub start_block: Option<u64>,
+    /// The schema holding the chain's tables. Chains from a file default to their name, the
+    /// default chain uses the database's default schema.
+    #[serde(default)]
+    pub schema: Option<String>,
+}
+
+impl ChainConfig {
+    /// The single chain of a process started without a chains file, indexed from `ETH_RPC_URL`.
+    pub fn single(rpc_url: Option<String>, start_block: Option<u64>) -> Self {
+        Self {
+            name: DEFAULT_CHAIN.to_string(),
+            chain_id: None,
+            rpc_url: rpc_url.unwrap_or_default(),
+            start_block,
+            schema: None,
+        }
+    }
+
+    /// Loads the chains listed in a JSON file, each with its own schema.
+    pub fn load(path: &Path) -> Result<Vec<Self>> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        let mut chains: Vec<Self> = serde_json::from_str(&contents)
+            .with_context(|| format!("parsing {}", path.display()))?;
+        for chain in &mut chains {
+            chain.schema.get_or_insert_with(|| chain.name.clone());
+        }
+        validate(&chains)?;
+        Ok(chains)
+    }
+
+    /// Connects to the chain's RPC, checking that it serves the configured chain.
+    pub async fn connect(&self) -> Result<EthProvider> {
+        if self.rpc_url.is_empty() {
+            bail!("No RPC URL is set for chain {}, set ETH_RPC_URL", self.name);
+        }
+        let provider = EthProvider::connect(&self.rpc_url).await?;
+        if let Some(expected) = self.chain_id {
+            let actual = provider.get_chainid().await?;
+            if actual != expected.into() {
+                bail!(
+                    "The RPC of chain {} serves chain ID {}, expected {}",
+                    self.name,
+                    actual,
+                    expected
+                );
+            }
+        }
+        Ok(provider)
+    }
+}
+
+/// Whether a name can be used unquoted in a URL path and as a PostgreSQL identifier.
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Checks that a chains file lists at least one chain, and that names and schemas are unique
+/// identifiers.
+pub fn validate(chains: &[ChainConfig]) -> Result<()> {
+    if chains.is_empty() {
+        bail!("No chains are configured");
+    }
+    let mut names = HashSet::new();
+    let mut schemas = HashSet::new();
+    for chain in chains {
+        if !is_identifier(&chain.name) {
+            bail!(
+                "Invalid chain name {:?}: use lowercase letters, digits and underscores",
+                chain.name
+            );
+        }
+        if !names.insert(&chain.name) {
+            bail!("Chain {} is configured twice", chain.name);
+        }
+        if let Some(schema) = &chain.schema {
+            if !is_identifier(schema) {
+                bail!("Invalid schema {:?} for chain {}", schema, chain.name);
+            }
+            if !schemas.insert(schema) {
+                bail!("Schema {} is used by several chains", schema);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,442 @@
+use crate::{
+    indexer::{EthProvider, TRANSFER_EVENT_SIGNATURE},
+    models::{self, DbAddress, DbHash, NewProxyUpgrade},
+    store::{blocking, Rows, SharedStore},
+};
+use anyhow::Result;
+use ethers::{
+    prelude::*,
+    types::{
+        transaction::eip2718::TypedTransaction, Log as EthersLog, Transaction as EthersTransaction,
+    },
+    utils::keccak256,
+};
+use std::collections::{HashMap, HashSet};
+
+/// EIP-1967 implementation slot, `keccak256("eip1967.proxy.implementation") - 1`.
+pub const EIP1967_IMPLEMENTATION_SLOT: H256 = H256([
+    0x36, 0x08, 0x94, 0xa1, 0x3b, 0xa1, 0xa3, 0x21, 0x06, 0x67, 0xc8, 0x28, 0x49, 0x2d, 0xb9, 0x8d,
+    0xca, 0x3e, 0x20, 0x76, 0xcc, 0x37, 0x35, 0xa9, 0x20, 0xa3, 0xca, 0x50, 0x5d, 0x38, 0x2b, 0xbc,
+]);
+
+/// EIP-1967 beacon slot, `keccak256("eip1967.proxy.beacon") - 1`.
+pub const EIP1967_BEACON_SLOT: H256 = H256([
+    0xa3, 0xf0, 0xad, 0x74, 0xe5, 0x42, 0x3a, 0xeb, 0xfd, 0x80, 0xd3, 0xef, 0x43, 0x46, 0x57, 0x83,
+    0x35, 0xa9, 0xa7, 0x2a, 0xea, 0xee, 0x59, 0xff, 0x6c, 0xb3, 0x58, 0x2b, 0x35, 0x13, 0x3d, 0x50,
+]);
+
+/// EIP-1822 (UUPS) implementation slot, `keccak256("PROXIABLE")`.
+pub const EIP1822_PROXIABLE_SLOT: H256 = H256([
+    0xc5, 0xf1, 0x6f, 0x0f, 0xcc, 0x63, 0x9f, 0xa4, 0x8a, 0x69, 0x47, 0x83, 0x6d, 0x98, 0x50, 0xf5,
+    0x04, 0x79, 0x85, 0x23, 0xbf, 0x8c, 0x9a, 0x3a, 0x87, 0xd5, 0x87, 0x6c, 0xf6, 0x22, 0xbc, 0xf7,
+]);
+
+// `Upgraded(address)`, emitted by EIP-1967 proxies and upgradeable beacons
+pub const UPGRADED_EVENT_SIGNATURE: H256 = H256([
+    0xbc, 0x7c, 0xd7, 0x5a, 0x20, 0xee, 0x27, 0xfd, 0x9a, 0xde, 0xba, 0xb3, 0x20, 0x41, 0xf7, 0x55,
+    0x21, 0x4d, 0xbc, 0x6b, 0xff, 0xa9, 0x0c, 0xc0, 0x22, 0x5b, 0x39, 0xda, 0x2e, 0x5c, 0x2d, 0x3b,
+]);
+
+/// Selector of the beacon's `implementation()` getter.
+const IMPLEMENTATION_SELECTOR: [u8; 4] = [0x5c, 0x60, 0xda, 0x1b];
+
+/// Selectors of the ERC20 (and ERC721) metadata getters `name()`, `symbol()` and `decimals()`.
+const NAME_SELECTOR: [u8; 4] = [0x06, 0xfd, 0xde, 0x03];
+const SYMBOL_SELECTOR: [u8; 4] = [0x95, 0xd8, 0x9b, 0x41];
+const DECIMALS_SELECTOR: [u8; 4] = [0x31, 0x3c, 0xe5, 0x67];
+
+/// Longest token name or symbol stored, in characters.
+const MAX_TOKEN_TEXT: usize = 64;
+
+/// Runtime code of an EIP-1167 minimal proxy, around the 20-byte implementation address.
+const MINIMAL_PROXY_PREFIX: [u8; 10] = [0x36, 0x3d, 0x3d, 0x37, 0x3d, 0x3d, 0x3d, 0x36, 0x3d, 0x73];
+const MINIMAL_PROXY_SUFFIX: [u8; 15] = [
+    0x5a, 0xf4, 0x3d, 0x82, 0x80, 0x3e, 0x90, 0x3d, 0x91, 0x60, 0x2b, 0x57, 0xfd, 0x5b, 0xf3,
+];
+
+/// The proxy patterns recognised by the indexer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProxyKind {
+    Eip1967,
+    Eip1822,
+    Beacon,
+    MinimalProxy,
+}
+
+impl ProxyKind {
+    /// The name stored in `contracts.proxy_kind`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProxyKind::Eip1967 => "eip1967",
+            ProxyKind::Eip1822 => "eip1822",
+            ProxyKind::Beacon => "beacon",
+            ProxyKind::MinimalProxy => "eip1167",
+        }
+    }
+}
+
+/// How a proxy forwards its calls.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyTarget {
+    pub kind: ProxyKind,
+    pub implementation: Option<Address>,
+    /// The beacon the implementation is read from, for beacon proxies.
+    pub beacon: Option<Address>,
+}
+
+/// A contract's code as seen at the block it was inspected at.
+#[derive(Clone, Debug)]
+pub struct DeployedContract {
+    pub code: Bytes,
+    pub proxy: Option<ProxyTarget>,
+    /// Set when the contract answers `name()` or `symbol()`.
+    pub token: Option<TokenMetadata>,
+}
+
+/// The metadata a token contract reports about itself.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<i16>,
+}
+
+/// Returns the implementation of an EIP-1167 minimal proxy from its runtime code.
+pub fn minimal_proxy_target(code: &[u8]) -> Option<Address> {
+    let rest = code.strip_prefix(&MINIMAL_PROXY_PREFIX)?;
+    (rest.len() == 20 + MINIMAL_PROXY_SUFFIX.len() && rest[20..] == MINIMAL_PROXY_SUFFIX)
+        .then(|| Address::from_slice(&rest[..20]))
+}
+
+/// The existing contracts a transaction may touch: its recipient, whatever its input, since a
+/// contract can first be seen receiving a plain transfer, and the emitters of its `Transfer`
+/// logs, which are tokens even when only called from another contract.
+pub(crate) fn called_contracts(
+    tx: &EthersTransaction,
+    receipt: &TransactionReceipt,
+) -> Vec<Address> {
+    let emitters = receipt
+        .logs
+        .iter()
+        .filter(|log| log.topics.first() == Some(&TRANSFER_EVENT_SIGNATURE))
+        .map(|log| log.address);
+    let mut called = Vec::new();
+    for address in tx.to.into_iter().chain(emitters) {
+        if !called.contains(&address) {
+            called.push(address);
+        }
+    }
+    called
+}
+
+/// The addresses that may be contracts in the given transactions: the `called_contracts` and
+/// the created contracts.
+pub fn contract_candidates(
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+) -> HashSet<Address> {
+    transactions_with_receipts
+        .iter()
+        .flat_map(|(tx, receipt)| {
+            called_contracts(tx, receipt)
+                .into_iter()
+                .chain(receipt.contract_address)
+        })
+        .collect()
+}
+
+/// The addresses of the given transactions that may hold code even if an earlier lookup found
+/// none: created contracts, `Transfer` emitters and recipients called with input.
+fn code_hints(
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+) -> HashSet<Address> {
+    transactions_with_receipts
+        .iter()
+        .flat_map(|(tx, receipt)| {
+            let emitters = receipt
+                .logs
+                .iter()
+                .filter(|log| log.topics.first() == Some(&TRANSFER_EVENT_SIGNATURE))
+                .map(|log| log.address);
+            tx.to
+                .filter(|_| !tx.input.is_empty())
+                .into_iter()
+                .chain(emitters)
+                .chain(receipt.contract_address)
+        })
+        .collect()
+}
+
+/// Fetches the code of the `contract_candidates` of the given transactions, and detects which
+/// of them are proxies or tokens.
+///
+/// Contracts whose code was already fetched are skipped, and so are accounts whose code was
+/// looked up without finding any, unless one of the `code_hints` names them again. The result
+/// leaves out addresses without code (EOAs).
+pub(crate) async fn inspect_contracts(
+    provider: &EthProvider,
+    store: &SharedStore,
+    block_number: u64,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+) -> Result<HashMap<Address, DeployedContract>> {
+    let mut candidates = contract_candidates(transactions_with_receipts);
+    if !candidates.is_empty() {
+        let addresses: Vec<Address> = candidates.iter().copied().collect();
+        let (known, checked) = blocking(store, move |store| {
+            Ok((
+                store.known_contracts(&addresses)?,
+                store.code_checked(&addresses)?,
+            ))
+        })
+        .await?;
+        let hints = code_hints(transactions_with_receipts);
+        candidates.retain(|address| {
+            !known.contains(address) && (!checked.contains(address) || hints.contains(address))
+        });
+    }
+
+    let block = Some(BlockId::Number(block_number.into()));
+    let inspections = candidates.iter().map(|address| async move {
+        let code = provider.get_code(*address, block).await?;
+        if code.is_empty() {
+            return Ok::<_, anyhow::Error>(None);
+        }
+        let proxy = detect_proxy(provider, *address, &code, block).await?;
+        let token = token_metadata(provider, *address, block).await;
+        Ok(Some(DeployedContract { code, proxy, token }))
+    });
+    let results = futures::future::join_all(inspections).await;
+
+    let mut contracts = HashMap::new();
+    for (address, result) in candidates.into_iter().zip(results) {
+        if let Some(contract) = result? {
+            contracts.insert(address, contract);
+        }
+    }
+    Ok(contracts)
+}
+
+/// Detects the proxy pattern of a contract from its code, then from the standard storage slots.
+async fn detect_proxy(
+    provider: &EthProvider,
+    address: Address,
+    code: &Bytes,
+    block: Option<BlockId>,
+) -> Result<Option<ProxyTarget>> {
+    if let Some(implementation) = minimal_proxy_target(code) {
+        return Ok(Some(ProxyTarget {
+            kind: ProxyKind::MinimalProxy,
+            implementation: Some(implementation),
+            beacon: None,
+        }));
+    }
+
+    let slot_address = |value: H256| (!value.is_zero()).then(|| Address::from(value));
+
+    let implementation = provider
+        .get_storage_at(address, EIP1967_IMPLEMENTATION_SLOT, block)
+        .await?;
+    if let Some(implementation) = slot_address(implementation) {
+        return Ok(Some(ProxyTarget {
+            kind: ProxyKind::Eip1967,
+            implementation: Some(implementation),
+            beacon: None,
+        }));
+    }
+
+    let beacon = provider
+        .get_storage_at(address, EIP1967_BEACON_SLOT, block)
+        .await?;
+    if let Some(beacon) = slot_address(beacon) {
+        let call: TypedTransaction = TransactionRequest::new()
+            .to(beacon)
+            .data(IMPLEMENTATION_SELECTOR.to_vec())
+            .into();
+        // A beacon that doesn't answer still identifies the proxy, just not its implementation.
+        let implementation = provider
+            .call(&call, block)
+            .await
+            .ok()
+            .filter(|output| output.len() == 32)
+            .and_then(|output| slot_address(H256::from_slice(&output)));
+        return Ok(Some(ProxyTarget {
+            kind: ProxyKind::Beacon,
+            implementation,
+            beacon: Some(beacon),
+        }));
+    }
+
+    let implementation = provider
+        .get_storage_at(address, EIP1822_PROXIABLE_SLOT, block)
+        .await?;
+    Ok(
+        slot_address(implementation).map(|implementation| ProxyTarget {
+            kind: ProxyKind::Eip1822,
+            implementation: Some(implementation),
+            beacon: None,
+        }),
+    )
+}
+
+/// Reads a contract's token metadata. Getters that revert or return something else are left out,
+/// and contracts without a name or symbol aren't tokens.
+async fn token_metadata(
+    provider: &EthProvider,
+    address: Address,
+    block: Option<BlockId>,
+) -> Option<TokenMetadata> {
+    let call = |selector: [u8; 4]| async move {
+        let call: TypedTransaction = TransactionRequest::new()
+            .to(address)
+            .data(selector.to_vec())
+            .into();
+        provider.call(&call, block).await.ok()
+    };
+    let name = call(NAME_SELECTOR)
+        .await
+        .and_then(|out| decode_token_text(&out));
+    let symbol = call(SYMBOL_SELECTOR)
+        .await
+        .and_then(|out| decode_token_text(&out));
+    if name.is_none() && symbol.is_none() {
+        return None;
+    }
+    let decimals = call(DECIMALS_SELECTOR)
+        .await
+        .filter(|out| out.len() == 32 && out[..31].iter().all(|b| *b == 0))
+        .map(|out| i16::from(out[31]));
+    Some(TokenMetadata {
+        name,
+        symbol,
+        decimals,
+    })
+}
+
+/// Decodes the output of `name()` or `symbol()`: an ABI string, or a NUL-padded `bytes32` as
+/// returned by some early tokens.
+pub fn decode_token_text(output: &[u8]) -> Option<String> {
+    let text = match ethers::abi::decode(&[ethers::abi::ParamType::String], output) {
+        Ok(mut tokens) => tokens.pop()?.into_string()?,
+        Err(_) if output.len() == 32 => String::from_utf8(output.to_vec())
+            .ok()?
+            .trim_end_matches('\0')
+            .to_string(),
+        Err(_) => return None,
+    };
+    let text: String = text
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TOKEN_TEXT)
+        .collect();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Stores a contract and its deduplicated bytecode.
+///
+/// `creation` is the transaction and block that deployed the contract, when it was seen. The
+/// proxy details and token metadata are replaced with the latest inspection.
+pub fn store_contract<R: Rows>(
+    rows: &mut R,
+    address: Address,
+    contract: &DeployedContract,
+    creation: Option<(H256, i64)>,
+) -> Result<()> {
+    let bytecode_hash = DbHash(H256(keccak256(&contract.code)));
+    rows.insert_bytecode(bytecode_hash, &contract.code)?;
+
+    let address = DbAddress(address);
+    let mut stored = rows
+        .contract(address)?
+        .unwrap_or_else(|| new_contract(address));
+    stored.bytecode_hash = Some(bytecode_hash);
+    if stored.creation_tx.is_none() {
+        stored.creation_tx = creation.map(|(tx_hash, _)| DbHash(tx_hash));
+    }
+    if stored.creation_block.is_none() {
+        stored.creation_block = creation.map(|(_, block_number)| block_number);
+    }
+    let proxy = contract.proxy.as_ref();
+    stored.proxy_kind = proxy.map(|p| p.kind.as_str().to_string());
+    stored.implementation = proxy.and_then(|p| p.implementation).map(DbAddress);
+    stored.beacon = proxy.and_then(|p| p.beacon).map(DbAddress);
+    let token = contract.token.clone().unwrap_or_default();
+    stored.token_name = token.name;
+    stored.token_symbol = token.symbol;
+    stored.token_decimals = token.decimals;
+    rows.save_contract(&stored)
+}
+
+/// A contract row with nothing known about the contract yet.
+fn new_contract(address: DbAddress) -> models::Contract {
+    models::Contract {
+        address,
+        bytecode_hash: None,
+        creation_tx: None,
+        creation_block: None,
+        proxy_kind: None,
+        implementation: None,
+        beacon: None,
+        token_name: None,
+        token_symbol: None,
+        token_decimals: None,
+    }
+}
+
+/// Applies an `Upgraded(address)` event to the proxy that emitted it.
+///
+/// When the emitter is the beacon of known proxies, those proxies are upgraded instead. An
+/// emitter that isn't a known proxy is recorded as an EIP-1967 one, since the event is only
+/// emitted by upgradeable contracts.
+pub fn record_upgrade<R: Rows>(rows: &mut R, tx_hash: H256, log: &EthersLog) -> Result<()> {
+    if log.topics.len() != 2 {
+        return Ok(());
+    }
+    let emitter = DbAddress(log.address);
+    let implementation = DbAddress(Address::from(log.topics[1]));
+
+    let beacon_proxies = rows.beacon_proxies(emitter)?;
+    let emitter_contract = if beacon_proxies.is_empty() {
+        rows.contract(emitter)?
+    } else {
+        None
+    };
+    let previous_implementation = match beacon_proxies.first() {
+        Some(proxy) => proxy.implementation,
+        None => emitter_contract.as_ref().and_then(|c| c.implementation),
+    };
+
+    rows.insert_proxy_upgrade(&NewProxyUpgrade {
+        tx_hash: DbHash(tx_hash),
+        proxy_address: emitter,
+        implementation,
+        previous_implementation,
+    })?;
+
+    if beacon_proxies.is_empty() {
+        let mut contract = emitter_contract.unwrap_or_else(|| new_contract(emitter));
+        contract
+            .proxy_kind
+            .get_or_insert_with(|| ProxyKind::Eip1967.as_str().to_string());
+        contract.implementation = Some(implementation);
+        rows.save_contract(&contract)?;
+    }
+    for mut proxy in beacon_proxies {
+        proxy.implementation = Some(implementation);
+        rows.save_contract(&proxy)?;
+    }
+
+    Ok(())
+}
+
+/// Restores the implementation a proxy (or a beacon's proxies) had before an upgrade.
+pub fn revert_upgrade<R: Rows>(rows: &mut R, upgrade: &models::ProxyUpgrade) -> Result<()> {
+    let mut proxies = rows.beacon_proxies(upgrade.proxy_address)?;
+    if proxies.is_empty() {
+        proxies.extend(rows.contract(upgrade.proxy_address)?);
+    }
+    for mut proxy in proxies {
+        proxy.implementation = upgrade.previous_implementation;
+        rows.save_contract(&proxy)?;
+    }
+    Ok(())
+}
@@ -1,20 +1,83 @@
 use diesel::{
+    connection::SimpleConnection,
     pg::PgConnection,
-    r2d2::{self, ConnectionManager},
+    r2d2::{self, ConnectionManager, CustomizeConnection},
+    result::QueryResult,
+    sqlite::SqliteConnection,
 };
 
+use std::time::Duration;
+
 // A type alias for the connection pool.
 pub type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 
+/// A connection pool to a SQLite database file, see `store::SqliteStore`.
+pub type SqlitePool = r2d2::Pool<ConnectionManager<SqliteConnection>>;
+
+/// Sizing and timeouts of a connection pool. The defaults are r2d2's.
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    /// Connections opened at most.
+    pub max_size: u32,
+    /// Idle connections kept open; defaults to `max_size`.
+    pub min_idle: Option<u32>,
+    /// How long getting a connection waits for one to be free before failing.
+    pub connection_timeout: Duration,
+    /// Idle connections above `min_idle` are closed after this long; never when unset.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            min_idle: None,
+            connection_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(10 * 60)),
+        }
+    }
+}
+
+impl PoolConfig {
+    fn builder<M: r2d2::ManageConnection>(&self) -> r2d2::Builder<M> {
+        r2d2::Pool::builder()
+            .max_size(self.max_size)
+            .min_idle(self.min_idle)
+            .connection_timeout(self.connection_timeout)
+            .idle_timeout(self.idle_timeout)
+    }
+}
+
 /// Establishes and returns a connection pool to the `PostgreSQL` database.
 ///
 /// # Panics
 ///
 /// The function will panic if it fails to create the connection pool.
-pub fn establish_connection_pool(database_url: &String) -> DbPool {
-    let manager = ConnectionManager::<PgConnection>::new(database_url);
+pub fn establish_connection_pool(database_url: &String, config: &PoolConfig) -> DbPool {
+    build_pg_pool(database_url, config.builder())
+}
 
-    let mut builder = r2d2::Pool::builder();
+/// Establishes a connection pool whose connections only see a chain's schema, which must exist,
+/// see `use_schema`.
+///
+/// # Panics
+///
+/// The function will panic if it fails to create the connection pool.
+pub fn establish_schema_pool(database_url: &String, schema: &str, config: &PoolConfig) -> DbPool {
+    let search_path = SearchPath(schema.to_string());
+    build_pg_pool(
+        database_url,
+        config
+            .builder()
+            .connection_customizer(Box::new(search_path)),
+    )
+}
+
+fn build_pg_pool(
+    database_url: &String,
+    mut builder: r2d2::Builder<ConnectionManager<PgConnection>>,
+) -> DbPool {
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
 
     if cfg!(test) {
         builder = builder.connection_timeout(std::time::Duration::new(1, 0));
@@ -24,3 +87,67 @@ pub fn establish_connection_pool(database_url: &String) -> DbPool {
         .build(manager)
         .expect("Failed to create database connection pool.")
 }
+
+/// Creates a chain's schema if needed and makes it the only one the connection sees, so the
+/// unqualified table names of the queries and migrations refer to the chain's tables. `schema`
+/// must be a plain identifier, see `chains::validate`.
+pub fn use_schema(connection: &mut PgConnection, schema: &str) -> QueryResult<()> {
+    connection.batch_execute(&format!(
+        "CREATE SCHEMA IF NOT EXISTS {0}; SET search_path TO {0}",
+        schema
+    ))
+}
+
+/// Points every pooled connection at a chain's schema, see `use_schema`.
+#[derive(Debug)]
+struct SearchPath(String);
+
+impl CustomizeConnection<PgConnection, r2d2::Error> for SearchPath {
+    fn on_acquire(&self, connection: &mut PgConnection) -> Result<(), r2d2::Error> {
+        connection
+            .batch_execute(&format!("SET search_path TO {}", self.0))
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// The SQLite database named by a `sqlite://` (or `sqlite:`) URL, e.g. `sqlite://indexer.db` or
+/// `sqlite::memory:`. Other URLs are PostgreSQL ones.
+pub fn sqlite_path(database_url: &str) -> Option<&str> {
+    database_url
+        .strip_prefix("sqlite://")
+        .or_else(|| database_url.strip_prefix("sqlite:"))
+}
+
+/// Settings applied to every SQLite connection: foreign keys aren't enforced by default, and
+/// writers wait for each other instead of failing with `SQLITE_BUSY`.
+#[derive(Debug)]
+struct SqlitePragmas;
+
+impl CustomizeConnection<SqliteConnection, r2d2::Error> for SqlitePragmas {
+    fn on_acquire(&self, connection: &mut SqliteConnection) -> Result<(), r2d2::Error> {
+        connection
+            .batch_execute(
+                "PRAGMA foreign_keys = ON; PRAGMA busy_timeout = 5000; PRAGMA journal_mode = WAL;",
+            )
+            .map_err(r2d2::Error::QueryError)
+    }
+}
+
+/// Establishes a connection pool to a SQLite database file, creating it if needed.
+///
+/// Every connection to `:memory:` opens its own database, so the pool keeps a single one, and
+/// never closes it: not when idle, nor once old, nor after a failed health check.
+pub fn establish_sqlite_pool(path: &str, config: &PoolConfig) -> anyhow::Result<SqlitePool> {
+    let mut builder = config
+        .builder()
+        .connection_customizer(Box::new(SqlitePragmas));
+    if path == ":memory:" {
+        builder = builder
+            .max_size(1)
+            .min_idle(None)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .test_on_check_out(false);
+    }
+    Ok(builder.build(ConnectionManager::<SqliteConnection>::new(path))?)
+}
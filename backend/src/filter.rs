@@ -0,0 +1,86 @@
+use anyhow::{Context, Result};
+use ethers::types::{
+    Address, BlockNumber, Filter, Log as EthersLog, Transaction as EthersTransaction, H256,
+};
+use serde::Deserialize;
+use std::{collections::HashSet, fs, path::Path};
+
+/// Restricts which transactions and logs are persisted. Block headers are always stored.
+///
+/// A log is kept when it matches both `addresses` and `topics` (an empty set matches anything),
+/// provided at least one of them is set. A transaction is kept when it was sent from an address
+/// in `from`, to an address in `to` or `addresses`, or emitted a kept log. Transactions matched
+/// directly keep all their logs, others only keep the logs that matched.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndexFilter {
+    /// Contracts whose logs and incoming transactions are indexed.
+    #[serde(default)]
+    pub addresses: HashSet<Address>,
+    /// Event signatures (`topic0`) to index.
+    #[serde(default)]
+    pub topics: HashSet<H256>,
+    /// Senders whose transactions are indexed.
+    #[serde(default)]
+    pub from: HashSet<Address>,
+    /// Recipients whose transactions are indexed.
+    #[serde(default)]
+    pub to: HashSet<Address>,
+}
+
+impl IndexFilter {
+    /// Loads a filter from a JSON file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Whether the filter selects logs, which makes `eth_getLogs` usable to find them.
+    pub fn has_log_criteria(&self) -> bool {
+        !self.addresses.is_empty() || !self.topics.is_empty()
+    }
+
+    pub fn matches_log(&self, log: &EthersLog) -> bool {
+        self.has_log_criteria()
+            && (self.addresses.is_empty() || self.addresses.contains(&log.address))
+            && (self.topics.is_empty()
+                || log.topics.first().is_some_and(|t| self.topics.contains(t)))
+    }
+
+    /// Whether a transaction is selected by its sender or recipient alone.
+    pub fn matches_transaction(&self, tx: &EthersTransaction) -> bool {
+        self.from.contains(&tx.from)
+            || tx
+                .to
+                .is_some_and(|to| self.to.contains(&to) || self.addresses.contains(&to))
+    }
+
+    /// Builds the `eth_getLogs` filter selecting matching logs between two blocks (inclusive).
+    ///
+    /// Returns `None` when the filter has no log criteria.
+    pub fn log_filter(&self, from_block: u64, to_block: u64) -> Option<Filter> {
+        self.has_log_criteria().then(|| {
+            self.base_log_filter()
+                .from_block(BlockNumber::Number(from_block.into()))
+                .to_block(BlockNumber::Number(to_block.into()))
+        })
+    }
+
+    /// Builds the `eth_getLogs` filter selecting matching logs of a single block.
+    pub fn block_log_filter(&self, block_hash: H256) -> Option<Filter> {
+        self.has_log_criteria()
+            .then(|| self.base_log_filter().at_block_hash(block_hash))
+    }
+
+    fn base_log_filter(&self) -> Filter {
+        let mut filter = Filter::new();
+        if !self.addresses.is_empty() {
+            filter = filter.address(self.addresses.iter().copied().collect::<Vec<_>>());
+        }
+        if !self.topics.is_empty() {
+            filter = filter.topic0(self.topics.iter().copied().collect::<Vec<_>>());
+        }
+        filter
+    }
+}
@@ -0,0 +1,154 @@
+use crate::schema::handler_migrations;
+use anyhow::{Context, Result};
+use common::DecodedLog;
+use diesel::{connection::SimpleConnection, prelude::*};
+use ethers::types::{Address, Log as EthersLog, H256};
+use std::collections::HashSet;
+
+/// Custom indexing logic for contract events.
+///
+/// Handlers run inside the database transaction of the block being indexed, after the log and
+/// any token transfer it represents have been stored, so an error aborts the whole block. When a
+/// block is rolled back because of a reorg, `rollback` is called for each of its logs in reverse
+/// order, before the block's rows are deleted.
+pub trait EventHandler: Send + Sync {
+    /// A unique name, used to track which of the handler's migrations have been applied.
+    fn name(&self) -> &str;
+
+    /// Contract addresses whose logs are dispatched to this handler. Empty matches any address.
+    fn addresses(&self) -> Vec<Address> {
+        Vec::new()
+    }
+
+    /// `topic0` values dispatched to this handler. Empty matches any event.
+    fn topics(&self) -> Vec<H256> {
+        Vec::new()
+    }
+
+    /// Processes a matching log. `decoded` is set when the log matched a known ABI.
+    fn handle(
+        &self,
+        connection: &mut PgConnection,
+        log: &EthersLog,
+        decoded: Option<&DecodedLog>,
+    ) -> Result<()>;
+
+    /// Reverts the effects of `handle` for a log whose block is being rolled back.
+    fn rollback(
+        &self,
+        _connection: &mut PgConnection,
+        _log: &EthersLog,
+        _decoded: Option<&DecodedLog>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// SQL migrations creating the tables the handler writes to, applied in order at startup.
+    fn migrations(&self) -> Vec<HandlerMigration> {
+        Vec::new()
+    }
+}
+
+/// A migration owned by an [`EventHandler`].
+pub struct HandlerMigration {
+    /// Identifies the migration among the handler's own, e.g. `"2025-07-23-create_swaps"`.
+    pub version: &'static str,
+    pub up: &'static str,
+}
+
+struct RegisteredHandler {
+    addresses: HashSet<Address>,
+    topics: HashSet<H256>,
+    handler: Box<dyn EventHandler>,
+}
+
+impl RegisteredHandler {
+    fn matches(&self, log: &EthersLog) -> bool {
+        (self.addresses.is_empty() || self.addresses.contains(&log.address))
+            && (self.topics.is_empty()
+                || log.topics.first().is_some_and(|t| self.topics.contains(t)))
+    }
+}
+
+/// The event handlers the indexer dispatches logs to, in registration order.
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: Vec<RegisteredHandler>,
+}
+
+impl HandlerRegistry {
+    pub fn register(&mut self, handler: impl EventHandler + 'static) {
+        self.handlers.push(RegisteredHandler {
+            addresses: handler.addresses().into_iter().collect(),
+            topics: handler.topics().into_iter().collect(),
+            handler: Box::new(handler),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Applies the pending migrations of every registered handler.
+    pub fn run_migrations(&self, connection: &mut PgConnection) -> Result<()> {
+        for registered in &self.handlers {
+            let name = registered.handler.name();
+            for migration in registered.handler.migrations() {
+                connection.transaction::<_, anyhow::Error, _>(|conn| {
+                    let applied = diesel::insert_into(handler_migrations::table)
+                        .values((
+                            handler_migrations::handler.eq(name),
+                            handler_migrations::version.eq(migration.version),
+                        ))
+                        .on_conflict_do_nothing()
+                        .execute(conn)?;
+                    if applied > 0 {
+                        conn.batch_execute(migration.up).with_context(|| {
+                            format!("running migration {} of {}", migration.version, name)
+                        })?;
+                        println!(
+                            "✅ Applied migration {} of handler {}",
+                            migration.version, name
+                        );
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Passes a newly indexed log to every matching handler.
+    pub fn dispatch(
+        &self,
+        connection: &mut PgConnection,
+        log: &EthersLog,
+        decoded: Option<&DecodedLog>,
+    ) -> Result<()> {
+        for registered in self.handlers.iter().filter(|h| h.matches(log)) {
+            registered
+                .handler
+                .handle(connection, log, decoded)
+                .with_context(|| format!("event handler {}", registered.handler.name()))?;
+        }
+        Ok(())
+    }
+
+    /// Reverts a log in every matching handler, in reverse registration order.
+    pub fn rollback(
+        &self,
+        connection: &mut PgConnection,
+        log: &EthersLog,
+        decoded: Option<&DecodedLog>,
+    ) -> Result<()> {
+        for registered in self.handlers.iter().rev().filter(|h| h.matches(log)) {
+            registered
+                .handler
+                .rollback(connection, log, decoded)
+                .with_context(|| {
+                    format!("rolling back event handler {}", registered.handler.name())
+                })?;
+        }
+        Ok(())
+    }
+}
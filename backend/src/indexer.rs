@@ -1,10 +1,24 @@
 use crate::{
-    db::DbPool,
-    models::{Account, Block, NewLog, NewTokenTransfer, Transaction},
-    schema::{accounts, blocks, logs, token_balances, token_transfers, transactions},
+    abi::{AbiRegistry, SharedAbiRegistry},
+    bulk,
+    chains::ChainConfig,
+    contracts::{
+        called_contracts, inspect_contracts, record_upgrade, revert_upgrade, store_contract,
+        DeployedContract, UPGRADED_EVENT_SIGNATURE,
+    },
+    filter::IndexFilter,
+    handlers::HandlerRegistry,
+    integrity::{verify_block_data, HeaderRoots},
+    l2,
+    models::{self, Block, DbAddress, DbHash, NewLog, NewTokenTransfer, Transaction},
+    partitions::Partitioning,
+    schema::{blocks, indexer_state, logs, transactions},
+    store::{blocking, Rows, SharedStore},
 };
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use common::DecodedLog;
 use diesel::{
     prelude::*,
     r2d2::{ConnectionManager, PooledConnection},
@@ -13,11 +27,16 @@ use ethers::{
     prelude::*,
     types::{Block as EthersBlock, Log as EthersLog, Transaction as EthersTransaction},
 };
-use std::{str::FromStr, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::RangeInclusive,
+    str::FromStr,
+    sync::Arc,
+};
 use tokio::time::{sleep, Duration};
 
-type EthProvider = Provider<Ws>;
-type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
+pub(crate) type EthProvider = Provider<Ws>;
+pub(crate) type DbConnection = PooledConnection<ConnectionManager<PgConnection>>;
 
 // Standard `Transfer(address,address,uint256)` event signature for ERC20 and ERC721
 pub const TRANSFER_EVENT_SIGNATURE: H256 = H256([
@@ -25,40 +44,124 @@ pub const TRANSFER_EVENT_SIGNATURE: H256 = H256([
     0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
 ]);
 
+/// State shared by every block processed by the indexer.
+#[derive(Clone, Default)]
+pub struct IndexerContext {
+    pub abi_registry: SharedAbiRegistry,
+    pub handlers: Arc<HandlerRegistry>,
+    /// Restricts the transactions and logs that are stored; everything is indexed when unset.
+    pub filter: Option<Arc<IndexFilter>>,
+    /// Checks each block's transactions and receipts against its header before storing it.
+    pub verify_blocks: bool,
+    /// The query indexes were deferred, and are built once the indexer catches up with the chain.
+    pub deferred_indexes: bool,
+    /// Blocks written together by `bulk::write_block_batch` while backfilling; blocks are written
+    /// one by one when this is 0 or 1.
+    pub batch_size: usize,
+    /// Creates the partitions blocks are written to, once the tables are partitioned.
+    pub partitioning: Option<Arc<Partitioning>>,
+}
+
+/// Creates any missing partitions for `blocks` when the tables are partitioned. Called before
+/// the transaction writing the blocks, see `Partitioning::ensure`.
+pub(crate) fn ensure_partitions(
+    connection: &mut PgConnection,
+    context: &IndexerContext,
+    blocks: RangeInclusive<i64>,
+) -> Result<()> {
+    match &context.partitioning {
+        Some(partitioning) => partitioning.ensure(connection, blocks),
+        None => Ok(()),
+    }
+}
+
 /// The main entry point for the indexer.
-pub async fn run_indexer(pool: DbPool, start_block: Option<u64>) -> Result<()> {
-    let rpc_url = std::env::var("ETH_RPC_URL").expect("ETH_RPC_URL must be set");
-    let provider = Arc::new(EthProvider::connect(&rpc_url).await?);
-    println!("✅ Indexer connected to Ethereum RPC");
+pub async fn run_indexer(
+    store: SharedStore,
+    context: IndexerContext,
+    chain: ChainConfig,
+) -> Result<()> {
+    let provider = Arc::new(chain.connect().await?);
+    println!("✅ Indexer connected to the RPC of chain {}", chain.name);
 
-    let mut conn = pool.get()?;
-    let mut start_block =
-        get_latest_indexed_block(&mut conn)?.unwrap_or(start_block.unwrap_or(0) as i64);
-    drop(conn); // Release connection before long-running loop
+    // Blocks are committed in order, so everything up to the latest indexed block is complete.
+    let mut start_block = blocking(&store, |store| store.latest_block_number())
+        .await?
+        .map_or(chain.start_block.unwrap_or(0) as i64, |latest| latest + 1);
 
-    println!("🚀 Starting indexer from block {}", start_block);
+    println!(
+        "🚀 Starting indexer of chain {} from block {}",
+        chain.name, start_block
+    );
 
+    let mut indexes_deferred = context.deferred_indexes;
     loop {
         let latest_block_on_chain = provider.get_block_number().await?.as_u64() as i64;
 
         if start_block > latest_block_on_chain {
+            if std::mem::take(&mut indexes_deferred) {
+                store.build_deferred_indexes();
+            }
             // Wait for new blocks to be mined
             sleep(Duration::from_secs(5)).await;
             continue;
         }
 
+        // Far from the head, whole batches of blocks are written at once.
+        let batch_end = start_block + context.batch_size as i64 - 1;
+        if context.batch_size > 1 && batch_end < latest_block_on_chain {
+            println!("Indexing blocks {}..={}", start_block, batch_end);
+            let result = async {
+                let batch =
+                    bulk::fetch_block_range(&store, &provider, &context, start_block..=batch_end)
+                        .await?;
+                if unwind_reorg(&store, &context, start_block, batch[0].block.parent_hash).await? {
+                    return Ok(false);
+                }
+                let context = context.clone();
+                blocking(&store, move |store| {
+                    store.write_block_batch(&context, &batch)
+                })
+                .await?;
+                Ok::<_, anyhow::Error>(true)
+            }
+            .await;
+            match result {
+                Ok(true) => {
+                    println!(
+                        "✅ Successfully indexed blocks {}..={}",
+                        start_block, batch_end
+                    );
+                    start_block = batch_end + 1;
+                }
+                Ok(false) => start_block -= 1,
+                Err(e) => {
+                    eprintln!(
+                        "Error indexing blocks {}..={}: {}",
+                        start_block, batch_end, e
+                    );
+                    sleep(Duration::from_secs(10)).await;
+                }
+            }
+            continue;
+        }
+
         println!("Indexing block {}", start_block);
 
         match provider.get_block_with_txs(start_block as u64).await {
             Ok(Some(block)) => {
-                let pool_clone = pool.clone();
-                let provider_clone = provider.clone();
-                tokio::spawn(async move {
-                    if let Err(e) = process_block(pool_clone, provider_clone, block).await {
+                if unwind_reorg(&store, &context, start_block, block.parent_hash).await? {
+                    start_block -= 1;
+                    continue;
+                }
+
+                match process_block(store.clone(), provider.clone(), context.clone(), block).await {
+                    Ok(()) => start_block += 1,
+                    Err(e) => {
                         eprintln!("Error processing block {}: {}", start_block, e);
+                        sleep(Duration::from_secs(10)).await;
                     }
-                });
-                start_block += 1;
+                }
             }
             Ok(None) => {
                 eprintln!("Block {} not found, waiting...", start_block);
@@ -72,205 +175,673 @@ pub async fn run_indexer(pool: DbPool, start_block: Option<u64>) -> Result<()> {
     }
 }
 
-/// Processes a single block and its transactions, storing them in the database.
+/// Rolls back the stored block below `number` if it isn't the parent of the fetched block at
+/// `number` anymore, so indexing can resume from there. Returns whether a block was rolled back.
+async fn unwind_reorg(
+    store: &SharedStore,
+    context: &IndexerContext,
+    number: i64,
+    parent_hash: H256,
+) -> Result<bool> {
+    let context = context.clone();
+    blocking(store, move |store| match store.block_hash(number - 1)? {
+        Some(stored_parent) if stored_parent != parent_hash => {
+            println!(
+                "⚠️ Reorg detected at block {}, rolling back block {}",
+                number,
+                number - 1
+            );
+            store.rollback_block(&context, stored_parent)?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    })
+    .await
+}
+
+/// A block fetched from the RPC with everything needed to store it.
+pub struct FetchedBlock {
+    pub block: EthersBlock<EthersTransaction>,
+    /// The transactions that can match the filter (all of them when unfiltered), with receipts.
+    pub transactions_with_receipts: Vec<(EthersTransaction, TransactionReceipt)>,
+    pub contracts: HashMap<Address, DeployedContract>,
+}
+
+/// Processes a single block and its transactions, storing them in the store.
 pub async fn process_block(
-    pool: DbPool,
+    store: SharedStore,
     provider: Arc<EthProvider>,
+    context: IndexerContext,
     block: EthersBlock<EthersTransaction>,
 ) -> Result<()> {
-    // Collect all transaction hashes from the block.
-    let tx_hashes: Vec<H256> = block.transactions.iter().map(|tx| tx.hash).collect();
+    let fetched = fetch_block_data(&store, &provider, &context, block).await?;
+    let number = fetched.block.number.unwrap().as_u64();
+
+    blocking(&store, move |store| {
+        store.write_block(
+            &context,
+            &block_row(&fetched.block)?,
+            &fetched.transactions_with_receipts,
+            &fetched.contracts,
+        )
+    })
+    .await?;
+
+    println!("✅ Successfully indexed block {}", number);
+    Ok(())
+}
+
+/// Fetches the receipts and contracts a block needs to be stored, verifying it against its header
+/// if enabled.
+pub async fn fetch_block_data(
+    store: &SharedStore,
+    provider: &EthProvider,
+    context: &IndexerContext,
+    block: EthersBlock<EthersTransaction>,
+) -> Result<FetchedBlock> {
+    // In filtered mode, only the transactions that can match need their receipts fetched.
+    let candidates: Vec<&EthersTransaction> = match &context.filter {
+        Some(filter) => {
+            let log_txs: HashSet<H256> = match filter.block_log_filter(block.hash.unwrap()) {
+                Some(log_filter) => provider
+                    .get_logs(&log_filter)
+                    .await?
+                    .iter()
+                    .filter_map(|log| log.transaction_hash)
+                    .collect(),
+                None => HashSet::new(),
+            };
+            block
+                .transactions
+                .iter()
+                .filter(|tx| filter.matches_transaction(tx) || log_txs.contains(&tx.hash))
+                .collect()
+        }
+        None => block.transactions.iter().collect(),
+    };
 
+    let transactions_with_receipts = if context.verify_blocks {
+        // The roots commit to every transaction, so all receipts are needed to check them.
+        let all = fetch_receipts(provider, block.transactions.clone()).await?;
+        verify_block_data(&HeaderRoots::of(&block), &all).map_err(|e| {
+            anyhow::anyhow!(
+                "Block {} failed verification: {}",
+                block.number.unwrap().as_u64(),
+                e
+            )
+        })?;
+        let candidates: HashSet<H256> = candidates.iter().map(|tx| tx.hash).collect();
+        all.into_iter()
+            .filter(|(tx, _)| candidates.contains(&tx.hash))
+            .collect()
+    } else {
+        fetch_receipts(provider, candidates.into_iter().cloned().collect()).await?
+    };
+    let contracts = inspect_contracts(
+        provider,
+        store,
+        block.number.unwrap().as_u64(),
+        &transactions_with_receipts,
+    )
+    .await?;
+
+    Ok(FetchedBlock {
+        block,
+        transactions_with_receipts,
+        contracts,
+    })
+}
+
+/// Fetches the receipts of the given transactions concurrently.
+pub(crate) async fn fetch_receipts(
+    provider: &EthProvider,
+    transactions: Vec<EthersTransaction>,
+) -> Result<Vec<(EthersTransaction, TransactionReceipt)>> {
     // Create a stream of futures to get all receipts concurrently.
-    let receipt_futures = tx_hashes
+    let receipt_futures = transactions
         .iter()
-        .map(|hash| provider.get_transaction_receipt(*hash));
+        .map(|tx| provider.get_transaction_receipt(tx.hash));
 
     // Await all futures and collect the results.
     let receipts_results = futures::future::join_all(receipt_futures).await;
 
     // Combine transactions with their corresponding receipts.
     let mut transactions_with_receipts = Vec::new();
-    for (tx, receipt_result) in block.transactions.iter().zip(receipts_results) {
+    for (tx, receipt_result) in transactions.into_iter().zip(receipts_results) {
         match receipt_result {
-            Ok(Some(receipt)) => transactions_with_receipts.push((tx.clone(), receipt)),
+            Ok(Some(receipt)) => transactions_with_receipts.push((tx, receipt)),
             Ok(None) => return Err(anyhow::anyhow!("Receipt not found for tx {}", tx.hash)),
             Err(e) => return Err(e.into()),
         }
     }
-
-    // Run all database operations in a single synchronous transaction.
-    let mut conn = pool.get()?;
-    process_block_data(&mut conn, &block, &transactions_with_receipts)?;
-
-    println!(
-        "✅ Successfully indexed block {}",
-        block.number.unwrap().as_u64()
-    );
-    Ok(())
+    Ok(transactions_with_receipts)
 }
 
 /// Executes all database writes for a block within a single transaction.
-pub fn process_block_data(
+///
+/// The block's own transaction list is only used for its length, so a header fetched without
+/// transactions can be stored along with a subset of them.
+pub fn process_block_data<TX>(
     connection: &mut DbConnection,
-    block: &EthersBlock<EthersTransaction>,
+    context: &IndexerContext,
+    block: &EthersBlock<TX>,
     transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+    contracts: &HashMap<Address, DeployedContract>,
 ) -> Result<()> {
-    connection.transaction::<_, anyhow::Error, _>(|conn| {
-        // Insert Block
-        let new_block = Block {
-            hash: format!("{:#x}", block.hash.unwrap()),
-            parent_hash: format!("{:#x}", block.parent_hash),
-            number: block.number.unwrap().as_u64() as i64,
-            timestamp: chrono::DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0)
-                .unwrap(),
-            miner: format!("{:#x}", block.author.unwrap()),
-            gas_used: u256_to_bigdecimal(block.gas_used)?,
-            gas_limit: u256_to_bigdecimal(block.gas_limit)?,
-            base_fee_per_gas: block
-                .base_fee_per_gas
-                .map(|v| u256_to_bigdecimal(v).unwrap()),
-            extra_data: block.extra_data.to_string(),
-            tx_count: block.transactions.len() as i32,
-        };
-        diesel::insert_into(blocks::table)
-            .values(&new_block)
-            .on_conflict_do_nothing()
-            .execute(conn)?;
-
-        // Process all collected transactions and receipts
-        for (pos, (tx, receipt)) in transactions_with_receipts.iter().enumerate() {
-            let from_addr_str = format!("{:#x}", tx.from);
-            let to_addr_str = tx.to.map(|a| format!("{:#x}", a));
-
-            ensure_account_exists(conn, &from_addr_str)?;
-            if let Some(to) = &to_addr_str {
-                ensure_account_exists(conn, to)?;
+    store_block(
+        connection,
+        context,
+        &block_row(block)?,
+        transactions_with_receipts,
+        contracts,
+    )
+}
+
+/// Writes a block row with the given transactions within a single transaction, see
+/// `process_block_data`.
+pub(crate) fn store_block(
+    connection: &mut DbConnection,
+    context: &IndexerContext,
+    new_block: &Block,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+    contracts: &HashMap<Address, DeployedContract>,
+) -> Result<()> {
+    ensure_partitions(connection, context, new_block.number..=new_block.number)?;
+    write_block(
+        connection,
+        context,
+        new_block,
+        transactions_with_receipts,
+        contracts,
+    )
+}
+
+/// Writes a block row with the given transactions, their logs and the contracts they touched as
+/// one transaction of `rows`.
+pub(crate) fn write_block<R: Rows>(
+    rows: &mut R,
+    context: &IndexerContext,
+    new_block: &Block,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+    contracts: &HashMap<Address, DeployedContract>,
+) -> Result<()> {
+    let staged = stage_block(context, new_block, transactions_with_receipts)?;
+    rows.in_transaction(|rows| {
+        rows.insert_block(new_block)?;
+
+        for StagedTransaction {
+            tx,
+            receipt,
+            row,
+            logs,
+        } in staged
+        {
+            for (address, activity) in transaction_activity(new_block, tx, receipt, contracts) {
+                record_account_activity(rows, address, &activity)?;
             }
 
-            let new_tx = Transaction {
-                hash: format!("{:#x}", tx.hash),
-                block_hash: format!("{:#x}", block.hash.unwrap()),
-                block_number: block.number.unwrap().as_u64() as i64,
-                from_address: from_addr_str,
-                to_address: to_addr_str,
-                value: u256_to_bigdecimal(tx.value)?,
-                gas_price: receipt
-                    .effective_gas_price
-                    .map(|v| u256_to_bigdecimal(v).unwrap()),
-                gas_used: receipt.gas_used.map(|v| u256_to_bigdecimal(v).unwrap()),
-                nonce: tx.nonce.as_u64() as i64,
-                position: pos as i32,
-            };
+            rows.insert_transaction(&row)?;
 
-            diesel::insert_into(transactions::table)
-                .values(&new_tx)
-                .on_conflict_do_nothing()
-                .execute(conn)?;
+            for address in called_contracts(tx, receipt) {
+                if let Some(contract) = contracts.get(&address) {
+                    store_contract(rows, address, contract, None)?;
+                }
+            }
+            if let Some((created, contract)) = receipt
+                .contract_address
+                .and_then(|created| Some((created, contracts.get(&created)?)))
+            {
+                store_contract(rows, created, contract, Some((tx.hash, new_block.number)))?;
+            }
 
-            for log in &receipt.logs {
-                process_log(conn, &format!("{:#x}", log.transaction_hash.unwrap()), log)?;
+            for log in logs {
+                write_log(rows, context, tx.hash, new_block.number, log)?;
             }
         }
         Ok(())
     })
 }
 
-/// Processes a single log entry.
-pub fn process_log(connection: &mut DbConnection, tx_hash: &str, log: &EthersLog) -> Result<()> {
-    let topic0 = log.topics.first().map(|h| format!("{:#x}", h));
-    let topic1 = log.topics.get(1).map(|h| format!("{:#x}", h));
-    let topic2 = log.topics.get(2).map(|h| format!("{:#x}", h));
-    let topic3 = log.topics.get(3).map(|h| format!("{:#x}", h));
-
-    // Insert the raw log
-    let new_log = NewLog {
-        tx_hash,
-        address: &format!("{:#x}", log.address),
-        topic0: topic0.as_deref(),
-        topic1: topic1.as_deref(),
-        topic2: topic2.as_deref(),
-        topic3: topic3.as_deref(),
-        data: &log.data.to_string(),
+/// A transaction to write, with the parts of its rows that can fail to build worked out.
+struct StagedTransaction<'a> {
+    tx: &'a EthersTransaction,
+    receipt: &'a TransactionReceipt,
+    row: Transaction,
+    logs: Vec<StagedLog<'a>>,
+}
+
+struct StagedLog<'a> {
+    log: &'a EthersLog,
+    decoded: Option<DecodedLog>,
+    decoded_json: Option<serde_json::Value>,
+    /// The value and token ID of a `Transfer`, see `transfer_amount`.
+    transfer: Option<(BigDecimal, Option<BigDecimal>)>,
+}
+
+impl<'a> StagedLog<'a> {
+    fn new(registry: &AbiRegistry, log: &'a EthersLog) -> Result<Self> {
+        let decoded = registry.decode_log(log.address, &log.topics, &log.data);
+        let transfer = match log.topics.first() {
+            Some(&topic) if topic == TRANSFER_EVENT_SIGNATURE => transfer_amount(log)?,
+            _ => None,
+        };
+        Ok(Self {
+            log,
+            decoded_json: decoded.as_ref().map(serde_json::to_value).transpose()?,
+            decoded,
+            transfer,
+        })
+    }
+}
+
+/// Builds the rows of a block's transactions and logs before anything is written, so that a
+/// store without transactions, like the memory store, can't be left with half a block.
+fn stage_block<'a>(
+    context: &IndexerContext,
+    block: &Block,
+    transactions_with_receipts: &'a [(EthersTransaction, TransactionReceipt)],
+) -> Result<Vec<StagedTransaction<'a>>> {
+    let registry = context
+        .abi_registry
+        .read()
+        .map_err(|_| anyhow::anyhow!("ABI registry lock poisoned"))?;
+    let mut staged = Vec::new();
+    for (pos, (tx, receipt)) in transactions_with_receipts.iter().enumerate() {
+        let Some(tx_logs) = stored_logs(context, tx, receipt) else {
+            continue;
+        };
+        staged.push(StagedTransaction {
+            tx,
+            receipt,
+            row: transaction_row(block, pos, tx, receipt)?,
+            logs: tx_logs
+                .into_iter()
+                .map(|log| StagedLog::new(&registry, log))
+                .collect::<Result<_>>()?,
+        });
+    }
+    Ok(staged)
+}
+
+/// Builds the stored row of a block header.
+pub(crate) fn block_row<TX>(block: &EthersBlock<TX>) -> Result<Block> {
+    Ok(Block {
+        hash: DbHash(block.hash.unwrap()),
+        parent_hash: DbHash(block.parent_hash),
+        number: block.number.unwrap().as_u64() as i64,
+        timestamp: chrono::DateTime::from_timestamp(block.timestamp.as_u64() as i64, 0).unwrap(),
+        miner: DbAddress(block.author.unwrap()),
+        gas_used: u256_to_bigdecimal(block.gas_used)?,
+        gas_limit: u256_to_bigdecimal(block.gas_limit)?,
+        base_fee_per_gas: block
+            .base_fee_per_gas
+            .map(|v| u256_to_bigdecimal(v).unwrap()),
+        extra_data: block.extra_data.to_string(),
+        tx_count: block.transactions.len() as i32,
+        transactions_root: Some(DbHash(block.transactions_root)),
+        receipts_root: Some(DbHash(block.receipts_root)),
+        logs_bloom: block.logs_bloom.map(|bloom| format!("{:#x}", bloom)),
+    })
+}
+
+/// Builds the stored row of a transaction at position `pos` of the fetched list, which is only
+/// used when the RPC omits its index.
+pub(crate) fn transaction_row(
+    block: &Block,
+    pos: usize,
+    tx: &EthersTransaction,
+    receipt: &TransactionReceipt,
+) -> Result<Transaction> {
+    Ok(Transaction {
+        hash: DbHash(tx.hash),
+        block_hash: block.hash,
+        block_number: block.number,
+        from_address: DbAddress(tx.from),
+        to_address: tx.to.map(DbAddress),
+        value: u256_to_bigdecimal(tx.value)?,
+        gas_price: receipt
+            .effective_gas_price
+            .map(|v| u256_to_bigdecimal(v).unwrap()),
+        gas_used: receipt.gas_used.map(|v| u256_to_bigdecimal(v).unwrap()),
+        nonce: tx.nonce.as_u64() as i64,
+        position: tx.transaction_index.map_or(pos, |i| i.as_usize()) as i32,
+        // A creation's input is init code rather than a call.
+        method_id: tx
+            .to
+            .and(tx.input.get(..4))
+            .map(|selector| format!("0x{}", hex::encode(selector))),
+        tx_type: tx.transaction_type.map(|t| t.as_u64() as i16),
+        l2: l2::l2_fields(tx, receipt)?,
+    })
+}
+
+/// The activity a transaction records for its sender, its recipient and the contract it created.
+///
+/// The recipient is flagged as a contract when `inspect_contracts` found its code, and as
+/// code-checked either way. A recipient found without code is looked up again when it is called
+/// with input, so an account first seen as an EOA, for instance when it received ETH before being
+/// deployed to, is flagged the next time it is called.
+pub(crate) fn transaction_activity(
+    block: &Block,
+    tx: &EthersTransaction,
+    receipt: &TransactionReceipt,
+    contracts: &HashMap<Address, DeployedContract>,
+) -> Vec<(Address, AccountActivity)> {
+    let activity = AccountActivity {
+        block_number: block.number,
+        tx_hash: tx.hash,
+        timestamp: block.timestamp,
+        sent: false,
+        received: false,
+        is_contract: false,
+        code_checked: false,
     };
-    diesel::insert_into(logs::table)
-        .values(&new_log)
-        .execute(connection)?;
+    let mut activities = vec![(
+        tx.from,
+        AccountActivity {
+            sent: true,
+            ..activity
+        },
+    )];
+    if let Some(to) = tx.to {
+        activities.push((
+            to,
+            AccountActivity {
+                received: true,
+                is_contract: contracts.contains_key(&to),
+                code_checked: true,
+                ..activity
+            },
+        ));
+    }
+    if let Some(created) = receipt.contract_address {
+        activities.push((
+            created,
+            AccountActivity {
+                is_contract: true,
+                code_checked: true,
+                ..activity
+            },
+        ));
+    }
+    activities
+}
 
-    // Check if it's a token transfer
-    if let Some(topic) = topic0 {
-        if H256::from_str(&topic)? == TRANSFER_EVENT_SIGNATURE {
-            process_token_transfer(connection, tx_hash, log)?;
-        }
+/// Selects the logs of a transaction that pass the context's filter, or `None` when neither the
+/// transaction nor any of its logs match, so it isn't stored at all.
+pub(crate) fn stored_logs<'a>(
+    context: &IndexerContext,
+    tx: &EthersTransaction,
+    receipt: &'a TransactionReceipt,
+) -> Option<Vec<&'a EthersLog>> {
+    let filter = context.filter.as_deref();
+    let tx_matched = filter.is_none_or(|f| f.matches_transaction(tx));
+    let tx_logs: Vec<&EthersLog> = receipt
+        .logs
+        .iter()
+        .filter(|log| tx_matched || filter.is_some_and(|f| f.matches_log(log)))
+        .collect();
+    (tx_matched || !tx_logs.is_empty()).then_some(tx_logs)
+}
+
+/// Processes a single log entry of a transaction in block `block_number`.
+pub fn process_log<R: Rows>(
+    rows: &mut R,
+    context: &IndexerContext,
+    tx_hash: H256,
+    block_number: i64,
+    log: &EthersLog,
+) -> Result<()> {
+    let staged = {
+        let registry = context
+            .abi_registry
+            .read()
+            .map_err(|_| anyhow::anyhow!("ABI registry lock poisoned"))?;
+        StagedLog::new(&registry, log)?
+    };
+    write_log(rows, context, tx_hash, block_number, staged)
+}
+
+/// Stores a log, applies the token transfer or proxy upgrade it represents and passes it to the
+/// event handlers.
+fn write_log<R: Rows>(
+    rows: &mut R,
+    context: &IndexerContext,
+    tx_hash: H256,
+    block_number: i64,
+    staged: StagedLog,
+) -> Result<()> {
+    let log = staged.log;
+    let topic = |i: usize| log.topics.get(i).copied().map(DbHash);
+    rows.insert_log(&NewLog {
+        tx_hash: DbHash(tx_hash),
+        address: DbAddress(log.address),
+        topic0: topic(0),
+        topic1: topic(1),
+        topic2: topic(2),
+        topic3: topic(3),
+        data: &log.data.to_string(),
+        decoded: staged.decoded_json.as_ref(),
+        block_number,
+    })?;
+
+    // Check if it's a token transfer or a proxy upgrade
+    if let Some(transfer) = staged.transfer {
+        store_token_transfer(rows, tx_hash, block_number, log, transfer)?;
+    } else if log.topics.first() == Some(&UPGRADED_EVENT_SIGNATURE) {
+        record_upgrade(rows, tx_hash, log)?;
+    }
+
+    if let Some(connection) = rows.handler_connection() {
+        context
+            .handlers
+            .dispatch(connection, log, staged.decoded.as_ref())?;
     }
 
     Ok(())
 }
 
-/// Processes a decoded Transfer event to update token balances.
-pub fn process_token_transfer(
-    connection: &mut DbConnection,
-    tx_hash: &str,
+/// Stores a Transfer event and applies it to the token balances.
+pub fn process_token_transfer<R: Rows>(
+    rows: &mut R,
+    tx_hash: H256,
+    block_number: i64,
     log: &EthersLog,
 ) -> Result<()> {
-    if log.topics.len() < 3 {
-        return Ok(());
+    match transfer_amount(log)? {
+        Some(transfer) => store_token_transfer(rows, tx_hash, block_number, log, transfer),
+        None => Ok(()),
     }
+}
 
-    let token_address = format!("{:#x}", log.address);
-    let from_address = format!("{:#x}", Address::from(log.topics[1]));
-    let to_address = format!("{:#x}", Address::from(log.topics[2]));
+/// Stores a Transfer event moving the given value and token ID, see `process_token_transfer`.
+fn store_token_transfer<R: Rows>(
+    rows: &mut R,
+    tx_hash: H256,
+    block_number: i64,
+    log: &EthersLog,
+    (value, token_id): (BigDecimal, Option<BigDecimal>),
+) -> Result<()> {
+    let token_address = DbAddress(log.address);
+    let from_address = DbAddress(Address::from(log.topics[1]));
+    let to_address = DbAddress(Address::from(log.topics[2]));
+
+    rows.insert_token_transfer(&NewTokenTransfer {
+        tx_hash: DbHash(tx_hash),
+        token_address,
+        from_address,
+        to_address,
+        value: Some(&value),
+        token_id: token_id.as_ref(),
+        block_number,
+    })?;
 
-    let (value, token_id) = if log.topics.len() == 4 {
-        let value = BigDecimal::from(1);
-        let token_id = Some(u256_to_bigdecimal(U256::from_big_endian(
-            log.topics[3].as_bytes(),
-        ))?);
-        (Some(value), token_id)
-    } else {
-        let value = Some(u256_to_bigdecimal(U256::from_big_endian(&log.data))?);
-        (value, None)
-    };
+    if !from_address.0.is_zero() {
+        rows.add_to_token_balance(from_address, token_address, token_id.as_ref(), &-&value)?;
+    }
+    credit_token_balance(rows, to_address, token_address, token_id.as_ref(), &value)
+}
 
-    let new_transfer = NewTokenTransfer {
-        tx_hash,
-        token_address: &token_address,
-        from_address: &from_address,
-        to_address: &to_address,
-        value: value.as_ref(),
-        token_id: token_id.as_ref(),
-    };
-    diesel::insert_into(token_transfers::table)
-        .values(&new_transfer)
-        .execute(connection)?;
+/// Reads the amount and, for ERC721, the token id moved by a `Transfer` log. Logs with fewer than
+/// three topics don't name both parties and are ignored.
+pub(crate) fn transfer_amount(log: &EthersLog) -> Result<Option<(BigDecimal, Option<BigDecimal>)>> {
+    match log.topics.len() {
+        0..=2 => Ok(None),
+        4 => {
+            let token_id = u256_to_bigdecimal(U256::from_big_endian(log.topics[3].as_bytes()))?;
+            Ok(Some((BigDecimal::from(1), Some(token_id))))
+        }
+        _ => {
+            let value = u256_to_bigdecimal(U256::from_big_endian(&log.data))?;
+            Ok(Some((value, None)))
+        }
+    }
+}
 
-    if from_address != format!("{:#x}", Address::zero()) {
-        diesel::update(
-            token_balances::table
-                .filter(token_balances::owner_address.eq(&from_address))
-                .filter(token_balances::token_address.eq(&token_address))
-                .filter(token_balances::token_id.is_not_distinct_from(&token_id)),
-        )
-        .set(token_balances::amount.eq(token_balances::amount - value.as_ref().unwrap()))
-        .execute(connection)?;
+/// Adds `value` to a balance, creating the row if the owner doesn't hold the token yet.
+///
+/// The row is updated first and only inserted when none matched. `ON CONFLICT` can't be used,
+/// since the unique constraint never matches the NULL `token_id` of ERC20 balances; the batch
+/// writer and the pruner upsert balances the same way.
+fn credit_token_balance<R: Rows>(
+    rows: &mut R,
+    owner_address: DbAddress,
+    token_address: DbAddress,
+    token_id: Option<&BigDecimal>,
+    value: &BigDecimal,
+) -> Result<()> {
+    if !rows.add_to_token_balance(owner_address, token_address, token_id, value)? {
+        rows.insert_token_balance(owner_address, token_address, token_id, value)?;
+    }
+    Ok(())
+}
+
+/// Reverts an indexed block, typically because it was reorged out of the canonical chain.
+///
+/// Event handlers, token balance changes and proxy upgrades are unwound in reverse order before the block and
+/// its dependent rows are deleted, then account activity is recomputed without them.
+pub fn rollback_block<R: Rows>(
+    rows: &mut R,
+    context: &IndexerContext,
+    block_hash: H256,
+) -> Result<()> {
+    let block_hash = DbHash(block_hash);
+    rows.in_transaction(|rows| {
+        let Some(block_number) = rows.block_number(block_hash)? else {
+            bail!("Block {} is not indexed", block_hash);
+        };
+
+        if let Some(conn) = rows
+            .handler_connection()
+            .filter(|_| !context.handlers.is_empty())
+        {
+            let stored_logs = logs::table
+                .inner_join(transactions::table)
+                .filter(transactions::block_hash.eq(block_hash))
+                .order(logs::id.desc())
+                .select(models::Log::as_select())
+                .load(conn)?;
+            for stored in stored_logs {
+                let log = stored_log_to_ethers(&stored, block_hash, block_number)?;
+                let decoded = stored.decoded.and_then(|d| serde_json::from_value(d).ok());
+                context.handlers.rollback(conn, &log, decoded.as_ref())?;
+            }
+        }
+
+        for transfer in rows.block_token_transfers(block_hash)? {
+            revert_token_transfer(rows, &transfer)?;
+        }
+        for upgrade in rows.block_proxy_upgrades(block_hash)? {
+            revert_upgrade(rows, &upgrade)?;
+        }
+
+        let participants = rows.block_participants(block_hash)?;
+        let created = rows.block_created_contracts(block_hash)?;
+        rows.delete_block(block_hash, block_number)?;
+        revert_account_activity(rows, block_number, &participants, &created)?;
+
+        println!("↩️ Rolled back block {}", block_number);
+        Ok(())
+    })
+}
+
+/// Undoes the balance changes applied by `process_token_transfer`.
+fn revert_token_transfer<R: Rows>(rows: &mut R, transfer: &models::TokenTransfer) -> Result<()> {
+    let Some(value) = &transfer.value else {
+        return Ok(());
+    };
+    let token_id = transfer.token_id.as_ref();
+
+    rows.add_to_token_balance(
+        transfer.to_address,
+        transfer.token_address,
+        token_id,
+        &-value,
+    )?;
+    if !transfer.from_address.0.is_zero() {
+        credit_token_balance(
+            rows,
+            transfer.from_address,
+            transfer.token_address,
+            token_id,
+            value,
+        )?;
     }
 
-    diesel::insert_into(token_balances::table)
-        .values((
-            token_balances::owner_address.eq(&to_address),
-            token_balances::token_address.eq(&token_address),
-            token_balances::amount.eq(value.as_ref().unwrap()),
-            token_balances::token_id.eq(&token_id),
-        ))
-        .on_conflict((
-            token_balances::owner_address,
-            token_balances::token_address,
-            token_balances::token_id,
-        ))
+    Ok(())
+}
+
+/// Rebuilds the RPC representation of a stored log, as passed to event handlers.
+fn stored_log_to_ethers(
+    stored: &models::Log,
+    block_hash: DbHash,
+    block_number: i64,
+) -> Result<EthersLog> {
+    let topics = [stored.topic0, stored.topic1, stored.topic2, stored.topic3]
+        .into_iter()
+        .map_while(|topic| topic.map(H256::from))
+        .collect();
+
+    Ok(EthersLog {
+        address: stored.address.0,
+        topics,
+        data: Bytes::from_str(&stored.data)?,
+        block_hash: Some(block_hash.0),
+        block_number: Some(U64::from(block_number as u64)),
+        transaction_hash: Some(stored.tx_hash.0),
+        ..Default::default()
+    })
+}
+
+/// Gets the hash of the indexed block at a given height.
+pub fn get_block_hash(connection: &mut DbConnection, number: i64) -> Result<Option<H256>> {
+    blocks::table
+        .filter(blocks::number.eq(number))
+        .select(blocks::hash)
+        .first::<DbHash>(connection)
+        .optional()
+        .map(|hash| hash.map(H256::from))
+        .map_err(Into::into)
+}
+
+/// Reads a value persisted by the indexer across restarts.
+pub fn get_indexer_state(connection: &mut DbConnection, key: &str) -> Result<Option<i64>> {
+    indexer_state::table
+        .filter(indexer_state::key.eq(key))
+        .select(indexer_state::value)
+        .first::<i64>(connection)
+        .optional()
+        .map_err(Into::into)
+}
+
+/// Persists a value for the indexer to pick up after a restart.
+pub fn set_indexer_state(connection: &mut DbConnection, key: &str, value: i64) -> Result<()> {
+    diesel::insert_into(indexer_state::table)
+        .values((indexer_state::key.eq(key), indexer_state::value.eq(value)))
+        .on_conflict(indexer_state::key)
         .do_update()
-        .set(token_balances::amount.eq(token_balances::amount + value.as_ref().unwrap()))
+        .set(indexer_state::value.eq(value))
         .execute(connection)?;
-
     Ok(())
 }
 
@@ -282,16 +853,104 @@ pub fn get_latest_indexed_block(connection: &mut DbConnection) -> Result<Option<
         .map_err(Into::into)
 }
 
-/// Ensures an account exists in the database, inserting it if it doesn't.
-pub fn ensure_account_exists(connection: &mut DbConnection, addr: &str) -> Result<()> {
-    let new_account = Account {
-        address: addr.to_string(),
-        created_at: chrono::Utc::now(),
-    };
-    diesel::insert_into(accounts::table)
-        .values(&new_account)
-        .on_conflict_do_nothing()
-        .execute(connection)?;
+/// How an account took part in an indexed transaction.
+#[derive(Clone, Copy)]
+pub struct AccountActivity {
+    pub block_number: i64,
+    pub tx_hash: H256,
+    pub timestamp: DateTime<Utc>,
+    pub sent: bool,
+    pub received: bool,
+    /// Set when the account is known to hold code, e.g. because the transaction created it.
+    pub is_contract: bool,
+    /// Set when `inspect_contracts` looked up the account's code for the transaction.
+    pub code_checked: bool,
+}
+
+/// Records an account's activity, creating the account if it hasn't been seen yet.
+///
+/// The first-seen position only moves backwards, so indexing blocks out of order (e.g. a
+/// backfill below an existing range) still yields the account's earliest on-chain appearance.
+pub fn record_account_activity<R: Rows>(
+    rows: &mut R,
+    address: Address,
+    activity: &AccountActivity,
+) -> Result<()> {
+    let address = DbAddress(address);
+    let mut account = rows.account(address)?.unwrap_or(models::Account {
+        address,
+        first_seen_block: None,
+        first_seen_tx: None,
+        first_seen_at: None,
+        last_active_block: None,
+        tx_sent_count: 0,
+        tx_received_count: 0,
+        is_contract: false,
+        code_checked: false,
+    });
+    if account
+        .first_seen_block
+        .is_none_or(|first| activity.block_number < first)
+    {
+        account.first_seen_block = Some(activity.block_number);
+        account.first_seen_tx = Some(DbHash(activity.tx_hash));
+        account.first_seen_at = Some(activity.timestamp);
+    }
+    account.last_active_block = account.last_active_block.max(Some(activity.block_number));
+    account.tx_sent_count += i64::from(activity.sent);
+    account.tx_received_count += i64::from(activity.received);
+    account.is_contract |= activity.is_contract;
+    account.code_checked |= activity.code_checked;
+    rows.save_account(&account)
+}
+
+/// Undoes the account activity recorded for a block's transactions, which must already be
+/// deleted, and for the contracts they created.
+///
+/// Counts are decremented, and first-seen or last-active positions that pointed into the
+/// rolled-back block are recomputed from the transactions that remain. Accounts first seen in the
+/// block that no remaining transaction involves are deleted.
+fn revert_account_activity<R: Rows>(
+    rows: &mut R,
+    block_number: i64,
+    rolled_back: &[(DbAddress, Option<DbAddress>)],
+    created: &[DbAddress],
+) -> Result<()> {
+    let mut counts: BTreeMap<DbAddress, (i64, i64)> =
+        created.iter().map(|address| (*address, (0, 0))).collect();
+    for (from, to) in rolled_back {
+        counts.entry(*from).or_default().0 += 1;
+        if let Some(to) = to {
+            counts.entry(*to).or_default().1 += 1;
+        }
+    }
+
+    for (address, (sent, received)) in counts {
+        let Some(mut account) = rows.account(address)? else {
+            continue;
+        };
+        account.tx_sent_count -= sent;
+        account.tx_received_count -= received;
+
+        let last_reverted = account.last_active_block >= Some(block_number);
+        let first_reverted = account.first_seen_block >= Some(block_number);
+        if last_reverted || first_reverted {
+            let span = rows.account_span(address)?;
+            if first_reverted && span.is_none() {
+                rows.delete_account(address)?;
+                continue;
+            }
+            if last_reverted {
+                account.last_active_block = span.map(|s| s.last_block);
+            }
+            if first_reverted {
+                account.first_seen_block = span.map(|s| s.first_block);
+                account.first_seen_tx = span.map(|s| s.first_tx);
+                account.first_seen_at = span.map(|s| s.first_at);
+            }
+        }
+        rows.save_account(&account)?;
+    }
     Ok(())
 }
 
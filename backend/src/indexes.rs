@@ -0,0 +1,148 @@
+use crate::{db::DbPool, partitions};
+use anyhow::Result;
+use diesel::{
+    prelude::*,
+    sql_types::{Array, Bool, Text},
+};
+
+/// Session setting that makes the `add_query_indexes` migration skip its indexes.
+pub const DEFER_INDEXES_SETTING: &str = "cw_indexer.defer_indexes";
+
+/// An index the API's queries rely on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QueryIndex {
+    pub name: &'static str,
+    /// Table and columns, as written after `ON` in `CREATE INDEX`.
+    pub target: &'static str,
+}
+
+/// The indexes created by the `add_query_indexes` and `add_retention` migrations, or later by
+/// `create_query_indexes` when they were deferred.
+pub const QUERY_INDEXES: &[QueryIndex] = &[
+    QueryIndex {
+        name: "blocks_number_idx",
+        target: "blocks (number)",
+    },
+    QueryIndex {
+        name: "transactions_block_hash_idx",
+        target: "transactions (block_hash)",
+    },
+    QueryIndex {
+        name: "transactions_from_address_idx",
+        target: "transactions (from_address)",
+    },
+    QueryIndex {
+        name: "transactions_to_address_idx",
+        target: "transactions (to_address)",
+    },
+    QueryIndex {
+        name: "logs_tx_hash_idx",
+        target: "logs (tx_hash)",
+    },
+    QueryIndex {
+        name: "logs_address_topic0_idx",
+        target: "logs (address, topic0)",
+    },
+    QueryIndex {
+        name: "logs_topic0_idx",
+        target: "logs (topic0)",
+    },
+    QueryIndex {
+        name: "token_transfers_tx_hash_idx",
+        target: "token_transfers (tx_hash)",
+    },
+    QueryIndex {
+        name: "transactions_block_number_idx",
+        target: "transactions (block_number)",
+    },
+    QueryIndex {
+        name: "logs_block_number_idx",
+        target: "logs (block_number)",
+    },
+    QueryIndex {
+        name: "token_transfers_block_number_idx",
+        target: "token_transfers (block_number)",
+    },
+];
+
+#[derive(QueryableByName)]
+struct ExistingIndex {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Bool)]
+    valid: bool,
+}
+
+/// Makes migrations run on this connection skip the query indexes, so an initial backfill isn't
+/// slowed down by maintaining them.
+pub fn defer_query_indexes(connection: &mut PgConnection) -> Result<()> {
+    diesel::sql_query(format!("SET {} = 'on'", DEFER_INDEXES_SETTING)).execute(connection)?;
+    Ok(())
+}
+
+/// Lists the query indexes that don't exist, or whose concurrent build failed and left them
+/// invalid.
+pub fn missing_query_indexes(connection: &mut PgConnection) -> Result<Vec<QueryIndex>> {
+    let names: Vec<&str> = QUERY_INDEXES.iter().map(|index| index.name).collect();
+    let existing = diesel::sql_query(
+        "SELECT c.relname::TEXT AS name, i.indisvalid AS valid FROM pg_class c \
+         JOIN pg_index i ON i.indexrelid = c.oid \
+         WHERE c.relname = ANY($1) AND pg_table_is_visible(c.oid)",
+    )
+    .bind::<Array<Text>, _>(names)
+    .load::<ExistingIndex>(connection)?;
+
+    Ok(QUERY_INDEXES
+        .iter()
+        .filter(|index| {
+            !existing
+                .iter()
+                .any(|found| found.name == index.name && found.valid)
+        })
+        .copied()
+        .collect())
+}
+
+/// Creates the missing query indexes, returning how many were built. `CONCURRENTLY` builds don't
+/// block writes, so the indexer can keep running, but take longer and can't run in a transaction.
+pub fn create_query_indexes(connection: &mut PgConnection, concurrently: bool) -> Result<usize> {
+    let missing = missing_query_indexes(connection)?;
+    for index in &missing {
+        // Indexes on partitioned tables can't be built concurrently.
+        let table = index.target.split_whitespace().next().unwrap_or_default();
+        let concurrently = if concurrently && !partitions::is_partitioned(connection, table)? {
+            " CONCURRENTLY"
+        } else {
+            ""
+        };
+        println!("🗂️ Creating index {}", index.name);
+        // A failed concurrent build leaves an invalid index behind, which has to be dropped first.
+        diesel::sql_query(format!(
+            "DROP INDEX{} IF EXISTS {}",
+            concurrently, index.name
+        ))
+        .execute(connection)?;
+        diesel::sql_query(format!(
+            "CREATE INDEX{} {} ON {}",
+            concurrently, index.name, index.target
+        ))
+        .execute(connection)?;
+    }
+    Ok(missing.len())
+}
+
+/// Builds the deferred query indexes concurrently on a blocking thread, once the indexer has
+/// caught up with the chain.
+pub fn build_deferred_indexes(pool: DbPool) {
+    println!("🗂️ Caught up with the chain, building deferred indexes");
+    tokio::task::spawn_blocking(move || {
+        let result = pool
+            .get()
+            .map_err(anyhow::Error::from)
+            .and_then(|mut conn| create_query_indexes(&mut conn, true));
+        match result {
+            Ok(created) => println!("✅ Built {} deferred indexes", created),
+            Err(e) => eprintln!("Failed to build deferred indexes: {}", e),
+        }
+    });
+}
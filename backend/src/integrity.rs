@@ -0,0 +1,440 @@
+use crate::{
+    chains::ChainConfig,
+    db::DbPool,
+    indexer::{fetch_receipts, u256_to_bigdecimal, DbConnection, EthProvider},
+    models,
+    schema::{blocks, logs, transactions},
+};
+use anyhow::{bail, ensure, Result};
+use diesel::prelude::*;
+use ethers::{
+    abi::ethereum_types::BloomInput,
+    prelude::*,
+    types::{Block as EthersBlock, Log as EthersLog, Transaction as EthersTransaction},
+    utils::{keccak256, rlp::RlpStream},
+};
+use std::{ops::RangeInclusive, str::FromStr};
+
+/// The highest transaction type whose receipts are encoded like EIP-1559 ones.
+const MAX_RECEIPT_TYPE: u64 = 4;
+
+/// The header fields committing to a block's transactions and receipts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderRoots {
+    pub transactions_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Option<Bloom>,
+}
+
+impl HeaderRoots {
+    pub fn of<TX>(block: &EthersBlock<TX>) -> Self {
+        Self {
+            transactions_root: block.transactions_root,
+            receipts_root: block.receipts_root,
+            logs_bloom: block.logs_bloom,
+        }
+    }
+
+    /// Reads the roots stored with a block, if it was indexed with them.
+    pub fn stored(block: &models::Block) -> Result<Option<Self>> {
+        let (Some(transactions_root), Some(receipts_root)) =
+            (block.transactions_root, block.receipts_root)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            transactions_root: transactions_root.0,
+            receipts_root: receipts_root.0,
+            logs_bloom: block
+                .logs_bloom
+                .as_deref()
+                .map(Bloom::from_str)
+                .transpose()?,
+        }))
+    }
+}
+
+/// Checks a block's full list of transactions and receipts, in block order, against the roots
+/// of its header.
+///
+/// Transactions of types that can't be re-encoded here (blob and later types) skip the
+/// transactions root check; their receipts are still checked.
+pub fn verify_block_data(
+    roots: &HeaderRoots,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+) -> Result<()> {
+    let mut encoded_transactions = Vec::new();
+    let mut encoded_receipts = Some(Vec::new());
+    let mut block_bloom = Bloom::zero();
+    for (tx, receipt) in transactions_with_receipts {
+        ensure!(
+            receipt.transaction_hash == tx.hash,
+            "receipt {:#x} returned for transaction {:#x}",
+            receipt.transaction_hash,
+            tx.hash
+        );
+
+        match tx.transaction_type.map_or(0, |t| t.as_u64()) {
+            0..=2 => {
+                let encoded = tx.rlp();
+                ensure!(
+                    H256(keccak256(&encoded)) == tx.hash,
+                    "transaction {:#x} doesn't match its hash",
+                    tx.hash
+                );
+                encoded_transactions.push(encoded.to_vec());
+            }
+            tx_type => {
+                println!(
+                    "⚠️ Skipping transactions root check: unsupported type {} in {:#x}",
+                    tx_type, tx.hash
+                );
+                encoded_transactions.clear();
+                break;
+            }
+        }
+    }
+    if !encoded_transactions.is_empty() || transactions_with_receipts.is_empty() {
+        let root = ordered_trie_root(encoded_transactions);
+        ensure!(
+            root == roots.transactions_root,
+            "transactions root is {:#x}, header has {:#x}",
+            root,
+            roots.transactions_root
+        );
+    }
+
+    for (tx, receipt) in transactions_with_receipts {
+        let bloom = logs_bloom(&receipt.logs);
+        ensure!(
+            bloom == receipt.logs_bloom,
+            "logs bloom of receipt {:#x} doesn't match its logs",
+            tx.hash
+        );
+        block_bloom.accrue_bloom(&bloom);
+
+        match (encode_receipt(receipt)?, encoded_receipts.as_mut()) {
+            (Some(encoded), Some(encoded_receipts)) => encoded_receipts.push(encoded),
+            (None, Some(_)) => {
+                println!(
+                    "⚠️ Skipping receipts root check: unsupported receipt type in {:#x}",
+                    tx.hash
+                );
+                encoded_receipts = None;
+            }
+            (_, None) => {}
+        }
+    }
+    if let Some(encoded_receipts) = encoded_receipts {
+        let root = ordered_trie_root(encoded_receipts);
+        ensure!(
+            root == roots.receipts_root,
+            "receipts root is {:#x}, header has {:#x}",
+            root,
+            roots.receipts_root
+        );
+    }
+
+    if let Some(header_bloom) = &roots.logs_bloom {
+        ensure!(
+            &block_bloom == header_bloom,
+            "logs bloom doesn't match the header"
+        );
+    }
+    Ok(())
+}
+
+/// Computes the bloom filter of a set of logs, over their addresses and topics.
+pub fn logs_bloom<'a>(logs: impl IntoIterator<Item = &'a EthersLog>) -> Bloom {
+    let mut bloom = Bloom::zero();
+    for log in logs {
+        bloom.accrue(BloomInput::Raw(log.address.as_bytes()));
+        for topic in &log.topics {
+            bloom.accrue(BloomInput::Raw(topic.as_bytes()));
+        }
+    }
+    bloom
+}
+
+/// Encodes a receipt as stored in the receipts trie, or `None` for unknown receipt types.
+pub fn encode_receipt(receipt: &TransactionReceipt) -> Result<Option<Vec<u8>>> {
+    let receipt_type = receipt.transaction_type.map_or(0, |t| t.as_u64());
+    if receipt_type > MAX_RECEIPT_TYPE {
+        return Ok(None);
+    }
+
+    let mut stream = RlpStream::new_list(4);
+    match (receipt.status, receipt.root) {
+        (Some(status), _) => stream.append(&status.as_u64()),
+        // Receipts before Byzantium commit to the post-transaction state root instead.
+        (None, Some(root)) => stream.append(&root),
+        (None, None) => bail!(
+            "receipt {:#x} has neither a status nor a state root",
+            receipt.transaction_hash
+        ),
+    };
+    stream.append(&receipt.cumulative_gas_used);
+    stream.append(&receipt.logs_bloom);
+    stream.append_list(&receipt.logs);
+
+    let mut encoded = Vec::new();
+    if receipt_type != 0 {
+        encoded.push(receipt_type as u8);
+    }
+    encoded.extend_from_slice(&stream.out());
+    Ok(Some(encoded))
+}
+
+/// Computes the root of a trie keyed by the RLP-encoded position of each value, as used for the
+/// transactions and receipts of a block.
+pub fn ordered_trie_root(values: impl IntoIterator<Item = Vec<u8>>) -> H256 {
+    trie_root(
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(index, value)| (ethers::utils::rlp::encode(&(index as u64)).to_vec(), value)),
+    )
+}
+
+/// Computes the root hash of a Merkle Patricia trie holding the given entries.
+pub fn trie_root(entries: impl IntoIterator<Item = (Vec<u8>, Vec<u8>)>) -> H256 {
+    let mut items: Vec<(Vec<u8>, Vec<u8>)> = entries
+        .into_iter()
+        .map(|(key, value)| (key.iter().flat_map(|b| [b >> 4, b & 0x0f]).collect(), value))
+        .collect();
+    items.sort();
+    if items.is_empty() {
+        // Hash of the empty RLP string.
+        return H256(keccak256([0x80]));
+    }
+    H256(keccak256(encode_node(&items, 0)))
+}
+
+/// RLP-encodes the node holding `items`, whose nibble keys share their first `depth` nibbles.
+fn encode_node(items: &[(Vec<u8>, Vec<u8>)], depth: usize) -> Vec<u8> {
+    let mut stream = RlpStream::new();
+
+    if let [(key, value)] = items {
+        stream.begin_list(2);
+        stream.append(&hex_prefix(&key[depth..], true));
+        stream.append(&value.as_slice());
+        return stream.out().to_vec();
+    }
+
+    let first = &items[0].0;
+    let shared = (depth..)
+        .take_while(|&i| {
+            items
+                .iter()
+                .all(|(key, _)| key.len() > i && key[i] == first[i])
+        })
+        .count();
+    if shared > 0 {
+        stream.begin_list(2);
+        stream.append(&hex_prefix(&first[depth..depth + shared], false));
+        append_child(&mut stream, &encode_node(items, depth + shared));
+        return stream.out().to_vec();
+    }
+
+    // Keys are sorted, so one ending at this node comes first and each branch is contiguous.
+    let (value, mut rest) = match items.split_first() {
+        Some(((key, value), rest)) if key.len() == depth => (Some(value), rest),
+        _ => (None, items),
+    };
+    stream.begin_list(17);
+    for nibble in 0..16 {
+        let len = rest
+            .iter()
+            .take_while(|(key, _)| key[depth] == nibble)
+            .count();
+        let (branch, remaining) = rest.split_at(len);
+        if branch.is_empty() {
+            stream.append_empty_data();
+        } else {
+            append_child(&mut stream, &encode_node(branch, depth + 1));
+        }
+        rest = remaining;
+    }
+    match value {
+        Some(value) => stream.append(&value.as_slice()),
+        None => stream.append_empty_data(),
+    };
+    stream.out().to_vec()
+}
+
+/// Embeds a child node, inline when its encoding is shorter than a hash.
+fn append_child(stream: &mut RlpStream, encoded: &[u8]) {
+    if encoded.len() < 32 {
+        stream.append_raw(encoded, 1);
+    } else {
+        stream.append(&keccak256(encoded).as_slice());
+    }
+}
+
+/// Hex-prefix encoding of a nibble path, flagging leaves and odd lengths.
+fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 0x20 } else { 0x00 };
+    let (mut encoded, rest) = if nibbles.len() % 2 == 1 {
+        (vec![flag | 0x10 | nibbles[0]], &nibbles[1..])
+    } else {
+        (vec![flag], nibbles)
+    };
+    encoded.extend(rest.chunks(2).map(|pair| (pair[0] << 4) | pair[1]));
+    encoded
+}
+
+/// The outcome of re-checking a range of stored blocks.
+#[derive(Debug, Default)]
+pub struct IntegrityReport {
+    pub verified: usize,
+    /// Blocks stored without header roots, which can't be checked.
+    pub skipped: usize,
+    pub failures: Vec<(i64, String)>,
+}
+
+/// Re-checks the stored blocks of a range: the provider's transactions and receipts must match
+/// the header roots stored when each block was indexed, and the stored transactions and logs must
+/// match them.
+pub async fn run_integrity_check(
+    pool: DbPool,
+    chain: &ChainConfig,
+    range: RangeInclusive<u64>,
+) -> Result<IntegrityReport> {
+    let provider = chain.connect().await?;
+    println!("✅ Verifier connected to Ethereum RPC");
+
+    let mut conn = pool.get()?;
+    let stored_blocks = blocks::table
+        .filter(blocks::number.between(*range.start() as i64, *range.end() as i64))
+        .order(blocks::number)
+        .select(models::Block::as_select())
+        .load(&mut conn)?;
+    drop(conn);
+
+    let mut report = IntegrityReport::default();
+    for stored in stored_blocks {
+        let Some(roots) = HeaderRoots::stored(&stored)? else {
+            println!("⏭️ Block {} has no stored roots, skipping", stored.number);
+            report.skipped += 1;
+            continue;
+        };
+        match check_stored_block(&provider, &pool, &stored, &roots).await {
+            Ok(()) => report.verified += 1,
+            Err(e) => {
+                eprintln!("❌ Block {} failed verification: {}", stored.number, e);
+                report.failures.push((stored.number, e.to_string()));
+            }
+        }
+    }
+
+    println!(
+        "✅ Verified {} blocks: {} failed, {} skipped",
+        report.verified,
+        report.failures.len(),
+        report.skipped
+    );
+    Ok(report)
+}
+
+async fn check_stored_block(
+    provider: &EthProvider,
+    pool: &DbPool,
+    stored: &models::Block,
+    roots: &HeaderRoots,
+) -> Result<()> {
+    let Some(block) = provider.get_block_with_txs(stored.number as u64).await? else {
+        bail!("block not found");
+    };
+    let canonical_hash = block.hash.unwrap_or_default();
+    ensure!(
+        canonical_hash == stored.hash.0,
+        "canonical block is {:#x}, stored block is {}",
+        canonical_hash,
+        stored.hash
+    );
+
+    let transactions_with_receipts = fetch_receipts(provider, block.transactions).await?;
+    verify_block_data(roots, &transactions_with_receipts)?;
+
+    let mut conn = pool.get()?;
+    check_stored_rows(&mut conn, stored, &transactions_with_receipts)
+}
+
+/// Compares the stored transactions and logs of a block with verified chain data.
+pub fn check_stored_rows(
+    connection: &mut DbConnection,
+    stored: &models::Block,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+) -> Result<()> {
+    ensure!(
+        stored.tx_count as usize == transactions_with_receipts.len(),
+        "stored transaction count is {}, block has {}",
+        stored.tx_count,
+        transactions_with_receipts.len()
+    );
+
+    let stored_transactions = transactions::table
+        .filter(transactions::block_hash.eq(stored.hash))
+        .select(models::Transaction::as_select())
+        .load(connection)?;
+    for stored_tx in stored_transactions {
+        let Some((tx, receipt)) = transactions_with_receipts
+            .iter()
+            .find(|(tx, _)| tx.hash == stored_tx.hash.0)
+        else {
+            bail!("stored transaction {} is not in the block", stored_tx.hash);
+        };
+        ensure!(
+            stored_tx.from_address.0 == tx.from
+                && stored_tx.to_address.map(Address::from) == tx.to
+                && stored_tx.value == u256_to_bigdecimal(tx.value)?
+                && stored_tx.nonce == tx.nonce.as_u64() as i64
+                && tx
+                    .transaction_index
+                    .is_none_or(|index| stored_tx.position == index.as_u64() as i32),
+            "stored transaction {} differs from the chain",
+            stored_tx.hash
+        );
+
+        let stored_logs = logs::table
+            .filter(logs::tx_hash.eq(stored_tx.hash))
+            .select(models::Log::as_select())
+            .load(connection)?;
+        for stored_log in stored_logs {
+            let topics = [
+                stored_log.topic0,
+                stored_log.topic1,
+                stored_log.topic2,
+                stored_log.topic3,
+            ];
+            let found = receipt.logs.iter().any(|log| {
+                log.address == stored_log.address.0
+                    && log.data.to_string() == stored_log.data
+                    && (0..4).all(|i| log.topics.get(i).copied() == topics[i].map(H256::from))
+            });
+            ensure!(
+                found,
+                "stored log {} of transaction {} is not in its receipt",
+                stored_log.id,
+                stored_tx.hash
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Parses an inclusive block range written `START..END`.
+pub fn parse_block_range(range: &str) -> Result<RangeInclusive<u64>, String> {
+    let (start, end) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected START..END, got `{}`", range))?;
+    let start: u64 = start.parse().map_err(|e| format!("invalid start: {}", e))?;
+    let end: u64 = end
+        .trim_start_matches('=')
+        .parse()
+        .map_err(|e| format!("invalid end: {}", e))?;
+    if start > end {
+        return Err(format!("range start {} is after its end {}", start, end));
+    }
+    Ok(start..=end)
+}
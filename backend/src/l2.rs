@@ -0,0 +1,43 @@
+use crate::{
+    indexer::u256_to_bigdecimal,
+    models::{DbHash, L2Fields},
+};
+use anyhow::{Context, Result};
+use bigdecimal::BigDecimal;
+use ethers::types::{
+    OtherFields, Transaction as EthersTransaction, TransactionReceipt, H256, U256, U64,
+};
+use serde::de::DeserializeOwned;
+
+/// The EIP-2718 type of OP-stack deposit transactions, which the rollup derives from L1 events.
+pub const DEPOSIT_TX_TYPE: i16 = 0x7e;
+
+/// Reads a field of the RPC's JSON that ethers doesn't model, absent or `null` on other chains.
+fn other_field<T: DeserializeOwned>(fields: &OtherFields, key: &str) -> Result<Option<T>> {
+    Ok(fields
+        .get_deserialized::<Option<T>>(key)
+        .transpose()
+        .with_context(|| format!("parsing `{}`", key))?
+        .flatten())
+}
+
+fn other_quantity(fields: &OtherFields, key: &str) -> Result<Option<BigDecimal>> {
+    other_field::<U256>(fields, key)?
+        .map(u256_to_bigdecimal)
+        .transpose()
+}
+
+/// Parses the fields that OP-stack and Arbitrum RPCs add to transactions and receipts: the L1
+/// fee data, the L1 block of Arbitrum transactions and the origin of OP-stack deposits.
+pub fn l2_fields(tx: &EthersTransaction, receipt: &TransactionReceipt) -> Result<L2Fields> {
+    Ok(L2Fields {
+        l1_fee: other_quantity(&receipt.other, "l1Fee")?,
+        l1_gas_used: other_quantity(&receipt.other, "l1GasUsed")?,
+        l1_gas_price: other_quantity(&receipt.other, "l1GasPrice")?,
+        gas_used_for_l1: other_quantity(&receipt.other, "gasUsedForL1")?,
+        l1_block_number: other_field::<U64>(&receipt.other, "l1BlockNumber")?
+            .map(|number| number.as_u64() as i64),
+        deposit_source_hash: other_field::<H256>(&tx.other, "sourceHash")?.map(DbHash),
+        deposit_mint: other_quantity(&tx.other, "mint")?,
+    })
+}
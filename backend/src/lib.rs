@@ -0,0 +1,35 @@
+#![allow(clippy::uninlined_format_args)]
+#![allow(clippy::cast_possible_wrap)]
+#![allow(clippy::cast_possible_truncation)]
+#![allow(clippy::cast_sign_loss)]
+
+use diesel_migrations::{embed_migrations, EmbeddedMigrations};
+
+pub mod abi;
+pub mod api;
+pub mod balance_verifier;
+pub mod bulk;
+pub mod chains;
+pub mod contracts;
+pub mod db;
+pub mod filter;
+pub mod handlers;
+pub mod indexer;
+pub mod indexes;
+pub mod integrity;
+pub mod l2;
+pub mod log_indexer;
+pub mod models;
+pub mod partitions;
+pub mod retention;
+pub mod schema;
+pub mod signatures;
+pub mod store;
+
+#[cfg(test)]
+mod tests;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+
+/// The schema of SQLite databases, see `store::SqliteStore`.
+pub const SQLITE_MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations_sqlite");
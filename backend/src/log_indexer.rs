@@ -0,0 +1,214 @@
+use crate::{
+    chains::ChainConfig,
+    contracts::{inspect_contracts, DeployedContract},
+    db::DbPool,
+    indexer::{
+        ensure_partitions, fetch_receipts, get_indexer_state, process_block_data,
+        set_indexer_state, DbConnection, EthProvider, IndexerContext,
+    },
+    indexes,
+    store::{PgStore, SharedStore},
+};
+use anyhow::{bail, Result};
+use diesel::Connection;
+use ethers::{
+    prelude::*,
+    types::{Block as EthersBlock, Log as EthersLog, Transaction as EthersTransaction},
+};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+use tokio::time::{sleep, Duration};
+
+/// `indexer_state` key holding the last block scanned in log mode.
+pub const LOG_CURSOR_KEY: &str = "log_mode_cursor";
+
+/// Blocks kept between the log-mode cursor and the chain head. Log mode only stores the headers
+/// of blocks with matching logs, so it can't detect reorgs and waits for blocks to settle.
+pub const LOG_MODE_CONFIRMATIONS: u64 = 12;
+
+const INITIAL_RANGE: u64 = 1_000;
+const MAX_RANGE: u64 = 10_000;
+
+/// Indexes the contracts selected by the context's filter by pulling their logs with
+/// `eth_getLogs`, instead of fetching every block and receipt.
+///
+/// Ranges grow while requests succeed and shrink when the provider rejects them for returning
+/// too many results. Only the headers, transactions and receipts referenced by matched logs are
+/// fetched, and they go through the same processing as in block mode.
+pub async fn run_log_indexer(
+    pool: DbPool,
+    context: IndexerContext,
+    chain: ChainConfig,
+) -> Result<()> {
+    let Some(filter) = context.filter.clone().filter(|f| f.has_log_criteria()) else {
+        bail!("Log mode requires a filter with addresses or topics");
+    };
+
+    let provider = Arc::new(chain.connect().await?);
+    println!(
+        "✅ Log indexer connected to the RPC of chain {}",
+        chain.name
+    );
+
+    let cursor = {
+        let pool = pool.clone();
+        tokio::task::spawn_blocking(move || get_indexer_state(&mut pool.get()?, LOG_CURSOR_KEY))
+            .await??
+    };
+    let mut next_block = cursor.map_or(chain.start_block.unwrap_or(0), |cursor| cursor as u64 + 1);
+
+    println!(
+        "🚀 Starting log indexer of chain {} from block {}",
+        chain.name, next_block
+    );
+
+    let mut range = INITIAL_RANGE;
+    let mut indexes_deferred = context.deferred_indexes;
+    loop {
+        let head = provider
+            .get_block_number()
+            .await?
+            .as_u64()
+            .saturating_sub(LOG_MODE_CONFIRMATIONS);
+        if next_block > head {
+            if std::mem::take(&mut indexes_deferred) {
+                indexes::build_deferred_indexes(pool.clone());
+            }
+            sleep(Duration::from_secs(5)).await;
+            continue;
+        }
+
+        let to_block = head.min(next_block + range - 1);
+        let log_filter = filter.log_filter(next_block, to_block).unwrap();
+
+        match provider.get_logs(&log_filter).await {
+            Ok(logs) => {
+                println!(
+                    "Found {} logs in blocks {}..={}",
+                    logs.len(),
+                    next_block,
+                    to_block
+                );
+                if let Err(e) = index_log_range(&pool, &provider, &context, to_block, logs).await {
+                    eprintln!("Error indexing blocks {}..={}: {}", next_block, to_block, e);
+                    sleep(Duration::from_secs(10)).await;
+                    continue;
+                }
+                next_block = to_block + 1;
+                range = (range * 2).min(MAX_RANGE);
+            }
+            Err(e) if range > 1 && is_range_too_large(&e.to_string()) => {
+                range /= 2;
+                println!(
+                    "Provider limit hit, shrinking log range to {} blocks",
+                    range
+                );
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error fetching logs for blocks {}..={}: {}",
+                    next_block, to_block, e
+                );
+                sleep(Duration::from_secs(10)).await;
+            }
+        }
+    }
+}
+
+/// Fetches what the logs of a range reference and stores it block by block, then moves the
+/// cursor to the end of the range.
+async fn index_log_range(
+    pool: &DbPool,
+    provider: &EthProvider,
+    context: &IndexerContext,
+    to_block: u64,
+    logs: Vec<EthersLog>,
+) -> Result<()> {
+    let store: SharedStore = Arc::new(PgStore::new(pool.clone()));
+    let mut by_block: BTreeMap<u64, Vec<EthersLog>> = BTreeMap::new();
+    for log in logs.into_iter().filter(|log| log.removed != Some(true)) {
+        let Some(number) = log.block_number else {
+            continue;
+        };
+        by_block.entry(number.as_u64()).or_default().push(log);
+    }
+
+    for (number, block_logs) in by_block {
+        let Some(header) = provider.get_block(number).await? else {
+            bail!("Block {} not found", number);
+        };
+
+        let tx_hashes: HashSet<H256> = block_logs
+            .iter()
+            .filter_map(|log| log.transaction_hash)
+            .collect();
+        let tx_futures = tx_hashes.iter().map(|hash| provider.get_transaction(*hash));
+        let mut transactions = Vec::new();
+        for (hash, result) in tx_hashes
+            .iter()
+            .zip(futures::future::join_all(tx_futures).await)
+        {
+            match result? {
+                Some(tx) => transactions.push(tx),
+                None => bail!("Transaction {:#x} not found", hash),
+            }
+        }
+        transactions.sort_by_key(|tx| tx.transaction_index);
+
+        let transactions_with_receipts = fetch_receipts(provider, transactions).await?;
+        let contracts =
+            inspect_contracts(provider, &store, number, &transactions_with_receipts).await?;
+        let (pool, context) = (pool.clone(), context.clone());
+        tokio::task::spawn_blocking(move || {
+            store_log_range_block(
+                &mut pool.get()?,
+                &context,
+                &header,
+                &transactions_with_receipts,
+                &contracts,
+            )
+        })
+        .await??;
+        println!("✅ Successfully indexed block {}", number);
+    }
+
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || {
+        set_indexer_state(&mut pool.get()?, LOG_CURSOR_KEY, to_block as i64)
+    })
+    .await?
+}
+
+/// Stores a block found through its logs and advances the cursor past it atomically.
+pub fn store_log_range_block(
+    connection: &mut DbConnection,
+    context: &IndexerContext,
+    header: &EthersBlock<H256>,
+    transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+    contracts: &HashMap<Address, DeployedContract>,
+) -> Result<()> {
+    let number = header.number.unwrap().as_u64() as i64;
+    ensure_partitions(connection, context, number..=number)?;
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        process_block_data(conn, context, header, transactions_with_receipts, contracts)?;
+        set_indexer_state(conn, LOG_CURSOR_KEY, number)
+    })
+}
+
+/// Whether a provider error means the requested range returned too many logs.
+pub fn is_range_too_large(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "too many results",
+        "query returned more than",
+        "response size exceeded",
+        "limit exceeded",
+        "block range is too large",
+        "range too large",
+        "exceed maximum block range",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
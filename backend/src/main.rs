@@ -3,35 +3,172 @@
 #![allow(clippy::cast_possible_truncation)]
 #![allow(clippy::cast_sign_loss)]
 
-use crate::api::{
-    get_account_details, get_block_details, get_recent_blocks, get_transaction_details,
-    get_transactions_for_block,
+use axum::{
+    routing::{get, post},
+    Router,
 };
-use axum::{routing::get, Router};
-use clap::Parser;
+use backend::{
+    abi::SharedAbiRegistry,
+    api::{
+        get_account_balances, get_account_details, get_account_tokens, get_balance_series,
+        get_block_details, get_chains, get_contract_details, get_contract_upgrades,
+        get_daily_stats, get_recent_blocks, get_transaction_details, get_transaction_logs,
+        get_transaction_transfers, get_transactions_for_block, search, upload_abi, AppState,
+    },
+    balance_verifier::{self, VerifyOptions},
+    chains::ChainConfig,
+    db::{self, DbPool, PoolConfig},
+    filter::IndexFilter,
+    handlers::HandlerRegistry,
+    indexer::{self, IndexerContext},
+    indexes,
+    integrity::{self, parse_block_range},
+    log_indexer,
+    partitions::{self, Partitioning},
+    retention::{self, RetentionPolicy},
+    signatures::SignatureDb,
+    store::{run_lag_monitor, PgStore, ReplicatedStore, SharedStore, SqliteStore},
+    MIGRATIONS,
+};
+use clap::{Parser, Subcommand, ValueEnum};
 use diesel::{Connection, PgConnection};
-use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
-use std::{env, net::SocketAddr};
+use diesel_migrations::MigrationHarness;
+use std::{env, net::SocketAddr, ops::RangeInclusive, path::PathBuf, sync::Arc, time::Duration};
 use tower_http::cors::{Any, CorsLayer};
 
-mod api;
-mod db;
-mod indexer;
-mod models;
-mod schema;
+/// How the indexer ingests chain data.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum IndexMode {
+    /// Fetch every block with its transactions and receipts
+    Blocks,
+    /// Pull the logs selected by `--filter` with `eth_getLogs` over block ranges
+    Logs,
+}
 
-#[cfg(test)]
-mod tests;
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare indexed token balances with `balanceOf`/`ownerOf` at the latest indexed block
+    VerifyBalances {
+        /// Check this many random balances instead of all of them
+        #[arg(long)]
+        sample: Option<i64>,
+        /// Overwrite mismatching balances with the on-chain values and flag their tokens as
+        /// non-standard
+        #[arg(long, default_value_t = false)]
+        fix: bool,
+    },
+    /// Re-check stored blocks against the transactions and receipts roots stored with them
+    Verify {
+        /// Inclusive block range, written `START..END`
+        #[arg(long, value_parser = parse_block_range)]
+        range: RangeInclusive<u64>,
+    },
+    /// Create the query indexes that are missing, e.g. after starting with `--defer-indexes`
+    CreateIndexes {
+        /// Build with `CREATE INDEX CONCURRENTLY`, which doesn't block the indexer's writes
+        #[arg(long, default_value_t = false)]
+        concurrently: bool,
+    },
+    /// Partition transactions, logs and token transfers by block number
+    #[command(subcommand)]
+    Partition(PartitionCommand),
+}
 
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
+#[derive(Subcommand, Debug)]
+enum PartitionCommand {
+    /// Convert the tables to partitions of `size` blocks, copying their rows; new partitions are
+    /// then created as the indexer advances
+    Enable {
+        #[arg(long)]
+        size: i64,
+    },
+    /// List the partitions and the blocks they hold
+    List,
+    /// Detach the partitions holding only blocks below `before`, leaving them as standalone
+    /// tables to archive, and prune their blocks
+    Detach {
+        #[arg(long)]
+        before: i64,
+        /// Drop the detached partitions instead of keeping them
+        #[arg(long, default_value_t = false)]
+        drop: bool,
+    },
+}
 
 #[derive(Parser, Debug)]
 #[command()]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     #[arg(long, default_value_t = false)]
     no_indexing: bool,
     #[arg(long)]
     start_block: Option<u64>,
+    /// Directory of contract ABI JSON files used to decode logs
+    #[arg(long)]
+    abi_dir: Option<PathBuf>,
+    /// Additional event and function signatures, in the format of `data/signatures.txt`
+    #[arg(long)]
+    signatures: Option<PathBuf>,
+    /// JSON file restricting indexing to the listed contracts, topics, senders and recipients
+    #[arg(long)]
+    filter: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = IndexMode::Blocks)]
+    mode: IndexMode,
+    /// Check each block's transactions and receipts against its header roots and logs bloom,
+    /// re-fetching blocks that don't match
+    #[arg(long, default_value_t = false)]
+    verify_blocks: bool,
+    /// Skip creating the query indexes in migrations, and build them concurrently once the indexer
+    /// has caught up with the chain
+    #[arg(long, default_value_t = false)]
+    defer_indexes: bool,
+    /// Write this many blocks per database transaction with bulk inserts while more than a batch
+    /// behind the chain head
+    #[arg(long, default_value_t = 1)]
+    batch_size: usize,
+    /// Prune blocks, with their transactions, logs and token transfers, once this many newer
+    /// blocks are indexed
+    #[arg(long)]
+    retain_blocks: Option<i64>,
+    /// Prune blocks older than this many days. Combined with `--retain-blocks`, blocks are kept
+    /// until both limits allow pruning them
+    #[arg(long)]
+    retain_days: Option<i64>,
+    /// Most connections kept in the database pool
+    #[arg(long, default_value_t = 10)]
+    db_pool_size: u32,
+    /// Idle connections kept open in the pool, all of them by default
+    #[arg(long)]
+    db_min_idle: Option<u32>,
+    /// Seconds to wait for a free pooled connection before failing
+    #[arg(long, default_value_t = 30)]
+    db_connection_timeout: u64,
+    /// Seconds after which idle connections above `--db-min-idle` are closed, 0 to keep them
+    #[arg(long, default_value_t = 600)]
+    db_idle_timeout: u64,
+    /// Blocks a read replica may be behind the primary before the API reads from the primary
+    #[arg(long, default_value_t = 10)]
+    max_replica_lag: i64,
+    /// JSON file listing the chains to index, each with its RPC, start block and schema, instead
+    /// of the single chain of `ETH_RPC_URL`
+    #[arg(long)]
+    chains: Option<PathBuf>,
+    /// The chain the subcommands work on, the first configured one by default
+    #[arg(long)]
+    chain: Option<String>,
+}
+
+impl Cli {
+    fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            max_size: self.db_pool_size,
+            min_idle: self.db_min_idle,
+            connection_timeout: Duration::from_secs(self.db_connection_timeout),
+            idle_timeout: (self.db_idle_timeout > 0)
+                .then(|| Duration::from_secs(self.db_idle_timeout)),
+        }
+    }
 }
 
 fn run_migrations(connection: &mut PgConnection) -> Result<(), anyhow::Error> {
@@ -43,50 +180,342 @@ fn run_migrations(connection: &mut PgConnection) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Connects to the schema of a chain, migrating it, and opens its connection pool.
+fn open_postgres(
+    database_url: &String,
+    chain: &ChainConfig,
+    cli: &Cli,
+    handlers: &HandlerRegistry,
+) -> anyhow::Result<(PgConnection, DbPool)> {
+    let mut conn = PgConnection::establish(database_url)
+        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+    if let Some(schema) = &chain.schema {
+        db::use_schema(&mut conn, schema)?;
+    }
+
+    if cli.defer_indexes {
+        indexes::defer_query_indexes(&mut conn)?;
+    }
+    run_migrations(&mut conn)?;
+    let missing_indexes = indexes::missing_query_indexes(&mut conn)?;
+    if !missing_indexes.is_empty() && !cli.defer_indexes {
+        println!(
+            "⚠️ {} query indexes of chain {} are missing, create them with `backend create-indexes --concurrently`",
+            missing_indexes.len(),
+            chain.name
+        );
+    }
+    handlers.run_migrations(&mut conn)?;
+
+    let pool = chain_pool(database_url, chain, &cli.pool_config());
+    Ok((conn, pool))
+}
+
+/// A connection pool to the schema of a chain.
+fn chain_pool(database_url: &String, chain: &ChainConfig, config: &PoolConfig) -> DbPool {
+    match &chain.schema {
+        Some(schema) => db::establish_schema_pool(database_url, schema, config),
+        None => db::establish_connection_pool(database_url, config),
+    }
+}
+
+/// Runs one of the maintenance commands, which all work on PostgreSQL.
+async fn run_command(
+    command: Command,
+    chain: &ChainConfig,
+    mut conn: PgConnection,
+    pool: DbPool,
+) -> anyhow::Result<()> {
+    match command {
+        Command::VerifyBalances { sample, fix } => {
+            let options = VerifyOptions { sample, fix };
+            let report = balance_verifier::run_balance_verifier(pool, chain, options).await?;
+            if !report.is_consistent() {
+                std::process::exit(1);
+            }
+        }
+        Command::Verify { range } => {
+            let report = integrity::run_integrity_check(pool, chain, range).await?;
+            if !report.failures.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Command::CreateIndexes { concurrently } => {
+            let created = indexes::create_query_indexes(&mut conn, concurrently)?;
+            println!("✅ Created {} indexes", created);
+        }
+        Command::Partition(PartitionCommand::Enable { size }) => {
+            partitions::enable_partitioning(&mut conn, size)?;
+            println!("✅ Partitioned by {} blocks", size);
+        }
+        Command::Partition(PartitionCommand::List) => {
+            for partition in partitions::list_partitions(&mut conn)? {
+                println!(
+                    "{}\t{}..{}",
+                    partition.name, partition.blocks.start, partition.blocks.end
+                );
+            }
+        }
+        Command::Partition(PartitionCommand::Detach { before, drop }) => {
+            let detached = partitions::detach_partitions(&mut conn, before, drop)?;
+            println!("✅ Detached {} partitions", detached.len());
+        }
+    }
+    Ok(())
+}
+
+/// Fails on the options that only work on PostgreSQL.
+fn check_sqlite_options(cli: &Cli) -> anyhow::Result<()> {
+    let unsupported = [
+        (cli.command.is_some(), "Subcommands"),
+        (matches!(cli.mode, IndexMode::Logs), "--mode logs"),
+        (cli.defer_indexes, "--defer-indexes"),
+        (cli.retain_blocks.is_some(), "--retain-blocks"),
+        (cli.retain_days.is_some(), "--retain-days"),
+        (cli.chains.is_some(), "--chains"),
+    ];
+    match unsupported.into_iter().find(|(used, _)| *used) {
+        Some((_, option)) => anyhow::bail!("{} can't be used with a SQLite database", option),
+        None => Ok(()),
+    }
+}
+
+/// The API routes of one chain, nested under `/api/<chain>`.
+fn chain_routes() -> Router<AppState> {
+    Router::new()
+        .route("/blocks", get(get_recent_blocks))
+        .route("/block/:id", get(get_block_details))
+        .route("/block/:id/transactions", get(get_transactions_for_block))
+        .route("/tx/:hash", get(get_transaction_details))
+        .route("/tx/:hash/logs", get(get_transaction_logs))
+        .route("/tx/:hash/transfers", get(get_transaction_transfers))
+        .route("/account/:address", get(get_account_details))
+        .route("/account/:address/tokens", get(get_account_tokens))
+        .route("/account/:address/balances", get(get_account_balances))
+        .route("/account/:address/balances/:token", get(get_balance_series))
+        .route("/contract/:address", get(get_contract_details))
+        .route("/contract/:address/upgrades", get(get_contract_upgrades))
+        .route("/stats/daily", get(get_daily_stats))
+        .route("/search", get(search))
+        .route("/admin/abi/:address", post(upload_abi))
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    if cli.verify_blocks && matches!(cli.mode, IndexMode::Logs) {
+        anyhow::bail!("--verify-blocks needs full blocks and can't be used with --mode logs");
+    }
 
     // Load .env file
     dotenvy::dotenv().ok();
 
     let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let mut conn = PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
+    // Comma-separated read replicas of a PostgreSQL database, used by the API
+    let read_urls: Vec<String> = env::var("DATABASE_READ_URL")
+        .map(|urls| {
+            urls.split(',')
+                .map(str::trim)
+                .filter(|url| !url.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
 
-    run_migrations(&mut conn)?;
+    // Without a chains file, the chain of `ETH_RPC_URL` is indexed into the default schema
+    let chains = match &cli.chains {
+        Some(path) => {
+            if cli.start_block.is_some() {
+                anyhow::bail!(
+                    "--start-block can't be used with --chains, set `start_block` per chain"
+                );
+            }
+            ChainConfig::load(path)?
+        }
+        None => vec![ChainConfig::single(
+            env::var("ETH_RPC_URL").ok(),
+            cli.start_block,
+        )],
+    };
+
+    let sqlite_path = db::sqlite_path(&database_url);
+    if sqlite_path.is_some() {
+        check_sqlite_options(&cli)?;
+        if !read_urls.is_empty() {
+            anyhow::bail!("DATABASE_READ_URL can't be used with a SQLite database");
+        }
+    }
 
-    // Establish DB connection pool
-    let pool = db::establish_connection_pool(&database_url);
-
-    if cli.no_indexing {
-        println!("🚫 Indexer is disabled by --no-indexing flag. Running in API-only mode.");
-    } else {
-        println!("🚀 Starting indexer background task...");
-        let indexer_pool = pool.clone();
-        tokio::spawn(async move {
-            if let Err(e) = indexer::run_indexer(indexer_pool, cli.start_block).await {
-                eprintln!("Indexer process failed: {}", e);
+    // Load the signature database used to label logs and transactions
+    let mut signatures = SignatureDb::bundled();
+    if let Some(path) = &cli.signatures {
+        let loaded = signatures.load_file(path)?;
+        println!("🏷️ Loaded {} signatures from {}", loaded, path.display());
+    }
+    let signatures = Arc::new(signatures);
+
+    // No event handlers are built in; deployments embedding the indexer register theirs here
+    let handlers = Arc::new(HandlerRegistry::default());
+
+    if let Some(command) = cli.command.take() {
+        let chain = match &cli.chain {
+            Some(name) => chains
+                .iter()
+                .find(|chain| &chain.name == name)
+                .ok_or_else(|| anyhow::anyhow!("Chain {} isn't configured", name))?,
+            None => &chains[0],
+        };
+        let (conn, pool) = open_postgres(&database_url, chain, &cli, &handlers)?;
+        return run_command(command, chain, conn, pool).await;
+    }
+
+    let filter = cli
+        .filter
+        .as_deref()
+        .map(IndexFilter::load)
+        .transpose()?
+        .map(Arc::new);
+    let retention = RetentionPolicy {
+        blocks: cli.retain_blocks,
+        max_age: cli.retain_days.map(chrono::Duration::days),
+    };
+    let admin_token = env::var("ADMIN_TOKEN").ok();
+
+    let chain_list: Vec<common::Chain> = chains
+        .iter()
+        .map(|chain| common::Chain {
+            name: chain.name.clone(),
+            chain_id: chain.chain_id,
+        })
+        .collect();
+    let mut app = Router::new()
+        .route("/api/chains", get(get_chains))
+        .with_state(Arc::new(chain_list));
+
+    for chain in chains {
+        // PostgreSQL's connection and pool, which the options that only work on PostgreSQL use
+        // directly
+        let mut postgres = None;
+        let store: SharedStore = match sqlite_path {
+            Some(path) => {
+                println!("🗄️ Using SQLite database {}", path);
+                Arc::new(SqliteStore::open(path, &cli.pool_config())?)
             }
-        });
+            None => {
+                let (conn, pool) = open_postgres(&database_url, &chain, &cli, &handlers)?;
+                let store = Arc::new(PgStore::new(pool.clone()));
+                postgres = Some((conn, pool));
+                store
+            }
+        };
+
+        // The API reads from the replicas that keep up with the primary, which the indexer
+        // writes to
+        let api_store: SharedStore = if read_urls.is_empty() {
+            store.clone()
+        } else {
+            let replicas = read_urls
+                .iter()
+                .map(|url| {
+                    let pool = chain_pool(url, &chain, &cli.pool_config());
+                    Arc::new(PgStore::new(pool)) as SharedStore
+                })
+                .collect();
+            let replicated = Arc::new(ReplicatedStore::new(
+                store.clone(),
+                replicas,
+                cli.max_replica_lag,
+            ));
+            println!(
+                "📚 Serving the API of chain {} from {} read replicas",
+                chain.name,
+                read_urls.len()
+            );
+            tokio::spawn(run_lag_monitor(replicated.clone()));
+            replicated
+        };
+
+        // Load known contract ABIs for log decoding
+        let abi_registry = SharedAbiRegistry::default();
+        {
+            let mut registry = abi_registry.write().unwrap();
+            if let Some(dir) = &cli.abi_dir {
+                let loaded = registry.load_dir(dir)?;
+                println!("📜 Loaded {} ABI files from {}", loaded, dir.display());
+            }
+            let stored = registry.load_from_store(store.as_ref())?;
+            println!("📜 Loaded {} uploaded ABIs of chain {}", stored, chain.name);
+        }
+
+        if retention.is_enabled() {
+            if let Some((_, pool)) = &postgres {
+                tokio::spawn(retention::run_pruner(pool.clone(), retention));
+            }
+        }
+
+        if cli.no_indexing {
+            println!(
+                "🚫 Indexer of chain {} is disabled by --no-indexing flag. Running in API-only mode.",
+                chain.name
+            );
+        } else {
+            println!(
+                "🚀 Starting indexer background task of chain {}...",
+                chain.name
+            );
+            let indexer_pool = postgres.as_ref().map(|(_, pool)| pool.clone());
+            let indexer_store = store.clone();
+            let context = IndexerContext {
+                abi_registry: abi_registry.clone(),
+                handlers: handlers.clone(),
+                filter: filter.clone(),
+                verify_blocks: cli.verify_blocks,
+                deferred_indexes: cli.defer_indexes,
+                batch_size: cli.batch_size,
+                partitioning: match &mut postgres {
+                    Some((conn, _)) => Partitioning::load(conn)?.map(Arc::new),
+                    None => None,
+                },
+            };
+            let mode = cli.mode;
+            let chain = chain.clone();
+            tokio::spawn(async move {
+                let name = chain.name.clone();
+                let result = match mode {
+                    IndexMode::Blocks => indexer::run_indexer(indexer_store, context, chain).await,
+                    IndexMode::Logs => {
+                        // Only PostgreSQL databases get here, see `check_sqlite_options`
+                        let pool = indexer_pool.expect("--mode logs needs PostgreSQL");
+                        log_indexer::run_log_indexer(pool, context, chain).await
+                    }
+                };
+                if let Err(e) = result {
+                    eprintln!("Indexer process of chain {} failed: {}", name, e);
+                }
+            });
+        }
+
+        let state = AppState {
+            store: api_store,
+            abi_registry,
+            signatures: signatures.clone(),
+            admin_token: admin_token.clone(),
+        };
+        // A single chain is also served unscoped, as before chains were configurable
+        if cli.chains.is_none() {
+            app = app.nest("/api", chain_routes().with_state(state.clone()));
+        }
+        app = app.nest(
+            &format!("/api/{}", chain.name),
+            chain_routes().with_state(state),
+        );
     }
 
     let cors = CorsLayer::new()
         .allow_methods(Any)
         .allow_origin(Any)
         .allow_headers(Any);
-
-    let app = Router::new()
-        .route("/api/blocks", get(get_recent_blocks))
-        .route("/api/block/:hash", get(get_block_details))
-        .route(
-            "/api/block/:hash/transactions",
-            get(get_transactions_for_block),
-        )
-        .route("/api/tx/:hash", get(get_transaction_details))
-        .route("/api/account/:address", get(get_account_details))
-        .with_state(pool)
-        .layer(cors);
+    let app = app.layer(cors);
 
     // Run it
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
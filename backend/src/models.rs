@@ -1,40 +1,175 @@
-use crate::schema::{accounts, blocks, logs, token_balances, token_transfers, transactions};
+use crate::schema::{
+    accounts, blocks, contract_abis, contracts, logs, proxy_upgrades, token_balances,
+    token_transfers, transactions,
+};
 use bigdecimal::BigDecimal;
-use chrono::offset::Utc;
-use chrono::DateTime;
-use diesel::prelude::*;
+use chrono::{offset::Utc, DateTime};
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    prelude::*,
+    serialize::{self, Output, ToSql},
+    sql_types::Bytea,
+    sqlite::{Sqlite, SqliteValue},
+};
+use ethers::types::{Address, H256};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
-#[derive(Queryable, Selectable, Insertable)]
+/// Declares a wrapper storing a fixed-size ethers hash type as `BYTEA` (a `BLOB` in SQLite).
+///
+/// The wrappers serialize to JSON and display as `0x`-prefixed lowercase hex, like the type
+/// they wrap.
+macro_rules! bytea_type {
+    ($(#[$meta:meta])* $name:ident($inner:ty)) => {
+        $(#[$meta])*
+        #[derive(
+            AsExpression,
+            FromSqlRow,
+            Clone,
+            Copy,
+            Debug,
+            Default,
+            PartialEq,
+            Eq,
+            Hash,
+            PartialOrd,
+            Ord,
+            Serialize,
+            Deserialize,
+        )]
+        #[diesel(sql_type = Bytea)]
+        #[serde(transparent)]
+        pub struct $name(pub $inner);
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                Self(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{:#x}", self.0)
+            }
+        }
+
+        impl ToSql<Bytea, Pg> for $name {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+                <[u8] as ToSql<Bytea, Pg>>::to_sql(self.0.as_bytes(), out)
+            }
+        }
+
+        impl FromSql<Bytea, Pg> for $name {
+            fn from_sql(value: PgValue<'_>) -> deserialize::Result<Self> {
+                Self::from_bytes(<Vec<u8> as FromSql<Bytea, Pg>>::from_sql(value)?)
+            }
+        }
+
+        impl ToSql<Bytea, Sqlite> for $name {
+            fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+                <[u8] as ToSql<Bytea, Sqlite>>::to_sql(self.0.as_bytes(), out)
+            }
+        }
+
+        impl FromSql<Bytea, Sqlite> for $name {
+            fn from_sql(value: SqliteValue<'_, '_, '_>) -> deserialize::Result<Self> {
+                Self::from_bytes(<Vec<u8> as FromSql<Bytea, Sqlite>>::from_sql(value)?)
+            }
+        }
+
+        impl $name {
+            fn from_bytes(bytes: Vec<u8>) -> deserialize::Result<Self> {
+                if bytes.len() != <$inner>::len_bytes() {
+                    return Err(format!(
+                        "expected {} bytes for {}, got {}",
+                        <$inner>::len_bytes(),
+                        stringify!($name),
+                        bytes.len()
+                    )
+                    .into());
+                }
+                Ok(Self(<$inner>::from_slice(&bytes)))
+            }
+        }
+    };
+}
+
+bytea_type!(
+    /// A 32-byte block, transaction or topic hash.
+    DbHash(H256)
+);
+
+bytea_type!(
+    /// A 20-byte account or contract address.
+    DbAddress(Address)
+);
+
+#[derive(Queryable, Selectable, Insertable, Clone)]
 #[diesel(table_name = blocks)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Block {
-    pub hash: String,
-    pub parent_hash: String,
+    pub hash: DbHash,
+    pub parent_hash: DbHash,
     pub number: i64,
     pub timestamp: DateTime<Utc>,
-    pub miner: String,
+    pub miner: DbAddress,
     pub gas_used: BigDecimal,
     pub gas_limit: BigDecimal,
     pub base_fee_per_gas: Option<BigDecimal>,
     pub extra_data: String,
     pub tx_count: i32,
+    pub transactions_root: Option<DbHash>,
+    pub receipts_root: Option<DbHash>,
+    pub logs_bloom: Option<String>,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, Clone)]
 #[diesel(table_name = transactions)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Transaction {
-    pub hash: String,
-    pub block_hash: String,
+    pub hash: DbHash,
+    pub block_hash: DbHash,
     pub block_number: i64,
-    pub from_address: String,
-    pub to_address: Option<String>,
+    pub from_address: DbAddress,
+    pub to_address: Option<DbAddress>,
     pub value: BigDecimal,
     pub gas_price: Option<BigDecimal>,
     pub gas_used: Option<BigDecimal>,
     pub nonce: i64,
     pub position: i32,
+    pub method_id: Option<String>, // 4-byte input selector, NULL for plain transfers
+    /// EIP-2718 type, e.g. 2 for EIP-1559 and `l2::DEPOSIT_TX_TYPE` for OP-stack deposits.
+    pub tx_type: Option<i16>,
+    #[diesel(embed)]
+    pub l2: L2Fields,
+}
+
+/// Fields of L2 transactions and receipts beyond Ethereum's, all `None` on L1 chains. See
+/// `l2::l2_fields`.
+#[derive(Queryable, Selectable, Insertable, Clone, Debug, Default, PartialEq)]
+#[diesel(table_name = transactions)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct L2Fields {
+    /// OP-stack: the L1 data fee, charged on top of the L2 gas.
+    pub l1_fee: Option<BigDecimal>,
+    pub l1_gas_used: Option<BigDecimal>,
+    pub l1_gas_price: Option<BigDecimal>,
+    /// Arbitrum: the part of `gas_used` paying for L1 calldata.
+    pub gas_used_for_l1: Option<BigDecimal>,
+    /// Arbitrum: the L1 block the transaction was sequenced against.
+    pub l1_block_number: Option<i64>,
+    /// OP-stack deposits: the hash identifying the L1 event the deposit comes from.
+    pub deposit_source_hash: Option<DbHash>,
+    /// OP-stack deposits: the ETH minted on L2, in wei.
+    pub deposit_mint: Option<BigDecimal>,
 }
 
 #[derive(Queryable, Selectable, Insertable, Clone, Debug, Serialize, Deserialize)]
@@ -46,69 +181,134 @@ pub struct Log {
     // For simplicity here, we assume a simple mapping.
     // To make this struct usable with `insert_into`, the `id` field should be omitted
     // or a separate `NewLog` struct should be created without it.
-    pub id: i32,
-    pub tx_hash: String,
-    pub address: String,
-    pub topic0: Option<String>,
-    pub topic1: Option<String>,
-    pub topic2: Option<String>,
-    pub topic3: Option<String>,
+    pub id: i64,
+    pub tx_hash: DbHash,
+    pub address: DbAddress,
+    pub topic0: Option<DbHash>,
+    pub topic1: Option<DbHash>,
+    pub topic2: Option<DbHash>,
+    pub topic3: Option<DbHash>,
     pub data: String,
+    pub decoded: Option<serde_json::Value>,
+    pub block_number: i64,
 }
 
 // A version of the Log struct for insertion, without the auto-generated `id`.
 #[derive(Insertable)]
 #[diesel(table_name = logs)]
+#[diesel(treat_none_as_default_value = false)]
 pub struct NewLog<'a> {
-    pub tx_hash: &'a str,
-    pub address: &'a str,
-    pub topic0: Option<&'a str>,
-    pub topic1: Option<&'a str>,
-    pub topic2: Option<&'a str>,
-    pub topic3: Option<&'a str>,
+    pub tx_hash: DbHash,
+    pub address: DbAddress,
+    pub topic0: Option<DbHash>,
+    pub topic1: Option<DbHash>,
+    pub topic2: Option<DbHash>,
+    pub topic3: Option<DbHash>,
     pub data: &'a str,
+    pub decoded: Option<&'a serde_json::Value>,
+    pub block_number: i64,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone)]
 #[diesel(table_name = accounts)]
+#[diesel(primary_key(address), treat_none_as_null = true)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct Account {
-    pub address: String,
-    pub created_at: DateTime<Utc>,
+    pub address: DbAddress,
+    pub first_seen_block: Option<i64>,
+    pub first_seen_tx: Option<DbHash>,
+    pub first_seen_at: Option<DateTime<Utc>>,
+    pub last_active_block: Option<i64>,
+    pub tx_sent_count: i64,
+    pub tx_received_count: i64,
+    pub is_contract: bool,
+    /// Whether the indexer has looked up the account's code.
+    pub code_checked: bool,
 }
 
 #[derive(Queryable, Selectable, Insertable, Clone, Debug, Serialize, Deserialize)]
 #[diesel(table_name = token_transfers)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct TokenTransfer {
-    pub id: i32,
-    pub tx_hash: String,
-    pub token_address: String,
-    pub from_address: String,
-    pub to_address: String,
+    pub id: i64,
+    pub tx_hash: DbHash,
+    pub token_address: DbAddress,
+    pub from_address: DbAddress,
+    pub to_address: DbAddress,
     pub value: Option<BigDecimal>,    // For ERC20
     pub token_id: Option<BigDecimal>, // For ERC721
+    pub block_number: i64,
 }
 
 // A struct for inserting new token transfers.
 #[derive(Insertable)]
 #[diesel(table_name = token_transfers)]
+#[diesel(treat_none_as_default_value = false)]
 pub struct NewTokenTransfer<'a> {
-    pub tx_hash: &'a str,
-    pub token_address: &'a str,
-    pub from_address: &'a str,
-    pub to_address: &'a str,
+    pub tx_hash: DbHash,
+    pub token_address: DbAddress,
+    pub from_address: DbAddress,
+    pub to_address: DbAddress,
     pub value: Option<&'a BigDecimal>,
     pub token_id: Option<&'a BigDecimal>,
+    pub block_number: i64,
 }
 
 #[derive(Queryable, Selectable, Insertable, Clone, Debug, Serialize, Deserialize)]
 #[diesel(table_name = token_balances)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct TokenBalance {
-    pub id: i32,
-    pub owner_address: String,
-    pub token_address: String,
+    pub id: i64,
+    pub owner_address: DbAddress,
+    pub token_address: DbAddress,
     pub amount: BigDecimal,
     pub token_id: Option<BigDecimal>, // NULL for ERC20, value for ERC721
 }
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = contract_abis)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ContractAbi {
+    pub address: DbAddress,
+    pub abi: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Selectable, Insertable, AsChangeset, Clone, Debug)]
+#[diesel(table_name = contracts)]
+#[diesel(primary_key(address), treat_none_as_null = true)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Contract {
+    pub address: DbAddress,
+    pub bytecode_hash: Option<DbHash>,
+    pub creation_tx: Option<DbHash>,
+    pub creation_block: Option<i64>,
+    pub proxy_kind: Option<String>,
+    pub implementation: Option<DbAddress>,
+    pub beacon: Option<DbAddress>,
+    pub token_name: Option<String>,
+    pub token_symbol: Option<String>,
+    pub token_decimals: Option<i16>,
+}
+
+#[derive(Queryable, Selectable, Clone, Debug)]
+#[diesel(table_name = proxy_upgrades)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ProxyUpgrade {
+    pub id: i64,
+    pub tx_hash: DbHash,
+    pub proxy_address: DbAddress,
+    pub implementation: DbAddress,
+    pub previous_implementation: Option<DbAddress>,
+}
+
+// A struct for inserting new proxy upgrades.
+#[derive(Insertable)]
+#[diesel(table_name = proxy_upgrades)]
+#[diesel(treat_none_as_default_value = false)]
+pub struct NewProxyUpgrade {
+    pub tx_hash: DbHash,
+    pub proxy_address: DbAddress,
+    pub implementation: DbAddress,
+    pub previous_implementation: Option<DbAddress>,
+}
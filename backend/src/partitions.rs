@@ -0,0 +1,472 @@
+use anyhow::{anyhow, bail, Result};
+use diesel::{
+    prelude::*,
+    sql_types::{Array, BigInt, Nullable, Text},
+};
+use std::{
+    collections::BTreeSet,
+    ops::{Range, RangeInclusive},
+    sync::Mutex,
+};
+
+use crate::{retention, schema::indexer_state};
+
+/// Tables that can be partitioned by block number, with their primary keys once partitioned; a
+/// partitioned table's unique constraints have to include the partition key.
+pub const PARTITIONED_TABLES: &[(&str, &str)] = &[
+    ("transactions", "hash, block_number"),
+    ("logs", "id, block_number"),
+    ("token_transfers", "id, block_number"),
+];
+
+/// `indexer_state` key holding the number of blocks per partition, set once partitioning is
+/// enabled.
+pub const PARTITION_SIZE_KEY: &str = "partition_size";
+
+/// A partition of one of the `PARTITIONED_TABLES`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    pub table: String,
+    pub name: String,
+    /// The block numbers it holds.
+    pub blocks: Range<i64>,
+}
+
+/// The name of `table`'s partition starting at block `start`.
+pub fn partition_name(table: &str, start: i64) -> String {
+    format!("{}_p{}", table, start)
+}
+
+/// Creates the partitions the indexer writes to as it advances.
+#[derive(Debug)]
+pub struct Partitioning {
+    size: i64,
+    /// Start blocks of the partitions known to exist in every partitioned table.
+    created: Mutex<BTreeSet<i64>>,
+}
+
+impl Partitioning {
+    pub fn new(size: i64) -> Self {
+        Self {
+            size,
+            created: Mutex::default(),
+        }
+    }
+
+    /// Loads the partition size, if partitioning was enabled.
+    pub fn load(connection: &mut PgConnection) -> Result<Option<Self>> {
+        Ok(partition_size(connection)?.map(Self::new))
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    /// Creates any missing partitions for `blocks`. This has to run outside the transaction
+    /// writing the blocks, as partitions created by a transaction that is rolled back would
+    /// still be remembered as existing.
+    pub fn ensure(&self, connection: &mut PgConnection, blocks: RangeInclusive<i64>) -> Result<()> {
+        let mut created = self
+            .created
+            .lock()
+            .map_err(|_| anyhow!("Partition cache lock poisoned"))?;
+        let first = blocks.start().div_euclid(self.size);
+        let last = blocks.end().div_euclid(self.size);
+        for start in (first..=last).map(|index| index * self.size) {
+            if created.contains(&start) {
+                continue;
+            }
+            for &(table, _) in PARTITIONED_TABLES {
+                create_partition(connection, table, start, self.size)?;
+            }
+            created.insert(start);
+        }
+        Ok(())
+    }
+}
+
+/// The number of blocks per partition, if partitioning was enabled.
+pub fn partition_size(connection: &mut PgConnection) -> Result<Option<i64>> {
+    Ok(indexer_state::table
+        .find(PARTITION_SIZE_KEY)
+        .select(indexer_state::value)
+        .first(connection)
+        .optional()?)
+}
+
+/// Whether `table` is a partitioned table.
+pub fn is_partitioned(connection: &mut PgConnection, table: &str) -> Result<bool> {
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+    let found = diesel::sql_query(
+        "SELECT COUNT(*) AS count FROM pg_class \
+         WHERE relname = $1 AND relkind = 'p' AND pg_table_is_visible(oid)",
+    )
+    .bind::<Text, _>(table)
+    .get_result::<Count>(connection)?;
+    Ok(found.count > 0)
+}
+
+fn create_partition(
+    connection: &mut PgConnection,
+    table: &str,
+    start: i64,
+    size: i64,
+) -> Result<()> {
+    diesel::sql_query(format!(
+        "CREATE TABLE IF NOT EXISTS {} PARTITION OF {} FOR VALUES FROM ({}) TO ({})",
+        partition_name(table, start),
+        table,
+        start,
+        start + size
+    ))
+    .execute(connection)?;
+    Ok(())
+}
+
+#[derive(QueryableByName)]
+struct Definition {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    definition: String,
+}
+
+#[derive(QueryableByName)]
+struct ForeignKey {
+    #[diesel(sql_type = Text)]
+    table_name: String,
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct OwnedSequence {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    column_name: String,
+}
+
+#[derive(QueryableByName)]
+struct BlockSpan {
+    #[diesel(sql_type = Nullable<BigInt>)]
+    first: Option<i64>,
+    #[diesel(sql_type = Nullable<BigInt>)]
+    last: Option<i64>,
+}
+
+/// Converts the `PARTITIONED_TABLES` into tables partitioned by ranges of `size` blocks, moving
+/// their rows into partitions covering the indexed blocks.
+///
+/// Foreign keys referencing these tables are dropped, since a foreign key to a partitioned table
+/// has to reference a unique key including the block number; rolling back a block deletes its
+/// dependent rows explicitly instead.
+///
+/// The rows are moved `COPY_BATCH_BLOCKS` blocks per transaction, so locks and WAL are held for
+/// one batch at a time. An interrupted conversion resumes from the rows left in the old tables
+/// when it is run again.
+pub fn enable_partitioning(connection: &mut PgConnection, size: i64) -> Result<()> {
+    if size <= 0 {
+        bail!("The partition size must be positive, got {}", size);
+    }
+    if let Some(existing) = partition_size(connection)? {
+        bail!("Partitioning is already enabled, by {} blocks", existing);
+    }
+
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        let tables: Vec<String> = PARTITIONED_TABLES
+            .iter()
+            .flat_map(|(table, _)| [table.to_string(), unpartitioned_name(table)])
+            .collect();
+        let references = diesel::sql_query(
+            "SELECT conrelid::regclass::TEXT AS table_name, conname::TEXT AS name \
+             FROM pg_constraint \
+             WHERE contype = 'f' AND confrelid::regclass::TEXT = ANY($1)",
+        )
+        .bind::<Array<Text>, _>(&tables)
+        .load::<ForeignKey>(conn)?;
+        for reference in references {
+            diesel::sql_query(format!(
+                "ALTER TABLE {} DROP CONSTRAINT {}",
+                reference.table_name, reference.name
+            ))
+            .execute(conn)?;
+            println!(
+                "🔗 Dropped foreign key {} on {}",
+                reference.name, reference.table_name
+            );
+        }
+
+        for &(table, _) in PARTITIONED_TABLES {
+            if !is_partitioned(conn, table)? {
+                let old = unpartitioned_name(table);
+                diesel::sql_query(format!("ALTER TABLE {} RENAME TO {}", table, old))
+                    .execute(conn)?;
+                diesel::sql_query(format!(
+                    "CREATE TABLE {} (LIKE {} INCLUDING DEFAULTS) PARTITION BY RANGE (block_number)",
+                    table, old
+                ))
+                .execute(conn)?;
+            }
+        }
+        Ok(())
+    })?;
+
+    for &(table, _) in PARTITIONED_TABLES {
+        move_rows(connection, table, size)?;
+    }
+
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        for &(table, primary_key) in PARTITIONED_TABLES {
+            finish_partitioned_table(conn, table, primary_key)?;
+        }
+        diesel::insert_into(indexer_state::table)
+            .values((
+                indexer_state::key.eq(PARTITION_SIZE_KEY),
+                indexer_state::value.eq(size),
+            ))
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// Blocks whose rows are moved into partitions per database transaction.
+pub const COPY_BATCH_BLOCKS: i64 = 1000;
+
+/// The name `table` has while its rows are moved into partitions.
+fn unpartitioned_name(table: &str) -> String {
+    format!("{}_unpartitioned", table)
+}
+
+/// Moves the rows of `table`'s old copy into partitions, `COPY_BATCH_BLOCKS` blocks per
+/// transaction.
+fn move_rows(connection: &mut PgConnection, table: &str, size: i64) -> Result<()> {
+    let old = unpartitioned_name(table);
+    let mut moved = 0;
+    loop {
+        let batch = connection.transaction::<_, anyhow::Error, _>(|conn| {
+            // The blocks of the next batch that have rows.
+            let span = diesel::sql_query(format!(
+                "SELECT first, \
+                 (SELECT MAX(block_number) FROM {old} WHERE block_number < first + $1) AS last \
+                 FROM (SELECT MIN(block_number) AS first FROM {old}) batch",
+            ))
+            .bind::<BigInt, _>(COPY_BATCH_BLOCKS)
+            .get_result::<BlockSpan>(conn)?;
+            let (Some(first), Some(last)) = (span.first, span.last) else {
+                return Ok(None);
+            };
+            let (first_index, last_index) = (first.div_euclid(size), last.div_euclid(size));
+            for start in (first_index..=last_index).map(|index| index * size) {
+                create_partition(conn, table, start, size)?;
+            }
+            let rows = diesel::sql_query(format!(
+                "WITH moved AS ( \
+                     DELETE FROM {old} WHERE block_number >= $1 AND block_number <= $2 \
+                     RETURNING * \
+                 ) \
+                 INSERT INTO {table} SELECT * FROM moved",
+            ))
+            .bind::<BigInt, _>(first)
+            .bind::<BigInt, _>(last)
+            .execute(conn)?;
+            Ok(Some(rows))
+        })?;
+        match batch {
+            Some(rows) => moved += rows,
+            None => break,
+        }
+    }
+    println!("🧩 Partitioned {} ({} rows)", table, moved);
+    Ok(())
+}
+
+/// Moves the sequences, keys and indexes of `table`'s emptied old copy to the partitioned table,
+/// and drops the old copy.
+fn finish_partitioned_table(
+    connection: &mut PgConnection,
+    table: &str,
+    primary_key: &str,
+) -> Result<()> {
+    let old = unpartitioned_name(table);
+    // Captured before the old table is dropped, so they're recreated on the partitioned table.
+    let indexes = diesel::sql_query(
+        "SELECT c.relname::TEXT AS name, pg_get_indexdef(i.indexrelid) AS definition \
+         FROM pg_index i JOIN pg_class c ON c.oid = i.indexrelid \
+         WHERE i.indrelid = to_regclass($1) AND NOT i.indisprimary",
+    )
+    .bind::<Text, _>(&old)
+    .load::<Definition>(connection)?;
+    let foreign_keys = diesel::sql_query(
+        "SELECT conname::TEXT AS name, pg_get_constraintdef(oid) AS definition \
+         FROM pg_constraint WHERE conrelid = to_regclass($1) AND contype = 'f'",
+    )
+    .bind::<Text, _>(&old)
+    .load::<Definition>(connection)?;
+    let sequences = diesel::sql_query(
+        "SELECT s.relname::TEXT AS name, a.attname::TEXT AS column_name \
+         FROM pg_depend d \
+         JOIN pg_class s ON s.oid = d.objid AND s.relkind = 'S' \
+         JOIN pg_attribute a ON a.attrelid = d.refobjid AND a.attnum = d.refobjsubid \
+         WHERE d.refobjid = to_regclass($1) AND d.deptype = 'a'",
+    )
+    .bind::<Text, _>(&old)
+    .load::<OwnedSequence>(connection)?;
+
+    // The id sequences move to the new table, so dropping the old one keeps them.
+    for sequence in &sequences {
+        diesel::sql_query(format!(
+            "ALTER SEQUENCE {} OWNED BY {}.{}",
+            sequence.name, table, sequence.column_name
+        ))
+        .execute(connection)?;
+    }
+    diesel::sql_query(format!("DROP TABLE {}", old)).execute(connection)?;
+
+    diesel::sql_query(format!(
+        "ALTER TABLE {} ADD PRIMARY KEY ({})",
+        table, primary_key
+    ))
+    .execute(connection)?;
+    for index in &indexes {
+        // The old table's indexes were dropped with it, so their names are free again.
+        let definition =
+            index
+                .definition
+                .replacen(&format!("{} USING", old), &format!("{} USING", table), 1);
+        diesel::sql_query(definition).execute(connection)?;
+    }
+    for foreign_key in &foreign_keys {
+        diesel::sql_query(format!(
+            "ALTER TABLE {} ADD CONSTRAINT {} {}",
+            table, foreign_key.name, foreign_key.definition
+        ))
+        .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Parses a range partition's bound, as shown by `pg_get_expr`, e.g.
+/// `FOR VALUES FROM ('0') TO ('1000')`.
+fn parse_bound(bound: &str) -> Option<Range<i64>> {
+    let numbers: Vec<i64> = bound
+        .split(|c: char| !(c.is_ascii_digit() || c == '-'))
+        .filter_map(|part| part.parse().ok())
+        .collect();
+    match numbers[..] {
+        [from, to] => Some(from..to),
+        _ => None,
+    }
+}
+
+/// Lists the partitions of the partitioned tables, ordered by table and block range.
+pub fn list_partitions(connection: &mut PgConnection) -> Result<Vec<Partition>> {
+    #[derive(QueryableByName)]
+    struct Row {
+        #[diesel(sql_type = Text)]
+        parent: String,
+        #[diesel(sql_type = Text)]
+        name: String,
+        #[diesel(sql_type = Text)]
+        bound: String,
+    }
+
+    let tables: Vec<&str> = PARTITIONED_TABLES.iter().map(|(table, _)| *table).collect();
+    let rows = diesel::sql_query(
+        "SELECT p.relname::TEXT AS parent, c.relname::TEXT AS name, \
+         pg_get_expr(c.relpartbound, c.oid) AS bound \
+         FROM pg_inherits i \
+         JOIN pg_class c ON c.oid = i.inhrelid \
+         JOIN pg_class p ON p.oid = i.inhparent \
+         WHERE p.relname = ANY($1) AND pg_table_is_visible(p.oid)",
+    )
+    .bind::<Array<Text>, _>(&tables)
+    .load::<Row>(connection)?;
+
+    let mut partitions: Vec<Partition> = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(Partition {
+                blocks: parse_bound(&row.bound)?,
+                table: row.parent,
+                name: row.name,
+            })
+        })
+        .collect();
+    partitions.sort_by(|a, b| (&a.table, a.blocks.start).cmp(&(&b.table, b.blocks.start)));
+    Ok(partitions)
+}
+
+/// Detaches the partitions holding only blocks below `before`, so their rows can be archived
+/// (e.g. with `pg_dump -t`) and dropped. The partitions are dropped right away when `drop` is
+/// set. Returns the detached partitions.
+///
+/// The detached blocks count as pruned, as in `retention::prune_blocks`: they're added to the
+/// daily totals and pruned balances first, and their block rows are deleted, so the API answers
+/// requests about them as pruned rather than with empty blocks.
+pub fn detach_partitions(
+    connection: &mut PgConnection,
+    before: i64,
+    drop: bool,
+) -> Result<Vec<Partition>> {
+    if partition_size(connection)?.is_none() {
+        bail!("Partitioning isn't enabled");
+    }
+    let detached: Vec<Partition> = list_partitions(connection)?
+        .into_iter()
+        .filter(|partition| partition.blocks.end <= before)
+        .collect();
+    let (Some(start), Some(end)) = (
+        detached
+            .iter()
+            .map(|partition| partition.blocks.start)
+            .min(),
+        detached.iter().map(|partition| partition.blocks.end).max(),
+    ) else {
+        return Ok(detached);
+    };
+
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        retention::summarize_blocks(conn, start..end)?;
+        for partition in &detached {
+            diesel::sql_query(format!(
+                "ALTER TABLE {} DETACH PARTITION {}",
+                partition.table, partition.name
+            ))
+            .execute(conn)?;
+            if drop {
+                diesel::sql_query(format!("DROP TABLE {}", partition.name)).execute(conn)?;
+                continue;
+            }
+            // The archived rows would otherwise cascade away with their blocks.
+            let foreign_keys = diesel::sql_query(
+                "SELECT conrelid::regclass::TEXT AS table_name, conname::TEXT AS name \
+                 FROM pg_constraint WHERE conrelid = to_regclass($1) AND contype = 'f'",
+            )
+            .bind::<Text, _>(&partition.name)
+            .load::<ForeignKey>(conn)?;
+            for foreign_key in foreign_keys {
+                diesel::sql_query(format!(
+                    "ALTER TABLE {} DROP CONSTRAINT {}",
+                    foreign_key.table_name, foreign_key.name
+                ))
+                .execute(conn)?;
+            }
+        }
+        retention::delete_blocks(conn, start..end)
+    })?;
+    for partition in &detached {
+        println!(
+            "📦 {} {} (blocks {}..{})",
+            if drop { "Dropped" } else { "Detached" },
+            partition.name,
+            partition.blocks.start,
+            partition.blocks.end
+        );
+    }
+    Ok(detached)
+}
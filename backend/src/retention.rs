@@ -0,0 +1,233 @@
+use crate::{
+    db::DbPool,
+    schema::{blocks, indexer_state},
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use diesel::{
+    dsl::{max, min, sql},
+    prelude::*,
+    sql_types::BigInt,
+};
+use std::ops::Range;
+use tokio::time::{sleep, Duration};
+
+/// `indexer_state` key holding the first block that hasn't been pruned.
+pub const PRUNED_BEFORE_KEY: &str = "pruned_before";
+
+/// Blocks deleted per database transaction, so each batch only holds its locks briefly.
+pub const PRUNE_BATCH_BLOCKS: i64 = 100;
+
+/// How often the pruner looks for blocks past the retention horizon.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Which blocks to keep. A block is pruned once every limit that is set allows it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RetentionPolicy {
+    /// Keep this many of the latest indexed blocks.
+    pub blocks: Option<i64>,
+    /// Keep the blocks mined within this long.
+    pub max_age: Option<chrono::Duration>,
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.blocks.is_some() || self.max_age.is_some()
+    }
+}
+
+/// The first block that has been kept, once pruning has run.
+pub fn pruned_before(connection: &mut PgConnection) -> Result<Option<i64>> {
+    Ok(indexer_state::table
+        .find(PRUNED_BEFORE_KEY)
+        .select(indexer_state::value)
+        .first(connection)
+        .optional()?)
+}
+
+/// The first block to keep under `policy` as of `now`, or `None` when nothing is indexed or no
+/// limit is set. The latest block is always kept, since the indexer resumes after it.
+pub fn retention_horizon(
+    connection: &mut PgConnection,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Result<Option<i64>> {
+    if !policy.is_enabled() {
+        return Ok(None);
+    }
+    let Some(latest) = blocks::table
+        .select(max(blocks::number))
+        .get_result::<Option<i64>>(connection)?
+    else {
+        return Ok(None);
+    };
+
+    let by_count = policy.blocks.map(|keep| latest - keep.max(1) + 1);
+    let by_age = match policy.max_age {
+        Some(age) => Some(
+            blocks::table
+                .filter(blocks::timestamp.ge(now - age))
+                .select(min(blocks::number))
+                .get_result::<Option<i64>>(connection)?
+                .unwrap_or(latest),
+        ),
+        None => None,
+    };
+    // Blocks are kept while any of the limits still covers them.
+    let horizon = by_count.into_iter().chain(by_age).min();
+    Ok(horizon.map(|horizon| horizon.min(latest)))
+}
+
+/// Deletes the blocks past the retention horizon in batches of `PRUNE_BATCH_BLOCKS`, returning
+/// how many were deleted.
+pub fn prune(
+    connection: &mut PgConnection,
+    policy: &RetentionPolicy,
+    now: DateTime<Utc>,
+) -> Result<usize> {
+    let Some(horizon) = retention_horizon(connection, policy, now)? else {
+        return Ok(0);
+    };
+    let mut pruned = 0;
+    while let Some(first) = blocks::table
+        .filter(blocks::number.lt(horizon))
+        .select(min(blocks::number))
+        .get_result::<Option<i64>>(connection)?
+    {
+        pruned += prune_blocks(connection, first..(first + PRUNE_BATCH_BLOCKS).min(horizon))?;
+    }
+    Ok(pruned)
+}
+
+/// Deletes the blocks numbered `range` with their transactions, logs, token transfers and proxy
+/// upgrades in one transaction. The blocks are added to `daily_stats` and their transfers to
+/// `pruned_balances` first; token balances, accounts and contracts are kept.
+pub fn prune_blocks(connection: &mut PgConnection, range: Range<i64>) -> Result<usize> {
+    let statements = [
+        "DELETE FROM logs WHERE block_number >= $1 AND block_number < $2",
+        "DELETE FROM token_transfers WHERE block_number >= $1 AND block_number < $2",
+        "DELETE FROM transactions WHERE block_number >= $1 AND block_number < $2",
+    ];
+
+    connection.transaction::<_, anyhow::Error, _>(|conn| {
+        summarize_blocks(conn, range.clone())?;
+        for statement in statements {
+            diesel::sql_query(statement)
+                .bind::<BigInt, _>(range.start)
+                .bind::<BigInt, _>(range.end)
+                .execute(conn)?;
+        }
+        delete_blocks(conn, range)
+    })
+}
+
+/// Adds the blocks numbered `range` to `daily_stats` and their transfers to `pruned_balances`,
+/// and unlinks their transactions from `proxy_upgrades` and `contracts`, ahead of those
+/// transactions going away.
+pub fn summarize_blocks(connection: &mut PgConnection, range: Range<i64>) -> Result<()> {
+    let statements = [
+        "INSERT INTO daily_stats (day, blocks, transactions, token_transfers, gas_used) \
+         SELECT (b.timestamp AT TIME ZONE 'UTC')::DATE, COUNT(*), SUM(b.tx_count)::BIGINT, \
+             COALESCE(SUM(t.transfers), 0)::BIGINT, SUM(b.gas_used) \
+         FROM blocks b \
+         LEFT JOIN ( \
+             SELECT block_number, COUNT(*) AS transfers FROM token_transfers \
+             WHERE block_number >= $1 AND block_number < $2 \
+             GROUP BY block_number \
+         ) t ON t.block_number = b.number \
+         WHERE b.number >= $1 AND b.number < $2 \
+         GROUP BY 1 \
+         ON CONFLICT (day) DO UPDATE SET \
+             blocks = daily_stats.blocks + EXCLUDED.blocks, \
+             transactions = daily_stats.transactions + EXCLUDED.transactions, \
+             token_transfers = daily_stats.token_transfers + EXCLUDED.token_transfers, \
+             gas_used = daily_stats.gas_used + EXCLUDED.gas_used",
+        // Existing rows are updated first and the rest inserted, as in
+        // `indexer::credit_token_balance`.
+        "WITH pruned AS ( \
+             SELECT owner_address, token_address, token_id, SUM(delta) AS delta FROM ( \
+                 SELECT to_address AS owner_address, token_address, token_id, value AS delta \
+                 FROM token_transfers WHERE block_number >= $1 AND block_number < $2 \
+                 UNION ALL \
+                 SELECT from_address, token_address, token_id, -value \
+                 FROM token_transfers WHERE block_number >= $1 AND block_number < $2 \
+             ) deltas \
+             GROUP BY owner_address, token_address, token_id \
+         ), updated AS ( \
+             UPDATE pruned_balances p SET amount = p.amount + pruned.delta FROM pruned \
+             WHERE p.owner_address = pruned.owner_address \
+                 AND p.token_address = pruned.token_address \
+                 AND p.token_id IS NOT DISTINCT FROM pruned.token_id \
+             RETURNING p.owner_address, p.token_address, p.token_id \
+         ) \
+         INSERT INTO pruned_balances (owner_address, token_address, token_id, amount) \
+         SELECT owner_address, token_address, token_id, delta FROM pruned \
+         WHERE NOT EXISTS ( \
+             SELECT 1 FROM updated u \
+             WHERE u.owner_address = pruned.owner_address \
+                 AND u.token_address = pruned.token_address \
+                 AND u.token_id IS NOT DISTINCT FROM pruned.token_id \
+         )",
+        // Contracts outlive their deployment transaction.
+        "UPDATE contracts SET creation_tx = NULL WHERE creation_tx IN ( \
+             SELECT hash FROM transactions WHERE block_number >= $1 AND block_number < $2 \
+         )",
+        "DELETE FROM proxy_upgrades WHERE tx_hash IN ( \
+             SELECT hash FROM transactions WHERE block_number >= $1 AND block_number < $2 \
+         )",
+    ];
+
+    for statement in statements {
+        diesel::sql_query(statement)
+            .bind::<BigInt, _>(range.start)
+            .bind::<BigInt, _>(range.end)
+            .execute(connection)?;
+    }
+    Ok(())
+}
+
+/// Deletes the blocks numbered `range`, once their other rows are gone, and records that the
+/// blocks before its end have been pruned.
+pub fn delete_blocks(connection: &mut PgConnection, range: Range<i64>) -> Result<usize> {
+    let pruned = diesel::delete(
+        blocks::table
+            .filter(blocks::number.ge(range.start))
+            .filter(blocks::number.lt(range.end)),
+    )
+    .execute(connection)?;
+    diesel::insert_into(indexer_state::table)
+        .values((
+            indexer_state::key.eq(PRUNED_BEFORE_KEY),
+            indexer_state::value.eq(range.end),
+        ))
+        .on_conflict(indexer_state::key)
+        .do_update()
+        .set(indexer_state::value.eq(sql::<BigInt>(
+            "GREATEST(indexer_state.value, EXCLUDED.value)",
+        )))
+        .execute(connection)?;
+    Ok(pruned)
+}
+
+/// Prunes the blocks past the retention horizon every minute, on a blocking thread.
+pub async fn run_pruner(pool: DbPool, policy: RetentionPolicy) {
+    println!(
+        "🧹 Pruning blocks outside the retention policy {:?}",
+        policy
+    );
+    loop {
+        let pool = pool.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let mut conn = pool.get()?;
+            prune(&mut conn, &policy, Utc::now())
+        })
+        .await;
+        match result {
+            Ok(Ok(0)) => {}
+            Ok(Ok(pruned)) => println!("🧹 Pruned {} blocks", pruned),
+            Ok(Err(e)) => eprintln!("Pruning failed: {}", e),
+            Err(e) => eprintln!("Pruning task failed: {}", e),
+        }
+        sleep(PRUNE_INTERVAL).await;
+    }
+}
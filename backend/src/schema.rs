@@ -2,44 +2,139 @@
 
 diesel::table! {
     accounts (address) {
-        address -> Text,
-        created_at -> Timestamptz,
+        address -> Bytea,
+        first_seen_block -> Nullable<Int8>,
+        first_seen_tx -> Nullable<Bytea>,
+        first_seen_at -> Nullable<Timestamptz>,
+        last_active_block -> Nullable<Int8>,
+        tx_sent_count -> Int8,
+        tx_received_count -> Int8,
+        is_contract -> Bool,
+        code_checked -> Bool,
     }
 }
 
 diesel::table! {
     blocks (hash) {
-        hash -> Text,
-        parent_hash -> Text,
+        hash -> Bytea,
+        parent_hash -> Bytea,
         number -> Int8,
         timestamp -> Timestamptz,
-        miner -> Text,
+        miner -> Bytea,
         gas_used -> Numeric,
         gas_limit -> Numeric,
         base_fee_per_gas -> Nullable<Numeric>,
         extra_data -> Text,
         tx_count -> Int4,
+        transactions_root -> Nullable<Bytea>,
+        receipts_root -> Nullable<Bytea>,
+        logs_bloom -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    bytecodes (hash) {
+        hash -> Bytea,
+        code -> Text,
+        size -> Int4,
+    }
+}
+
+diesel::table! {
+    contract_abis (address) {
+        address -> Bytea,
+        abi -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    contracts (address) {
+        address -> Bytea,
+        bytecode_hash -> Nullable<Bytea>,
+        creation_tx -> Nullable<Bytea>,
+        creation_block -> Nullable<Int8>,
+        proxy_kind -> Nullable<Text>,
+        implementation -> Nullable<Bytea>,
+        beacon -> Nullable<Bytea>,
+        token_name -> Nullable<Text>,
+        token_symbol -> Nullable<Text>,
+        token_decimals -> Nullable<Int2>,
+    }
+}
+
+diesel::table! {
+    daily_stats (day) {
+        day -> Date,
+        blocks -> Int8,
+        transactions -> Int8,
+        token_transfers -> Int8,
+        gas_used -> Numeric,
+    }
+}
+
+diesel::table! {
+    handler_migrations (handler, version) {
+        handler -> Text,
+        version -> Text,
+        applied_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    indexer_state (key) {
+        key -> Text,
+        value -> Int8,
     }
 }
 
 diesel::table! {
     logs (id) {
-        id -> Int4,
-        tx_hash -> Text,
-        address -> Text,
-        topic0 -> Nullable<Text>,
-        topic1 -> Nullable<Text>,
-        topic2 -> Nullable<Text>,
-        topic3 -> Nullable<Text>,
+        id -> Int8,
+        tx_hash -> Bytea,
+        address -> Bytea,
+        topic0 -> Nullable<Bytea>,
+        topic1 -> Nullable<Bytea>,
+        topic2 -> Nullable<Bytea>,
+        topic3 -> Nullable<Bytea>,
         data -> Text,
+        decoded -> Nullable<Jsonb>,
+        block_number -> Int8,
+    }
+}
+
+diesel::table! {
+    non_standard_tokens (token_address) {
+        token_address -> Bytea,
+        reason -> Text,
+        flagged_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    proxy_upgrades (id) {
+        id -> Int8,
+        tx_hash -> Bytea,
+        proxy_address -> Bytea,
+        implementation -> Bytea,
+        previous_implementation -> Nullable<Bytea>,
+    }
+}
+
+diesel::table! {
+    pruned_balances (owner_address, token_address) {
+        owner_address -> Bytea,
+        token_address -> Bytea,
+        token_id -> Nullable<Numeric>,
+        amount -> Numeric,
     }
 }
 
 diesel::table! {
     token_balances (id) {
-        id -> Int4,
-        owner_address -> Text,
-        token_address -> Text,
+        id -> Int8,
+        owner_address -> Bytea,
+        token_address -> Bytea,
         amount -> Numeric,
         token_id -> Nullable<Numeric>,
     }
@@ -47,39 +142,60 @@ diesel::table! {
 
 diesel::table! {
     token_transfers (id) {
-        id -> Int4,
-        tx_hash -> Text,
-        token_address -> Text,
-        from_address -> Text,
-        to_address -> Text,
+        id -> Int8,
+        tx_hash -> Bytea,
+        token_address -> Bytea,
+        from_address -> Bytea,
+        to_address -> Bytea,
         value -> Nullable<Numeric>,
         token_id -> Nullable<Numeric>,
+        block_number -> Int8,
     }
 }
 
 diesel::table! {
     transactions (hash) {
-        hash -> Text,
-        block_hash -> Text,
+        hash -> Bytea,
+        block_hash -> Bytea,
         block_number -> Int8,
-        from_address -> Text,
-        to_address -> Nullable<Text>,
+        from_address -> Bytea,
+        to_address -> Nullable<Bytea>,
         value -> Numeric,
         gas_price -> Nullable<Numeric>,
         gas_used -> Nullable<Numeric>,
         nonce -> Int8,
         position -> Int4,
+        method_id -> Nullable<Text>,
+        tx_type -> Nullable<Int2>,
+        l1_fee -> Nullable<Numeric>,
+        l1_gas_used -> Nullable<Numeric>,
+        l1_gas_price -> Nullable<Numeric>,
+        gas_used_for_l1 -> Nullable<Numeric>,
+        l1_block_number -> Nullable<Int8>,
+        deposit_source_hash -> Nullable<Bytea>,
+        deposit_mint -> Nullable<Numeric>,
     }
 }
 
+diesel::joinable!(contracts -> bytecodes (bytecode_hash));
 diesel::joinable!(logs -> transactions (tx_hash));
+diesel::joinable!(proxy_upgrades -> transactions (tx_hash));
 diesel::joinable!(token_transfers -> transactions (tx_hash));
 diesel::joinable!(transactions -> blocks (block_hash));
 
 diesel::allow_tables_to_appear_in_same_query!(
     accounts,
     blocks,
+    bytecodes,
+    contract_abis,
+    contracts,
+    daily_stats,
+    handler_migrations,
+    indexer_state,
     logs,
+    non_standard_tokens,
+    proxy_upgrades,
+    pruned_balances,
     token_balances,
     token_transfers,
     transactions,
@@ -0,0 +1,98 @@
+use anyhow::{bail, Context, Result};
+use ethers::{types::H256, utils::keccak256};
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+/// The signature list compiled into the binary.
+const BUNDLED_SIGNATURES: &str = include_str!("../data/signatures.txt");
+
+/// A signature database shared with the API handlers.
+pub type SharedSignatureDb = Arc<SignatureDb>;
+
+/// An offline database of well-known event and function signatures.
+///
+/// Lookups return every known candidate, since unrelated signatures can share a 4-byte
+/// selector.
+#[derive(Default)]
+pub struct SignatureDb {
+    events: HashMap<H256, Vec<String>>,
+    functions: HashMap<[u8; 4], Vec<String>>,
+}
+
+impl SignatureDb {
+    /// Returns a database populated with the bundled signatures.
+    pub fn bundled() -> Self {
+        let mut db = Self::default();
+        db.load_str(BUNDLED_SIGNATURES)
+            .expect("Bundled signature list is malformed.");
+        db
+    }
+
+    /// Loads additional signatures from a file in the bundled list format.
+    pub fn load_file(&mut self, path: &Path) -> Result<usize> {
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        self.load_str(&contents)
+            .with_context(|| format!("parsing {}", path.display()))
+    }
+
+    /// Parses a signature list, returning the number of signatures added.
+    ///
+    /// Each non-empty line that isn't a `#` comment is `event <signature>`,
+    /// `function <signature>`, or a hex selector followed by its signature.
+    pub fn load_str(&mut self, contents: &str) -> Result<usize> {
+        let mut added = 0;
+        for (line_no, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((kind, signature)) = line.split_once(char::is_whitespace) else {
+                bail!("line {}: expected `<kind> <signature>`", line_no + 1);
+            };
+            let signature = signature.trim();
+
+            match kind {
+                "event" => self.add_event(H256(keccak256(signature)), signature),
+                "function" => {
+                    let hash = keccak256(signature);
+                    self.add_function([hash[0], hash[1], hash[2], hash[3]], signature);
+                }
+                selector => {
+                    let bytes = hex::decode(selector.trim_start_matches("0x"))
+                        .with_context(|| format!("line {}: invalid selector", line_no + 1))?;
+                    match bytes.len() {
+                        4 => self.add_function([bytes[0], bytes[1], bytes[2], bytes[3]], signature),
+                        32 => self.add_event(H256::from_slice(&bytes), signature),
+                        _ => bail!("line {}: selector must be 4 or 32 bytes", line_no + 1),
+                    }
+                }
+            }
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Returns the candidate signatures for a log's `topic0`.
+    pub fn event_names(&self, topic0: &H256) -> &[String] {
+        self.events.get(topic0).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the candidate signatures for a transaction's 4-byte input selector.
+    pub fn method_names(&self, selector: &[u8; 4]) -> &[String] {
+        self.functions.get(selector).map_or(&[], Vec::as_slice)
+    }
+
+    fn add_event(&mut self, topic0: H256, signature: &str) {
+        let candidates = self.events.entry(topic0).or_default();
+        if !candidates.iter().any(|s| s == signature) {
+            candidates.push(signature.to_string());
+        }
+    }
+
+    fn add_function(&mut self, selector: [u8; 4], signature: &str) {
+        let candidates = self.functions.entry(selector).or_default();
+        if !candidates.iter().any(|s| s == signature) {
+            candidates.push(signature.to_string());
+        }
+    }
+}
@@ -0,0 +1,621 @@
+use super::{
+    balance_points, reject_handlers, sum_balances, sum_daily_stats, token_rank, AccountSpan, Rows,
+    Store, StoredContract, StoredUpgrade,
+};
+use crate::{
+    contracts::DeployedContract,
+    indexer::{self, IndexerContext},
+    models::{self, DbAddress, DbHash, NewLog, NewProxyUpgrade, NewTokenTransfer},
+};
+use anyhow::{anyhow, Result};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use common::{BalancePoint, DailyStats, HistoricalBalance};
+use ethers::types::{Address, Bytes, Transaction as EthersTransaction, TransactionReceipt, H256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// A store keeping everything in memory, for tests and embedders that don't need persistence.
+///
+/// It follows the PostgreSQL store row for row, except that pruning doesn't apply and event
+/// handlers, which write through a `PgConnection`, can't be registered.
+#[derive(Default)]
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+#[derive(Default)]
+struct Tables {
+    blocks: Vec<models::Block>,
+    transactions: Vec<models::Transaction>,
+    logs: Vec<models::Log>,
+    token_transfers: Vec<models::TokenTransfer>,
+    token_balances: Vec<models::TokenBalance>,
+    accounts: BTreeMap<DbAddress, models::Account>,
+    contracts: BTreeMap<DbAddress, models::Contract>,
+    bytecodes: HashMap<DbHash, String>,
+    proxy_upgrades: Vec<models::ProxyUpgrade>,
+    abis: HashMap<DbAddress, serde_json::Value>,
+    /// The last id handed out in each table, like a serial column's sequence.
+    last_ids: HashMap<&'static str, i64>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Tables) -> T) -> Result<T> {
+        let tables = self
+            .tables
+            .lock()
+            .map_err(|_| anyhow!("memory store lock poisoned"))?;
+        Ok(f(&tables))
+    }
+
+    /// Applies `f` to the tables. It must only fail before changing them, so a failed write
+    /// leaves nothing behind like a rolled back database transaction; see `Rows::in_transaction`.
+    fn write(&self, f: impl FnOnce(&mut Tables) -> Result<()>) -> Result<()> {
+        let mut tables = self
+            .tables
+            .lock()
+            .map_err(|_| anyhow!("memory store lock poisoned"))?;
+        f(&mut tables)
+    }
+}
+
+impl Tables {
+    fn next_id(&mut self, table: &'static str) -> i64 {
+        let id = self.last_ids.entry(table).or_default();
+        *id += 1;
+        *id
+    }
+
+    /// The hashes of a block's transactions.
+    fn block_txs(&self, block_hash: DbHash) -> HashSet<DbHash> {
+        self.transactions
+            .iter()
+            .filter(|tx| tx.block_hash == block_hash)
+            .map(|tx| tx.hash)
+            .collect()
+    }
+}
+
+impl Rows for Tables {
+    fn in_transaction(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        f(self)
+    }
+
+    fn insert_block(&mut self, block: &models::Block) -> Result<()> {
+        if !self.blocks.iter().any(|b| b.hash == block.hash) {
+            self.blocks.push(block.clone());
+        }
+        Ok(())
+    }
+
+    fn insert_transaction(&mut self, tx: &models::Transaction) -> Result<()> {
+        if !self
+            .transactions
+            .iter()
+            .any(|stored| stored.hash == tx.hash)
+        {
+            self.transactions.push(tx.clone());
+        }
+        Ok(())
+    }
+
+    fn insert_log(&mut self, log: &NewLog) -> Result<()> {
+        let id = self.next_id("logs");
+        self.logs.push(models::Log {
+            id,
+            tx_hash: log.tx_hash,
+            address: log.address,
+            topic0: log.topic0,
+            topic1: log.topic1,
+            topic2: log.topic2,
+            topic3: log.topic3,
+            data: log.data.to_string(),
+            decoded: log.decoded.cloned(),
+            block_number: log.block_number,
+        });
+        Ok(())
+    }
+
+    fn insert_token_transfer(&mut self, transfer: &NewTokenTransfer) -> Result<()> {
+        let id = self.next_id("token_transfers");
+        self.token_transfers.push(models::TokenTransfer {
+            id,
+            tx_hash: transfer.tx_hash,
+            token_address: transfer.token_address,
+            from_address: transfer.from_address,
+            to_address: transfer.to_address,
+            value: transfer.value.cloned(),
+            token_id: transfer.token_id.cloned(),
+            block_number: transfer.block_number,
+        });
+        Ok(())
+    }
+
+    fn insert_proxy_upgrade(&mut self, upgrade: &NewProxyUpgrade) -> Result<()> {
+        let id = self.next_id("proxy_upgrades");
+        self.proxy_upgrades.push(models::ProxyUpgrade {
+            id,
+            tx_hash: upgrade.tx_hash,
+            proxy_address: upgrade.proxy_address,
+            implementation: upgrade.implementation,
+            previous_implementation: upgrade.previous_implementation,
+        });
+        Ok(())
+    }
+
+    fn add_to_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        delta: &BigDecimal,
+    ) -> Result<bool> {
+        let balance = self.token_balances.iter_mut().find(|b| {
+            b.owner_address == owner && b.token_address == token && b.token_id.as_ref() == token_id
+        });
+        Ok(balance.map(|b| b.amount += delta).is_some())
+    }
+
+    fn insert_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        amount: &BigDecimal,
+    ) -> Result<()> {
+        let id = self.next_id("token_balances");
+        self.token_balances.push(models::TokenBalance {
+            id,
+            owner_address: owner,
+            token_address: token,
+            amount: amount.clone(),
+            token_id: token_id.cloned(),
+        });
+        Ok(())
+    }
+
+    fn account(&mut self, address: DbAddress) -> Result<Option<models::Account>> {
+        Ok(self.accounts.get(&address).cloned())
+    }
+
+    fn save_account(&mut self, account: &models::Account) -> Result<()> {
+        self.accounts.insert(account.address, account.clone());
+        Ok(())
+    }
+
+    fn delete_account(&mut self, address: DbAddress) -> Result<()> {
+        self.accounts.remove(&address);
+        Ok(())
+    }
+
+    fn account_span(&mut self, address: DbAddress) -> Result<Option<AccountSpan>> {
+        let creation_tx = self.contracts.get(&address).and_then(|c| c.creation_tx);
+        let involved = |tx: &&models::Transaction| {
+            tx.from_address == address
+                || tx.to_address == Some(address)
+                || creation_tx == Some(tx.hash)
+        };
+        let Some(first) = self
+            .transactions
+            .iter()
+            .filter(involved)
+            .min_by_key(|tx| (tx.block_number, tx.position))
+        else {
+            return Ok(None);
+        };
+        let Some(first_block) = self.blocks.iter().find(|b| b.hash == first.block_hash) else {
+            return Ok(None);
+        };
+        let last_block = self
+            .transactions
+            .iter()
+            .filter(involved)
+            .map(|tx| tx.block_number)
+            .max()
+            .unwrap_or(first.block_number);
+        Ok(Some(AccountSpan {
+            first_block: first.block_number,
+            first_tx: first.hash,
+            first_at: first_block.timestamp,
+            last_block,
+        }))
+    }
+
+    fn contract(&mut self, address: DbAddress) -> Result<Option<models::Contract>> {
+        Ok(self.contracts.get(&address).cloned())
+    }
+
+    fn beacon_proxies(&mut self, beacon: DbAddress) -> Result<Vec<models::Contract>> {
+        Ok(self
+            .contracts
+            .values()
+            .filter(|c| c.beacon == Some(beacon))
+            .cloned()
+            .collect())
+    }
+
+    fn save_contract(&mut self, contract: &models::Contract) -> Result<()> {
+        self.contracts.insert(contract.address, contract.clone());
+        Ok(())
+    }
+
+    fn insert_bytecode(&mut self, hash: DbHash, code: &Bytes) -> Result<()> {
+        self.bytecodes
+            .entry(hash)
+            .or_insert_with(|| code.to_string());
+        Ok(())
+    }
+
+    fn block_number(&mut self, block_hash: DbHash) -> Result<Option<i64>> {
+        Ok(self
+            .blocks
+            .iter()
+            .find(|b| b.hash == block_hash)
+            .map(|b| b.number))
+    }
+
+    fn block_token_transfers(&mut self, block_hash: DbHash) -> Result<Vec<models::TokenTransfer>> {
+        let block_txs = self.block_txs(block_hash);
+        Ok(self
+            .token_transfers
+            .iter()
+            .rev()
+            .filter(|transfer| block_txs.contains(&transfer.tx_hash))
+            .cloned()
+            .collect())
+    }
+
+    fn block_proxy_upgrades(&mut self, block_hash: DbHash) -> Result<Vec<models::ProxyUpgrade>> {
+        let block_txs = self.block_txs(block_hash);
+        Ok(self
+            .proxy_upgrades
+            .iter()
+            .rev()
+            .filter(|upgrade| block_txs.contains(&upgrade.tx_hash))
+            .cloned()
+            .collect())
+    }
+
+    fn block_participants(
+        &mut self,
+        block_hash: DbHash,
+    ) -> Result<Vec<(DbAddress, Option<DbAddress>)>> {
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|tx| tx.block_hash == block_hash)
+            .map(|tx| (tx.from_address, tx.to_address))
+            .collect())
+    }
+
+    fn block_created_contracts(&mut self, block_hash: DbHash) -> Result<Vec<DbAddress>> {
+        let block_txs = self.block_txs(block_hash);
+        Ok(self
+            .contracts
+            .values()
+            .filter(|c| c.creation_tx.is_some_and(|tx| block_txs.contains(&tx)))
+            .map(|c| c.address)
+            .collect())
+    }
+
+    fn delete_block(&mut self, block_hash: DbHash, _block_number: i64) -> Result<()> {
+        let block_txs = self.block_txs(block_hash);
+        self.logs.retain(|log| !block_txs.contains(&log.tx_hash));
+        self.token_transfers
+            .retain(|transfer| !block_txs.contains(&transfer.tx_hash));
+        self.proxy_upgrades
+            .retain(|upgrade| !block_txs.contains(&upgrade.tx_hash));
+        self.contracts
+            .retain(|_, c| !c.creation_tx.is_some_and(|tx| block_txs.contains(&tx)));
+        self.transactions.retain(|tx| tx.block_hash != block_hash);
+        self.blocks.retain(|b| b.hash != block_hash);
+        Ok(())
+    }
+}
+
+impl Store for MemoryStore {
+    fn latest_block_number(&self) -> Result<Option<i64>> {
+        self.read(|t| t.blocks.iter().map(|b| b.number).max())
+    }
+
+    fn block_hash(&self, number: i64) -> Result<Option<H256>> {
+        self.read(|t| {
+            t.blocks
+                .iter()
+                .find(|b| b.number == number)
+                .map(|b| b.hash.0)
+        })
+    }
+
+    fn known_contracts(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        self.read(|t| {
+            addresses
+                .iter()
+                .copied()
+                .filter(|a| {
+                    t.contracts
+                        .get(&DbAddress(*a))
+                        .is_some_and(|c| c.bytecode_hash.is_some())
+                })
+                .collect()
+        })
+    }
+
+    fn code_checked(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        self.read(|t| {
+            addresses
+                .iter()
+                .copied()
+                .filter(|a| {
+                    t.accounts
+                        .get(&DbAddress(*a))
+                        .is_some_and(|a| a.code_checked)
+                })
+                .collect()
+        })
+    }
+
+    fn write_block(
+        &self,
+        context: &IndexerContext,
+        block: &models::Block,
+        transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+        contracts: &HashMap<Address, DeployedContract>,
+    ) -> Result<()> {
+        reject_handlers(context, "memory")?;
+        self.write(|t| {
+            indexer::write_block(t, context, block, transactions_with_receipts, contracts)
+        })
+    }
+
+    fn rollback_block(&self, context: &IndexerContext, block_hash: H256) -> Result<()> {
+        reject_handlers(context, "memory")?;
+        self.write(|t| indexer::rollback_block(t, context, block_hash))
+    }
+
+    fn recent_blocks(&self, before: Option<i64>, limit: i64) -> Result<Vec<models::Block>> {
+        self.read(|t| {
+            let mut blocks: Vec<models::Block> = t
+                .blocks
+                .iter()
+                .filter(|b| before.is_none_or(|before| b.number < before))
+                .cloned()
+                .collect();
+            blocks.sort_by_key(|b| std::cmp::Reverse(b.number));
+            blocks.truncate(limit.max(0) as usize);
+            blocks
+        })
+    }
+
+    fn block(&self, hash: DbHash) -> Result<Option<models::Block>> {
+        self.read(|t| t.blocks.iter().find(|b| b.hash == hash).cloned())
+    }
+
+    fn block_transactions(
+        &self,
+        block_hash: DbHash,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::Transaction>> {
+        self.read(|t| {
+            let mut transactions: Vec<models::Transaction> = t
+                .transactions
+                .iter()
+                .filter(|tx| tx.block_hash == block_hash)
+                .filter(|tx| after.is_none_or(|after| tx.position > after))
+                .cloned()
+                .collect();
+            transactions.sort_by_key(|tx| tx.position);
+            transactions.truncate(limit.max(0) as usize);
+            transactions
+        })
+    }
+
+    fn transaction(&self, hash: DbHash) -> Result<Option<models::Transaction>> {
+        self.read(|t| t.transactions.iter().find(|tx| tx.hash == hash).cloned())
+    }
+
+    fn transaction_logs(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::Log>> {
+        self.read(|t| {
+            let mut logs: Vec<models::Log> = t
+                .logs
+                .iter()
+                .filter(|log| log.tx_hash == tx_hash)
+                .filter(|log| after.is_none_or(|after| log.id > after))
+                .cloned()
+                .collect();
+            logs.sort_by_key(|log| log.id);
+            logs.truncate(limit.max(0) as usize);
+            logs
+        })
+    }
+
+    fn transaction_token_transfers(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenTransfer>> {
+        self.read(|t| {
+            let mut transfers: Vec<models::TokenTransfer> = t
+                .token_transfers
+                .iter()
+                .filter(|transfer| transfer.tx_hash == tx_hash)
+                .filter(|transfer| after.is_none_or(|after| transfer.id > after))
+                .cloned()
+                .collect();
+            transfers.sort_by_key(|transfer| transfer.id);
+            transfers.truncate(limit.max(0) as usize);
+            transfers
+        })
+    }
+
+    fn account(&self, address: DbAddress) -> Result<Option<models::Account>> {
+        self.read(|t| t.accounts.get(&address).cloned())
+    }
+
+    fn token_balances(
+        &self,
+        owner: DbAddress,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenBalance>> {
+        self.read(|t| {
+            t.token_balances
+                .iter()
+                .filter(|b| b.owner_address == owner && b.id > after.unwrap_or(0))
+                .take(limit.max(0) as usize)
+                .cloned()
+                .collect()
+        })
+    }
+
+    fn balances_at(
+        &self,
+        owner: DbAddress,
+        block_number: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoricalBalance>> {
+        self.read(|t| {
+            sum_balances(
+                owner,
+                t.token_transfers
+                    .iter()
+                    .filter(|transfer| transfer.block_number <= block_number),
+                offset,
+                limit,
+            )
+        })
+    }
+
+    fn balance_series(
+        &self,
+        owner: DbAddress,
+        token: DbAddress,
+        from: i64,
+        to: i64,
+        limit: i64,
+    ) -> Result<Vec<BalancePoint>> {
+        self.read(|t| {
+            balance_points(
+                owner,
+                t.token_transfers.iter().filter(|transfer| {
+                    transfer.token_address == token && transfer.block_number <= to
+                }),
+                from,
+                limit,
+            )
+        })
+    }
+
+    fn daily_stats(&self, first_day: NaiveDate) -> Result<Vec<DailyStats>> {
+        self.read(|t| {
+            sum_daily_stats(
+                t.blocks
+                    .iter()
+                    .filter(|block| block.timestamp.date_naive() >= first_day),
+                |number| {
+                    t.token_transfers
+                        .iter()
+                        .filter(|transfer| transfer.block_number == number)
+                        .count() as i64
+                },
+            )
+        })
+    }
+
+    fn contract(&self, address: DbAddress) -> Result<Option<StoredContract>> {
+        self.read(|t| {
+            let contract = t.contracts.get(&address)?.clone();
+            let bytecode = contract
+                .bytecode_hash
+                .and_then(|hash| t.bytecodes.get(&hash))
+                .map(|code| {
+                    let size = (code.len() - 2) / 2;
+                    (code.clone(), size as i32)
+                });
+            Some((contract, bytecode))
+        })
+    }
+
+    fn search_tokens(&self, prefix: &str, limit: i64) -> Result<Vec<models::Contract>> {
+        let prefix = prefix.to_lowercase();
+        let matches = |text: &Option<String>| {
+            text.as_ref()
+                .is_some_and(|text| text.to_lowercase().starts_with(&prefix))
+        };
+        self.read(|t| {
+            let mut tokens: Vec<models::Contract> = t
+                .contracts
+                .values()
+                .filter(|c| matches(&c.token_symbol) || matches(&c.token_name))
+                .cloned()
+                .collect();
+            tokens.sort_by_cached_key(|c| token_rank(c, &prefix));
+            tokens.truncate(limit.max(0) as usize);
+            tokens
+        })
+    }
+
+    fn proxy_upgrades(
+        &self,
+        proxies: &[DbAddress],
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredUpgrade>> {
+        self.read(|t| {
+            t.proxy_upgrades
+                .iter()
+                .filter(|upgrade| {
+                    proxies.contains(&upgrade.proxy_address) && upgrade.id > after.unwrap_or(0)
+                })
+                .filter_map(|upgrade| {
+                    let tx = t
+                        .transactions
+                        .iter()
+                        .find(|tx| tx.hash == upgrade.tx_hash)?;
+                    Some((
+                        upgrade.id,
+                        upgrade.tx_hash,
+                        tx.block_number,
+                        upgrade.implementation,
+                    ))
+                })
+                .take(limit.max(0) as usize)
+                .collect()
+        })
+    }
+
+    fn pruned_before(&self) -> Result<Option<i64>> {
+        Ok(None)
+    }
+
+    fn save_abi(&self, address: Address, abi: &serde_json::Value) -> Result<()> {
+        self.write(|t| {
+            t.abis.insert(DbAddress(address), abi.clone());
+            Ok(())
+        })
+    }
+
+    fn uploaded_abis(&self) -> Result<Vec<(Address, serde_json::Value)>> {
+        self.read(|t| {
+            t.abis
+                .iter()
+                .map(|(address, abi)| (address.0, abi.clone()))
+                .collect()
+        })
+    }
+}
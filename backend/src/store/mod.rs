@@ -0,0 +1,331 @@
+use crate::{
+    contracts::DeployedContract,
+    indexer::{block_row, FetchedBlock, IndexerContext},
+    models::{self, DbAddress, DbHash},
+};
+use anyhow::{bail, Result};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use common::{BalancePoint, DailyStats, HistoricalBalance};
+use ethers::types::{Address, Transaction as EthersTransaction, TransactionReceipt, H256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+mod memory;
+mod postgres;
+mod replicas;
+mod rows;
+mod sqlite;
+
+pub use memory::MemoryStore;
+pub use postgres::PgStore;
+pub use replicas::{run_lag_monitor, ReplicatedStore};
+pub use rows::{AccountSpan, Rows};
+pub use sqlite::SqliteStore;
+
+/// The store shared by the indexer and the API handlers.
+pub type SharedStore = Arc<dyn Store>;
+
+/// A contract with its bytecode and size, when the code was fetched.
+pub type StoredContract = (models::Contract, Option<(String, i32)>);
+
+/// A proxy upgrade as listed by the API: its ID, the upgrading transaction, its block and the new
+/// implementation.
+pub type StoredUpgrade = (i64, DbHash, i64, DbAddress);
+
+/// Where the indexer writes blocks and the API reads them from.
+///
+/// Writes follow the semantics of `process_block_data` and `rollback_block`: a block is written
+/// or rolled back as a whole, or not at all.
+pub trait Store: Send + Sync {
+    /// The highest indexed block number.
+    fn latest_block_number(&self) -> Result<Option<i64>>;
+
+    /// The hash of the indexed block at a given height.
+    fn block_hash(&self, number: i64) -> Result<Option<H256>>;
+
+    /// The given addresses whose code has already been fetched into `contracts`.
+    fn known_contracts(&self, addresses: &[Address]) -> Result<HashSet<Address>>;
+
+    /// The given addresses whose code the indexer has already looked up, with or without finding
+    /// any.
+    fn code_checked(&self, addresses: &[Address]) -> Result<HashSet<Address>>;
+
+    /// Stores a block with the given transactions, their logs and the contracts they touched.
+    fn write_block(
+        &self,
+        context: &IndexerContext,
+        block: &models::Block,
+        transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+        contracts: &HashMap<Address, DeployedContract>,
+    ) -> Result<()>;
+
+    /// Stores consecutive blocks while backfilling. Blocks are written one by one unless the
+    /// store has a faster path.
+    fn write_block_batch(&self, context: &IndexerContext, batch: &[FetchedBlock]) -> Result<()> {
+        for fetched in batch {
+            self.write_block(
+                context,
+                &block_row(&fetched.block)?,
+                &fetched.transactions_with_receipts,
+                &fetched.contracts,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reverts an indexed block and everything derived from it.
+    fn rollback_block(&self, context: &IndexerContext, block_hash: H256) -> Result<()>;
+
+    /// Builds the indexes deferred while backfilling, once the indexer has caught up.
+    fn build_deferred_indexes(&self) {}
+
+    /// The latest blocks numbered below `before`, newest first.
+    fn recent_blocks(&self, before: Option<i64>, limit: i64) -> Result<Vec<models::Block>>;
+
+    fn block(&self, hash: DbHash) -> Result<Option<models::Block>>;
+
+    /// The stored transactions of a block after position `after`, in block order.
+    fn block_transactions(
+        &self,
+        block_hash: DbHash,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::Transaction>>;
+
+    fn transaction(&self, hash: DbHash) -> Result<Option<models::Transaction>>;
+
+    /// The logs of a transaction with an ID above `after`, in ID order.
+    fn transaction_logs(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::Log>>;
+
+    /// The token transfers of a transaction with an ID above `after`, in ID order.
+    fn transaction_token_transfers(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenTransfer>>;
+
+    fn account(&self, address: DbAddress) -> Result<Option<models::Account>>;
+
+    /// The current token balances of an account with an ID above `after`, in ID order.
+    fn token_balances(
+        &self,
+        owner: DbAddress,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenBalance>>;
+
+    /// An account's non-zero token balances as of a block, summed from its transfers, in token
+    /// order from the `offset`-th on.
+    fn balances_at(
+        &self,
+        owner: DbAddress,
+        block_number: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoricalBalance>>;
+
+    /// An account's balance of a token after each block numbered `from..=to` in which it changed,
+    /// up to `limit` points.
+    fn balance_series(
+        &self,
+        owner: DbAddress,
+        token: DbAddress,
+        from: i64,
+        to: i64,
+        limit: i64,
+    ) -> Result<Vec<BalancePoint>>;
+
+    /// Per-day totals from `first_day` on, including pruned blocks.
+    fn daily_stats(&self, first_day: NaiveDate) -> Result<Vec<DailyStats>>;
+
+    fn contract(&self, address: DbAddress) -> Result<Option<StoredContract>>;
+
+    /// Up to `limit` token contracts whose symbol or name starts with `prefix`, ignoring case.
+    /// Exact symbols come first, then other symbol matches, then name matches, each from the
+    /// shortest matching text, then by address; see `token_rank`.
+    fn search_tokens(&self, prefix: &str, limit: i64) -> Result<Vec<models::Contract>>;
+
+    /// The upgrades applied to the given proxies or beacons with an ID above `after`, oldest
+    /// first.
+    fn proxy_upgrades(
+        &self,
+        proxies: &[DbAddress],
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredUpgrade>>;
+
+    /// The first block that hasn't been pruned, if pruning has run.
+    fn pruned_before(&self) -> Result<Option<i64>>;
+
+    /// Persists an uploaded ABI, replacing the previous one of the address.
+    fn save_abi(&self, address: Address, abi: &serde_json::Value) -> Result<()>;
+
+    /// The ABIs uploaded through the admin API, by address.
+    fn uploaded_abis(&self) -> Result<Vec<(Address, serde_json::Value)>>;
+}
+
+diesel::define_sql_function!(
+    fn lower(text: diesel::sql_types::Nullable<diesel::sql_types::Text>)
+        -> diesel::sql_types::Nullable<diesel::sql_types::Text>
+);
+
+diesel::define_sql_function!(
+    fn length(text: diesel::sql_types::Nullable<diesel::sql_types::Text>)
+        -> diesel::sql_types::Nullable<diesel::sql_types::Integer>
+);
+
+/// A `LIKE` pattern matching lowercase text that starts with `prefix`, escaped with `\\`.
+fn like_prefix(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.to_lowercase().chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Where a token found by `Store::search_tokens` ranks, lowest first: `USDC` above `USDC.e`
+/// above `USD Coin`, for stores that can't sort in SQL.
+fn token_rank(contract: &models::Contract, prefix: &str) -> (bool, bool, usize, DbAddress) {
+    let prefix = prefix.to_lowercase();
+    let symbol = contract.token_symbol.as_deref().map(str::to_lowercase);
+    let symbol_matches = symbol.as_ref().is_some_and(|s| s.starts_with(&prefix));
+    let matched = if symbol_matches {
+        &contract.token_symbol
+    } else {
+        &contract.token_name
+    };
+    (
+        symbol.as_ref() != Some(&prefix),
+        !symbol_matches,
+        matched.as_ref().map_or(0, |text| text.chars().count()),
+        contract.address,
+    )
+}
+
+/// Runs store calls on Tokio's blocking threads, so the queries made by the API handlers and the
+/// indexer loop don't hold up the runtime's workers.
+pub async fn blocking<T, F>(store: &SharedStore, f: F) -> Result<T>
+where
+    F: FnOnce(&dyn Store) -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || f(store.as_ref())).await?
+}
+
+/// Fails when event handlers are registered, since they write through a `PgConnection`.
+fn reject_handlers(context: &IndexerContext, store: &str) -> Result<()> {
+    if !context.handlers.is_empty() {
+        bail!(
+            "Event handlers write through PostgreSQL and can't be used with the {} store",
+            store
+        );
+    }
+    Ok(())
+}
+
+/// Sums the given transfers into an owner's non-zero balances, keeping `limit` from the
+/// `offset`-th on, for stores that can't do it in SQL. See `Store::balances_at`.
+fn sum_balances<'a>(
+    owner: DbAddress,
+    transfers: impl IntoIterator<Item = &'a models::TokenTransfer>,
+    offset: i64,
+    limit: i64,
+) -> Vec<HistoricalBalance> {
+    let mut sums: BTreeMap<(DbAddress, Option<BigDecimal>), BigDecimal> = BTreeMap::new();
+    for transfer in transfers {
+        let Some(value) = &transfer.value else {
+            continue;
+        };
+        let key = (transfer.token_address, transfer.token_id.clone());
+        if transfer.to_address == owner {
+            *sums.entry(key.clone()).or_default() += value;
+        }
+        if transfer.from_address == owner {
+            *sums.entry(key).or_default() -= value;
+        }
+    }
+    sums.into_iter()
+        .filter(|(_, amount)| *amount != BigDecimal::default())
+        .skip(offset.max(0) as usize)
+        .take(limit.max(0) as usize)
+        .map(|((token_address, token_id), amount)| HistoricalBalance {
+            token_address: token_address.to_string(),
+            token_id,
+            amount,
+        })
+        .collect()
+}
+
+/// Runs the balance of an owner through the given transfers of one token, keeping up to `limit`
+/// blocks from `from` on. See `Store::balance_series`.
+fn balance_points<'a>(
+    owner: DbAddress,
+    transfers: impl IntoIterator<Item = &'a models::TokenTransfer>,
+    from: i64,
+    limit: i64,
+) -> Vec<BalancePoint> {
+    let mut deltas: BTreeMap<i64, BigDecimal> = BTreeMap::new();
+    for transfer in transfers {
+        let Some(value) = &transfer.value else {
+            continue;
+        };
+        if transfer.to_address == owner {
+            *deltas.entry(transfer.block_number).or_default() += value;
+        }
+        if transfer.from_address == owner {
+            *deltas.entry(transfer.block_number).or_default() -= value;
+        }
+    }
+    let mut amount = BigDecimal::default();
+    let mut points = Vec::new();
+    for (block_number, delta) in deltas {
+        if points.len() as i64 >= limit {
+            break;
+        }
+        amount += delta;
+        if block_number >= from {
+            points.push(BalancePoint {
+                block_number,
+                amount: amount.clone(),
+            });
+        }
+    }
+    points
+}
+
+/// Adds up blocks into per-day totals, given the number of token transfers of each block.
+fn sum_daily_stats<'a>(
+    blocks: impl IntoIterator<Item = &'a models::Block>,
+    token_transfers: impl Fn(i64) -> i64,
+) -> Vec<DailyStats> {
+    let mut days: BTreeMap<NaiveDate, DailyStats> = BTreeMap::new();
+    for block in blocks {
+        let day = block.timestamp.date_naive();
+        let stats = days.entry(day).or_insert_with(|| DailyStats {
+            day,
+            blocks: 0,
+            transactions: 0,
+            token_transfers: 0,
+            gas_used: BigDecimal::default(),
+        });
+        stats.blocks += 1;
+        stats.transactions += i64::from(block.tx_count);
+        stats.token_transfers += token_transfers(block.number);
+        stats.gas_used += &block.gas_used;
+    }
+    days.into_values().collect()
+}
@@ -0,0 +1,664 @@
+use super::{length, like_prefix, lower, AccountSpan, Rows, Store, StoredContract, StoredUpgrade};
+use crate::{
+    bulk,
+    contracts::DeployedContract,
+    db::DbPool,
+    indexer::{self, DbConnection, FetchedBlock, IndexerContext},
+    indexes,
+    models::{self, DbAddress, DbHash, NewLog, NewProxyUpgrade, NewTokenTransfer},
+    retention,
+    schema::{
+        accounts, blocks, bytecodes, contract_abis, contracts, logs, proxy_upgrades,
+        token_balances, token_transfers, transactions,
+    },
+};
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use common::{BalancePoint, DailyStats, HistoricalBalance};
+use diesel::{
+    dsl::case_when,
+    prelude::*,
+    sql_types::{BigInt, Bytea, Date, Integer, Nullable, Numeric},
+};
+use ethers::types::{Address, Bytes, Transaction as EthersTransaction, TransactionReceipt, H256};
+use std::collections::{HashMap, HashSet};
+
+/// The PostgreSQL store, running the indexer's and API's queries on a connection pool.
+#[derive(Clone)]
+pub struct PgStore {
+    pool: DbPool,
+}
+
+impl PgStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[derive(QueryableByName)]
+struct BalanceRow {
+    #[diesel(sql_type = Bytea)]
+    token_address: DbAddress,
+    #[diesel(sql_type = Nullable<Numeric>)]
+    token_id: Option<BigDecimal>,
+    #[diesel(sql_type = Numeric)]
+    amount: BigDecimal,
+}
+
+#[derive(QueryableByName)]
+struct BalancePointRow {
+    #[diesel(sql_type = BigInt)]
+    block_number: i64,
+    #[diesel(sql_type = Numeric)]
+    amount: BigDecimal,
+}
+
+#[derive(QueryableByName)]
+struct DailyStatsRow {
+    #[diesel(sql_type = Date)]
+    day: NaiveDate,
+    #[diesel(sql_type = BigInt)]
+    blocks: i64,
+    #[diesel(sql_type = BigInt)]
+    transactions: i64,
+    #[diesel(sql_type = BigInt)]
+    token_transfers: i64,
+    #[diesel(sql_type = Numeric)]
+    gas_used: BigDecimal,
+}
+
+impl Rows for DbConnection {
+    fn in_transaction(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        Connection::transaction(self, f)
+    }
+
+    fn handler_connection(&mut self) -> Option<&mut PgConnection> {
+        Some(&mut **self)
+    }
+
+    fn insert_block(&mut self, block: &models::Block) -> Result<()> {
+        diesel::insert_into(blocks::table)
+            .values(block)
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_transaction(&mut self, tx: &models::Transaction) -> Result<()> {
+        diesel::insert_into(transactions::table)
+            .values(tx)
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_log(&mut self, log: &NewLog) -> Result<()> {
+        diesel::insert_into(logs::table).values(log).execute(self)?;
+        Ok(())
+    }
+
+    fn insert_token_transfer(&mut self, transfer: &NewTokenTransfer) -> Result<()> {
+        diesel::insert_into(token_transfers::table)
+            .values(transfer)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_proxy_upgrade(&mut self, upgrade: &NewProxyUpgrade) -> Result<()> {
+        diesel::insert_into(proxy_upgrades::table)
+            .values(upgrade)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn add_to_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        delta: &BigDecimal,
+    ) -> Result<bool> {
+        let updated = diesel::update(
+            token_balances::table
+                .filter(token_balances::owner_address.eq(owner))
+                .filter(token_balances::token_address.eq(token))
+                .filter(token_balances::token_id.is_not_distinct_from(token_id)),
+        )
+        .set(token_balances::amount.eq(token_balances::amount + delta))
+        .execute(self)?;
+        Ok(updated > 0)
+    }
+
+    fn insert_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        amount: &BigDecimal,
+    ) -> Result<()> {
+        diesel::insert_into(token_balances::table)
+            .values((
+                token_balances::owner_address.eq(owner),
+                token_balances::token_address.eq(token),
+                token_balances::amount.eq(amount),
+                token_balances::token_id.eq(token_id),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn account(&mut self, address: DbAddress) -> Result<Option<models::Account>> {
+        Ok(accounts::table.find(address).first(self).optional()?)
+    }
+
+    fn save_account(&mut self, account: &models::Account) -> Result<()> {
+        diesel::insert_into(accounts::table)
+            .values(account)
+            .on_conflict(accounts::address)
+            .do_update()
+            .set(account)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_account(&mut self, address: DbAddress) -> Result<()> {
+        diesel::delete(accounts::table.find(address)).execute(self)?;
+        Ok(())
+    }
+
+    fn account_span(&mut self, address: DbAddress) -> Result<Option<AccountSpan>> {
+        let involved = || {
+            let creation_tx = contracts::table
+                .find(address)
+                .select(contracts::creation_tx);
+            transactions::from_address
+                .eq(address)
+                .or(transactions::to_address.eq(address))
+                .or(transactions::hash.nullable().eq_any(creation_tx))
+        };
+        let first = transactions::table
+            .inner_join(blocks::table)
+            .filter(involved())
+            .order((transactions::block_number, transactions::position))
+            .select((
+                transactions::block_number,
+                transactions::hash,
+                blocks::timestamp,
+            ))
+            .first(self)
+            .optional()?;
+        let last: Option<i64> = transactions::table
+            .filter(involved())
+            .select(diesel::dsl::max(transactions::block_number))
+            .get_result(self)?;
+        Ok(first.map(|(first_block, first_tx, first_at)| AccountSpan {
+            first_block,
+            first_tx,
+            first_at,
+            last_block: last.unwrap_or(first_block),
+        }))
+    }
+
+    fn contract(&mut self, address: DbAddress) -> Result<Option<models::Contract>> {
+        Ok(contracts::table.find(address).first(self).optional()?)
+    }
+
+    fn beacon_proxies(&mut self, beacon: DbAddress) -> Result<Vec<models::Contract>> {
+        Ok(contracts::table
+            .filter(contracts::beacon.eq(beacon))
+            .load(self)?)
+    }
+
+    fn save_contract(&mut self, contract: &models::Contract) -> Result<()> {
+        diesel::insert_into(contracts::table)
+            .values(contract)
+            .on_conflict(contracts::address)
+            .do_update()
+            .set(contract)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_bytecode(&mut self, hash: DbHash, code: &Bytes) -> Result<()> {
+        diesel::insert_into(bytecodes::table)
+            .values((
+                bytecodes::hash.eq(hash),
+                bytecodes::code.eq(code.to_string()),
+                bytecodes::size.eq(code.len() as i32),
+            ))
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn block_number(&mut self, block_hash: DbHash) -> Result<Option<i64>> {
+        Ok(blocks::table
+            .find(block_hash)
+            .select(blocks::number)
+            .first(self)
+            .optional()?)
+    }
+
+    fn block_token_transfers(&mut self, block_hash: DbHash) -> Result<Vec<models::TokenTransfer>> {
+        Ok(token_transfers::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_hash.eq(block_hash))
+            .order(token_transfers::id.desc())
+            .select(models::TokenTransfer::as_select())
+            .load(self)?)
+    }
+
+    fn block_proxy_upgrades(&mut self, block_hash: DbHash) -> Result<Vec<models::ProxyUpgrade>> {
+        Ok(proxy_upgrades::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_hash.eq(block_hash))
+            .order(proxy_upgrades::id.desc())
+            .select(models::ProxyUpgrade::as_select())
+            .load(self)?)
+    }
+
+    fn block_participants(
+        &mut self,
+        block_hash: DbHash,
+    ) -> Result<Vec<(DbAddress, Option<DbAddress>)>> {
+        Ok(transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .select((transactions::from_address, transactions::to_address))
+            .load(self)?)
+    }
+
+    fn block_created_contracts(&mut self, block_hash: DbHash) -> Result<Vec<DbAddress>> {
+        let block_txs = transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .select(transactions::hash.nullable());
+        Ok(contracts::table
+            .filter(contracts::creation_tx.eq_any(block_txs))
+            .select(contracts::address)
+            .load(self)?)
+    }
+
+    fn delete_block(&mut self, block_hash: DbHash, block_number: i64) -> Result<()> {
+        // Foreign keys to a partitioned table must reference a unique key including the
+        // partition key, which the `tx_hash` columns don't, so rows depending on the block's
+        // transactions are deleted here rather than by cascades.
+        let block_txs = || transactions::table.filter(transactions::block_hash.eq(block_hash));
+        diesel::delete(
+            logs::table
+                .filter(logs::block_number.eq(block_number))
+                .filter(logs::tx_hash.eq_any(block_txs().select(transactions::hash))),
+        )
+        .execute(self)?;
+        diesel::delete(
+            token_transfers::table
+                .filter(token_transfers::block_number.eq(block_number))
+                .filter(token_transfers::tx_hash.eq_any(block_txs().select(transactions::hash))),
+        )
+        .execute(self)?;
+        diesel::delete(
+            proxy_upgrades::table
+                .filter(proxy_upgrades::tx_hash.eq_any(block_txs().select(transactions::hash))),
+        )
+        .execute(self)?;
+        diesel::delete(contracts::table.filter(
+            contracts::creation_tx.eq_any(block_txs().select(transactions::hash.nullable())),
+        ))
+        .execute(self)?;
+        diesel::delete(blocks::table.find(block_hash)).execute(self)?;
+        Ok(())
+    }
+}
+
+impl Store for PgStore {
+    fn latest_block_number(&self) -> Result<Option<i64>> {
+        indexer::get_latest_indexed_block(&mut self.pool.get()?)
+    }
+
+    fn block_hash(&self, number: i64) -> Result<Option<H256>> {
+        indexer::get_block_hash(&mut self.pool.get()?, number)
+    }
+
+    fn known_contracts(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        Ok(contracts::table
+            .filter(contracts::address.eq_any(addresses.iter().copied().map(DbAddress)))
+            .filter(contracts::bytecode_hash.is_not_null())
+            .select(contracts::address)
+            .load::<DbAddress>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(Address::from)
+            .collect())
+    }
+
+    fn code_checked(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        Ok(accounts::table
+            .filter(accounts::address.eq_any(addresses.iter().copied().map(DbAddress)))
+            .filter(accounts::code_checked)
+            .select(accounts::address)
+            .load::<DbAddress>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(Address::from)
+            .collect())
+    }
+
+    fn write_block(
+        &self,
+        context: &IndexerContext,
+        block: &models::Block,
+        transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+        contracts: &HashMap<Address, DeployedContract>,
+    ) -> Result<()> {
+        indexer::store_block(
+            &mut self.pool.get()?,
+            context,
+            block,
+            transactions_with_receipts,
+            contracts,
+        )
+    }
+
+    fn write_block_batch(&self, context: &IndexerContext, batch: &[FetchedBlock]) -> Result<()> {
+        bulk::write_block_batch(&mut self.pool.get()?, context, batch)
+    }
+
+    fn rollback_block(&self, context: &IndexerContext, block_hash: H256) -> Result<()> {
+        indexer::rollback_block(&mut self.pool.get()?, context, block_hash)
+    }
+
+    fn build_deferred_indexes(&self) {
+        indexes::build_deferred_indexes(self.pool.clone());
+    }
+
+    fn recent_blocks(&self, before: Option<i64>, limit: i64) -> Result<Vec<models::Block>> {
+        Ok(blocks::table
+            .filter(blocks::number.lt(before.unwrap_or(i64::MAX)))
+            .order(blocks::number.desc())
+            .limit(limit)
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn block(&self, hash: DbHash) -> Result<Option<models::Block>> {
+        Ok(blocks::table
+            .filter(blocks::hash.eq(hash))
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn block_transactions(
+        &self,
+        block_hash: DbHash,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::Transaction>> {
+        Ok(transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .filter(transactions::position.gt(after.unwrap_or(-1)))
+            .order(transactions::position.asc())
+            .limit(limit)
+            .select(models::Transaction::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn transaction(&self, hash: DbHash) -> Result<Option<models::Transaction>> {
+        Ok(transactions::table
+            .filter(transactions::hash.eq(hash))
+            .select(models::Transaction::as_select())
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn transaction_logs(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::Log>> {
+        Ok(logs::table
+            .filter(logs::tx_hash.eq(tx_hash))
+            .filter(logs::id.gt(after.unwrap_or(0)))
+            .order(logs::id.asc())
+            .limit(limit)
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn transaction_token_transfers(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenTransfer>> {
+        Ok(token_transfers::table
+            .filter(token_transfers::tx_hash.eq(tx_hash))
+            .filter(token_transfers::id.gt(after.unwrap_or(0)))
+            .order(token_transfers::id.asc())
+            .limit(limit)
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn account(&self, address: DbAddress) -> Result<Option<models::Account>> {
+        Ok(accounts::table
+            .find(address)
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn token_balances(
+        &self,
+        owner: DbAddress,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenBalance>> {
+        Ok(token_balances::table
+            .filter(token_balances::owner_address.eq(owner))
+            .filter(token_balances::id.gt(after.unwrap_or(0)))
+            .order(token_balances::id.asc())
+            .limit(limit)
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn balances_at(
+        &self,
+        owner: DbAddress,
+        block_number: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoricalBalance>> {
+        // Pruned transfers are summed in `pruned_balances`.
+        let rows = diesel::sql_query(
+            "SELECT token_address, token_id, SUM(delta) AS amount FROM ( \
+                 SELECT token_address, token_id, value AS delta FROM token_transfers \
+                 WHERE to_address = $1 AND block_number <= $2 \
+                 UNION ALL \
+                 SELECT token_address, token_id, -value FROM token_transfers \
+                 WHERE from_address = $1 AND block_number <= $2 \
+                 UNION ALL \
+                 SELECT token_address, token_id, amount FROM pruned_balances \
+                 WHERE owner_address = $1 \
+             ) deltas \
+             GROUP BY token_address, token_id \
+             HAVING SUM(delta) <> 0 \
+             ORDER BY token_address, token_id NULLS FIRST \
+             OFFSET $3 LIMIT $4",
+        )
+        .bind::<Bytea, _>(owner)
+        .bind::<BigInt, _>(block_number)
+        .bind::<BigInt, _>(offset)
+        .bind::<BigInt, _>(limit)
+        .load::<BalanceRow>(&mut self.pool.get()?)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| HistoricalBalance {
+                token_address: row.token_address.to_string(),
+                token_id: row.token_id,
+                amount: row.amount,
+            })
+            .collect())
+    }
+
+    fn balance_series(
+        &self,
+        owner: DbAddress,
+        token: DbAddress,
+        from: i64,
+        to: i64,
+        limit: i64,
+    ) -> Result<Vec<BalancePoint>> {
+        let mut conn = self.pool.get()?;
+        let pruned_before = retention::pruned_before(&mut conn)?;
+
+        // The running total starts from the first transfer, so `from` only trims the output.
+        // Pruned transfers are summed in `pruned_balances`, which counts as the block before the
+        // first kept.
+        let rows = diesel::sql_query(
+            "SELECT block_number, amount FROM ( \
+                 SELECT block_number, SUM(SUM(delta)) OVER (ORDER BY block_number) AS amount FROM ( \
+                     SELECT block_number, value AS delta FROM token_transfers \
+                     WHERE to_address = $1 AND token_address = $2 \
+                     UNION ALL \
+                     SELECT block_number, -value FROM token_transfers \
+                     WHERE from_address = $1 AND token_address = $2 \
+                     UNION ALL \
+                     SELECT $5 - 1, amount FROM pruned_balances \
+                     WHERE owner_address = $1 AND token_address = $2 \
+                 ) deltas \
+                 WHERE block_number <= $4 \
+                 GROUP BY block_number \
+             ) series \
+             WHERE block_number >= $3 \
+             ORDER BY block_number \
+             LIMIT $6",
+        )
+        .bind::<Bytea, _>(owner)
+        .bind::<Bytea, _>(token)
+        .bind::<BigInt, _>(from)
+        .bind::<BigInt, _>(to)
+        .bind::<BigInt, _>(pruned_before.unwrap_or(0))
+        .bind::<BigInt, _>(limit)
+        .load::<BalancePointRow>(&mut conn)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| BalancePoint {
+                block_number: row.block_number,
+                amount: row.amount,
+            })
+            .collect())
+    }
+
+    fn daily_stats(&self, first_day: NaiveDate) -> Result<Vec<DailyStats>> {
+        let rows = diesel::sql_query(
+            "SELECT day, SUM(blocks)::BIGINT AS blocks, SUM(transactions)::BIGINT AS transactions, \
+                 SUM(token_transfers)::BIGINT AS token_transfers, SUM(gas_used) AS gas_used FROM ( \
+                 SELECT day, blocks, transactions, token_transfers, gas_used FROM daily_stats \
+                 WHERE day >= $1 \
+                 UNION ALL \
+                 SELECT (b.timestamp AT TIME ZONE 'UTC')::DATE, 1, b.tx_count, \
+                     (SELECT COUNT(*) FROM token_transfers t WHERE t.block_number = b.number), \
+                     b.gas_used \
+                 FROM blocks b WHERE b.timestamp >= $1::TIMESTAMP AT TIME ZONE 'UTC' \
+             ) stats \
+             GROUP BY day \
+             ORDER BY day",
+        )
+        .bind::<Date, _>(first_day)
+        .load::<DailyStatsRow>(&mut self.pool.get()?)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| DailyStats {
+                day: row.day,
+                blocks: row.blocks,
+                transactions: row.transactions,
+                token_transfers: row.token_transfers,
+                gas_used: row.gas_used,
+            })
+            .collect())
+    }
+
+    fn contract(&self, address: DbAddress) -> Result<Option<StoredContract>> {
+        Ok(contracts::table
+            .left_join(bytecodes::table)
+            .filter(contracts::address.eq(address))
+            .select((
+                models::Contract::as_select(),
+                (bytecodes::code, bytecodes::size).nullable(),
+            ))
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn search_tokens(&self, prefix: &str, limit: i64) -> Result<Vec<models::Contract>> {
+        let pattern = like_prefix(prefix);
+        let symbol_matches = lower(contracts::token_symbol)
+            .like(pattern.clone())
+            .escape('\\');
+        Ok(contracts::table
+            .filter(
+                symbol_matches
+                    .clone()
+                    .or(lower(contracts::token_name).like(pattern).escape('\\')),
+            )
+            .order((
+                case_when(
+                    lower(contracts::token_symbol).eq(prefix.to_lowercase()),
+                    0.into_sql::<Integer>(),
+                )
+                .otherwise(1.into_sql::<Integer>()),
+                case_when(symbol_matches.clone(), 0.into_sql::<Integer>())
+                    .otherwise(1.into_sql::<Integer>()),
+                case_when(symbol_matches, length(contracts::token_symbol))
+                    .otherwise(length(contracts::token_name)),
+                contracts::address.asc(),
+            ))
+            .limit(limit)
+            .select(models::Contract::as_select())
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn proxy_upgrades(
+        &self,
+        proxies: &[DbAddress],
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredUpgrade>> {
+        Ok(proxy_upgrades::table
+            .inner_join(transactions::table)
+            .filter(proxy_upgrades::proxy_address.eq_any(proxies))
+            .filter(proxy_upgrades::id.gt(after.unwrap_or(0)))
+            .order(proxy_upgrades::id.asc())
+            .limit(limit)
+            .select((
+                proxy_upgrades::id,
+                proxy_upgrades::tx_hash,
+                transactions::block_number,
+                proxy_upgrades::implementation,
+            ))
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn pruned_before(&self) -> Result<Option<i64>> {
+        let mut conn = self.pool.get()?;
+        retention::pruned_before(&mut conn)
+    }
+
+    fn save_abi(&self, address: Address, abi: &serde_json::Value) -> Result<()> {
+        diesel::insert_into(contract_abis::table)
+            .values((
+                contract_abis::address.eq(DbAddress(address)),
+                contract_abis::abi.eq(abi),
+            ))
+            .on_conflict(contract_abis::address)
+            .do_update()
+            .set(contract_abis::abi.eq(abi))
+            .execute(&mut self.pool.get()?)?;
+        Ok(())
+    }
+
+    fn uploaded_abis(&self) -> Result<Vec<(Address, serde_json::Value)>> {
+        Ok(contract_abis::table
+            .load::<models::ContractAbi>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(|row| (row.address.0, row.abi))
+            .collect())
+    }
+}
@@ -0,0 +1,271 @@
+use super::{SharedStore, Store, StoredContract, StoredUpgrade};
+use crate::{
+    contracts::DeployedContract,
+    indexer::{FetchedBlock, IndexerContext},
+    models::{self, DbAddress, DbHash},
+};
+use anyhow::Result;
+use chrono::NaiveDate;
+use common::{BalancePoint, DailyStats, HistoricalBalance};
+use ethers::types::{Address, Transaction as EthersTransaction, TransactionReceipt, H256};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicI64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::time::sleep;
+
+/// How often the replicas' lag is measured.
+const LAG_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A read replica with the lag measured by the last check.
+struct Replica {
+    store: SharedStore,
+    /// Blocks behind the primary, `i64::MAX` until checked or while unreachable.
+    lag: AtomicI64,
+}
+
+/// Routes reads to read replicas and writes to the primary.
+///
+/// Reads are spread over the replicas at most `max_lag` blocks behind the primary, and go to the
+/// primary when none is, so the API doesn't serve data older than that. Lag is measured by
+/// `check_lag`, and replicas aren't used before the first check.
+pub struct ReplicatedStore {
+    primary: SharedStore,
+    replicas: Vec<Replica>,
+    max_lag: i64,
+    next: AtomicUsize,
+}
+
+impl ReplicatedStore {
+    pub fn new(primary: SharedStore, replicas: Vec<SharedStore>, max_lag: i64) -> Self {
+        Self {
+            primary,
+            replicas: replicas
+                .into_iter()
+                .map(|store| Replica {
+                    store,
+                    lag: AtomicI64::new(i64::MAX),
+                })
+                .collect(),
+            max_lag,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Measures how many blocks each replica is behind the primary, returning the lags in the
+    /// order of the replicas (`None` for the unreachable ones).
+    pub fn check_lag(&self) -> Result<Vec<Option<i64>>> {
+        let head = self.primary.latest_block_number()?.unwrap_or(-1);
+        Ok(self
+            .replicas
+            .iter()
+            .map(|replica| {
+                let lag = match replica.store.latest_block_number() {
+                    Ok(latest) => Some((head - latest.unwrap_or(-1)).max(0)),
+                    Err(_) => None,
+                };
+                replica
+                    .lag
+                    .store(lag.unwrap_or(i64::MAX), Ordering::Relaxed);
+                lag
+            })
+            .collect())
+    }
+
+    /// The next replica within `max_lag` of the primary, or the primary.
+    fn reader(&self) -> &dyn Store {
+        let count = self.replicas.len();
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        (0..count)
+            .map(|offset| &self.replicas[(start + offset) % count])
+            .find(|replica| replica.lag.load(Ordering::Relaxed) <= self.max_lag)
+            .map_or(self.primary.as_ref(), |replica| replica.store.as_ref())
+    }
+}
+
+/// Measures the replicas' lag every few seconds, on a blocking thread, reporting replicas as
+/// they fall behind or catch up.
+pub async fn run_lag_monitor(store: Arc<ReplicatedStore>) {
+    let mut lagging = vec![false; store.replicas.len()];
+    loop {
+        let checked = store.clone();
+        match tokio::task::spawn_blocking(move || checked.check_lag()).await {
+            Ok(Ok(lags)) => {
+                for (index, lag) in lags.into_iter().enumerate() {
+                    let behind = lag.is_none_or(|lag| lag > store.max_lag);
+                    if behind != lagging[index] {
+                        match lag {
+                            _ if !behind => println!("✅ Read replica {} caught up", index + 1),
+                            Some(lag) => println!(
+                                "⚠️ Read replica {} is {} blocks behind, reading from the primary",
+                                index + 1,
+                                lag
+                            ),
+                            None => println!(
+                                "⚠️ Read replica {} is unreachable, reading from the primary",
+                                index + 1
+                            ),
+                        }
+                        lagging[index] = behind;
+                    }
+                }
+            }
+            Ok(Err(e)) => eprintln!("Replica lag check failed: {}", e),
+            Err(e) => eprintln!("Replica lag check task failed: {}", e),
+        }
+        sleep(LAG_CHECK_INTERVAL).await;
+    }
+}
+
+impl Store for ReplicatedStore {
+    fn latest_block_number(&self) -> Result<Option<i64>> {
+        self.reader().latest_block_number()
+    }
+
+    fn block_hash(&self, number: i64) -> Result<Option<H256>> {
+        self.reader().block_hash(number)
+    }
+
+    fn known_contracts(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        self.primary.known_contracts(addresses)
+    }
+
+    fn code_checked(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        self.primary.code_checked(addresses)
+    }
+
+    fn write_block(
+        &self,
+        context: &IndexerContext,
+        block: &models::Block,
+        transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+        contracts: &HashMap<Address, DeployedContract>,
+    ) -> Result<()> {
+        self.primary
+            .write_block(context, block, transactions_with_receipts, contracts)
+    }
+
+    fn write_block_batch(&self, context: &IndexerContext, batch: &[FetchedBlock]) -> Result<()> {
+        self.primary.write_block_batch(context, batch)
+    }
+
+    fn rollback_block(&self, context: &IndexerContext, block_hash: H256) -> Result<()> {
+        self.primary.rollback_block(context, block_hash)
+    }
+
+    fn build_deferred_indexes(&self) {
+        self.primary.build_deferred_indexes()
+    }
+
+    fn recent_blocks(&self, before: Option<i64>, limit: i64) -> Result<Vec<models::Block>> {
+        self.reader().recent_blocks(before, limit)
+    }
+
+    fn block(&self, hash: DbHash) -> Result<Option<models::Block>> {
+        self.reader().block(hash)
+    }
+
+    fn block_transactions(
+        &self,
+        block_hash: DbHash,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::Transaction>> {
+        self.reader().block_transactions(block_hash, after, limit)
+    }
+
+    fn transaction(&self, hash: DbHash) -> Result<Option<models::Transaction>> {
+        self.reader().transaction(hash)
+    }
+
+    fn transaction_logs(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::Log>> {
+        self.reader().transaction_logs(tx_hash, after, limit)
+    }
+
+    fn transaction_token_transfers(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenTransfer>> {
+        self.reader()
+            .transaction_token_transfers(tx_hash, after, limit)
+    }
+
+    fn account(&self, address: DbAddress) -> Result<Option<models::Account>> {
+        self.reader().account(address)
+    }
+
+    fn token_balances(
+        &self,
+        owner: DbAddress,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenBalance>> {
+        self.reader().token_balances(owner, after, limit)
+    }
+
+    fn balances_at(
+        &self,
+        owner: DbAddress,
+        block_number: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoricalBalance>> {
+        self.reader()
+            .balances_at(owner, block_number, offset, limit)
+    }
+
+    fn balance_series(
+        &self,
+        owner: DbAddress,
+        token: DbAddress,
+        from: i64,
+        to: i64,
+        limit: i64,
+    ) -> Result<Vec<BalancePoint>> {
+        self.reader().balance_series(owner, token, from, to, limit)
+    }
+
+    fn daily_stats(&self, first_day: NaiveDate) -> Result<Vec<DailyStats>> {
+        self.reader().daily_stats(first_day)
+    }
+
+    fn contract(&self, address: DbAddress) -> Result<Option<StoredContract>> {
+        self.reader().contract(address)
+    }
+
+    fn search_tokens(&self, prefix: &str, limit: i64) -> Result<Vec<models::Contract>> {
+        self.reader().search_tokens(prefix, limit)
+    }
+
+    fn proxy_upgrades(
+        &self,
+        proxies: &[DbAddress],
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredUpgrade>> {
+        self.reader().proxy_upgrades(proxies, after, limit)
+    }
+
+    fn pruned_before(&self) -> Result<Option<i64>> {
+        self.reader().pruned_before()
+    }
+
+    fn save_abi(&self, address: Address, abi: &serde_json::Value) -> Result<()> {
+        self.primary.save_abi(address, abi)
+    }
+
+    fn uploaded_abis(&self) -> Result<Vec<(Address, serde_json::Value)>> {
+        self.primary.uploaded_abis()
+    }
+}
@@ -0,0 +1,104 @@
+use crate::models::{self, DbAddress, DbHash, NewLog, NewProxyUpgrade, NewTokenTransfer};
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::PgConnection;
+use ethers::types::Bytes;
+
+/// The row-level reads and writes that `indexer::write_block` and `indexer::rollback_block` are
+/// built from.
+///
+/// Each store implements them over its own tables, so the rules for token balances, proxy
+/// upgrades and account activity are applied the same way by all of them.
+pub trait Rows {
+    /// Runs `f` as one database transaction. Stores without transactions must only fail before
+    /// changing anything.
+    fn in_transaction(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()>;
+
+    /// The connection event handlers write through, for the PostgreSQL store.
+    fn handler_connection(&mut self) -> Option<&mut PgConnection> {
+        None
+    }
+
+    /// Inserts a block unless it's already stored.
+    fn insert_block(&mut self, block: &models::Block) -> Result<()>;
+
+    /// Inserts a transaction unless it's already stored.
+    fn insert_transaction(&mut self, tx: &models::Transaction) -> Result<()>;
+
+    fn insert_log(&mut self, log: &NewLog) -> Result<()>;
+
+    fn insert_token_transfer(&mut self, transfer: &NewTokenTransfer) -> Result<()>;
+
+    fn insert_proxy_upgrade(&mut self, upgrade: &NewProxyUpgrade) -> Result<()>;
+
+    /// Adds `delta` to a balance, returning `false` when the owner has no balance of the token.
+    fn add_to_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        delta: &BigDecimal,
+    ) -> Result<bool>;
+
+    fn insert_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        amount: &BigDecimal,
+    ) -> Result<()>;
+
+    fn account(&mut self, address: DbAddress) -> Result<Option<models::Account>>;
+
+    /// Inserts an account or replaces the stored one.
+    fn save_account(&mut self, account: &models::Account) -> Result<()>;
+
+    fn delete_account(&mut self, address: DbAddress) -> Result<()>;
+
+    /// The first and last of the stored transactions an account sent, received or was created
+    /// by.
+    fn account_span(&mut self, address: DbAddress) -> Result<Option<AccountSpan>>;
+
+    fn contract(&mut self, address: DbAddress) -> Result<Option<models::Contract>>;
+
+    /// The contracts whose beacon is `beacon`.
+    fn beacon_proxies(&mut self, beacon: DbAddress) -> Result<Vec<models::Contract>>;
+
+    /// Inserts a contract or replaces the stored one.
+    fn save_contract(&mut self, contract: &models::Contract) -> Result<()>;
+
+    /// Inserts a bytecode unless it's already stored.
+    fn insert_bytecode(&mut self, hash: DbHash, code: &Bytes) -> Result<()>;
+
+    /// The number of a stored block.
+    fn block_number(&mut self, block_hash: DbHash) -> Result<Option<i64>>;
+
+    /// The token transfers of a block, newest first.
+    fn block_token_transfers(&mut self, block_hash: DbHash) -> Result<Vec<models::TokenTransfer>>;
+
+    /// The proxy upgrades of a block, newest first.
+    fn block_proxy_upgrades(&mut self, block_hash: DbHash) -> Result<Vec<models::ProxyUpgrade>>;
+
+    /// The sender and recipient of each transaction of a block.
+    fn block_participants(
+        &mut self,
+        block_hash: DbHash,
+    ) -> Result<Vec<(DbAddress, Option<DbAddress>)>>;
+
+    /// The contracts created by the transactions of a block.
+    fn block_created_contracts(&mut self, block_hash: DbHash) -> Result<Vec<DbAddress>>;
+
+    /// Deletes a block with its transactions, and their logs, token transfers, proxy upgrades and
+    /// created contracts.
+    fn delete_block(&mut self, block_hash: DbHash, block_number: i64) -> Result<()>;
+}
+
+/// Where an account first and last took part in the stored transactions.
+#[derive(Clone, Copy)]
+pub struct AccountSpan {
+    pub first_block: i64,
+    pub first_tx: DbHash,
+    pub first_at: DateTime<Utc>,
+    pub last_block: i64,
+}
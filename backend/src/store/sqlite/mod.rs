@@ -0,0 +1,869 @@
+use super::{
+    balance_points, length, like_prefix, lower, reject_handlers, sum_balances, sum_daily_stats,
+    AccountSpan, Rows, Store, StoredContract, StoredUpgrade,
+};
+use crate::{
+    contracts::DeployedContract,
+    db::{self, PoolConfig, SqlitePool},
+    indexer::{self, IndexerContext},
+    models::{self, DbAddress, DbHash, NewLog, NewProxyUpgrade, NewTokenTransfer},
+    SQLITE_MIGRATIONS,
+};
+use anyhow::{anyhow, Result};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDate, Utc};
+use common::{BalancePoint, DailyStats, HistoricalBalance};
+use diesel::{
+    dsl::case_when,
+    prelude::*,
+    sql_types::Integer,
+    sqlite::{Sqlite, SqliteConnection},
+};
+use diesel_migrations::MigrationHarness;
+use ethers::types::{Address, Bytes, Transaction as EthersTransaction, TransactionReceipt, H256};
+use schema::{
+    accounts, blocks, bytecodes, contract_abis, contracts, logs, proxy_upgrades, token_balances,
+    token_transfers, transactions,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+mod schema;
+
+/// A store backed by a SQLite database file, for local and single-node deployments.
+///
+/// It follows the PostgreSQL store row for row. SQLite can't hold uint256 amounts in a number, so
+/// they're stored as decimal strings and balances are added up here rather than in SQL. Pruning
+/// doesn't apply and event handlers, which write through a `PgConnection`, can't be registered.
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens the database at `path` (`:memory:` for a temporary one), creating it and running
+    /// pending migrations.
+    pub fn open(path: &str, pool_config: &PoolConfig) -> Result<Self> {
+        let pool = db::establish_sqlite_pool(path, pool_config)?;
+        pool.get()?
+            .run_pending_migrations(SQLITE_MIGRATIONS)
+            .map_err(|e| anyhow!(e))?;
+        Ok(Self { pool })
+    }
+}
+
+fn parse_decimal(value: &str) -> Result<BigDecimal> {
+    Ok(BigDecimal::from_str(value)?)
+}
+
+fn parse_optional_decimal(value: Option<String>) -> Result<Option<BigDecimal>> {
+    value.as_deref().map(parse_decimal).transpose()
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = blocks)]
+#[diesel(check_for_backend(Sqlite))]
+struct BlockRow {
+    hash: DbHash,
+    parent_hash: DbHash,
+    number: i64,
+    timestamp: DateTime<Utc>,
+    miner: DbAddress,
+    gas_used: String,
+    gas_limit: String,
+    base_fee_per_gas: Option<String>,
+    extra_data: String,
+    tx_count: i32,
+    transactions_root: Option<DbHash>,
+    receipts_root: Option<DbHash>,
+    logs_bloom: Option<String>,
+}
+
+impl BlockRow {
+    fn new(block: &models::Block) -> Self {
+        Self {
+            hash: block.hash,
+            parent_hash: block.parent_hash,
+            number: block.number,
+            timestamp: block.timestamp,
+            miner: block.miner,
+            gas_used: block.gas_used.to_string(),
+            gas_limit: block.gas_limit.to_string(),
+            base_fee_per_gas: block.base_fee_per_gas.as_ref().map(ToString::to_string),
+            extra_data: block.extra_data.clone(),
+            tx_count: block.tx_count,
+            transactions_root: block.transactions_root,
+            receipts_root: block.receipts_root,
+            logs_bloom: block.logs_bloom.clone(),
+        }
+    }
+
+    fn into_model(self) -> Result<models::Block> {
+        Ok(models::Block {
+            hash: self.hash,
+            parent_hash: self.parent_hash,
+            number: self.number,
+            timestamp: self.timestamp,
+            miner: self.miner,
+            gas_used: parse_decimal(&self.gas_used)?,
+            gas_limit: parse_decimal(&self.gas_limit)?,
+            base_fee_per_gas: parse_optional_decimal(self.base_fee_per_gas)?,
+            extra_data: self.extra_data,
+            tx_count: self.tx_count,
+            transactions_root: self.transactions_root,
+            receipts_root: self.receipts_root,
+            logs_bloom: self.logs_bloom,
+        })
+    }
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(table_name = transactions)]
+#[diesel(check_for_backend(Sqlite))]
+struct TransactionRow {
+    hash: DbHash,
+    block_hash: DbHash,
+    block_number: i64,
+    from_address: DbAddress,
+    to_address: Option<DbAddress>,
+    value: String,
+    gas_price: Option<String>,
+    gas_used: Option<String>,
+    nonce: i64,
+    position: i32,
+    method_id: Option<String>,
+    tx_type: Option<i16>,
+    l1_fee: Option<String>,
+    l1_gas_used: Option<String>,
+    l1_gas_price: Option<String>,
+    gas_used_for_l1: Option<String>,
+    l1_block_number: Option<i64>,
+    deposit_source_hash: Option<DbHash>,
+    deposit_mint: Option<String>,
+}
+
+impl TransactionRow {
+    fn new(tx: &models::Transaction) -> Self {
+        Self {
+            hash: tx.hash,
+            block_hash: tx.block_hash,
+            block_number: tx.block_number,
+            from_address: tx.from_address,
+            to_address: tx.to_address,
+            value: tx.value.to_string(),
+            gas_price: tx.gas_price.as_ref().map(ToString::to_string),
+            gas_used: tx.gas_used.as_ref().map(ToString::to_string),
+            nonce: tx.nonce,
+            position: tx.position,
+            method_id: tx.method_id.clone(),
+            tx_type: tx.tx_type,
+            l1_fee: tx.l2.l1_fee.as_ref().map(ToString::to_string),
+            l1_gas_used: tx.l2.l1_gas_used.as_ref().map(ToString::to_string),
+            l1_gas_price: tx.l2.l1_gas_price.as_ref().map(ToString::to_string),
+            gas_used_for_l1: tx.l2.gas_used_for_l1.as_ref().map(ToString::to_string),
+            l1_block_number: tx.l2.l1_block_number,
+            deposit_source_hash: tx.l2.deposit_source_hash,
+            deposit_mint: tx.l2.deposit_mint.as_ref().map(ToString::to_string),
+        }
+    }
+
+    fn into_model(self) -> Result<models::Transaction> {
+        Ok(models::Transaction {
+            hash: self.hash,
+            block_hash: self.block_hash,
+            block_number: self.block_number,
+            from_address: self.from_address,
+            to_address: self.to_address,
+            value: parse_decimal(&self.value)?,
+            gas_price: parse_optional_decimal(self.gas_price)?,
+            gas_used: parse_optional_decimal(self.gas_used)?,
+            nonce: self.nonce,
+            position: self.position,
+            method_id: self.method_id,
+            tx_type: self.tx_type,
+            l2: models::L2Fields {
+                l1_fee: parse_optional_decimal(self.l1_fee)?,
+                l1_gas_used: parse_optional_decimal(self.l1_gas_used)?,
+                l1_gas_price: parse_optional_decimal(self.l1_gas_price)?,
+                gas_used_for_l1: parse_optional_decimal(self.gas_used_for_l1)?,
+                l1_block_number: self.l1_block_number,
+                deposit_source_hash: self.deposit_source_hash,
+                deposit_mint: parse_optional_decimal(self.deposit_mint)?,
+            },
+        })
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = logs)]
+#[diesel(check_for_backend(Sqlite))]
+struct LogRow {
+    id: i64,
+    tx_hash: DbHash,
+    address: DbAddress,
+    topic0: Option<DbHash>,
+    topic1: Option<DbHash>,
+    topic2: Option<DbHash>,
+    topic3: Option<DbHash>,
+    data: String,
+    decoded: Option<String>,
+    block_number: i64,
+}
+
+impl LogRow {
+    fn into_model(self) -> Result<models::Log> {
+        Ok(models::Log {
+            id: self.id,
+            tx_hash: self.tx_hash,
+            address: self.address,
+            topic0: self.topic0,
+            topic1: self.topic1,
+            topic2: self.topic2,
+            topic3: self.topic3,
+            data: self.data,
+            decoded: self
+                .decoded
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()?,
+            block_number: self.block_number,
+        })
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = token_transfers)]
+#[diesel(check_for_backend(Sqlite))]
+struct TokenTransferRow {
+    id: i64,
+    tx_hash: DbHash,
+    token_address: DbAddress,
+    from_address: DbAddress,
+    to_address: DbAddress,
+    value: Option<String>,
+    token_id: Option<String>,
+    block_number: i64,
+}
+
+impl TokenTransferRow {
+    fn into_model(self) -> Result<models::TokenTransfer> {
+        Ok(models::TokenTransfer {
+            id: self.id,
+            tx_hash: self.tx_hash,
+            token_address: self.token_address,
+            from_address: self.from_address,
+            to_address: self.to_address,
+            value: parse_optional_decimal(self.value)?,
+            token_id: parse_optional_decimal(self.token_id)?,
+            block_number: self.block_number,
+        })
+    }
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = token_balances)]
+#[diesel(check_for_backend(Sqlite))]
+struct TokenBalanceRow {
+    id: i64,
+    owner_address: DbAddress,
+    token_address: DbAddress,
+    amount: String,
+    token_id: Option<String>,
+}
+
+impl TokenBalanceRow {
+    fn into_model(self) -> Result<models::TokenBalance> {
+        Ok(models::TokenBalance {
+            id: self.id,
+            owner_address: self.owner_address,
+            token_address: self.token_address,
+            amount: parse_decimal(&self.amount)?,
+            token_id: parse_optional_decimal(self.token_id)?,
+        })
+    }
+}
+
+fn into_models<R, T>(rows: Vec<R>, into_model: impl Fn(R) -> Result<T>) -> Result<Vec<T>> {
+    rows.into_iter().map(into_model).collect()
+}
+
+impl Rows for SqliteConnection {
+    fn in_transaction(&mut self, f: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.immediate_transaction(f)
+    }
+
+    fn insert_block(&mut self, block: &models::Block) -> Result<()> {
+        diesel::insert_into(blocks::table)
+            .values(BlockRow::new(block))
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_transaction(&mut self, tx: &models::Transaction) -> Result<()> {
+        diesel::insert_into(transactions::table)
+            .values(TransactionRow::new(tx))
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_log(&mut self, log: &NewLog) -> Result<()> {
+        diesel::insert_into(logs::table)
+            .values((
+                logs::tx_hash.eq(log.tx_hash),
+                logs::address.eq(log.address),
+                logs::topic0.eq(log.topic0),
+                logs::topic1.eq(log.topic1),
+                logs::topic2.eq(log.topic2),
+                logs::topic3.eq(log.topic3),
+                logs::data.eq(log.data),
+                logs::decoded.eq(log.decoded.map(serde_json::to_string).transpose()?),
+                logs::block_number.eq(log.block_number),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_token_transfer(&mut self, transfer: &NewTokenTransfer) -> Result<()> {
+        diesel::insert_into(token_transfers::table)
+            .values((
+                token_transfers::tx_hash.eq(transfer.tx_hash),
+                token_transfers::token_address.eq(transfer.token_address),
+                token_transfers::from_address.eq(transfer.from_address),
+                token_transfers::to_address.eq(transfer.to_address),
+                token_transfers::value.eq(transfer.value.map(ToString::to_string)),
+                token_transfers::token_id.eq(transfer.token_id.map(ToString::to_string)),
+                token_transfers::block_number.eq(transfer.block_number),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_proxy_upgrade(&mut self, upgrade: &NewProxyUpgrade) -> Result<()> {
+        diesel::insert_into(proxy_upgrades::table)
+            .values((
+                proxy_upgrades::tx_hash.eq(upgrade.tx_hash),
+                proxy_upgrades::proxy_address.eq(upgrade.proxy_address),
+                proxy_upgrades::implementation.eq(upgrade.implementation),
+                proxy_upgrades::previous_implementation.eq(upgrade.previous_implementation),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    /// The amounts are decimal strings, so the balance is read and written back within the
+    /// block's transaction rather than updated in SQL. `IS` matches the NULL `token_id` of ERC20
+    /// balances, like `IS NOT DISTINCT FROM` in PostgreSQL.
+    fn add_to_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        delta: &BigDecimal,
+    ) -> Result<bool> {
+        let balance = token_balances::table
+            .filter(token_balances::owner_address.eq(owner))
+            .filter(token_balances::token_address.eq(token))
+            .filter(token_balances::token_id.is(token_id.map(ToString::to_string)))
+            .select((token_balances::id, token_balances::amount))
+            .first::<(i64, String)>(self)
+            .optional()?;
+        let Some((id, amount)) = balance else {
+            return Ok(false);
+        };
+        let amount = parse_decimal(&amount)? + delta;
+        diesel::update(token_balances::table.find(id))
+            .set(token_balances::amount.eq(amount.to_string()))
+            .execute(self)?;
+        Ok(true)
+    }
+
+    fn insert_token_balance(
+        &mut self,
+        owner: DbAddress,
+        token: DbAddress,
+        token_id: Option<&BigDecimal>,
+        amount: &BigDecimal,
+    ) -> Result<()> {
+        diesel::insert_into(token_balances::table)
+            .values((
+                token_balances::owner_address.eq(owner),
+                token_balances::token_address.eq(token),
+                token_balances::amount.eq(amount.to_string()),
+                token_balances::token_id.eq(token_id.map(ToString::to_string)),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn account(&mut self, address: DbAddress) -> Result<Option<models::Account>> {
+        Ok(accounts::table.find(address).first(self).optional()?)
+    }
+
+    fn save_account(&mut self, account: &models::Account) -> Result<()> {
+        diesel::replace_into(accounts::table)
+            .values((
+                accounts::address.eq(account.address),
+                accounts::first_seen_block.eq(account.first_seen_block),
+                accounts::first_seen_tx.eq(account.first_seen_tx),
+                accounts::first_seen_at.eq(account.first_seen_at),
+                accounts::last_active_block.eq(account.last_active_block),
+                accounts::tx_sent_count.eq(account.tx_sent_count),
+                accounts::tx_received_count.eq(account.tx_received_count),
+                accounts::is_contract.eq(account.is_contract),
+                accounts::code_checked.eq(account.code_checked),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn delete_account(&mut self, address: DbAddress) -> Result<()> {
+        diesel::delete(accounts::table.find(address)).execute(self)?;
+        Ok(())
+    }
+
+    fn account_span(&mut self, address: DbAddress) -> Result<Option<AccountSpan>> {
+        let involved = || {
+            let creation_tx = contracts::table
+                .find(address)
+                .select(contracts::creation_tx);
+            transactions::from_address
+                .eq(address)
+                .or(transactions::to_address.eq(address))
+                .or(transactions::hash.nullable().eq_any(creation_tx))
+        };
+        let first = transactions::table
+            .inner_join(blocks::table)
+            .filter(involved())
+            .order((transactions::block_number, transactions::position))
+            .select((
+                transactions::block_number,
+                transactions::hash,
+                blocks::timestamp,
+            ))
+            .first(self)
+            .optional()?;
+        let last: Option<i64> = transactions::table
+            .filter(involved())
+            .select(diesel::dsl::max(transactions::block_number))
+            .get_result(self)?;
+        Ok(first.map(|(first_block, first_tx, first_at)| AccountSpan {
+            first_block,
+            first_tx,
+            first_at,
+            last_block: last.unwrap_or(first_block),
+        }))
+    }
+
+    fn contract(&mut self, address: DbAddress) -> Result<Option<models::Contract>> {
+        Ok(contracts::table.find(address).first(self).optional()?)
+    }
+
+    fn beacon_proxies(&mut self, beacon: DbAddress) -> Result<Vec<models::Contract>> {
+        Ok(contracts::table
+            .filter(contracts::beacon.eq(beacon))
+            .load(self)?)
+    }
+
+    fn save_contract(&mut self, contract: &models::Contract) -> Result<()> {
+        diesel::replace_into(contracts::table)
+            .values((
+                contracts::address.eq(contract.address),
+                contracts::bytecode_hash.eq(contract.bytecode_hash),
+                contracts::creation_tx.eq(contract.creation_tx),
+                contracts::creation_block.eq(contract.creation_block),
+                contracts::proxy_kind.eq(&contract.proxy_kind),
+                contracts::implementation.eq(contract.implementation),
+                contracts::beacon.eq(contract.beacon),
+                contracts::token_name.eq(&contract.token_name),
+                contracts::token_symbol.eq(&contract.token_symbol),
+                contracts::token_decimals.eq(contract.token_decimals),
+            ))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn insert_bytecode(&mut self, hash: DbHash, code: &Bytes) -> Result<()> {
+        diesel::insert_into(bytecodes::table)
+            .values((
+                bytecodes::hash.eq(hash),
+                bytecodes::code.eq(code.to_string()),
+                bytecodes::size.eq(code.len() as i32),
+            ))
+            .on_conflict_do_nothing()
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn block_number(&mut self, block_hash: DbHash) -> Result<Option<i64>> {
+        Ok(blocks::table
+            .find(block_hash)
+            .select(blocks::number)
+            .first(self)
+            .optional()?)
+    }
+
+    fn block_token_transfers(&mut self, block_hash: DbHash) -> Result<Vec<models::TokenTransfer>> {
+        let rows = token_transfers::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_hash.eq(block_hash))
+            .order(token_transfers::id.desc())
+            .select(TokenTransferRow::as_select())
+            .load(self)?;
+        into_models(rows, TokenTransferRow::into_model)
+    }
+
+    fn block_proxy_upgrades(&mut self, block_hash: DbHash) -> Result<Vec<models::ProxyUpgrade>> {
+        Ok(proxy_upgrades::table
+            .inner_join(transactions::table)
+            .filter(transactions::block_hash.eq(block_hash))
+            .order(proxy_upgrades::id.desc())
+            .select(proxy_upgrades::all_columns)
+            .load(self)?)
+    }
+
+    fn block_participants(
+        &mut self,
+        block_hash: DbHash,
+    ) -> Result<Vec<(DbAddress, Option<DbAddress>)>> {
+        Ok(transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .select((transactions::from_address, transactions::to_address))
+            .load(self)?)
+    }
+
+    fn block_created_contracts(&mut self, block_hash: DbHash) -> Result<Vec<DbAddress>> {
+        let block_txs = transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .select(transactions::hash.nullable());
+        Ok(contracts::table
+            .filter(contracts::creation_tx.eq_any(block_txs))
+            .select(contracts::address)
+            .load(self)?)
+    }
+
+    fn delete_block(&mut self, block_hash: DbHash, _block_number: i64) -> Result<()> {
+        // Transactions, and the logs, token transfers, proxy upgrades and contracts depending on
+        // them, are deleted by cascades.
+        diesel::delete(blocks::table.find(block_hash)).execute(self)?;
+        Ok(())
+    }
+}
+
+impl Store for SqliteStore {
+    fn latest_block_number(&self) -> Result<Option<i64>> {
+        Ok(blocks::table
+            .select(diesel::dsl::max(blocks::number))
+            .get_result(&mut self.pool.get()?)?)
+    }
+
+    fn block_hash(&self, number: i64) -> Result<Option<H256>> {
+        Ok(blocks::table
+            .filter(blocks::number.eq(number))
+            .select(blocks::hash)
+            .first::<DbHash>(&mut self.pool.get()?)
+            .optional()?
+            .map(H256::from))
+    }
+
+    fn known_contracts(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        Ok(contracts::table
+            .filter(contracts::address.eq_any(addresses.iter().copied().map(DbAddress)))
+            .filter(contracts::bytecode_hash.is_not_null())
+            .select(contracts::address)
+            .load::<DbAddress>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(Address::from)
+            .collect())
+    }
+
+    fn code_checked(&self, addresses: &[Address]) -> Result<HashSet<Address>> {
+        Ok(accounts::table
+            .filter(accounts::address.eq_any(addresses.iter().copied().map(DbAddress)))
+            .filter(accounts::code_checked)
+            .select(accounts::address)
+            .load::<DbAddress>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(Address::from)
+            .collect())
+    }
+
+    fn write_block(
+        &self,
+        context: &IndexerContext,
+        block: &models::Block,
+        transactions_with_receipts: &[(EthersTransaction, TransactionReceipt)],
+        contracts: &HashMap<Address, DeployedContract>,
+    ) -> Result<()> {
+        reject_handlers(context, "SQLite")?;
+        indexer::write_block(
+            &mut *self.pool.get()?,
+            context,
+            block,
+            transactions_with_receipts,
+            contracts,
+        )
+    }
+
+    fn rollback_block(&self, context: &IndexerContext, block_hash: H256) -> Result<()> {
+        reject_handlers(context, "SQLite")?;
+        indexer::rollback_block(&mut *self.pool.get()?, context, block_hash)
+    }
+
+    fn recent_blocks(&self, before: Option<i64>, limit: i64) -> Result<Vec<models::Block>> {
+        let rows = blocks::table
+            .filter(blocks::number.lt(before.unwrap_or(i64::MAX)))
+            .order(blocks::number.desc())
+            .limit(limit)
+            .select(BlockRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        into_models(rows, BlockRow::into_model)
+    }
+
+    fn block(&self, hash: DbHash) -> Result<Option<models::Block>> {
+        blocks::table
+            .find(hash)
+            .select(BlockRow::as_select())
+            .first(&mut self.pool.get()?)
+            .optional()?
+            .map(BlockRow::into_model)
+            .transpose()
+    }
+
+    fn block_transactions(
+        &self,
+        block_hash: DbHash,
+        after: Option<i32>,
+        limit: i64,
+    ) -> Result<Vec<models::Transaction>> {
+        let rows = transactions::table
+            .filter(transactions::block_hash.eq(block_hash))
+            .filter(transactions::position.gt(after.unwrap_or(-1)))
+            .order(transactions::position.asc())
+            .limit(limit)
+            .select(TransactionRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        into_models(rows, TransactionRow::into_model)
+    }
+
+    fn transaction(&self, hash: DbHash) -> Result<Option<models::Transaction>> {
+        transactions::table
+            .find(hash)
+            .select(TransactionRow::as_select())
+            .first(&mut self.pool.get()?)
+            .optional()?
+            .map(TransactionRow::into_model)
+            .transpose()
+    }
+
+    fn transaction_logs(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::Log>> {
+        let rows = logs::table
+            .filter(logs::tx_hash.eq(tx_hash))
+            .filter(logs::id.gt(after.unwrap_or(0)))
+            .order(logs::id.asc())
+            .limit(limit)
+            .select(LogRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        into_models(rows, LogRow::into_model)
+    }
+
+    fn transaction_token_transfers(
+        &self,
+        tx_hash: DbHash,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenTransfer>> {
+        let rows = token_transfers::table
+            .filter(token_transfers::tx_hash.eq(tx_hash))
+            .filter(token_transfers::id.gt(after.unwrap_or(0)))
+            .order(token_transfers::id.asc())
+            .limit(limit)
+            .select(TokenTransferRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        into_models(rows, TokenTransferRow::into_model)
+    }
+
+    fn account(&self, address: DbAddress) -> Result<Option<models::Account>> {
+        Ok(accounts::table
+            .find(address)
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn token_balances(
+        &self,
+        owner: DbAddress,
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<models::TokenBalance>> {
+        let rows = token_balances::table
+            .filter(token_balances::owner_address.eq(owner))
+            .filter(token_balances::id.gt(after.unwrap_or(0)))
+            .order(token_balances::id.asc())
+            .limit(limit)
+            .select(TokenBalanceRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        into_models(rows, TokenBalanceRow::into_model)
+    }
+
+    fn balances_at(
+        &self,
+        owner: DbAddress,
+        block_number: i64,
+        offset: i64,
+        limit: i64,
+    ) -> Result<Vec<HistoricalBalance>> {
+        let rows = token_transfers::table
+            .filter(
+                token_transfers::to_address
+                    .eq(owner)
+                    .or(token_transfers::from_address.eq(owner)),
+            )
+            .filter(token_transfers::block_number.le(block_number))
+            .select(TokenTransferRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        let transfers = into_models(rows, TokenTransferRow::into_model)?;
+        Ok(sum_balances(owner, &transfers, offset, limit))
+    }
+
+    fn balance_series(
+        &self,
+        owner: DbAddress,
+        token: DbAddress,
+        from: i64,
+        to: i64,
+        limit: i64,
+    ) -> Result<Vec<BalancePoint>> {
+        let rows = token_transfers::table
+            .filter(
+                token_transfers::to_address
+                    .eq(owner)
+                    .or(token_transfers::from_address.eq(owner)),
+            )
+            .filter(token_transfers::token_address.eq(token))
+            .filter(token_transfers::block_number.le(to))
+            .select(TokenTransferRow::as_select())
+            .load(&mut self.pool.get()?)?;
+        let transfers = into_models(rows, TokenTransferRow::into_model)?;
+        Ok(balance_points(owner, &transfers, from, limit))
+    }
+
+    fn daily_stats(&self, first_day: NaiveDate) -> Result<Vec<DailyStats>> {
+        let mut conn = self.pool.get()?;
+        let since = first_day.and_time(chrono::NaiveTime::MIN).and_utc();
+        let rows = blocks::table
+            .filter(blocks::timestamp.ge(since))
+            .select(BlockRow::as_select())
+            .load(&mut conn)?;
+        let blocks = into_models(rows, BlockRow::into_model)?;
+
+        let first_block = blocks.iter().map(|b| b.number).min().unwrap_or(i64::MAX);
+        let transfer_counts: HashMap<i64, i64> = token_transfers::table
+            .filter(token_transfers::block_number.ge(first_block))
+            .group_by(token_transfers::block_number)
+            .select((token_transfers::block_number, diesel::dsl::count_star()))
+            .load(&mut conn)?
+            .into_iter()
+            .collect();
+
+        Ok(sum_daily_stats(&blocks, |number| {
+            transfer_counts.get(&number).copied().unwrap_or(0)
+        }))
+    }
+
+    fn contract(&self, address: DbAddress) -> Result<Option<StoredContract>> {
+        Ok(contracts::table
+            .left_join(bytecodes::table)
+            .filter(contracts::address.eq(address))
+            .select((
+                contracts::all_columns,
+                (bytecodes::code, bytecodes::size).nullable(),
+            ))
+            .first(&mut self.pool.get()?)
+            .optional()?)
+    }
+
+    fn search_tokens(&self, prefix: &str, limit: i64) -> Result<Vec<models::Contract>> {
+        let pattern = like_prefix(prefix);
+        let symbol_matches = lower(contracts::token_symbol)
+            .like(pattern.clone())
+            .escape('\\');
+        Ok(contracts::table
+            .filter(
+                symbol_matches
+                    .clone()
+                    .or(lower(contracts::token_name).like(pattern).escape('\\')),
+            )
+            .order((
+                case_when(
+                    lower(contracts::token_symbol).eq(prefix.to_lowercase()),
+                    0.into_sql::<Integer>(),
+                )
+                .otherwise(1.into_sql::<Integer>()),
+                case_when(symbol_matches.clone(), 0.into_sql::<Integer>())
+                    .otherwise(1.into_sql::<Integer>()),
+                case_when(symbol_matches, length(contracts::token_symbol))
+                    .otherwise(length(contracts::token_name)),
+                contracts::address.asc(),
+            ))
+            .limit(limit)
+            .select(contracts::all_columns)
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn proxy_upgrades(
+        &self,
+        proxies: &[DbAddress],
+        after: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredUpgrade>> {
+        Ok(proxy_upgrades::table
+            .inner_join(transactions::table)
+            .filter(proxy_upgrades::proxy_address.eq_any(proxies))
+            .filter(proxy_upgrades::id.gt(after.unwrap_or(0)))
+            .order(proxy_upgrades::id.asc())
+            .limit(limit)
+            .select((
+                proxy_upgrades::id,
+                proxy_upgrades::tx_hash,
+                transactions::block_number,
+                proxy_upgrades::implementation,
+            ))
+            .load(&mut self.pool.get()?)?)
+    }
+
+    fn pruned_before(&self) -> Result<Option<i64>> {
+        Ok(None)
+    }
+
+    fn save_abi(&self, address: Address, abi: &serde_json::Value) -> Result<()> {
+        let abi = abi.to_string();
+        diesel::insert_into(contract_abis::table)
+            .values((
+                contract_abis::address.eq(DbAddress(address)),
+                contract_abis::abi.eq(&abi),
+            ))
+            .on_conflict(contract_abis::address)
+            .do_update()
+            .set(contract_abis::abi.eq(&abi))
+            .execute(&mut self.pool.get()?)?;
+        Ok(())
+    }
+
+    fn uploaded_abis(&self) -> Result<Vec<(Address, serde_json::Value)>> {
+        contract_abis::table
+            .select((contract_abis::address, contract_abis::abi))
+            .load::<(DbAddress, String)>(&mut self.pool.get()?)?
+            .into_iter()
+            .map(|(address, abi)| Ok((address.0, serde_json::from_str(&abi)?)))
+            .collect()
+    }
+}
@@ -0,0 +1,155 @@
+//! The tables of `migrations_sqlite`, mirroring `crate::schema` with SQLite types.
+
+diesel::table! {
+    accounts (address) {
+        address -> Binary,
+        first_seen_block -> Nullable<BigInt>,
+        first_seen_tx -> Nullable<Binary>,
+        first_seen_at -> Nullable<TimestamptzSqlite>,
+        last_active_block -> Nullable<BigInt>,
+        tx_sent_count -> BigInt,
+        tx_received_count -> BigInt,
+        is_contract -> Bool,
+        code_checked -> Bool,
+    }
+}
+
+diesel::table! {
+    blocks (hash) {
+        hash -> Binary,
+        parent_hash -> Binary,
+        number -> BigInt,
+        timestamp -> TimestamptzSqlite,
+        miner -> Binary,
+        gas_used -> Text,
+        gas_limit -> Text,
+        base_fee_per_gas -> Nullable<Text>,
+        extra_data -> Text,
+        tx_count -> Integer,
+        transactions_root -> Nullable<Binary>,
+        receipts_root -> Nullable<Binary>,
+        logs_bloom -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    bytecodes (hash) {
+        hash -> Binary,
+        code -> Text,
+        size -> Integer,
+    }
+}
+
+diesel::table! {
+    contract_abis (address) {
+        address -> Binary,
+        abi -> Text,
+        created_at -> TimestamptzSqlite,
+    }
+}
+
+diesel::table! {
+    contracts (address) {
+        address -> Binary,
+        bytecode_hash -> Nullable<Binary>,
+        creation_tx -> Nullable<Binary>,
+        creation_block -> Nullable<BigInt>,
+        proxy_kind -> Nullable<Text>,
+        implementation -> Nullable<Binary>,
+        beacon -> Nullable<Binary>,
+        token_name -> Nullable<Text>,
+        token_symbol -> Nullable<Text>,
+        token_decimals -> Nullable<SmallInt>,
+    }
+}
+
+diesel::table! {
+    logs (id) {
+        id -> BigInt,
+        tx_hash -> Binary,
+        address -> Binary,
+        topic0 -> Nullable<Binary>,
+        topic1 -> Nullable<Binary>,
+        topic2 -> Nullable<Binary>,
+        topic3 -> Nullable<Binary>,
+        data -> Text,
+        decoded -> Nullable<Text>,
+        block_number -> BigInt,
+    }
+}
+
+diesel::table! {
+    proxy_upgrades (id) {
+        id -> BigInt,
+        tx_hash -> Binary,
+        proxy_address -> Binary,
+        implementation -> Binary,
+        previous_implementation -> Nullable<Binary>,
+    }
+}
+
+diesel::table! {
+    token_balances (id) {
+        id -> BigInt,
+        owner_address -> Binary,
+        token_address -> Binary,
+        amount -> Text,
+        token_id -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    token_transfers (id) {
+        id -> BigInt,
+        tx_hash -> Binary,
+        token_address -> Binary,
+        from_address -> Binary,
+        to_address -> Binary,
+        value -> Nullable<Text>,
+        token_id -> Nullable<Text>,
+        block_number -> BigInt,
+    }
+}
+
+diesel::table! {
+    transactions (hash) {
+        hash -> Binary,
+        block_hash -> Binary,
+        block_number -> BigInt,
+        from_address -> Binary,
+        to_address -> Nullable<Binary>,
+        value -> Text,
+        gas_price -> Nullable<Text>,
+        gas_used -> Nullable<Text>,
+        nonce -> BigInt,
+        position -> Integer,
+        method_id -> Nullable<Text>,
+        tx_type -> Nullable<SmallInt>,
+        l1_fee -> Nullable<Text>,
+        l1_gas_used -> Nullable<Text>,
+        l1_gas_price -> Nullable<Text>,
+        gas_used_for_l1 -> Nullable<Text>,
+        l1_block_number -> Nullable<BigInt>,
+        deposit_source_hash -> Nullable<Binary>,
+        deposit_mint -> Nullable<Text>,
+    }
+}
+
+diesel::joinable!(contracts -> bytecodes (bytecode_hash));
+diesel::joinable!(logs -> transactions (tx_hash));
+diesel::joinable!(proxy_upgrades -> transactions (tx_hash));
+diesel::joinable!(token_transfers -> transactions (tx_hash));
+diesel::joinable!(transactions -> blocks (block_hash));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    accounts,
+    blocks,
+    bytecodes,
+    contract_abis,
+    contracts,
+    logs,
+    proxy_upgrades,
+    token_balances,
+    token_transfers,
+    transactions,
+);
@@ -0,0 +1,133 @@
+use super::environment::{create_mock_block, create_mock_transaction, mock_transfer_log, TestDb};
+use crate::{
+    abi::{parse_abi, AbiRegistry},
+    indexer::{process_log, IndexerContext},
+    models,
+    schema::logs,
+};
+use diesel::prelude::*;
+use ethers::types::{Address, Bytes, H256};
+use std::fs;
+
+const ERC20_ABI: &str = r#"[{
+    "anonymous": false,
+    "type": "event",
+    "name": "Transfer",
+    "inputs": [
+        {"indexed": true, "name": "from", "type": "address"},
+        {"indexed": true, "name": "to", "type": "address"},
+        {"indexed": false, "name": "value", "type": "uint256"}
+    ]
+}]"#;
+
+#[test]
+fn test_decode_log_with_registered_abi() {
+    let token: Address = "0xcccccccccccccccccccccccccccccccccccccccc"
+        .parse()
+        .unwrap();
+    let from: Address = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        .parse()
+        .unwrap();
+    let to: Address = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+        .parse()
+        .unwrap();
+
+    let mut registry = AbiRegistry::default();
+    registry.register(
+        Some(token),
+        parse_abi(&serde_json::from_str(ERC20_ABI).unwrap()).unwrap(),
+    );
+
+    let log = mock_transfer_log(token, from, to, 500);
+    let decoded = registry
+        .decode_log(log.address, &log.topics, &log.data)
+        .unwrap();
+
+    assert_eq!(decoded.name, "Transfer");
+    assert_eq!(decoded.signature, "Transfer(address,address,uint256)");
+    assert_eq!(decoded.params.len(), 3);
+    assert_eq!(decoded.params[0].name, "from");
+    assert_eq!(decoded.params[0].value, format!("{:#x}", from));
+    assert!(decoded.params[0].indexed);
+    assert_eq!(decoded.params[2].name, "value");
+    assert_eq!(decoded.params[2].kind, "uint256");
+    assert_eq!(decoded.params[2].value, "500");
+
+    // Known signatures are decoded for any emitting address.
+    let other: Address = "0xdddddddddddddddddddddddddddddddddddddddd"
+        .parse()
+        .unwrap();
+    let log = mock_transfer_log(other, from, to, 7);
+    let decoded = registry
+        .decode_log(log.address, &log.topics, &log.data)
+        .unwrap();
+    assert_eq!(decoded.params[2].value, "7");
+
+    // Unknown events are left undecoded.
+    assert!(registry
+        .decode_log(token, &[H256::repeat_byte(0x11)], &Bytes::default())
+        .is_none());
+}
+
+#[test]
+fn test_load_abi_dir() {
+    let dir = std::env::temp_dir().join(format!("abis_{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("0xcccccccccccccccccccccccccccccccccccccccc.json"),
+        format!(r#"{{"contractName": "Token", "abi": {}}}"#, ERC20_ABI),
+    )
+    .unwrap();
+    fs::write(dir.join("README.md"), "not an abi").unwrap();
+
+    let mut registry = AbiRegistry::default();
+    let loaded = registry.load_dir(&dir).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(loaded, 1);
+    let log = mock_transfer_log(
+        "0xcccccccccccccccccccccccccccccccccccccccc"
+            .parse()
+            .unwrap(),
+        Address::zero(),
+        Address::repeat_byte(0xbb),
+        1,
+    );
+    assert!(registry
+        .decode_log(log.address, &log.topics, &log.data)
+        .is_some());
+}
+
+#[test]
+fn test_process_log_stores_decoded_params() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let mock_block = create_mock_block(&mut conn, 1);
+    let mock_tx = create_mock_transaction(&mut conn, &mock_block, 0);
+
+    let context = IndexerContext::default();
+    context.abi_registry.write().unwrap().register(
+        None,
+        parse_abi(&serde_json::from_str(ERC20_ABI).unwrap()).unwrap(),
+    );
+
+    let log = mock_transfer_log(
+        Address::repeat_byte(0xcc),
+        Address::repeat_byte(0xaa),
+        Address::repeat_byte(0xbb),
+        42,
+    );
+    process_log(
+        &mut conn,
+        &context,
+        mock_tx.hash.0,
+        mock_tx.block_number,
+        &log,
+    )
+    .unwrap();
+
+    let inserted = logs::table.first::<models::Log>(&mut conn).unwrap();
+    let decoded: common::DecodedLog = serde_json::from_value(inserted.decoded.unwrap()).unwrap();
+    assert_eq!(decoded.name, "Transfer");
+    assert_eq!(decoded.params[2].value, "42");
+}
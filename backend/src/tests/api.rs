@@ -3,19 +3,37 @@ use super::environment::{
     create_mock_transaction, TestDb,
 };
 use crate::{
+    abi::SharedAbiRegistry,
     api::{
-        get_account_details, get_block_details, get_recent_blocks, get_transaction_details,
-        get_transactions_for_block,
+        get_account_balances, get_account_details, get_account_tokens, get_balance_series,
+        get_block_details, get_recent_blocks, get_transaction_details, get_transactions_for_block,
+        parse_address, parse_block_id, parse_hash, search, upload_abi, ApiError, AppState,
+        BalanceSeriesQuery, BalancesQuery, BlockId, PageQuery, SearchQuery,
     },
-    schema::token_balances,
+    contracts::{store_contract, DeployedContract, TokenMetadata},
+    models::{DbAddress, DbHash},
+    schema::{contract_abis, token_balances},
+    signatures::SignatureDb,
 };
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
 };
 use bigdecimal::BigDecimal;
+use common::{ErrorResponse, SearchResult};
 use diesel::prelude::*;
-use std::str::FromStr;
+use ethers::{
+    types::{Address, Bytes, H256},
+    utils::to_checksum,
+};
+use std::{str::FromStr, sync::Arc};
+
+/// A well-formed hash that no mock uses.
+fn missing_hash() -> String {
+    format!("{:#x}", H256::repeat_byte(0xfe))
+}
 
 #[tokio::test]
 async fn test_get_recent_blocks() {
@@ -23,23 +41,27 @@ async fn test_get_recent_blocks() {
     let mut conn = db.pool.get().unwrap();
 
     // Case 1: Empty database should return an empty list.
-    let result = get_recent_blocks(State(db.pool.clone())).await;
-    assert_eq!(result.unwrap().0.len(), 0);
+    let result = get_recent_blocks(State(db.store()), Query(PageQuery::default())).await;
+    let page = result.unwrap().0;
+    assert_eq!(page.items.len(), 0);
+    assert_eq!(page.next_cursor, None);
 
     // Case 2: Database with a few blocks.
     create_mock_block(&mut conn, 101);
     create_mock_block(&mut conn, 102);
     create_mock_block(&mut conn, 103);
 
-    let result = get_recent_blocks(State(db.pool.clone())).await.unwrap();
-    let blocks = result.0;
+    let result = get_recent_blocks(State(db.store()), Query(PageQuery::default()))
+        .await
+        .unwrap();
+    let blocks = result.0.items;
 
     assert_eq!(blocks.len(), 3);
     // Blocks should be ordered by number descending.
     assert_eq!(blocks[0].number, 103);
     assert_eq!(blocks[1].number, 102);
     assert_eq!(blocks[2].number, 101);
-    assert_eq!(blocks[0].hash, format!("0x{:064x}", 103));
+    assert_eq!(blocks[0].hash, format!("{:#x}", H256::from_low_u64_be(103)));
     assert_eq!(
         blocks[0].miner,
         "0x1111111111111111111111111111111111111111"
@@ -52,21 +74,21 @@ async fn test_get_block_details() {
     let mut conn = db.pool.get().unwrap();
 
     // Case 1: Block not found.
-    let result = get_block_details(
-        State(db.pool.clone()),
-        Path("0xnonexistenthash".to_string()),
-    )
-    .await;
-    assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    let result = get_block_details(State(db.store()), Path(missing_hash())).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+
+    // Case 2: Malformed hash.
+    let result = get_block_details(State(db.store()), Path("0xnonexistenthash".to_string())).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
 
-    // Case 2: Block is found.
+    // Case 3: Block is found.
     let mock_block = create_mock_block(&mut conn, 100);
-    let result = get_block_details(State(db.pool.clone()), Path(mock_block.hash.clone()))
+    let result = get_block_details(State(db.store()), Path(mock_block.hash.to_string()))
         .await
         .unwrap();
     let block_detail = result.0;
 
-    assert_eq!(block_detail.hash, mock_block.hash);
+    assert_eq!(block_detail.hash, mock_block.hash.to_string());
     assert_eq!(block_detail.number, 100);
     assert_eq!(
         block_detail.miner,
@@ -75,6 +97,68 @@ async fn test_get_block_details() {
     assert_eq!(block_detail.tx_count, 1);
 }
 
+#[test]
+fn test_parse_block_id() {
+    let hash = H256::repeat_byte(0xab);
+    assert_eq!(parse_block_id("12345"), Ok(BlockId::Number(12345)));
+    assert_eq!(parse_block_id("0x3039"), Ok(BlockId::Number(12345)));
+    assert_eq!(parse_block_id("latest"), Ok(BlockId::Latest));
+    assert_eq!(parse_block_id("Finalized"), Ok(BlockId::Finalized));
+    assert_eq!(
+        parse_block_id(&format!("{:#x}", hash)),
+        Ok(BlockId::Hash(DbHash(hash)))
+    );
+
+    for id in ["", "-1", "pending", "0x", "0xzz", "99999999999999999999"] {
+        let Err(ApiError::InvalidInput { kind, .. }) = parse_block_id(id) else {
+            panic!("{:?} should be rejected", id);
+        };
+        assert_eq!(kind, "invalid_block_id");
+    }
+}
+
+#[tokio::test]
+async fn test_get_block_by_number_and_tag() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let block = |id: &str| get_block_details(State(db.store()), Path(id.to_string()));
+
+    // Case 1: Nothing is indexed yet.
+    assert_eq!(
+        block("latest").await.unwrap_err().status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Case 2: Blocks 4 to 69 are missing, as in log mode.
+    for number in [1, 2, 3, 70] {
+        create_mock_block(&mut conn, number);
+    }
+    let latest = create_mock_block(&mut conn, 71);
+    create_mock_transaction(&mut conn, &latest, 0);
+
+    assert_eq!(block("2").await.unwrap().0.number, 2);
+    assert_eq!(block("0x46").await.unwrap().0.number, 70);
+    assert_eq!(block("latest").await.unwrap().0.number, 71);
+    // Block 7 would be finalized, so the highest stored block below it is.
+    assert_eq!(block("finalized").await.unwrap().0.number, 3);
+    assert_eq!(
+        block("50").await.unwrap_err().status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let transactions = get_transactions_for_block(
+        State(db.store()),
+        State(Arc::new(SignatureDb::default())),
+        Path("71".to_string()),
+        Query(PageQuery::default()),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert_eq!(transactions.items.len(), 1);
+    assert_eq!(transactions.items[0].block_number, 71);
+}
+
 #[tokio::test]
 async fn test_get_transactions_for_block() {
     let db = TestDb::new();
@@ -82,30 +166,41 @@ async fn test_get_transactions_for_block() {
 
     // Case 1: Block hash does not exist, should return empty list.
     let result = get_transactions_for_block(
-        State(db.pool.clone()),
-        Path("0xnonexistenthash".to_string()),
+        State(db.store()),
+        State(Arc::new(SignatureDb::bundled())),
+        Path(missing_hash()),
+        Query(PageQuery::default()),
     )
     .await
     .unwrap();
-    assert_eq!(result.0.len(), 0);
+    assert_eq!(result.0.items.len(), 0);
 
     // Case 2: Block with multiple transactions.
     let mock_block = create_mock_block(&mut conn, 200);
     let tx1 = create_mock_transaction(&mut conn, &mock_block, 0);
     let tx2 = create_mock_transaction(&mut conn, &mock_block, 1);
 
-    let result = get_transactions_for_block(State(db.pool.clone()), Path(mock_block.hash.clone()))
-        .await
-        .unwrap();
-    let transactions = result.0;
+    let result = get_transactions_for_block(
+        State(db.store()),
+        State(Arc::new(SignatureDb::bundled())),
+        Path(mock_block.hash.to_string()),
+        Query(PageQuery::default()),
+    )
+    .await
+    .unwrap();
+    let transactions = result.0.items;
 
     assert_eq!(transactions.len(), 2);
-    assert_eq!(transactions[0].hash, tx1.hash);
-    assert_eq!(transactions[1].hash, tx2.hash);
+    assert_eq!(transactions[0].hash, tx1.hash.to_string());
+    assert_eq!(transactions[1].hash, tx2.hash.to_string());
     assert_eq!(
         transactions[0].from_address,
         "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
     );
+    assert_eq!(
+        transactions[0].method_name,
+        vec!["transfer(address,uint256)".to_string()]
+    );
 }
 
 #[tokio::test]
@@ -114,9 +209,14 @@ async fn test_get_transaction_details() {
     let mut conn = db.pool.get().unwrap();
 
     // Case 1: Transaction not found.
-    let result =
-        get_transaction_details(State(db.pool.clone()), Path("0xnonexistenttx".to_string())).await;
-    assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    let result = get_transaction_details(
+        State(db.store()),
+        State(SharedAbiRegistry::default()),
+        State(Arc::new(SignatureDb::bundled())),
+        Path(missing_hash()),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
 
     // Case 2: Transaction with logs and transfers is found.
     let mock_block = create_mock_block(&mut conn, 300);
@@ -124,32 +224,38 @@ async fn test_get_transaction_details() {
     create_mock_log(&mut conn, &mock_tx);
     create_mock_token_transfer(&mut conn, &mock_tx);
 
-    let result = get_transaction_details(State(db.pool.clone()), Path(mock_tx.hash.clone()))
-        .await
-        .unwrap();
+    let result = get_transaction_details(
+        State(db.store()),
+        State(SharedAbiRegistry::default()),
+        State(Arc::new(SignatureDb::bundled())),
+        Path(mock_tx.hash.to_string()),
+    )
+    .await
+    .unwrap();
     let tx_detail = result.0;
 
-    assert_eq!(tx_detail.transaction.hash, mock_tx.hash);
-    assert_eq!(tx_detail.logs.len(), 1);
-    assert_eq!(tx_detail.token_transfers.len(), 1);
+    assert_eq!(tx_detail.transaction.hash, mock_tx.hash.to_string());
+    assert_eq!(tx_detail.logs.items.len(), 1);
+    assert_eq!(tx_detail.token_transfers.items.len(), 1);
 
     // Assert log details
     assert_eq!(
-        tx_detail.logs[0].address,
+        tx_detail.logs.items[0].address,
         "0xcccccccccccccccccccccccccccccccccccccccc"
     );
     assert_eq!(
-        tx_detail.logs[0].topic0,
+        tx_detail.logs.items[0].topic0,
         Some("0xdddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd".to_string())
     );
+    assert!(tx_detail.logs.items[0].event_name.is_empty());
 
     // Assert token transfer details
     assert_eq!(
-        tx_detail.token_transfers[0].token_address,
+        tx_detail.token_transfers.items[0].token_address,
         "0xcccccccccccccccccccccccccccccccccccccccc"
     );
     assert_eq!(
-        tx_detail.token_transfers[0].value,
+        tx_detail.token_transfers.items[0].value,
         Some(BigDecimal::from(500))
     );
 }
@@ -158,21 +264,22 @@ async fn test_get_transaction_details() {
 async fn test_get_account_details() {
     let db = TestDb::new();
     let mut conn = db.pool.get().unwrap();
-    let owner_address = "0xowneraddress0000000000000000000000000000";
+    let owner_address = "0x0eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee";
+    let owner = DbAddress(owner_address.parse().unwrap());
 
     // Case 1: Account not found.
-    let result = get_account_details(State(db.pool.clone()), Path(owner_address.to_string())).await;
-    assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+    let result = get_account_details(State(db.store()), Path(owner_address.to_string())).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
 
     // Case 2: Account exists with various token balances.
-    create_mock_account(&mut conn, owner_address);
+    create_mock_account(&mut conn, owner.0);
 
     // Insert an ERC20-like token balance.
     let token1_addr = "0x1111111111111111111111111111111111111111";
     diesel::insert_into(token_balances::table)
         .values((
-            token_balances::owner_address.eq(owner_address),
-            token_balances::token_address.eq(token1_addr),
+            token_balances::owner_address.eq(owner),
+            token_balances::token_address.eq(DbAddress(Address::repeat_byte(0x11))),
             token_balances::amount.eq(BigDecimal::from_str("123.45").unwrap()),
         ))
         .execute(&mut conn)
@@ -182,25 +289,30 @@ async fn test_get_account_details() {
     let token2_addr = "0x2222222222222222222222222222222222222222";
     diesel::insert_into(token_balances::table)
         .values((
-            token_balances::owner_address.eq(owner_address),
-            token_balances::token_address.eq(token2_addr),
+            token_balances::owner_address.eq(owner),
+            token_balances::token_address.eq(DbAddress(Address::repeat_byte(0x22))),
             token_balances::amount.eq(BigDecimal::from(1)),
             token_balances::token_id.eq(Some(BigDecimal::from(999))),
         ))
         .execute(&mut conn)
         .unwrap();
 
-    let result = get_account_details(State(db.pool.clone()), Path(owner_address.to_string()))
+    let result = get_account_details(State(db.store()), Path(owner_address.to_string()))
         .await
         .unwrap();
     let account_detail = result.0;
 
     assert_eq!(account_detail.address, owner_address);
-    assert_eq!(account_detail.token_balances.len(), 2);
+    assert_eq!(account_detail.checksum_address, to_checksum(&owner.0, None));
+    assert_eq!(account_detail.first_seen_block, Some(1));
+    assert_eq!(account_detail.tx_sent_count, 1);
+    assert!(!account_detail.is_contract);
+    assert_eq!(account_detail.token_balances.items.len(), 2);
 
     // Verify balances (order isn't guaranteed, so find each one).
     let token1_balance = account_detail
         .token_balances
+        .items
         .iter()
         .find(|b| b.token_address == token1_addr)
         .unwrap();
@@ -212,9 +324,428 @@ async fn test_get_account_details() {
 
     let token2_balance = account_detail
         .token_balances
+        .items
         .iter()
         .find(|b| b.token_address == token2_addr)
         .unwrap();
     assert_eq!(token2_balance.amount, BigDecimal::from(1));
     assert_eq!(token2_balance.token_id, Some(BigDecimal::from(999)));
+
+    // Case 3: The address is normalized, so any case and prefix finds the same account.
+    for input in [
+        account_detail.checksum_address.clone(),
+        owner_address.to_uppercase().replacen("0X", "0x", 1),
+        owner_address.trim_start_matches("0x").to_string(),
+    ] {
+        let result = get_account_details(State(db.store()), Path(input)).await;
+        assert_eq!(result.unwrap().0.address, owner_address);
+    }
+
+    // Case 4: The remaining balances are paged through the tokens endpoint.
+    let tokens = |cursor: Option<String>| {
+        get_account_tokens(
+            State(db.store()),
+            Path(owner_address.to_string()),
+            Query(PageQuery {
+                cursor,
+                limit: Some(1),
+            }),
+        )
+    };
+    let first = tokens(None).await.unwrap().0;
+    assert_eq!(first.items.len(), 1);
+    let second = tokens(first.next_cursor).await.unwrap().0;
+    assert_eq!(second.items.len(), 1);
+    assert_ne!(second.items[0].token_address, first.items[0].token_address);
+    assert_eq!(second.next_cursor, None);
+}
+
+#[tokio::test]
+async fn test_get_account_balances_at_block() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let recipient = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    let token = "0xcccccccccccccccccccccccccccccccccccccccc";
+
+    // The recipient gets 500 tokens in each of blocks 1 to 3.
+    for number in 1..=3 {
+        let block = create_mock_block(&mut conn, number);
+        let tx = create_mock_transaction(&mut conn, &block, 0);
+        create_mock_token_transfer(&mut conn, &tx);
+    }
+
+    let balances_at = |block: Option<i64>| {
+        get_account_balances(
+            State(db.store()),
+            Path(recipient.to_string()),
+            Query(BalancesQuery { block }),
+            Query(PageQuery::default()),
+        )
+    };
+
+    let at_two = balances_at(Some(2)).await.unwrap().0;
+    assert_eq!(at_two.block_number, 2);
+    assert_eq!(at_two.balances.items.len(), 1);
+    assert_eq!(at_two.balances.items[0].token_address, token);
+    assert_eq!(at_two.balances.items[0].amount, BigDecimal::from(1000));
+
+    // Before any transfer the account held nothing.
+    assert!(balances_at(Some(0))
+        .await
+        .unwrap()
+        .0
+        .balances
+        .items
+        .is_empty());
+
+    // Without a block, balances are as of the latest indexed block.
+    let latest = balances_at(None).await.unwrap().0;
+    assert_eq!(latest.block_number, 3);
+    assert_eq!(latest.balances.items[0].amount, BigDecimal::from(1500));
+
+    let series = |from: Option<i64>, to: Option<i64>| {
+        get_balance_series(
+            State(db.store()),
+            Path((recipient.to_string(), token.to_string())),
+            Query(BalanceSeriesQuery { from, to }),
+            Query(PageQuery::default()),
+        )
+    };
+
+    let points: Vec<(i64, BigDecimal)> = series(None, None)
+        .await
+        .unwrap()
+        .0
+        .items
+        .into_iter()
+        .map(|p| (p.block_number, p.amount))
+        .collect();
+    assert_eq!(
+        points,
+        vec![
+            (1, BigDecimal::from(500)),
+            (2, BigDecimal::from(1000)),
+            (3, BigDecimal::from(1500)),
+        ]
+    );
+
+    // A window keeps the running total of earlier blocks.
+    let window = series(Some(2), Some(2)).await.unwrap().0.items;
+    assert_eq!(window.len(), 1);
+    assert_eq!(window[0].amount, BigDecimal::from(1000));
+
+    // Later pages keep it too.
+    let page = |cursor: Option<String>| {
+        get_balance_series(
+            State(db.store()),
+            Path((recipient.to_string(), token.to_string())),
+            Query(BalanceSeriesQuery {
+                from: None,
+                to: None,
+            }),
+            Query(PageQuery {
+                cursor,
+                limit: Some(2),
+            }),
+        )
+    };
+    let first = page(None).await.unwrap().0;
+    assert_eq!(first.items.len(), 2);
+    let second = page(first.next_cursor).await.unwrap().0;
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.items[0].block_number, 3);
+    assert_eq!(second.items[0].amount, BigDecimal::from(1500));
+    assert_eq!(second.next_cursor, None);
+}
+
+#[tokio::test]
+async fn test_pagination() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    for number in 1..=5 {
+        create_mock_block(&mut conn, number);
+    }
+    let block = create_mock_block(&mut conn, 6);
+    for position in 0..3 {
+        create_mock_transaction(&mut conn, &block, position);
+    }
+
+    // Walking the pages returns every block once, newest first.
+    let mut numbers = Vec::new();
+    let mut cursor = None;
+    loop {
+        let query = PageQuery {
+            cursor,
+            limit: Some(4),
+        };
+        let page = get_recent_blocks(State(db.store()), Query(query))
+            .await
+            .unwrap()
+            .0;
+        assert!(page.items.len() <= 4);
+        numbers.extend(page.items.iter().map(|b| b.number));
+        match page.next_cursor {
+            Some(next) => cursor = Some(next),
+            None => break,
+        }
+    }
+    assert_eq!(numbers, vec![6, 5, 4, 3, 2, 1]);
+
+    let transactions = |cursor: Option<String>| {
+        get_transactions_for_block(
+            State(db.store()),
+            State(Arc::new(SignatureDb::default())),
+            Path(block.hash.to_string()),
+            Query(PageQuery {
+                cursor,
+                limit: Some(2),
+            }),
+        )
+    };
+    let first = transactions(None).await.unwrap().0;
+    assert_eq!(first.items.len(), 2);
+    let second = transactions(first.next_cursor).await.unwrap().0;
+    assert_eq!(second.items.len(), 1);
+    assert_eq!(second.next_cursor, None);
+
+    // Limits are capped, and a page that ends the list has no cursor.
+    let query = PageQuery {
+        cursor: None,
+        limit: Some(1_000_000),
+    };
+    let page = get_recent_blocks(State(db.store()), Query(query))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(page.items.len(), 6);
+    assert_eq!(page.next_cursor, None);
+
+    // Malformed cursors are rejected.
+    let query = PageQuery {
+        cursor: Some("not-a-cursor".to_string()),
+        limit: None,
+    };
+    let err = get_recent_blocks(State(db.store()), Query(query))
+        .await
+        .unwrap_err();
+    let ApiError::InvalidInput { kind, .. } = err else {
+        panic!("expected invalid input, got {:?}", err);
+    };
+    assert_eq!(kind, "invalid_cursor");
+}
+
+#[tokio::test]
+async fn test_search() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let block = create_mock_block(&mut conn, 42);
+    let tx = create_mock_transaction(&mut conn, &block, 0);
+    let account = Address::repeat_byte(0xaa);
+    create_mock_account(&mut conn, account);
+
+    let token = |name: &str, symbol: &str| DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80]),
+        proxy: None,
+        token: Some(TokenMetadata {
+            name: Some(name.to_string()),
+            symbol: Some(symbol.to_string()),
+            decimals: Some(6),
+        }),
+    };
+    let (usdc, bridged) = (Address::repeat_byte(0xc2), Address::repeat_byte(0xc1));
+    store_contract(&mut conn, usdc, &token("USD Coin", "USDC"), None).unwrap();
+    store_contract(&mut conn, bridged, &token("Bridged USDC", "USDC.e"), None).unwrap();
+    let hexlike = Address::repeat_byte(0xc3);
+    store_contract(&mut conn, hexlike, &token("beef", "0xDEAD"), None).unwrap();
+
+    let search = |q: &str| {
+        let q = q.to_string();
+        let store = db.store();
+        async move {
+            search(State(store), Query(SearchQuery { q }))
+                .await
+                .unwrap()
+                .0
+        }
+    };
+
+    assert_eq!(
+        search("42").await,
+        vec![SearchResult::Block {
+            hash: block.hash.to_string(),
+            number: 42,
+        }]
+    );
+    assert_eq!(search("0x2a").await, search("42").await);
+    assert_eq!(
+        search(&tx.hash.to_string()).await,
+        vec![SearchResult::Transaction {
+            hash: tx.hash.to_string(),
+            block_number: 42,
+        }]
+    );
+    assert_eq!(
+        search(&to_checksum(&account, None)).await,
+        vec![SearchResult::Account {
+            address: format!("{:#x}", account),
+        }]
+    );
+    let SearchResult::Contract { token, .. } = &search(&format!("{:#x}", usdc)).await[0] else {
+        panic!("expected a contract");
+    };
+    assert_eq!(token.as_ref().unwrap().symbol.as_deref(), Some("USDC"));
+
+    // Symbol and name prefixes find tokens, ignoring case, with exact symbols first.
+    let addresses = |results: Vec<SearchResult>| {
+        results
+            .into_iter()
+            .map(|result| match result {
+                SearchResult::Token { address, .. } => address,
+                other => panic!("expected a token, got {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+    let (usdc, bridged) = (format!("{:#x}", usdc), format!("{:#x}", bridged));
+    assert_eq!(
+        addresses(search("usdc").await),
+        vec![usdc.clone(), bridged.clone()]
+    );
+    assert_eq!(addresses(search("bridged").await), vec![bridged]);
+    assert_eq!(addresses(search("USD C").await), vec![usdc]);
+
+    // Names and symbols that look like hex are found once no block or account matches.
+    let hexlike = format!("{:#x}", hexlike);
+    assert_eq!(addresses(search("0xdead").await), vec![hexlike.clone()]);
+    assert_eq!(addresses(search("beef").await), vec![hexlike]);
+
+    // Unknown blocks, hashes and addresses, and LIKE wildcards, find nothing.
+    for q in [
+        "7",
+        "latest_",
+        "%",
+        "_",
+        &missing_hash(),
+        &format!("{:#x}", Address::zero()),
+    ] {
+        assert_eq!(search(q).await, vec![], "{:?}", q);
+    }
+    assert_eq!(search("  ").await, vec![]);
+}
+
+#[tokio::test]
+async fn test_upload_abi() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let address = "0xcccccccccccccccccccccccccccccccccccccccc";
+    let abi: serde_json::Value = serde_json::json!([{
+        "anonymous": false,
+        "type": "event",
+        "name": "Ping",
+        "inputs": [{"indexed": false, "name": "value", "type": "uint256"}]
+    }]);
+
+    let state = AppState {
+        store: db.store(),
+        abi_registry: SharedAbiRegistry::default(),
+        signatures: Arc::new(SignatureDb::default()),
+        admin_token: Some("secret".to_string()),
+    };
+
+    // Case 1: Missing admin token.
+    let result = upload_abi(
+        State(state.clone()),
+        HeaderMap::new(),
+        Path(address.to_string()),
+        Json(abi.clone()),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::UNAUTHORIZED);
+
+    let mut headers = HeaderMap::new();
+    headers.insert("x-admin-token", "secret".parse().unwrap());
+
+    // Case 2: Malformed ABI.
+    let result = upload_abi(
+        State(state.clone()),
+        headers.clone(),
+        Path(address.to_string()),
+        Json(serde_json::json!({"not": "an abi"})),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::BAD_REQUEST);
+
+    // Case 3: Valid upload is persisted and registered.
+    let result = upload_abi(
+        State(state.clone()),
+        headers,
+        Path(address.to_string()),
+        Json(abi),
+    )
+    .await;
+    assert_eq!(result.unwrap(), StatusCode::NO_CONTENT);
+
+    let stored: i64 = contract_abis::table
+        .filter(contract_abis::address.eq(DbAddress(address.parse().unwrap())))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(stored, 1);
+
+    let data = ethers::types::Bytes::from(vec![0u8; 32]);
+    let topic0 = ethers::utils::keccak256("Ping(uint256)").into();
+    assert!(state
+        .abi_registry
+        .read()
+        .unwrap()
+        .decode_log(address.parse().unwrap(), &[topic0], &data)
+        .is_some());
+}
+
+#[test]
+fn test_parse_address() {
+    // The EIP-55 example address, in checksummed, lowercase and unprefixed uppercase forms.
+    let expected = DbAddress(Address::from_slice(
+        &hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap(),
+    ));
+    for input in [
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+        "5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED",
+        " 0X5aaeb6053f3e94c9b9a09f33669435e7ef1beaed ",
+    ] {
+        assert_eq!(parse_address(input), Ok(expected), "{}", input);
+    }
+    assert_eq!(
+        to_checksum(&expected.0, None),
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+    );
+
+    let ApiError::InvalidInput { kind, message } = parse_address("0x5aae").unwrap_err() else {
+        panic!("expected an invalid input error");
+    };
+    assert_eq!(kind, "invalid_address");
+    assert!(message.contains("expected 40 hex digits, got 4"));
+
+    let error = parse_hash(&format!("0x{}g", "0".repeat(63))).unwrap_err();
+    assert!(matches!(
+        &error,
+        ApiError::InvalidInput { kind: "invalid_hash", message } if message.contains("'g'")
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_input_response() {
+    let db = TestDb::new();
+    let error = get_account_details(State(db.store()), Path("0xnotanaddress".to_string()))
+        .await
+        .unwrap_err();
+
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: ErrorResponse = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body.error, "invalid_address");
+    assert!(body.message.contains("invalid hex character 'n'"));
 }
@@ -0,0 +1,107 @@
+use super::environment::TestDb;
+use crate::{
+    balance_verifier::{
+        apply_corrections, balances_after, negative_balances, BalanceMismatch, BalanceReport,
+    },
+    models::DbAddress,
+    schema::{non_standard_tokens, token_balances},
+};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use ethers::types::Address;
+
+fn insert_balance(conn: &mut PgConnection, owner: Address, token: Address, amount: i64) -> i64 {
+    diesel::insert_into(token_balances::table)
+        .values((
+            token_balances::owner_address.eq(DbAddress(owner)),
+            token_balances::token_address.eq(DbAddress(token)),
+            token_balances::amount.eq(BigDecimal::from(amount)),
+        ))
+        .returning(token_balances::id)
+        .get_result(conn)
+        .unwrap()
+}
+
+fn mismatch(balance_id: i64, token: Address, indexed: i64, on_chain: i64) -> BalanceMismatch {
+    BalanceMismatch {
+        balance_id,
+        token_address: DbAddress(token),
+        owner_address: DbAddress(Address::repeat_byte(0xaa)),
+        token_id: None,
+        indexed: BigDecimal::from(indexed),
+        on_chain: BigDecimal::from(on_chain),
+    }
+}
+
+#[test]
+fn test_balance_selection() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let token = Address::repeat_byte(0xcc);
+
+    let first = insert_balance(&mut conn, Address::repeat_byte(0x01), token, 10);
+    let negative = insert_balance(&mut conn, Address::repeat_byte(0x02), token, -5);
+    let last = insert_balance(&mut conn, Address::repeat_byte(0x03), token, 0);
+
+    let negatives = negative_balances(&mut conn).unwrap();
+    assert_eq!(negatives.len(), 1);
+    assert_eq!(negatives[0].id, negative);
+
+    // Pages follow id order, so a full scan visits every row once.
+    let page = balances_after(&mut conn, 0, 2).unwrap();
+    assert_eq!(
+        page.iter().map(|b| b.id).collect::<Vec<_>>(),
+        vec![first, negative]
+    );
+    let page = balances_after(&mut conn, negative, 2).unwrap();
+    assert_eq!(page.iter().map(|b| b.id).collect::<Vec<_>>(), vec![last]);
+}
+
+#[test]
+fn test_apply_corrections_flags_token() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let token = Address::repeat_byte(0xcc);
+    let id = insert_balance(&mut conn, Address::repeat_byte(0xaa), token, 1000);
+
+    let mismatches = vec![mismatch(id, token, 1000, 990)];
+    assert_eq!(apply_corrections(&mut conn, &mismatches, 42).unwrap(), 1);
+
+    let amount: BigDecimal = token_balances::table
+        .find(id)
+        .select(token_balances::amount)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(amount, BigDecimal::from(990));
+
+    let reason: String = non_standard_tokens::table
+        .find(DbAddress(token))
+        .select(non_standard_tokens::reason)
+        .first(&mut conn)
+        .unwrap();
+    assert!(reason.contains("fee-on-transfer"));
+    assert!(reason.contains("block 42"));
+
+    // Flagging an already flagged token keeps the first reason.
+    apply_corrections(&mut conn, &[mismatch(id, token, 990, 1200)], 43).unwrap();
+    let flags: i64 = non_standard_tokens::table
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(flags, 1);
+}
+
+#[test]
+fn test_report_consistency() {
+    let token = Address::repeat_byte(0xcc);
+    assert!(BalanceReport::default().is_consistent());
+
+    let mut report = BalanceReport {
+        mismatches: vec![mismatch(1, token, 5, 6)],
+        ..Default::default()
+    };
+    assert!(!report.is_consistent());
+    report.corrected = 1;
+    assert!(report.is_consistent());
+    assert!(report.mismatches[0].reason(1).contains("rebasing"));
+}
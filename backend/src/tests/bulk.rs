@@ -0,0 +1,264 @@
+use super::environment::{
+    mock_ethers_block, mock_ethers_transaction, mock_receipt, mock_transfer_log, TestDb,
+};
+use crate::{
+    bulk::write_block_batch,
+    contracts::{DeployedContract, UPGRADED_EVENT_SIGNATURE},
+    indexer::{process_block_data, FetchedBlock, IndexerContext, TRANSFER_EVENT_SIGNATURE},
+    models::{DbAddress, DbHash},
+    schema::{accounts, contracts, logs, token_balances, token_transfers, transactions},
+};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use ethers::types::{Address, Bytes, Log as EthersLog, H256};
+use std::collections::HashMap;
+
+fn nft_transfer_log(token: Address, from: Address, to: Address, token_id: u64) -> EthersLog {
+    EthersLog {
+        address: token,
+        topics: vec![
+            TRANSFER_EVENT_SIGNATURE,
+            H256::from(from),
+            H256::from(to),
+            H256::from_low_u64_be(token_id),
+        ],
+        ..Default::default()
+    }
+}
+
+fn address(byte: u8) -> Address {
+    Address::repeat_byte(byte)
+}
+
+/// Three blocks of token activity, including balances that exist before the batch, balances
+/// debited before they're first credited, a contract deployment and a proxy upgrade.
+fn mock_chain() -> Vec<FetchedBlock> {
+    let (token, nft, proxy) = (address(0x70), address(0x71), address(0x72));
+    let (a, b, d, e, f, g) = (
+        address(0x0a),
+        address(0x0b),
+        address(0x0d),
+        address(0x0e),
+        address(0x0f),
+        address(0x06),
+    );
+    let zero = Address::zero();
+    let code = DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80]),
+        proxy: None,
+        token: None,
+    };
+
+    let block_txs: Vec<Vec<(Option<Address>, Vec<EthersLog>)>> = vec![
+        vec![
+            (
+                Some(token),
+                vec![
+                    mock_transfer_log(token, zero, a, 1000),
+                    mock_transfer_log(token, a, b, 300),
+                    nft_transfer_log(nft, zero, b, 7),
+                    mock_transfer_log(token, g, a, 30),
+                ],
+            ),
+            (None, vec![]),
+        ],
+        vec![(
+            Some(token),
+            vec![
+                mock_transfer_log(token, a, b, 200),
+                mock_transfer_log(token, d, e, 50),
+                nft_transfer_log(nft, b, a, 7),
+            ],
+        )],
+        vec![(
+            Some(proxy),
+            vec![
+                mock_transfer_log(token, e, d, 20),
+                mock_transfer_log(token, d, f, 10),
+                EthersLog {
+                    address: proxy,
+                    topics: vec![UPGRADED_EVENT_SIGNATURE, H256::from(address(0x73))],
+                    ..Default::default()
+                },
+            ],
+        )],
+    ];
+
+    block_txs
+        .into_iter()
+        .enumerate()
+        .map(|(i, txs)| {
+            let number = i as u64 + 1;
+            let senders = [a, b, e];
+            let txs: Vec<_> = txs
+                .into_iter()
+                .enumerate()
+                .map(|(index, (to, logs))| {
+                    let mut tx = mock_ethers_transaction(senders[index + i % 2], to, index as u64);
+                    tx.hash = H256::from_low_u64_be((number << 8) | index as u64);
+                    (tx, logs)
+                })
+                .collect();
+            let block = mock_ethers_block(number, txs.iter().map(|(tx, _)| tx.clone()).collect());
+            let transactions_with_receipts = block
+                .transactions
+                .iter()
+                .zip(txs)
+                .map(|(tx, (_, logs))| {
+                    let mut receipt = mock_receipt(tx, logs);
+                    if tx.to.is_none() {
+                        receipt.contract_address = Some(address(0xc0));
+                    }
+                    (tx.clone(), receipt)
+                })
+                .collect();
+            let contracts = match number {
+                1 => HashMap::from([(token, code.clone()), (address(0xc0), code.clone())]),
+                3 => HashMap::from([(proxy, code.clone())]),
+                _ => HashMap::new(),
+            };
+            FetchedBlock {
+                block,
+                transactions_with_receipts,
+                contracts,
+            }
+        })
+        .collect()
+}
+
+type AccountRow = (
+    DbAddress,
+    Option<i64>,
+    Option<DbHash>,
+    Option<i64>,
+    i64,
+    i64,
+    bool,
+    bool,
+);
+type BalanceRow = (DbAddress, DbAddress, Option<BigDecimal>, BigDecimal);
+type TransferRow = (DbHash, DbAddress, DbAddress, Option<BigDecimal>, i64);
+
+/// The stored rows compared between both write paths.
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    accounts: Vec<AccountRow>,
+    balances: Vec<BalanceRow>,
+    transfers: Vec<TransferRow>,
+    logs: Vec<(DbHash, DbAddress, Option<DbHash>, String)>,
+    transactions: Vec<(DbHash, i32)>,
+    contracts: Vec<(DbAddress, Option<DbHash>, Option<DbAddress>)>,
+}
+
+fn snapshot(conn: &mut PgConnection) -> Snapshot {
+    Snapshot {
+        accounts: accounts::table
+            .order(accounts::address)
+            .select((
+                accounts::address,
+                accounts::first_seen_block,
+                accounts::first_seen_tx,
+                accounts::last_active_block,
+                accounts::tx_sent_count,
+                accounts::tx_received_count,
+                accounts::is_contract,
+                accounts::code_checked,
+            ))
+            .load(conn)
+            .unwrap(),
+        balances: token_balances::table
+            .order((
+                token_balances::owner_address,
+                token_balances::token_address,
+                token_balances::token_id,
+            ))
+            .select((
+                token_balances::owner_address,
+                token_balances::token_address,
+                token_balances::token_id,
+                token_balances::amount,
+            ))
+            .load(conn)
+            .unwrap(),
+        transfers: token_transfers::table
+            .order(token_transfers::id)
+            .select((
+                token_transfers::tx_hash,
+                token_transfers::from_address,
+                token_transfers::to_address,
+                token_transfers::token_id,
+                token_transfers::block_number,
+            ))
+            .load(conn)
+            .unwrap(),
+        logs: logs::table
+            .order(logs::id)
+            .select((logs::tx_hash, logs::address, logs::topic1, logs::data))
+            .load(conn)
+            .unwrap(),
+        transactions: transactions::table
+            .order(transactions::hash)
+            .select((transactions::hash, transactions::position))
+            .load(conn)
+            .unwrap(),
+        contracts: contracts::table
+            .order(contracts::address)
+            .select((
+                contracts::address,
+                contracts::creation_tx,
+                contracts::implementation,
+            ))
+            .load(conn)
+            .unwrap(),
+    }
+}
+
+/// A balance held before the batch, which its transfers update in place.
+fn seed_balance(conn: &mut PgConnection) {
+    diesel::insert_into(token_balances::table)
+        .values((
+            token_balances::owner_address.eq(DbAddress(address(0x06))),
+            token_balances::token_address.eq(DbAddress(address(0x70))),
+            token_balances::amount.eq(BigDecimal::from(100)),
+        ))
+        .execute(conn)
+        .unwrap();
+}
+
+#[test]
+fn test_batch_matches_block_by_block() {
+    let context = IndexerContext::default();
+    let chain = mock_chain();
+
+    let one_by_one = TestDb::new();
+    let mut conn = one_by_one.pool.get().unwrap();
+    seed_balance(&mut conn);
+    for fetched in &chain {
+        process_block_data(
+            &mut conn,
+            &context,
+            &fetched.block,
+            &fetched.transactions_with_receipts,
+            &fetched.contracts,
+        )
+        .unwrap();
+    }
+    let expected = snapshot(&mut conn);
+
+    let batched = TestDb::new();
+    let mut conn = batched.pool.get().unwrap();
+    seed_balance(&mut conn);
+    write_block_batch(&mut conn, &context, &chain).unwrap();
+    let actual = snapshot(&mut conn);
+
+    assert_eq!(actual, expected);
+
+    // D was debited before any credit, so only what it received afterwards counts.
+    let d_balance = actual
+        .balances
+        .iter()
+        .find(|(owner, ..)| owner.0 == address(0x0d))
+        .map(|(.., amount)| amount.clone());
+    assert_eq!(d_balance, Some(BigDecimal::from(10)));
+    assert_eq!(actual.contracts.len(), 3);
+}
@@ -0,0 +1,83 @@
+use super::environment::{mock_ethers_block, TestDb};
+use crate::{
+    chains::{self, ChainConfig},
+    db::{self, PoolConfig},
+    indexer::{block_row, IndexerContext},
+    store::{PgStore, Store},
+    MIGRATIONS,
+};
+use diesel::{Connection, PgConnection};
+use diesel_migrations::MigrationHarness;
+use std::{collections::HashMap, fs};
+
+fn chain(name: &str, schema: &str) -> ChainConfig {
+    ChainConfig {
+        name: name.to_string(),
+        chain_id: None,
+        rpc_url: "ws://localhost:8545".to_string(),
+        start_block: None,
+        schema: Some(schema.to_string()),
+    }
+}
+
+#[test]
+fn test_load_chains() {
+    let path = std::env::temp_dir().join(format!("chains_{}.json", uuid::Uuid::new_v4()));
+    fs::write(
+        &path,
+        r#"[
+            {"name": "mainnet", "chain_id": 1, "rpc_url": "wss://eth.example", "schema": "public"},
+            {"name": "base", "chain_id": 8453, "rpc_url": "wss://base.example", "start_block": 100}
+        ]"#,
+    )
+    .unwrap();
+    let chains = ChainConfig::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(chains.len(), 2);
+    assert_eq!(chains[0].schema.as_deref(), Some("public"));
+    // Schemas default to the chain's name.
+    assert_eq!(chains[1].schema.as_deref(), Some("base"));
+    assert_eq!(chains[1].chain_id, Some(8453));
+    assert_eq!(chains[1].start_block, Some(100));
+}
+
+#[test]
+fn test_invalid_chains_are_rejected() {
+    assert!(chains::validate(&[chain("mainnet", "mainnet"), chain("base", "base")]).is_ok());
+    assert!(chains::validate(&[]).is_err());
+    assert!(chains::validate(&[chain("Base-Sepolia", "base")]).is_err());
+    assert!(chains::validate(&[chain("base", "base; DROP SCHEMA public")]).is_err());
+    assert!(chains::validate(&[chain("base", "a"), chain("base", "b")]).is_err());
+    assert!(chains::validate(&[chain("base", "shared"), chain("op", "shared")]).is_err());
+}
+
+#[test]
+fn test_chains_are_isolated_in_schemas() {
+    let db = TestDb::new();
+    let stores: Vec<PgStore> = ["mainnet", "base"]
+        .into_iter()
+        .map(|schema| {
+            let mut conn = PgConnection::establish(&db.db_url).unwrap();
+            db::use_schema(&mut conn, schema).unwrap();
+            conn.run_pending_migrations(MIGRATIONS).unwrap();
+            let pool = db::establish_schema_pool(&db.db_url, schema, &PoolConfig::default());
+            PgStore::new(pool)
+        })
+        .collect();
+
+    let block = mock_ethers_block(7, vec![]);
+    stores[0]
+        .write_block(
+            &IndexerContext::default(),
+            &block_row(&block).unwrap(),
+            &[],
+            &HashMap::new(),
+        )
+        .unwrap();
+
+    assert_eq!(stores[0].latest_block_number().unwrap(), Some(7));
+    assert_eq!(stores[1].latest_block_number().unwrap(), None);
+    let public = PgStore::new(db.pool.clone());
+    assert_eq!(public.latest_block_number().unwrap(), None);
+}
@@ -0,0 +1,245 @@
+use super::environment::{
+    create_mock_block, create_mock_transaction, mock_ethers_transaction, mock_receipt,
+    mock_transfer_log, TestDb,
+};
+use crate::{
+    api::get_contract_details,
+    contracts::{
+        contract_candidates, decode_token_text, minimal_proxy_target, store_contract,
+        DeployedContract, ProxyKind, ProxyTarget, EIP1822_PROXIABLE_SLOT, EIP1967_BEACON_SLOT,
+        EIP1967_IMPLEMENTATION_SLOT, UPGRADED_EVENT_SIGNATURE,
+    },
+    indexer::{process_log, rollback_block, IndexerContext},
+    models::DbAddress,
+    schema::{bytecodes, contracts},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+};
+use diesel::prelude::*;
+use ethers::{
+    types::{Address, Bytes, Log as EthersLog, H256, U256},
+    utils::keccak256,
+};
+
+fn upgraded_log(proxy: Address, implementation: Address, tx_hash: H256) -> EthersLog {
+    EthersLog {
+        address: proxy,
+        topics: vec![UPGRADED_EVENT_SIGNATURE, H256::from(implementation)],
+        transaction_hash: Some(tx_hash),
+        ..Default::default()
+    }
+}
+
+fn implementation_of(conn: &mut PgConnection, address: Address) -> Option<Address> {
+    contracts::table
+        .find(DbAddress(address))
+        .select(contracts::implementation)
+        .first::<Option<DbAddress>>(conn)
+        .unwrap()
+        .map(Address::from)
+}
+
+#[test]
+fn test_proxy_constants() {
+    let minus_one = |label: &str| {
+        let slot = U256::from_big_endian(&keccak256(label)) - 1;
+        let mut bytes = [0u8; 32];
+        slot.to_big_endian(&mut bytes);
+        H256(bytes)
+    };
+    assert_eq!(
+        EIP1967_IMPLEMENTATION_SLOT,
+        minus_one("eip1967.proxy.implementation")
+    );
+    assert_eq!(EIP1967_BEACON_SLOT, minus_one("eip1967.proxy.beacon"));
+    assert_eq!(EIP1822_PROXIABLE_SLOT, H256(keccak256("PROXIABLE")));
+    assert_eq!(
+        UPGRADED_EVENT_SIGNATURE,
+        H256(keccak256("Upgraded(address)"))
+    );
+}
+
+#[test]
+fn test_minimal_proxy_target() {
+    let implementation = Address::repeat_byte(0xbe);
+    let code = [
+        hex::decode("363d3d373d3d3d363d73").unwrap(),
+        implementation.as_bytes().to_vec(),
+        hex::decode("5af43d82803e903d91602b57fd5bf3").unwrap(),
+    ]
+    .concat();
+    assert_eq!(minimal_proxy_target(&code), Some(implementation));
+
+    // Trailing bytes mean this is some other contract.
+    let mut longer = code.clone();
+    longer.push(0x00);
+    assert_eq!(minimal_proxy_target(&longer), None);
+    assert_eq!(minimal_proxy_target(&code[..code.len() - 1]), None);
+    assert_eq!(minimal_proxy_target(&[]), None);
+}
+
+#[test]
+fn test_decode_token_text() {
+    use ethers::abi::{encode, Token};
+
+    let string = encode(&[Token::String("USD Coin".to_string())]);
+    assert_eq!(decode_token_text(&string), Some("USD Coin".to_string()));
+
+    // Early tokens such as MKR return a NUL-padded bytes32.
+    let mut bytes32 = [0u8; 32];
+    bytes32[..3].copy_from_slice(b"MKR");
+    assert_eq!(decode_token_text(&bytes32), Some("MKR".to_string()));
+
+    let control = encode(&[Token::String(" A\u{0}B\n ".to_string())]);
+    assert_eq!(decode_token_text(&control), Some("AB".to_string()));
+    let long = encode(&[Token::String("x".repeat(100))]);
+    assert_eq!(decode_token_text(&long).map(|s| s.len()), Some(64));
+
+    assert_eq!(decode_token_text(&[]), None);
+    assert_eq!(decode_token_text(&[0u8; 32]), None);
+    assert_eq!(decode_token_text(&[0xff; 7]), None);
+}
+
+#[test]
+fn test_contract_candidates() {
+    let sender = Address::repeat_byte(0x01);
+    let called = Address::repeat_byte(0x02);
+    let paid = Address::repeat_byte(0x03);
+    let created = Address::repeat_byte(0x04);
+
+    let token = Address::repeat_byte(0x05);
+
+    let call = mock_ethers_transaction(sender, Some(called), 0);
+    // A token only called from another contract is seen through its `Transfer` logs.
+    let transfer_log = mock_transfer_log(token, called, sender, 1);
+    let other_log = EthersLog {
+        address: Address::repeat_byte(0x06),
+        ..upgraded_log(Address::zero(), Address::zero(), call.hash)
+    };
+    // A plain transfer can be the first time a contract is seen.
+    let mut transfer = mock_ethers_transaction(sender, Some(paid), 1);
+    transfer.input = Bytes::new();
+    let creation = mock_ethers_transaction(sender, None, 2);
+    let mut creation_receipt = mock_receipt(&creation, vec![]);
+    creation_receipt.contract_address = Some(created);
+
+    let transactions = vec![
+        (
+            call.clone(),
+            mock_receipt(&call, vec![transfer_log, other_log]),
+        ),
+        (transfer.clone(), mock_receipt(&transfer, vec![])),
+        (creation, creation_receipt),
+    ];
+    let mut candidates: Vec<Address> = contract_candidates(&transactions).into_iter().collect();
+    candidates.sort();
+    assert_eq!(candidates, vec![called, paid, created, token]);
+}
+
+#[tokio::test]
+async fn test_store_contract_and_upgrades() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let context = IndexerContext::default();
+    let proxy = Address::repeat_byte(0x01);
+    let clone = Address::repeat_byte(0x02);
+    let first_impl = Address::repeat_byte(0x0a);
+    let second_impl = Address::repeat_byte(0x0b);
+
+    let block = create_mock_block(&mut conn, 1);
+    let deploy_tx = create_mock_transaction(&mut conn, &block, 0);
+    let deployed = DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80, 0x60, 0x40]),
+        proxy: Some(ProxyTarget {
+            kind: ProxyKind::Eip1967,
+            implementation: Some(first_impl),
+            beacon: None,
+        }),
+        token: None,
+    };
+    store_contract(&mut conn, proxy, &deployed, Some((deploy_tx.hash.0, 1))).unwrap();
+    store_contract(&mut conn, clone, &deployed, None).unwrap();
+
+    // Identical code is stored once.
+    let stored_bytecodes: i64 = bytecodes::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(stored_bytecodes, 1);
+
+    let upgrade_block = create_mock_block(&mut conn, 2);
+    let upgrade_tx = create_mock_transaction(&mut conn, &upgrade_block, 0);
+    process_log(
+        &mut conn,
+        &context,
+        upgrade_tx.hash.0,
+        upgrade_tx.block_number,
+        &upgraded_log(proxy, second_impl, upgrade_tx.hash.0),
+    )
+    .unwrap();
+    assert_eq!(implementation_of(&mut conn, proxy), Some(second_impl));
+
+    let detail = get_contract_details(State(db.store()), Path(format!("{:#x}", proxy)))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(detail.creation_tx, Some(deploy_tx.hash.to_string()));
+    assert_eq!(detail.bytecode_size, Some(4));
+    let proxy_info = detail.proxy.unwrap();
+    assert_eq!(proxy_info.kind, "eip1967");
+    assert_eq!(
+        proxy_info.implementation,
+        Some(format!("{:#x}", second_impl))
+    );
+    assert_eq!(detail.upgrades.items.len(), 1);
+    assert_eq!(detail.upgrades.items[0].block_number, 2);
+
+    // A reorg restores the previous implementation.
+    rollback_block(&mut conn, &context, upgrade_block.hash.0).unwrap();
+    assert_eq!(implementation_of(&mut conn, proxy), Some(first_impl));
+
+    // Reorging out the deployment removes the contract.
+    rollback_block(&mut conn, &context, block.hash.0).unwrap();
+    let result = get_contract_details(State(db.store()), Path(format!("{:#x}", proxy))).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn test_beacon_upgrade_updates_its_proxies() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let context = IndexerContext::default();
+    let beacon = Address::repeat_byte(0xbc);
+    let proxy = Address::repeat_byte(0x01);
+    let new_impl = Address::repeat_byte(0x0b);
+
+    let deployed = DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80]),
+        proxy: Some(ProxyTarget {
+            kind: ProxyKind::Beacon,
+            implementation: Some(Address::repeat_byte(0x0a)),
+            beacon: Some(beacon),
+        }),
+        token: None,
+    };
+    store_contract(&mut conn, proxy, &deployed, None).unwrap();
+
+    let block = create_mock_block(&mut conn, 1);
+    let tx = create_mock_transaction(&mut conn, &block, 0);
+    process_log(
+        &mut conn,
+        &context,
+        tx.hash.0,
+        tx.block_number,
+        &upgraded_log(beacon, new_impl, tx.hash.0),
+    )
+    .unwrap();
+
+    assert_eq!(implementation_of(&mut conn, proxy), Some(new_impl));
+    // The beacon itself isn't mistaken for a proxy.
+    let beacon_rows: i64 = contracts::table
+        .filter(contracts::address.eq(DbAddress(beacon)))
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(beacon_rows, 0);
+}
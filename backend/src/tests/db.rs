@@ -1,5 +1,14 @@
-use super::environment::TestDb;
-use crate::db::establish_connection_pool;
+use super::environment::{revert_migrations_through, TestDb};
+use crate::{
+    db::{establish_connection_pool, establish_sqlite_pool, PoolConfig},
+    models::DbAddress,
+    schema::accounts,
+    MIGRATIONS,
+};
+use diesel::{prelude::*, sql_types::Text};
+use diesel_migrations::MigrationHarness;
+use ethers::types::Address;
+use std::time::Duration;
 
 #[test]
 fn test_establish_connection_pool() {
@@ -7,7 +16,7 @@ fn test_establish_connection_pool() {
     let db = TestDb::new();
 
     // Use the URL of the temporary database to establish a new connection pool.
-    let pool = establish_connection_pool(&db.db_url);
+    let pool = establish_connection_pool(&db.db_url, &PoolConfig::default());
 
     // Assert that we can successfully get a connection from the pool.
     // This verifies that the pool was configured and built correctly.
@@ -17,11 +26,95 @@ fn test_establish_connection_pool() {
     );
 }
 
+#[test]
+fn test_connection_pool_uses_config() {
+    let db = TestDb::new();
+    let config = PoolConfig {
+        max_size: 2,
+        min_idle: Some(1),
+        idle_timeout: None,
+        ..PoolConfig::default()
+    };
+    let pool = establish_connection_pool(&db.db_url, &config);
+
+    assert_eq!(pool.max_size(), 2);
+    assert_eq!(pool.min_idle(), Some(1));
+    assert_eq!(pool.idle_timeout(), None);
+    let _held = (pool.get().unwrap(), pool.get().unwrap());
+    assert!(pool.get().is_err(), "A third connection was handed out");
+}
+
+#[test]
+fn test_in_memory_sqlite_pool_keeps_its_connection() {
+    let config = PoolConfig {
+        idle_timeout: Some(Duration::from_millis(1)),
+        ..PoolConfig::default()
+    };
+    let pool = establish_sqlite_pool(":memory:", &config).unwrap();
+
+    // The database lives as long as its only connection, so the pool must never replace it.
+    assert_eq!(pool.max_size(), 1);
+    assert_eq!(pool.idle_timeout(), None);
+    assert_eq!(pool.max_lifetime(), None);
+    diesel::sql_query("CREATE TABLE kept (id INTEGER)")
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    diesel::sql_query("INSERT INTO kept VALUES (1)")
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(pool.state().connections, 1);
+}
+
 #[test]
 #[should_panic(expected = "Failed to create database connection pool.")]
 fn test_establish_connection_pool_with_invalid_url() {
     // This test ensures that the function panics with a specific message
     // when provided with an invalid database URL, as expected.
     let invalid_url = "postgres://invalid".to_string();
-    establish_connection_pool(&invalid_url);
+    establish_connection_pool(&invalid_url, &PoolConfig::default());
+}
+
+const BYTEA_MIGRATION: &str = "20250730090000";
+
+#[test]
+fn test_hash_columns_migrate_to_bytea() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let address = Address::repeat_byte(0xab);
+
+    // Existing databases store addresses as 0x-prefixed hex text.
+    revert_migrations_through(&mut conn, BYTEA_MIGRATION);
+    diesel::sql_query(
+        "INSERT INTO accounts (address, tx_sent_count, tx_received_count, is_contract) \
+         VALUES ($1, 0, 0, false)",
+    )
+    .bind::<Text, _>(format!("{:#x}", address))
+    .execute(&mut conn)
+    .unwrap();
+
+    conn.run_pending_migrations(MIGRATIONS).unwrap();
+    let stored: DbAddress = accounts::table
+        .select(accounts::address)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(stored.0, address);
+    assert_eq!(stored.to_string(), format!("{:#x}", address));
+
+    // Reverting restores the text form.
+    revert_migrations_through(&mut conn, BYTEA_MIGRATION);
+    let text: Vec<String> = diesel::sql_query("SELECT address FROM accounts")
+        .load::<AddressText>(&mut conn)
+        .unwrap()
+        .into_iter()
+        .map(|row| row.address)
+        .collect();
+    assert_eq!(text, vec![format!("{:#x}", address)]);
+    conn.run_pending_migrations(MIGRATIONS).unwrap();
+}
+
+#[derive(QueryableByName)]
+struct AddressText {
+    #[diesel(sql_type = Text)]
+    address: String,
 }
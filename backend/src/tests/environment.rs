@@ -1,7 +1,9 @@
 use crate::{
     db::DbPool,
-    models::{Account, Block, NewLog, NewTokenTransfer, Transaction},
+    indexer::TRANSFER_EVENT_SIGNATURE,
+    models::{Account, Block, DbAddress, DbHash, L2Fields, NewLog, NewTokenTransfer, Transaction},
     schema::{accounts, blocks, logs, token_transfers, transactions},
+    store::{PgStore, SharedStore},
     MIGRATIONS,
 };
 use bigdecimal::BigDecimal;
@@ -12,7 +14,20 @@ use diesel::{
     Connection, RunQueryDsl,
 };
 use diesel_migrations::MigrationHarness;
-use std::{env, str::FromStr};
+use ethers::types::{
+    Address, Block as EthersBlock, Bytes, Log as EthersLog, Transaction as EthersTransaction,
+    TransactionReceipt, H256, U256, U64,
+};
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    env,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
 use url::Url;
 
 pub struct TestDb {
@@ -68,6 +83,24 @@ impl TestDb {
     }
 }
 
+impl TestDb {
+    /// A store backed by the test database.
+    pub fn store(&self) -> SharedStore {
+        Arc::new(PgStore::new(self.pool.clone()))
+    }
+}
+
+/// Reverts the applied migrations down to and including `version` (e.g. `20250730090000`), to
+/// load data in the schema an older database would have before migrating it.
+pub fn revert_migrations_through(conn: &mut PgConnection, version: &str) {
+    loop {
+        let reverted = conn.revert_last_migration(MIGRATIONS).unwrap();
+        if reverted.to_string() == version {
+            break;
+        }
+    }
+}
+
 impl Drop for TestDb {
     fn drop(&mut self) {
         let mut admin_url = Url::parse(&self.admin_conn_url).unwrap();
@@ -102,18 +135,21 @@ impl Drop for TestDb {
 /// Hashes are generated predictably based on the block number.
 pub fn create_mock_block(conn: &mut PgConnection, block_number: i64) -> Block {
     let block = Block {
-        hash: format!("0x{:064x}", block_number),
-        parent_hash: format!("0x{:064x}", block_number - 1),
+        hash: DbHash(H256::from_low_u64_be(block_number as u64)),
+        parent_hash: DbHash(H256::from_low_u64_be(block_number as u64 - 1)),
         number: block_number,
         timestamp: DateTime::parse_from_rfc3339("2025-07-19T12:00:00-03:00")
             .unwrap()
             .with_timezone(&Utc),
-        miner: "0x1111111111111111111111111111111111111111".to_string(),
+        miner: DbAddress(Address::repeat_byte(0x11)),
         gas_used: BigDecimal::from(1_500_000),
         gas_limit: BigDecimal::from(30_000_000),
         base_fee_per_gas: Some(BigDecimal::from(50)),
         extra_data: "0x00".to_string(),
         tx_count: 1,
+        transactions_root: None,
+        receipts_root: None,
+        logs_bloom: None,
     };
     diesel::insert_into(blocks::table)
         .values(&block)
@@ -131,16 +167,21 @@ pub fn create_mock_transaction(
     tx_index: i32,
 ) -> Transaction {
     let tx = Transaction {
-        hash: format!("0x{:063x}{:x}", block.number, tx_index),
-        block_hash: block.hash.clone(),
+        hash: DbHash(H256::from_low_u64_be(
+            ((block.number as u64) << 4) | tx_index as u64,
+        )),
+        block_hash: block.hash,
         block_number: block.number,
-        from_address: "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa".to_string(),
-        to_address: Some("0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb".to_string()),
+        from_address: DbAddress(Address::repeat_byte(0xaa)),
+        to_address: Some(DbAddress(Address::repeat_byte(0xbb))),
         value: BigDecimal::from_str("1000000000000000000").unwrap(), // 1 ETH
         gas_price: Some(BigDecimal::from(20)),
         gas_used: Some(BigDecimal::from(21000)),
         nonce: block.number + i64::from(tx_index),
         position: tx_index,
+        method_id: Some("0xa9059cbb".to_string()), // transfer(address,uint256)
+        tx_type: Some(2),
+        l2: L2Fields::default(),
     };
     diesel::insert_into(transactions::table)
         .values(&tx)
@@ -152,13 +193,15 @@ pub fn create_mock_transaction(
 /// Inserts a mock log with deterministic data into the database.
 pub fn create_mock_log(conn: &mut PgConnection, tx: &Transaction) {
     let log = NewLog {
-        tx_hash: &tx.hash,
-        address: "0xcccccccccccccccccccccccccccccccccccccccc",
-        topic0: Some("0xdddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd"),
-        topic1: Some("0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee"),
+        tx_hash: tx.hash,
+        address: DbAddress(Address::repeat_byte(0xcc)),
+        topic0: Some(DbHash(H256::repeat_byte(0xdd))),
+        topic1: Some(DbHash(H256::repeat_byte(0xee))),
         topic2: None,
         topic3: None,
         data: "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        decoded: None,
+        block_number: tx.block_number,
     };
     diesel::insert_into(logs::table)
         .values(&log)
@@ -171,12 +214,13 @@ pub fn create_mock_token_transfer(conn: &mut PgConnection, tx: &Transaction) {
     let v = BigDecimal::from(500);
 
     let transfer = NewTokenTransfer {
-        tx_hash: &tx.hash,
-        token_address: "0xcccccccccccccccccccccccccccccccccccccccc", // Same as log address for consistency
-        from_address: &tx.from_address,
-        to_address: tx.to_address.as_ref().unwrap(),
+        tx_hash: tx.hash,
+        token_address: DbAddress(Address::repeat_byte(0xcc)), // Same as log address for consistency
+        from_address: tx.from_address,
+        to_address: tx.to_address.unwrap(),
         value: Some(&v),
         token_id: None,
+        block_number: tx.block_number,
     };
     diesel::insert_into(token_transfers::table)
         .values(&transfer)
@@ -184,13 +228,22 @@ pub fn create_mock_token_transfer(conn: &mut PgConnection, tx: &Transaction) {
         .expect("Failed to insert mock token transfer");
 }
 
-/// Inserts a mock account into the database with a deterministic timestamp.
-pub fn create_mock_account(conn: &mut PgConnection, address: &str) {
+/// Inserts a mock account into the database, first seen in a deterministic block.
+pub fn create_mock_account(conn: &mut PgConnection, address: Address) {
     let account = Account {
-        address: address.to_string(),
-        created_at: DateTime::parse_from_rfc3339("2025-07-19T12:00:00-03:00")
-            .unwrap()
-            .with_timezone(&Utc),
+        address: DbAddress(address),
+        first_seen_block: Some(1),
+        first_seen_tx: Some(DbHash(H256::from_low_u64_be(1))),
+        first_seen_at: Some(
+            DateTime::parse_from_rfc3339("2025-07-19T12:00:00-03:00")
+                .unwrap()
+                .with_timezone(&Utc),
+        ),
+        last_active_block: Some(1),
+        tx_sent_count: 1,
+        tx_received_count: 0,
+        is_contract: false,
+        code_checked: false,
     };
     diesel::insert_into(accounts::table)
         .values(&account)
@@ -198,3 +251,183 @@ pub fn create_mock_account(conn: &mut PgConnection, address: &str) {
         .execute(conn)
         .expect("Failed to insert mock account");
 }
+
+/// Builds an RPC block, as returned by `eth_getBlockByNumber`, containing the given transactions.
+pub fn mock_ethers_block(
+    block_number: u64,
+    transactions: Vec<EthersTransaction>,
+) -> EthersBlock<EthersTransaction> {
+    EthersBlock {
+        hash: Some(H256::from_low_u64_be(block_number)),
+        parent_hash: H256::from_low_u64_be(block_number - 1),
+        number: Some(U64::from(block_number)),
+        timestamp: U256::from(1_752_937_200 + block_number * 12),
+        author: Some(Address::repeat_byte(0x11)),
+        gas_used: U256::from(1_500_000),
+        gas_limit: U256::from(30_000_000),
+        base_fee_per_gas: Some(U256::from(50)),
+        transactions: transactions
+            .into_iter()
+            .map(|mut tx| {
+                tx.block_hash = Some(H256::from_low_u64_be(block_number));
+                tx.block_number = Some(U64::from(block_number));
+                tx
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+/// Builds an RPC transaction with a hash derived from its sender and position.
+pub fn mock_ethers_transaction(
+    from: Address,
+    to: Option<Address>,
+    index: u64,
+) -> EthersTransaction {
+    EthersTransaction {
+        hash: H256::from_low_u64_be((u64::from(from.0[0]) << 32) | index),
+        from,
+        to,
+        value: U256::from(1000),
+        nonce: U256::from(index),
+        transaction_index: Some(U64::from(index)),
+        input: Bytes::from(vec![0xa9, 0x05, 0x9c, 0xbb]),
+        ..Default::default()
+    }
+}
+
+/// Builds the receipt of a transaction emitting the given logs.
+pub fn mock_receipt(tx: &EthersTransaction, logs: Vec<EthersLog>) -> TransactionReceipt {
+    TransactionReceipt {
+        transaction_hash: tx.hash,
+        transaction_index: tx.transaction_index.unwrap_or_default(),
+        from: tx.from,
+        to: tx.to,
+        gas_used: Some(U256::from(21000)),
+        effective_gas_price: Some(U256::from(20)),
+        logs: logs
+            .into_iter()
+            .map(|mut log| {
+                log.transaction_hash = Some(tx.hash);
+                log
+            })
+            .collect(),
+        status: Some(U64::from(1)),
+        ..Default::default()
+    }
+}
+
+/// Builds an ERC20 `Transfer` log of `amount` `token`s from `from` to `to`.
+pub fn mock_transfer_log(token: Address, from: Address, to: Address, amount: u64) -> EthersLog {
+    let mut data = [0u8; 32];
+    U256::from(amount).to_big_endian(&mut data);
+    EthersLog {
+        address: token,
+        topics: vec![TRANSFER_EVENT_SIGNATURE, H256::from(from), H256::from(to)],
+        data: Bytes::from(data.to_vec()),
+        ..Default::default()
+    }
+}
+
+/// A JSON-RPC node serving fixed blocks over a websocket, to run the indexer without a chain.
+#[derive(Default)]
+pub struct MockNode {
+    blocks: HashMap<u64, EthersBlock<EthersTransaction>>,
+    receipts: HashMap<H256, TransactionReceipt>,
+    code: HashMap<Address, Bytes>,
+    /// `eth_call` results by contract and calldata.
+    calls: HashMap<(Address, Bytes), Bytes>,
+    /// The addresses of the `eth_getCode` requests served so far.
+    code_lookups: Arc<Mutex<Vec<Address>>>,
+}
+
+impl MockNode {
+    /// Adds a block, replacing the one of the same number, and the receipts of its transactions.
+    pub fn add_block(
+        &mut self,
+        block: EthersBlock<EthersTransaction>,
+        receipts: Vec<TransactionReceipt>,
+    ) {
+        for receipt in receipts {
+            self.receipts.insert(receipt.transaction_hash, receipt);
+        }
+        self.blocks.insert(block.number.unwrap().as_u64(), block);
+    }
+
+    pub fn set_code(&mut self, address: Address, code: Bytes) {
+        self.code.insert(address, code);
+    }
+
+    /// The addresses of the `eth_getCode` requests served so far, also once the node is served.
+    pub fn code_lookups(&self) -> Arc<Mutex<Vec<Address>>> {
+        self.code_lookups.clone()
+    }
+
+    pub fn set_call(&mut self, address: Address, data: Bytes, result: Bytes) {
+        self.calls.insert((address, data), result);
+    }
+
+    /// Serves the node on a local port until the test ends, returning its URL.
+    pub async fn serve(self) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let node = Arc::new(self);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let node = node.clone();
+                tokio::spawn(async move {
+                    let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+                    while let Some(Ok(Message::Text(text))) = socket.next().await {
+                        let request: Value = serde_json::from_str(&text).unwrap();
+                        let response = json!({
+                            "jsonrpc": "2.0",
+                            "id": request["id"],
+                            "result": node.respond(request["method"].as_str().unwrap(), &request["params"]),
+                        });
+                        if socket
+                            .send(Message::Text(response.to_string()))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn respond(&self, method: &str, params: &Value) -> Value {
+        let param = |i: usize| params[i].clone();
+        let address = || serde_json::from_value::<Address>(param(0)).unwrap();
+        match method {
+            "eth_chainId" => json!(U64::from(1)),
+            "eth_blockNumber" => json!(U64::from(self.blocks.keys().max().copied().unwrap_or(0))),
+            "eth_getBlockByNumber" => {
+                let number = serde_json::from_value::<U64>(param(0)).unwrap().as_u64();
+                json!(self.blocks.get(&number))
+            }
+            "eth_getTransactionReceipt" => {
+                json!(self
+                    .receipts
+                    .get(&serde_json::from_value::<H256>(param(0)).unwrap()))
+            }
+            "eth_getCode" => {
+                self.code_lookups.lock().unwrap().push(address());
+                json!(self.code.get(&address()).cloned().unwrap_or_default())
+            }
+            "eth_getStorageAt" => json!(H256::zero()),
+            "eth_call" => {
+                let to = serde_json::from_value::<Address>(params[0]["to"].clone()).unwrap();
+                let data = params[0]
+                    .get("input")
+                    .or(params[0].get("data"))
+                    .map(|data| serde_json::from_value::<Bytes>(data.clone()).unwrap())
+                    .unwrap_or_default();
+                json!(self.calls.get(&(to, data)).cloned().unwrap_or_default())
+            }
+            _ => panic!("Unexpected RPC call {}", method),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use super::environment::{mock_ethers_block, mock_ethers_transaction, mock_receipt, TestDb};
+use crate::{
+    filter::IndexFilter,
+    indexer::{process_block_data, IndexerContext, TRANSFER_EVENT_SIGNATURE},
+    models::{DbAddress, DbHash},
+    schema::{blocks, logs, transactions},
+};
+use diesel::prelude::*;
+use ethers::types::{Address, Log as EthersLog, H256};
+use std::{collections::HashMap, sync::Arc};
+
+fn log_from(address: Address, topic0: H256) -> EthersLog {
+    EthersLog {
+        address,
+        topics: vec![topic0],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_filter_matching() {
+    let watched = Address::repeat_byte(0xcc);
+    let filter: IndexFilter = serde_json::from_value(serde_json::json!({
+        "addresses": [format!("{:#x}", watched)],
+        "from": [format!("{:#x}", Address::repeat_byte(0xaa))],
+    }))
+    .unwrap();
+
+    assert!(filter.matches_log(&log_from(watched, TRANSFER_EVENT_SIGNATURE)));
+    assert!(!filter.matches_log(&log_from(
+        Address::repeat_byte(0xdd),
+        TRANSFER_EVENT_SIGNATURE
+    )));
+
+    let sent = mock_ethers_transaction(Address::repeat_byte(0xaa), None, 0);
+    let called = mock_ethers_transaction(Address::repeat_byte(0xbb), Some(watched), 1);
+    let unrelated = mock_ethers_transaction(Address::repeat_byte(0xbb), Some(Address::zero()), 2);
+    assert!(filter.matches_transaction(&sent));
+    assert!(filter.matches_transaction(&called));
+    assert!(!filter.matches_transaction(&unrelated));
+
+    // Topic-only filters match the event from any contract.
+    let topics_only = IndexFilter {
+        topics: [TRANSFER_EVENT_SIGNATURE].into(),
+        ..Default::default()
+    };
+    assert!(topics_only.matches_log(&log_from(Address::zero(), TRANSFER_EVENT_SIGNATURE)));
+    assert!(!topics_only.matches_log(&log_from(Address::zero(), H256::zero())));
+
+    // Filters on senders and recipients alone can't be expressed with `eth_getLogs`.
+    let senders_only = IndexFilter {
+        from: filter.from.clone(),
+        ..Default::default()
+    };
+    assert!(!senders_only.matches_log(&log_from(watched, TRANSFER_EVENT_SIGNATURE)));
+    assert!(senders_only.log_filter(0, 10).is_none());
+    assert!(filter.log_filter(0, 10).is_some());
+}
+
+#[test]
+fn test_unknown_filter_fields_are_rejected() {
+    let result: Result<IndexFilter, _> =
+        serde_json::from_value(serde_json::json!({ "address": [] }));
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_process_block_data_with_filter() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let watched = Address::repeat_byte(0xcc);
+
+    let context = IndexerContext {
+        filter: Some(Arc::new(IndexFilter {
+            addresses: [watched].into(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    // One transaction emits a watched log next to an unrelated one, the other is unrelated.
+    let emitter = mock_ethers_transaction(Address::repeat_byte(0xaa), Some(Address::zero()), 0);
+    let unrelated = mock_ethers_transaction(Address::repeat_byte(0xbb), Some(Address::zero()), 1);
+    let block = mock_ethers_block(7, vec![emitter.clone(), unrelated.clone()]);
+    let receipts = vec![
+        (
+            emitter.clone(),
+            mock_receipt(
+                &emitter,
+                vec![
+                    log_from(watched, H256::zero()),
+                    log_from(Address::repeat_byte(0xdd), H256::zero()),
+                ],
+            ),
+        ),
+        (unrelated.clone(), mock_receipt(&unrelated, vec![])),
+    ];
+
+    process_block_data(&mut conn, &context, &block, &receipts, &HashMap::new()).unwrap();
+
+    // The header is kept for continuity, with the block's real transaction count.
+    let tx_count: i32 = blocks::table
+        .select(blocks::tx_count)
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(tx_count, 2);
+
+    let stored_txs: Vec<DbHash> = transactions::table
+        .select(transactions::hash)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(stored_txs, vec![DbHash(emitter.hash)]);
+
+    let stored_logs: Vec<DbAddress> = logs::table.select(logs::address).load(&mut conn).unwrap();
+    assert_eq!(stored_logs, vec![DbAddress(watched)]);
+}
@@ -0,0 +1,173 @@
+use super::environment::{create_mock_block, create_mock_transaction, mock_transfer_log, TestDb};
+use crate::{
+    handlers::{EventHandler, HandlerMigration, HandlerRegistry},
+    indexer::{process_log, rollback_block, IndexerContext, TRANSFER_EVENT_SIGNATURE},
+    models::DbAddress,
+    schema::{logs, token_balances},
+};
+use anyhow::Result;
+use bigdecimal::BigDecimal;
+use common::DecodedLog;
+use diesel::{prelude::*, sql_types::Text};
+use ethers::types::{Address, Log as EthersLog, H256};
+use std::sync::Arc;
+
+/// Records every `Transfer` emitted by one token into its own table.
+struct TransferRecorder {
+    token: Address,
+}
+
+impl EventHandler for TransferRecorder {
+    fn name(&self) -> &str {
+        "transfer_recorder"
+    }
+
+    fn addresses(&self) -> Vec<Address> {
+        vec![self.token]
+    }
+
+    fn topics(&self) -> Vec<H256> {
+        vec![TRANSFER_EVENT_SIGNATURE]
+    }
+
+    fn handle(
+        &self,
+        connection: &mut PgConnection,
+        log: &EthersLog,
+        _decoded: Option<&DecodedLog>,
+    ) -> Result<()> {
+        diesel::sql_query("INSERT INTO recorded_transfers (tx_hash) VALUES ($1)")
+            .bind::<Text, _>(format!("{:#x}", log.transaction_hash.unwrap_or_default()))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    fn rollback(
+        &self,
+        connection: &mut PgConnection,
+        log: &EthersLog,
+        _decoded: Option<&DecodedLog>,
+    ) -> Result<()> {
+        diesel::sql_query("DELETE FROM recorded_transfers WHERE tx_hash = $1")
+            .bind::<Text, _>(format!("{:#x}", log.transaction_hash.unwrap_or_default()))
+            .execute(connection)?;
+        Ok(())
+    }
+
+    fn migrations(&self) -> Vec<HandlerMigration> {
+        vec![HandlerMigration {
+            version: "2025-07-23-create_recorded_transfers",
+            up: "CREATE TABLE recorded_transfers (tx_hash TEXT NOT NULL)",
+        }]
+    }
+}
+
+fn transfer_log(token: Address, tx_hash: H256, amount: u64) -> EthersLog {
+    let from = Address::repeat_byte(0xaa);
+    EthersLog {
+        transaction_hash: Some(tx_hash),
+        ..mock_transfer_log(token, from, Address::repeat_byte(0xbb), amount)
+    }
+}
+
+fn recorded_count(conn: &mut PgConnection) -> i64 {
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = diesel::sql_types::BigInt)]
+        count: i64,
+    }
+    diesel::sql_query("SELECT COUNT(*) AS count FROM recorded_transfers")
+        .get_result::<Count>(conn)
+        .unwrap()
+        .count
+}
+
+#[test]
+fn test_handler_dispatch_and_rollback() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let token = Address::repeat_byte(0xcc);
+
+    let mut handlers = HandlerRegistry::default();
+    handlers.register(TransferRecorder { token });
+    handlers.run_migrations(&mut conn).unwrap();
+    // Applied migrations are not run again.
+    handlers.run_migrations(&mut conn).unwrap();
+
+    let context = IndexerContext {
+        handlers: Arc::new(handlers),
+        ..Default::default()
+    };
+
+    let mock_block = create_mock_block(&mut conn, 1);
+    let mock_tx = create_mock_transaction(&mut conn, &mock_block, 0);
+
+    // Only logs from the handler's token are dispatched to it.
+    process_log(
+        &mut conn,
+        &context,
+        mock_tx.hash.0,
+        mock_tx.block_number,
+        &transfer_log(token, mock_tx.hash.0, 10),
+    )
+    .unwrap();
+    let other = Address::repeat_byte(0xdd);
+    process_log(
+        &mut conn,
+        &context,
+        mock_tx.hash.0,
+        mock_tx.block_number,
+        &transfer_log(other, mock_tx.hash.0, 10),
+    )
+    .unwrap();
+    assert_eq!(recorded_count(&mut conn), 1);
+
+    rollback_block(&mut conn, &context, mock_block.hash.0).unwrap();
+    assert_eq!(recorded_count(&mut conn), 0);
+    let remaining_logs: i64 = logs::table.count().get_result(&mut conn).unwrap();
+    assert_eq!(remaining_logs, 0);
+}
+
+#[test]
+fn test_rollback_block_reverts_token_balances() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let context = IndexerContext::default();
+    let token = Address::repeat_byte(0xcc);
+    let sender = DbAddress(Address::repeat_byte(0xaa));
+    let recipient = DbAddress(Address::repeat_byte(0xbb));
+
+    diesel::insert_into(token_balances::table)
+        .values((
+            token_balances::owner_address.eq(sender),
+            token_balances::token_address.eq(DbAddress(token)),
+            token_balances::amount.eq(BigDecimal::from(1000)),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+
+    let mock_block = create_mock_block(&mut conn, 1);
+    let mock_tx = create_mock_transaction(&mut conn, &mock_block, 0);
+    process_log(
+        &mut conn,
+        &context,
+        mock_tx.hash.0,
+        mock_tx.block_number,
+        &transfer_log(token, mock_tx.hash.0, 300),
+    )
+    .unwrap();
+
+    let balance = |conn: &mut PgConnection, owner: DbAddress| -> BigDecimal {
+        token_balances::table
+            .filter(token_balances::owner_address.eq(owner))
+            .select(token_balances::amount)
+            .first(conn)
+            .unwrap()
+    };
+    assert_eq!(balance(&mut conn, sender), BigDecimal::from(700));
+    assert_eq!(balance(&mut conn, recipient), BigDecimal::from(300));
+
+    rollback_block(&mut conn, &context, mock_block.hash.0).unwrap();
+    assert_eq!(balance(&mut conn, sender), BigDecimal::from(1000));
+    assert_eq!(balance(&mut conn, recipient), BigDecimal::from(0));
+}
@@ -1,16 +1,21 @@
-use super::environment::{create_mock_block, create_mock_transaction, TestDb};
+use super::environment::{
+    create_mock_block, create_mock_transaction, mock_ethers_block, mock_ethers_transaction,
+    mock_receipt, TestDb,
+};
 use crate::{
+    contracts::DeployedContract,
     indexer::{
-        ensure_account_exists, get_latest_indexed_block, process_log, process_token_transfer,
-        u256_to_bigdecimal, TRANSFER_EVENT_SIGNATURE,
+        get_block_hash, get_latest_indexed_block, process_block_data, process_log,
+        process_token_transfer, rollback_block, u256_to_bigdecimal, IndexerContext,
+        TRANSFER_EVENT_SIGNATURE,
     },
-    models,
-    schema::{accounts, logs, token_balances, token_transfers},
+    models::{self, DbAddress, DbHash},
+    schema::{accounts, logs, token_balances, token_transfers, transactions},
 };
 use bigdecimal::BigDecimal;
 use diesel::prelude::*;
 use ethers::types::{Address, Bytes, Log as EthersLog, H256, U256};
-use std::str::FromStr;
+use std::{collections::HashMap, str::FromStr};
 
 #[test]
 fn test_u256_to_bigdecimal_conversion() {
@@ -30,24 +35,103 @@ fn test_u256_to_bigdecimal_conversion() {
 }
 
 #[test]
-fn test_ensure_account_exists() {
+fn test_account_activity_tracks_chain_time() {
     let db = TestDb::new();
     let mut conn = db.pool.get().unwrap();
-    let addr = "0x1234567890123456789012345678901234567890";
-
-    // First time, account should be created.
-    ensure_account_exists(&mut conn, addr).unwrap();
-    let count: i64 = accounts::table
-        .filter(accounts::address.eq(addr))
-        .count()
-        .get_result(&mut conn)
-        .unwrap();
-    assert_eq!(count, 1);
+    let context = IndexerContext::default();
+    let sender = Address::repeat_byte(0xaa);
+    let contract = Address::repeat_byte(0xcc);
+    let load = |conn: &mut PgConnection, address: Address| -> models::Account {
+        accounts::table
+            .find(DbAddress(address))
+            .first(conn)
+            .unwrap()
+    };
 
-    // Second time, should do nothing.
-    ensure_account_exists(&mut conn, addr).unwrap();
-    let count: i64 = accounts::table.count().get_result(&mut conn).unwrap();
-    assert_eq!(count, 1);
+    // Block 5 is indexed first; block 3 is backfilled afterwards.
+    let later = mock_ethers_transaction(sender, Some(contract), 0);
+    let later_block = mock_ethers_block(5, vec![later.clone()]);
+    let contracts = HashMap::from([(
+        contract,
+        DeployedContract {
+            code: Bytes::from(vec![0x60, 0x00]),
+            proxy: None,
+            token: None,
+        },
+    )]);
+    process_block_data(
+        &mut conn,
+        &context,
+        &later_block,
+        &[(later.clone(), mock_receipt(&later, vec![]))],
+        &contracts,
+    )
+    .unwrap();
+
+    let mut earlier = mock_ethers_transaction(sender, None, 1);
+    earlier.input = Bytes::from(vec![0x60, 0x80, 0x60, 0x40]);
+    let created = Address::repeat_byte(0xdd);
+    let mut receipt = mock_receipt(&earlier, vec![]);
+    receipt.contract_address = Some(created);
+    let earlier_block = mock_ethers_block(3, vec![earlier.clone()]);
+    process_block_data(
+        &mut conn,
+        &context,
+        &earlier_block,
+        &[(earlier.clone(), receipt)],
+        &HashMap::new(),
+    )
+    .unwrap();
+
+    let account = load(&mut conn, sender);
+    assert_eq!(account.first_seen_block, Some(3));
+    assert_eq!(account.first_seen_tx, Some(DbHash(earlier.hash)));
+    assert_eq!(
+        account.first_seen_at.unwrap().timestamp(),
+        earlier_block.timestamp.as_u64() as i64
+    );
+    assert_eq!(account.last_active_block, Some(5));
+    assert_eq!((account.tx_sent_count, account.tx_received_count), (2, 0));
+    assert!(!account.is_contract);
+
+    let called = load(&mut conn, contract);
+    assert_eq!((called.tx_sent_count, called.tx_received_count), (0, 1));
+    assert!(called.is_contract);
+
+    let deployed = load(&mut conn, created);
+    assert_eq!(deployed.first_seen_tx, Some(DbHash(earlier.hash)));
+    assert!(deployed.is_contract);
+
+    // Only calls have a method id; a creation's input is init code.
+    let method_id = |conn: &mut PgConnection, hash: H256| -> Option<String> {
+        transactions::table
+            .find(DbHash(hash))
+            .select(transactions::method_id)
+            .first(conn)
+            .unwrap()
+    };
+    assert_eq!(
+        method_id(&mut conn, later.hash),
+        Some("0xa9059cbb".to_string())
+    );
+    assert_eq!(method_id(&mut conn, earlier.hash), None);
+
+    // Rolling back block 5 leaves only the activity of block 3, and drops the account only
+    // block 5 had seen.
+    rollback_block(&mut conn, &context, later_block.hash.unwrap()).unwrap();
+    let account = load(&mut conn, sender);
+    assert_eq!(account.first_seen_block, Some(3));
+    assert_eq!(account.last_active_block, Some(3));
+    assert_eq!(account.tx_sent_count, 1);
+    let called: Option<models::Account> = accounts::table
+        .find(DbAddress(contract))
+        .first(&mut conn)
+        .optional()
+        .unwrap();
+    assert!(called.is_none());
+    let deployed = load(&mut conn, created);
+    assert_eq!(deployed.first_seen_block, Some(3));
+    assert_eq!(deployed.last_active_block, Some(3));
 }
 
 #[test]
@@ -70,6 +154,17 @@ fn test_get_latest_indexed_block() {
     assert_eq!(latest, Some(505));
 }
 
+#[test]
+fn test_get_block_hash() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+
+    assert_eq!(get_block_hash(&mut conn, 42).unwrap(), None);
+
+    let block = create_mock_block(&mut conn, 42);
+    assert_eq!(get_block_hash(&mut conn, 42).unwrap(), Some(block.hash.0));
+}
+
 #[test]
 fn test_process_log_insertion() {
     let db = TestDb::new();
@@ -92,18 +187,19 @@ fn test_process_log_insertion() {
         ..Default::default()
     };
 
-    process_log(&mut conn, &mock_tx.hash, &eth_log).unwrap();
+    process_log(
+        &mut conn,
+        &IndexerContext::default(),
+        mock_tx.hash.0,
+        mock_tx.block_number,
+        &eth_log,
+    )
+    .unwrap();
 
     let inserted_log = logs::table.first::<models::Log>(&mut conn).unwrap();
     assert_eq!(inserted_log.tx_hash, mock_tx.hash);
-    assert_eq!(
-        inserted_log.address,
-        "0xcccccccccccccccccccccccccccccccccccccccc"
-    );
-    assert_eq!(
-        inserted_log.topic0,
-        Some("0xdddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddddd".to_string())
-    );
+    assert_eq!(inserted_log.address, DbAddress(Address::repeat_byte(0xcc)));
+    assert_eq!(inserted_log.topic0, Some(DbHash(H256::repeat_byte(0xdd))));
     assert_eq!(inserted_log.data, "0xff");
 }
 
@@ -128,8 +224,8 @@ fn test_process_erc20_token_transfer() {
     // Setup initial balance for the sender
     diesel::insert_into(token_balances::table)
         .values((
-            token_balances::owner_address.eq(format!("{:#x}", from_addr)),
-            token_balances::token_address.eq(format!("{:#x}", token_addr)),
+            token_balances::owner_address.eq(DbAddress(from_addr)),
+            token_balances::token_address.eq(DbAddress(token_addr)),
             token_balances::amount.eq(BigDecimal::from(1000)),
         ))
         .execute(&mut conn)
@@ -149,7 +245,7 @@ fn test_process_erc20_token_transfer() {
         ..Default::default()
     };
 
-    process_token_transfer(&mut conn, &mock_tx.hash, &eth_log).unwrap();
+    process_token_transfer(&mut conn, mock_tx.hash.0, mock_tx.block_number, &eth_log).unwrap();
 
     // Verify transfer record
     let transfer = token_transfers::table
@@ -160,14 +256,14 @@ fn test_process_erc20_token_transfer() {
 
     // Verify final balances
     let from_balance: BigDecimal = token_balances::table
-        .filter(token_balances::owner_address.eq(format!("{:#x}", from_addr)))
+        .filter(token_balances::owner_address.eq(DbAddress(from_addr)))
         .select(token_balances::amount)
         .first(&mut conn)
         .unwrap();
     assert_eq!(from_balance, BigDecimal::from(500)); // 1000 - 500
 
     let to_balance: BigDecimal = token_balances::table
-        .filter(token_balances::owner_address.eq(format!("{:#x}", to_addr)))
+        .filter(token_balances::owner_address.eq(DbAddress(to_addr)))
         .select(token_balances::amount)
         .first(&mut conn)
         .unwrap();
@@ -195,8 +291,8 @@ fn test_process_erc721_token_transfer() {
     // Setup initial balance for the sender
     diesel::insert_into(token_balances::table)
         .values((
-            token_balances::owner_address.eq(format!("{:#x}", from_addr)),
-            token_balances::token_address.eq(format!("{:#x}", token_addr)),
+            token_balances::owner_address.eq(DbAddress(from_addr)),
+            token_balances::token_address.eq(DbAddress(token_addr)),
             token_balances::amount.eq(BigDecimal::from(1)),
             token_balances::token_id.eq(Some(BigDecimal::from(721))),
         ))
@@ -217,7 +313,7 @@ fn test_process_erc721_token_transfer() {
         ..Default::default()
     };
 
-    process_token_transfer(&mut conn, &mock_tx.hash, &eth_log).unwrap();
+    process_token_transfer(&mut conn, mock_tx.hash.0, mock_tx.block_number, &eth_log).unwrap();
 
     // Verify transfer record
     let transfer = token_transfers::table
@@ -228,7 +324,7 @@ fn test_process_erc721_token_transfer() {
 
     // Verify final balances
     let from_balance: BigDecimal = token_balances::table
-        .filter(token_balances::owner_address.eq(format!("{:#x}", from_addr)))
+        .filter(token_balances::owner_address.eq(DbAddress(from_addr)))
         .filter(token_balances::token_id.eq(Some(BigDecimal::from(721))))
         .select(token_balances::amount)
         .first(&mut conn)
@@ -236,7 +332,7 @@ fn test_process_erc721_token_transfer() {
     assert_eq!(from_balance, BigDecimal::from(0)); // 1 - 1
 
     let to_balance: models::TokenBalance = token_balances::table
-        .filter(token_balances::owner_address.eq(format!("{:#x}", to_addr)))
+        .filter(token_balances::owner_address.eq(DbAddress(to_addr)))
         .first(&mut conn)
         .unwrap();
     assert_eq!(to_balance.amount, BigDecimal::from(1));
@@ -0,0 +1,52 @@
+use super::environment::{revert_migrations_through, TestDb};
+use crate::{
+    indexes::{create_query_indexes, defer_query_indexes, missing_query_indexes, QUERY_INDEXES},
+    MIGRATIONS,
+};
+use diesel::{prelude::*, sql_types::Text};
+use diesel_migrations::MigrationHarness;
+
+const INDEXES_MIGRATION: &str = "20250731090000";
+
+#[derive(QueryableByName)]
+struct Plan {
+    #[diesel(sql_type = Text, column_name = "QUERY PLAN")]
+    line: String,
+}
+
+#[test]
+fn test_migration_creates_query_indexes() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    assert_eq!(missing_query_indexes(&mut conn).unwrap(), vec![]);
+
+    // The block's transactions are looked up through the index rather than a sequential scan.
+    diesel::sql_query("SET enable_seqscan = off")
+        .execute(&mut conn)
+        .unwrap();
+    let plan = diesel::sql_query("EXPLAIN SELECT * FROM transactions WHERE block_hash = '\\x00'")
+        .load::<Plan>(&mut conn)
+        .unwrap();
+    assert!(plan
+        .iter()
+        .any(|row| row.line.contains("transactions_block_hash_idx")));
+}
+
+#[test]
+fn test_deferred_query_indexes() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+
+    revert_migrations_through(&mut conn, INDEXES_MIGRATION);
+    defer_query_indexes(&mut conn).unwrap();
+    conn.run_pending_migrations(MIGRATIONS).unwrap();
+    assert_eq!(missing_query_indexes(&mut conn).unwrap(), QUERY_INDEXES);
+
+    assert_eq!(
+        create_query_indexes(&mut conn, true).unwrap(),
+        QUERY_INDEXES.len()
+    );
+    assert_eq!(missing_query_indexes(&mut conn).unwrap(), vec![]);
+    // Nothing is left to build on a second run.
+    assert_eq!(create_query_indexes(&mut conn, false).unwrap(), 0);
+}
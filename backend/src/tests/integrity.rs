@@ -0,0 +1,193 @@
+use super::environment::{
+    create_mock_block, create_mock_log, create_mock_transaction, mock_ethers_transaction,
+    mock_receipt, TestDb,
+};
+use crate::integrity::{
+    check_stored_rows, encode_receipt, logs_bloom, ordered_trie_root, parse_block_range, trie_root,
+    verify_block_data, HeaderRoots,
+};
+use ethers::{
+    types::{
+        Address, Bloom, Bytes, Log as EthersLog, Transaction as EthersTransaction,
+        TransactionReceipt, H256, U256, U64,
+    },
+    utils::keccak256,
+};
+use std::str::FromStr;
+
+fn h256(hex: &str) -> H256 {
+    H256::from_str(hex).unwrap()
+}
+
+/// A legacy transaction whose hash matches its encoding.
+fn signed_transaction(index: u64) -> EthersTransaction {
+    let mut tx = mock_ethers_transaction(
+        Address::repeat_byte(0xaa),
+        Some(Address::repeat_byte(0xbb)),
+        index,
+    );
+    tx.gas = U256::from(21000);
+    tx.gas_price = Some(U256::from(20));
+    tx.v = U64::from(27);
+    tx.r = U256::from(1);
+    tx.s = U256::from(2);
+    tx.hash = H256(keccak256(tx.rlp()));
+    tx
+}
+
+fn opaque_log(byte: u8) -> EthersLog {
+    EthersLog {
+        address: Address::repeat_byte(0xcc),
+        topics: vec![H256::repeat_byte(byte)],
+        data: Bytes::from(vec![byte; 32]),
+        ..Default::default()
+    }
+}
+
+/// Builds a block's transactions and receipts along with the header roots they commit to.
+fn block_data() -> (HeaderRoots, Vec<(EthersTransaction, TransactionReceipt)>) {
+    let mut cumulative_gas = U256::zero();
+    let data: Vec<_> = (0..2)
+        .map(|index| {
+            let tx = signed_transaction(index);
+            let mut receipt = mock_receipt(&tx, vec![opaque_log(index as u8 + 1)]);
+            cumulative_gas += U256::from(21000);
+            receipt.cumulative_gas_used = cumulative_gas;
+            receipt.logs_bloom = logs_bloom(&receipt.logs);
+            (tx, receipt)
+        })
+        .collect();
+
+    let mut bloom = Bloom::zero();
+    for (_, receipt) in &data {
+        bloom.accrue_bloom(&receipt.logs_bloom);
+    }
+    let roots = HeaderRoots {
+        transactions_root: ordered_trie_root(data.iter().map(|(tx, _)| tx.rlp().to_vec())),
+        receipts_root: ordered_trie_root(
+            data.iter()
+                .map(|(_, receipt)| encode_receipt(receipt).unwrap().unwrap()),
+        ),
+        logs_bloom: Some(bloom),
+    };
+    (roots, data)
+}
+
+#[test]
+fn test_trie_root_vectors() {
+    let entries = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        trie_root(Vec::new()),
+        h256("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")
+    );
+    assert_eq!(
+        trie_root(entries(&[
+            ("doe", "reindeer"),
+            ("dog", "puppy"),
+            ("dogglesworth", "cat"),
+        ])),
+        h256("0x8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3")
+    );
+    // Insertion order doesn't matter, and "do" ends inside a branch.
+    assert_eq!(
+        trie_root(entries(&[
+            ("horse", "stallion"),
+            ("do", "verb"),
+            ("doge", "coin"),
+            ("dog", "puppy"),
+        ])),
+        h256("0x5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84")
+    );
+}
+
+#[test]
+fn test_verify_block_data() {
+    let (roots, data) = block_data();
+    verify_block_data(&roots, &data).unwrap();
+
+    // An empty block commits to empty tries.
+    let empty = HeaderRoots {
+        transactions_root: trie_root(Vec::new()),
+        receipts_root: trie_root(Vec::new()),
+        logs_bloom: Some(Bloom::zero()),
+    };
+    verify_block_data(&empty, &[]).unwrap();
+
+    // A provider dropping a transaction.
+    assert!(verify_block_data(&roots, &data[..1]).is_err());
+
+    // A transaction whose fields don't match its hash.
+    let mut altered = data.clone();
+    altered[0].0.value = U256::from(1_000_000);
+    let error = verify_block_data(&roots, &altered).unwrap_err();
+    assert!(error.to_string().contains("doesn't match its hash"));
+
+    // A log altered along with its receipt's bloom.
+    let mut altered = data.clone();
+    altered[1].1.logs[0].data = Bytes::from(vec![0x00]);
+    let error = verify_block_data(&roots, &altered).unwrap_err();
+    assert!(error.to_string().contains("receipts root"));
+
+    // A log whose address no longer matches the receipt's bloom.
+    let mut altered = data.clone();
+    altered[1].1.logs[0].address = Address::repeat_byte(0xdd);
+    let error = verify_block_data(&roots, &altered).unwrap_err();
+    assert!(error.to_string().contains("logs bloom"));
+
+    // A header bloom that doesn't cover the receipts.
+    let mut wrong_bloom = roots.clone();
+    wrong_bloom.logs_bloom = Some(Bloom::zero());
+    assert!(verify_block_data(&wrong_bloom, &data).is_err());
+}
+
+#[test]
+fn test_check_stored_rows() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let block = create_mock_block(&mut conn, 1);
+    let stored_tx = create_mock_transaction(&mut conn, &block, 0);
+    create_mock_log(&mut conn, &stored_tx);
+
+    let mut tx = mock_ethers_transaction(
+        Address::repeat_byte(0xaa),
+        Some(Address::repeat_byte(0xbb)),
+        0,
+    );
+    tx.hash = stored_tx.hash.0;
+    tx.value = U256::exp10(18);
+    tx.nonce = U256::from(1);
+    let log = EthersLog {
+        address: Address::repeat_byte(0xcc),
+        topics: vec![H256::repeat_byte(0xdd), H256::repeat_byte(0xee)],
+        data: Bytes::from(vec![0xff; 32]),
+        ..Default::default()
+    };
+    let receipt = mock_receipt(&tx, vec![log]);
+    let mut data = vec![(tx, receipt)];
+    check_stored_rows(&mut conn, &block, &data).unwrap();
+
+    // The stored log is no longer what the chain has.
+    data[0].1.logs[0].topics.pop();
+    let error = check_stored_rows(&mut conn, &block, &data).unwrap_err();
+    assert!(error.to_string().contains("stored log"));
+
+    data[0].0.value = U256::one();
+    let error = check_stored_rows(&mut conn, &block, &data).unwrap_err();
+    assert!(error.to_string().contains("differs from the chain"));
+}
+
+#[test]
+fn test_parse_block_range() {
+    assert_eq!(parse_block_range("10..20"), Ok(10..=20));
+    assert_eq!(parse_block_range("10..=20"), Ok(10..=20));
+    assert_eq!(parse_block_range("7..7"), Ok(7..=7));
+    assert!(parse_block_range("20..10").is_err());
+    assert!(parse_block_range("10-20").is_err());
+    assert!(parse_block_range("a..b").is_err());
+}
@@ -0,0 +1,132 @@
+use super::environment::{mock_ethers_block, mock_ethers_transaction, mock_receipt, TestDb};
+use crate::{
+    db::PoolConfig,
+    indexer::{block_row, IndexerContext},
+    l2::{l2_fields, DEPOSIT_TX_TYPE},
+    models::{DbHash, L2Fields},
+    store::{SharedStore, SqliteStore},
+};
+use bigdecimal::BigDecimal;
+use ethers::types::{Address, Transaction as EthersTransaction, TransactionReceipt, H256};
+use serde_json::json;
+use std::{collections::HashMap, sync::Arc};
+
+const SOURCE_HASH: H256 = H256::repeat_byte(0x5e);
+
+/// An OP-stack deposit as returned by `eth_getTransactionByHash`.
+fn deposit_transaction() -> EthersTransaction {
+    serde_json::from_value(json!({
+        "hash": format!("{:#x}", H256::repeat_byte(0xde)),
+        "blockHash": null,
+        "blockNumber": null,
+        "transactionIndex": "0x0",
+        "from": "0xdeaddeaddeaddeaddeaddeaddeaddeaddead0001",
+        "to": "0x4200000000000000000000000000000000000015",
+        "value": "0x0",
+        "gas": "0xf4240",
+        "gasPrice": "0x0",
+        "input": "0x440a5e20",
+        "nonce": "0x2a",
+        "type": "0x7e",
+        "v": "0x0",
+        "r": "0x0",
+        "s": "0x0",
+        "sourceHash": format!("{:#x}", SOURCE_HASH),
+        "mint": "0xde0b6b3a7640000",
+        "isSystemTx": false,
+        "depositReceiptVersion": "0x1"
+    }))
+    .unwrap()
+}
+
+/// A transaction of an OP-stack chain, whose receipt carries the L1 data fee.
+fn op_transaction() -> (EthersTransaction, TransactionReceipt) {
+    let tx = mock_ethers_transaction(Address::repeat_byte(0xaa), Some(Address::zero()), 1);
+    let mut receipt = mock_receipt(&tx, vec![]);
+    receipt.other.insert("l1Fee".into(), json!("0x3e8"));
+    receipt.other.insert("l1GasUsed".into(), json!("0x640"));
+    receipt.other.insert("l1GasPrice".into(), json!("0x7"));
+    receipt.other.insert("l1FeeScalar".into(), json!("0.684"));
+    (tx, receipt)
+}
+
+#[test]
+fn test_l2_fields_are_parsed() {
+    let (tx, receipt) = op_transaction();
+    let fields = l2_fields(&tx, &receipt).unwrap();
+    assert_eq!(fields.l1_fee, Some(BigDecimal::from(1000)));
+    assert_eq!(fields.l1_gas_used, Some(BigDecimal::from(1600)));
+    assert_eq!(fields.l1_gas_price, Some(BigDecimal::from(7)));
+    assert_eq!(fields.deposit_source_hash, None);
+
+    let deposit = deposit_transaction();
+    assert_eq!(
+        deposit.transaction_type.unwrap().as_u64() as i16,
+        DEPOSIT_TX_TYPE
+    );
+    let fields = l2_fields(&deposit, &mock_receipt(&deposit, vec![])).unwrap();
+    assert_eq!(fields.deposit_source_hash, Some(DbHash(SOURCE_HASH)));
+    assert_eq!(
+        fields.deposit_mint,
+        Some(BigDecimal::from(1_000_000_000_000_000_000u64))
+    );
+
+    // Arbitrum receipts report the L1 gas and block instead.
+    let mut receipt = mock_receipt(&tx, vec![]);
+    receipt.other.insert("gasUsedForL1".into(), json!("0x1f4"));
+    receipt
+        .other
+        .insert("l1BlockNumber".into(), json!("0x1312d00"));
+    let fields = l2_fields(&tx, &receipt).unwrap();
+    assert_eq!(fields.gas_used_for_l1, Some(BigDecimal::from(500)));
+    assert_eq!(fields.l1_block_number, Some(20_000_000));
+    assert_eq!(fields.l1_fee, None);
+
+    // L1 transactions have none of them.
+    let l1_fields = l2_fields(&tx, &mock_receipt(&tx, vec![])).unwrap();
+    assert_eq!(l1_fields, L2Fields::default());
+}
+
+#[test]
+fn test_l2_fields_are_stored() {
+    let db = TestDb::new();
+    let stores: [SharedStore; 2] = [
+        db.store(),
+        Arc::new(SqliteStore::open(":memory:", &PoolConfig::default()).unwrap()),
+    ];
+
+    let deposit = deposit_transaction();
+    let (tx, receipt) = op_transaction();
+    let transactions_with_receipts = vec![
+        (deposit.clone(), mock_receipt(&deposit, vec![])),
+        (tx.clone(), receipt),
+    ];
+    let block = mock_ethers_block(1, vec![deposit.clone(), tx.clone()]);
+    for store in stores {
+        store
+            .write_block(
+                &IndexerContext::default(),
+                &block_row(&block).unwrap(),
+                &transactions_with_receipts,
+                &HashMap::new(),
+            )
+            .unwrap();
+
+        let stored: common::Transaction = store
+            .transaction(DbHash(deposit.hash))
+            .unwrap()
+            .unwrap()
+            .into();
+        assert_eq!(stored.tx_type, Some(DEPOSIT_TX_TYPE));
+        assert_eq!(
+            stored.deposit_source_hash,
+            Some(format!("{:#x}", SOURCE_HASH))
+        );
+
+        // The fee includes the L1 data fee: 21000 gas at 20 wei, plus 1000 wei.
+        let stored: common::Transaction =
+            store.transaction(DbHash(tx.hash)).unwrap().unwrap().into();
+        assert_eq!(stored.l1_fee, Some(BigDecimal::from(1000)));
+        assert_eq!(stored.fee, Some(BigDecimal::from(21000 * 20 + 1000)));
+    }
+}
@@ -0,0 +1,131 @@
+use super::environment::{
+    mock_ethers_block, mock_ethers_transaction, mock_receipt, mock_transfer_log, TestDb,
+};
+use crate::{
+    filter::IndexFilter,
+    indexer::{get_indexer_state, process_block_data, IndexerContext},
+    log_indexer::{is_range_too_large, store_log_range_block, LOG_CURSOR_KEY},
+    models::{DbAddress, DbHash},
+    schema::{blocks, logs, token_transfers, transactions},
+};
+use bigdecimal::BigDecimal;
+use diesel::prelude::*;
+use ethers::types::{Address, Block as EthersBlock, H256};
+use std::{collections::HashMap, sync::Arc};
+
+#[test]
+fn test_is_range_too_large() {
+    assert!(is_range_too_large(
+        "(code: -32005, message: query returned more than 10000 results, data: None)"
+    ));
+    assert!(is_range_too_large(
+        "Log response size exceeded. You can make eth_getLogs requests with up to a 2K block range"
+    ));
+    assert!(is_range_too_large("eth_getLogs block range is too large"));
+    assert!(!is_range_too_large("connection reset by peer"));
+}
+
+type StoredRows = (
+    Vec<(DbHash, i32, Option<BigDecimal>)>,
+    Vec<(DbHash, DbAddress, Option<DbHash>, String)>,
+    Vec<(DbHash, DbAddress, DbAddress, DbAddress, Option<BigDecimal>)>,
+);
+
+fn stored_rows(conn: &mut PgConnection) -> StoredRows {
+    let txs = transactions::table
+        .select((
+            transactions::hash,
+            transactions::position,
+            transactions::gas_used,
+        ))
+        .order(transactions::hash)
+        .load(conn)
+        .unwrap();
+    let logs = logs::table
+        .select((logs::tx_hash, logs::address, logs::topic0, logs::data))
+        .order(logs::id)
+        .load(conn)
+        .unwrap();
+    let transfers = token_transfers::table
+        .select((
+            token_transfers::tx_hash,
+            token_transfers::token_address,
+            token_transfers::from_address,
+            token_transfers::to_address,
+            token_transfers::value,
+        ))
+        .order(token_transfers::id)
+        .load(conn)
+        .unwrap();
+    (txs, logs, transfers)
+}
+
+#[test]
+fn test_log_mode_matches_block_mode() {
+    let token = Address::repeat_byte(0xcc);
+    let context = IndexerContext {
+        filter: Some(Arc::new(IndexFilter {
+            addresses: [token].into(),
+            ..Default::default()
+        })),
+        ..Default::default()
+    };
+
+    let transfer = mock_transfer_log(
+        token,
+        Address::repeat_byte(0xaa),
+        Address::repeat_byte(0xbb),
+        250,
+    );
+
+    let unrelated = mock_ethers_transaction(Address::repeat_byte(0x01), Some(Address::zero()), 0);
+    let matched = mock_ethers_transaction(Address::repeat_byte(0xaa), Some(Address::zero()), 1);
+    let block = mock_ethers_block(9, vec![unrelated.clone(), matched.clone()]);
+    let matched_receipt = mock_receipt(&matched, vec![transfer]);
+
+    // Block mode sees every transaction of the block.
+    let block_db = TestDb::new();
+    let mut block_conn = block_db.pool.get().unwrap();
+    process_block_data(
+        &mut block_conn,
+        &context,
+        &block,
+        &[
+            (unrelated.clone(), mock_receipt(&unrelated, vec![])),
+            (matched.clone(), matched_receipt.clone()),
+        ],
+        &HashMap::new(),
+    )
+    .unwrap();
+
+    // Log mode only fetches the header and the transactions referenced by matched logs.
+    let mut header_json = serde_json::to_value(&block).unwrap();
+    header_json["transactions"] = serde_json::json!([unrelated.hash, matched.hash]);
+    let header: EthersBlock<H256> = serde_json::from_value(header_json).unwrap();
+
+    let log_db = TestDb::new();
+    let mut log_conn = log_db.pool.get().unwrap();
+    store_log_range_block(
+        &mut log_conn,
+        &context,
+        &header,
+        &[(matched.clone(), matched_receipt)],
+        &HashMap::new(),
+    )
+    .unwrap();
+
+    let block_rows = stored_rows(&mut block_conn);
+    assert_eq!(block_rows.0.len(), 1);
+    assert_eq!(block_rows.2.len(), 1);
+    assert_eq!(block_rows, stored_rows(&mut log_conn));
+
+    let tx_count: i32 = blocks::table
+        .select(blocks::tx_count)
+        .first(&mut log_conn)
+        .unwrap();
+    assert_eq!(tx_count, 2);
+    assert_eq!(
+        get_indexer_state(&mut log_conn, LOG_CURSOR_KEY).unwrap(),
+        Some(9)
+    );
+}
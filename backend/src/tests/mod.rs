@@ -1,4 +1,19 @@
+mod abi;
 mod api;
+mod balance_verifier;
+mod bulk;
+mod chains;
+mod contracts;
 mod db;
 mod environment;
+mod filter;
+mod handlers;
 mod indexer;
+mod indexes;
+mod integrity;
+mod l2;
+mod log_indexer;
+mod partitions;
+mod retention;
+mod signatures;
+mod store;
@@ -0,0 +1,159 @@
+use super::environment::{
+    create_mock_block, create_mock_log, create_mock_token_transfer, create_mock_transaction,
+    mock_ethers_block, mock_ethers_transaction, mock_receipt, mock_transfer_log, TestDb,
+};
+use crate::{
+    indexer::{process_block_data, rollback_block, DbConnection, IndexerContext},
+    indexes::missing_query_indexes,
+    models::Transaction,
+    partitions::{
+        detach_partitions, enable_partitioning, is_partitioned, list_partitions, Partitioning,
+        PARTITIONED_TABLES,
+    },
+    retention,
+    schema::{blocks, logs, pruned_balances, token_transfers, transactions},
+};
+use diesel::{prelude::*, sql_types::BigInt};
+use ethers::types::{Address, H256};
+use std::{collections::HashMap, sync::Arc};
+
+fn partition_names(conn: &mut PgConnection) -> Vec<String> {
+    list_partitions(conn)
+        .unwrap()
+        .into_iter()
+        .map(|partition| partition.name)
+        .collect()
+}
+
+fn counts(conn: &mut PgConnection) -> (i64, i64, i64) {
+    (
+        transactions::table.count().get_result(conn).unwrap(),
+        logs::table.count().get_result(conn).unwrap(),
+        token_transfers::table.count().get_result(conn).unwrap(),
+    )
+}
+
+/// Indexes a block with one transaction emitting a token transfer.
+fn index_block(conn: &mut DbConnection, context: &IndexerContext, number: u64) -> H256 {
+    let token = Address::repeat_byte(0x70);
+    let log = mock_transfer_log(
+        token,
+        Address::repeat_byte(0x0a),
+        Address::repeat_byte(0x0b),
+        100,
+    );
+    let mut tx = mock_ethers_transaction(Address::repeat_byte(0x0a), Some(token), 0);
+    tx.hash = H256::from_low_u64_be(number << 8);
+    let block = mock_ethers_block(number, vec![tx.clone()]);
+    let receipt = mock_receipt(&tx, vec![log]);
+    process_block_data(conn, context, &block, &[(tx, receipt)], &HashMap::new()).unwrap();
+    block.hash.unwrap()
+}
+
+#[test]
+fn test_enable_partitioning_keeps_rows() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    for number in [5, 1500] {
+        let block = create_mock_block(&mut conn, number);
+        let tx = create_mock_transaction(&mut conn, &block, 0);
+        create_mock_log(&mut conn, &tx);
+        create_mock_token_transfer(&mut conn, &tx);
+    }
+
+    enable_partitioning(&mut conn, 1000).unwrap();
+    for (table, _) in PARTITIONED_TABLES {
+        assert!(is_partitioned(&mut conn, table).unwrap());
+    }
+    assert_eq!(
+        partition_names(&mut conn),
+        vec![
+            "logs_p0",
+            "logs_p1000",
+            "token_transfers_p0",
+            "token_transfers_p1000",
+            "transactions_p0",
+            "transactions_p1000",
+        ]
+    );
+    assert_eq!(counts(&mut conn), (2, 2, 2));
+    // The indexes are recreated on the partitioned tables.
+    assert_eq!(missing_query_indexes(&mut conn).unwrap(), vec![]);
+
+    // Ids keep coming from the same sequences.
+    let tx = transactions::table
+        .filter(transactions::block_number.eq(1500))
+        .select(Transaction::as_select())
+        .first(&mut conn)
+        .unwrap();
+    create_mock_log(&mut conn, &tx);
+    let ids: Vec<i64> = logs::table
+        .order(logs::id)
+        .select(logs::id)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(ids, vec![1, 2, 3]);
+
+    assert!(enable_partitioning(&mut conn, 1000).is_err());
+}
+
+#[test]
+fn test_partitions_follow_the_indexer() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    enable_partitioning(&mut conn, 100).unwrap();
+    assert!(partition_names(&mut conn).is_empty());
+    let context = IndexerContext {
+        partitioning: Some(Arc::new(Partitioning::new(100))),
+        ..Default::default()
+    };
+
+    let block_hash = index_block(&mut conn, &context, 250);
+    assert_eq!(
+        partition_names(&mut conn),
+        vec!["logs_p200", "token_transfers_p200", "transactions_p200"]
+    );
+    assert_eq!(counts(&mut conn), (1, 1, 1));
+
+    // Rolling back deletes the block's rows without foreign key cascades.
+    rollback_block(&mut conn, &context, block_hash).unwrap();
+    assert_eq!(counts(&mut conn), (0, 0, 0));
+
+    index_block(&mut conn, &context, 250);
+    index_block(&mut conn, &context, 50);
+    let detached = detach_partitions(&mut conn, 100, false).unwrap();
+    assert_eq!(detached.len(), 3);
+    assert!(detached
+        .iter()
+        .all(|partition| partition.blocks == (0..100)));
+    assert_eq!(counts(&mut conn), (1, 1, 1));
+
+    // The detached blocks count as pruned, and their transfers as pruned balances.
+    let blocks: Vec<i64> = blocks::table
+        .select(blocks::number)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(blocks, vec![250]);
+    assert_eq!(retention::pruned_before(&mut conn).unwrap(), Some(100));
+    let pruned: i64 = pruned_balances::table
+        .count()
+        .get_result(&mut conn)
+        .unwrap();
+    assert_eq!(pruned, 2);
+
+    // Detached partitions keep their rows for archiving.
+    #[derive(QueryableByName)]
+    struct Count {
+        #[diesel(sql_type = BigInt)]
+        count: i64,
+    }
+    let archived = diesel::sql_query("SELECT COUNT(*) AS count FROM transactions_p0")
+        .get_result::<Count>(&mut conn)
+        .unwrap();
+    assert_eq!(archived.count, 1);
+
+    detach_partitions(&mut conn, 300, true).unwrap();
+    assert!(partition_names(&mut conn).is_empty());
+    assert_eq!(counts(&mut conn), (0, 0, 0));
+    assert_eq!(retention::pruned_before(&mut conn).unwrap(), Some(300));
+}
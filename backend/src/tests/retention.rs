@@ -0,0 +1,189 @@
+use super::environment::{
+    create_mock_block, create_mock_log, create_mock_token_transfer, create_mock_transaction, TestDb,
+};
+use crate::{
+    api::{
+        get_account_balances, get_balance_series, get_block_details, get_daily_stats,
+        get_transactions_for_block, search, BalanceSeriesQuery, BalancesQuery, DailyStatsQuery,
+        PageQuery, SearchQuery,
+    },
+    contracts::{store_contract, DeployedContract},
+    models::{DbAddress, DbHash},
+    retention::{prune, pruned_before, retention_horizon, RetentionPolicy},
+    schema::{blocks, contracts, logs, token_transfers, transactions},
+    signatures::SignatureDb,
+};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use bigdecimal::BigDecimal;
+use chrono::{Duration, Utc};
+use diesel::prelude::*;
+use ethers::types::{Address, Bytes};
+use std::sync::Arc;
+
+fn policy(blocks: Option<i64>, days: Option<i64>) -> RetentionPolicy {
+    RetentionPolicy {
+        blocks,
+        max_age: days.map(Duration::days),
+    }
+}
+
+#[test]
+fn test_retention_horizon() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let mined = create_mock_block(&mut conn, 1).timestamp;
+    for number in 2..=10 {
+        create_mock_block(&mut conn, number);
+    }
+    diesel::update(blocks::table.filter(blocks::number.ge(8)))
+        .set(blocks::timestamp.eq(mined + Duration::days(8)))
+        .execute(&mut conn)
+        .unwrap();
+    let now = mined + Duration::days(10);
+    let horizon = |conn: &mut PgConnection, policy: RetentionPolicy| {
+        retention_horizon(conn, &policy, now).unwrap()
+    };
+
+    assert_eq!(horizon(&mut conn, policy(None, None)), None);
+    assert_eq!(horizon(&mut conn, policy(Some(3), None)), Some(8));
+    assert_eq!(horizon(&mut conn, policy(None, Some(5))), Some(8));
+    // The latest block is always kept.
+    assert_eq!(horizon(&mut conn, policy(Some(0), None)), Some(10));
+    assert_eq!(horizon(&mut conn, policy(None, Some(1))), Some(10));
+    // Blocks are kept while either limit covers them.
+    assert_eq!(horizon(&mut conn, policy(Some(5), Some(5))), Some(6));
+}
+
+#[tokio::test]
+async fn test_prune_keeps_aggregates() {
+    let db = TestDb::new();
+    let mut conn = db.pool.get().unwrap();
+    let recipient = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+    let token = "0xcccccccccccccccccccccccccccccccccccccccc";
+
+    // The recipient gets 500 tokens in each of blocks 1 to 3, and in block 115.
+    for number in 1..=120 {
+        let block = create_mock_block(&mut conn, number);
+        let tx = create_mock_transaction(&mut conn, &block, 0);
+        create_mock_log(&mut conn, &tx);
+        if number <= 3 || number == 115 {
+            create_mock_token_transfer(&mut conn, &tx);
+        }
+        if number == 1 {
+            let deployed = DeployedContract {
+                code: Bytes::from(vec![0x60, 0x80]),
+                proxy: None,
+                token: None,
+            };
+            store_contract(
+                &mut conn,
+                Address::repeat_byte(0xc0),
+                &deployed,
+                Some((tx.hash.0, 1)),
+            )
+            .unwrap();
+        }
+    }
+    diesel::update(blocks::table)
+        .set(blocks::timestamp.eq(Utc::now()))
+        .execute(&mut conn)
+        .unwrap();
+
+    // Two batches, as 110 blocks are past the horizon.
+    let pruned = prune(&mut conn, &policy(Some(10), None), Utc::now()).unwrap();
+    assert_eq!(pruned, 110);
+    assert_eq!(pruned_before(&mut conn).unwrap(), Some(111));
+    let remaining: Vec<i64> = blocks::table
+        .order(blocks::number)
+        .select(blocks::number)
+        .load(&mut conn)
+        .unwrap();
+    assert_eq!(remaining, (111..=120).collect::<Vec<_>>());
+    let counts: (i64, i64, i64) = (
+        transactions::table.count().get_result(&mut conn).unwrap(),
+        logs::table.count().get_result(&mut conn).unwrap(),
+        token_transfers::table
+            .count()
+            .get_result(&mut conn)
+            .unwrap(),
+    );
+    assert_eq!(counts, (10, 10, 1));
+
+    // The contract outlives its deployment transaction.
+    let creation: (Option<i64>, Option<DbHash>) = contracts::table
+        .find(DbAddress(Address::repeat_byte(0xc0)))
+        .select((contracts::creation_block, contracts::creation_tx))
+        .first(&mut conn)
+        .unwrap();
+    assert_eq!(creation, (Some(1), None));
+
+    // Daily statistics still count the pruned blocks.
+    let stats = get_daily_stats(State(db.store()), Query(DailyStatsQuery { days: Some(1) }))
+        .await
+        .unwrap()
+        .0;
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats[0].blocks, 120);
+    assert_eq!(stats[0].transactions, 120);
+    assert_eq!(stats[0].token_transfers, 4);
+    assert_eq!(stats[0].gas_used, BigDecimal::from(120 * 1_500_000));
+
+    // Historical balances start from the pruned transfers.
+    let balances_at = |block: Option<i64>| {
+        get_account_balances(
+            State(db.store()),
+            Path(recipient.to_string()),
+            Query(BalancesQuery { block }),
+            Query(PageQuery::default()),
+        )
+    };
+    let latest = balances_at(None).await.unwrap().0;
+    assert_eq!(latest.balances.items[0].amount, BigDecimal::from(2000));
+    let at_112 = balances_at(Some(112)).await.unwrap().0;
+    assert_eq!(at_112.balances.items[0].amount, BigDecimal::from(1500));
+    let result = balances_at(Some(5)).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::GONE);
+
+    let series = |from: Option<i64>| {
+        get_balance_series(
+            State(db.store()),
+            Path((recipient.to_string(), token.to_string())),
+            Query(BalanceSeriesQuery { from, to: None }),
+            Query(PageQuery::default()),
+        )
+    };
+    let points = series(None).await.unwrap().0.items;
+    assert_eq!(points.len(), 1);
+    assert_eq!(points[0].block_number, 115);
+    assert_eq!(points[0].amount, BigDecimal::from(2000));
+    let result = series(Some(100)).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::GONE);
+
+    // Pruned blocks are gone when named by number, as opposed to never indexed.
+    let block = |id: &str| get_block_details(State(db.store()), Path(id.to_string()));
+    assert_eq!(block("115").await.unwrap().0.number, 115);
+    assert_eq!(block("5").await.unwrap_err().status(), StatusCode::GONE);
+    assert_eq!(
+        block("121").await.unwrap_err().status(),
+        StatusCode::NOT_FOUND
+    );
+    let result = get_transactions_for_block(
+        State(db.store()),
+        State(Arc::new(SignatureDb::bundled())),
+        Path("0x5".to_string()),
+        Query(PageQuery::default()),
+    )
+    .await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::GONE);
+    let result = search(State(db.store()), Query(SearchQuery { q: "5".to_string() })).await;
+    assert_eq!(result.unwrap_err().status(), StatusCode::GONE);
+
+    // Nothing is left to prune.
+    assert_eq!(
+        prune(&mut conn, &policy(Some(10), None), Utc::now()).unwrap(),
+        0
+    );
+}
@@ -0,0 +1,58 @@
+use crate::{indexer::TRANSFER_EVENT_SIGNATURE, signatures::SignatureDb};
+
+#[test]
+fn test_bundled_signatures() {
+    let db = SignatureDb::bundled();
+
+    assert_eq!(
+        db.event_names(&TRANSFER_EVENT_SIGNATURE),
+        ["Transfer(address,address,uint256)"]
+    );
+    assert_eq!(
+        db.method_names(&[0xa9, 0x05, 0x9c, 0xbb]),
+        ["transfer(address,uint256)"]
+    );
+    assert!(db.method_names(&[0, 0, 0, 0]).is_empty());
+}
+
+#[test]
+fn test_selector_collisions_return_all_candidates() {
+    let mut db = SignatureDb::default();
+    let added = db
+        .load_str(
+            "# comment\n\
+             function transfer(address,uint256)\n\
+             0xa9059cbb many_msg_babbage(bytes1)\n\
+             \n\
+             function transfer(address,uint256)\n",
+        )
+        .unwrap();
+    assert_eq!(added, 3);
+
+    // The duplicate line is only listed once.
+    assert_eq!(
+        db.method_names(&[0xa9, 0x05, 0x9c, 0xbb]),
+        ["transfer(address,uint256)", "many_msg_babbage(bytes1)"]
+    );
+}
+
+#[test]
+fn test_explicit_event_hash() {
+    let mut db = SignatureDb::default();
+    db.load_str(
+        "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef Transfer(address,address,uint256)",
+    )
+    .unwrap();
+    assert_eq!(
+        db.event_names(&TRANSFER_EVENT_SIGNATURE),
+        ["Transfer(address,address,uint256)"]
+    );
+}
+
+#[test]
+fn test_malformed_signature_lines() {
+    let mut db = SignatureDb::default();
+    assert!(db.load_str("transfer(address,uint256)").is_err());
+    assert!(db.load_str("0xzz transfer(address,uint256)").is_err());
+    assert!(db.load_str("0xa9059c transfer(address,uint256)").is_err());
+}
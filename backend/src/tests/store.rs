@@ -0,0 +1,542 @@
+use super::environment::{
+    mock_ethers_block, mock_ethers_transaction, mock_receipt, mock_transfer_log, MockNode, TestDb,
+};
+use crate::{
+    abi::SharedAbiRegistry,
+    api::{
+        get_account_balances, get_account_details, get_balance_series, get_contract_details,
+        get_recent_blocks, get_transaction_details, get_transactions_for_block, search,
+        BalanceSeriesQuery, BalancesQuery, PageQuery, SearchQuery,
+    },
+    chains::ChainConfig,
+    contracts::{DeployedContract, TokenMetadata},
+    db::PoolConfig,
+    handlers::{EventHandler, HandlerRegistry},
+    indexer::{block_row, run_indexer, IndexerContext},
+    models::DbAddress,
+    signatures::SignatureDb,
+    store::{MemoryStore, ReplicatedStore, SharedStore, SqliteStore, Store},
+};
+use axum::extract::{Path, Query, State};
+use common::DecodedLog;
+use diesel::PgConnection;
+use ethers::{
+    abi::{encode, Token},
+    types::{
+        Address, Bytes, Log as EthersLog, Transaction as EthersTransaction, TransactionReceipt,
+        H256,
+    },
+    utils::keccak256,
+};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::time::{sleep, timeout};
+
+const TOKEN: Address = Address::repeat_byte(0xcc);
+const ALICE: Address = Address::repeat_byte(0xaa);
+const BOB: Address = Address::repeat_byte(0xbb);
+
+fn token_contract() -> DeployedContract {
+    DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80]),
+        proxy: None,
+        token: Some(TokenMetadata {
+            name: Some("Test Token".to_string()),
+            symbol: Some("TST".to_string()),
+            decimals: Some(18),
+        }),
+    }
+}
+
+/// Block 1 deploys the token and mints 1000 to Alice, block 2 moves 300 of them to Bob.
+fn chain_blocks() -> Vec<(u64, Vec<(EthersTransaction, TransactionReceipt)>)> {
+    let deploy = mock_ethers_transaction(ALICE, None, 0);
+    let mut deploy_receipt = mock_receipt(
+        &deploy,
+        vec![mock_transfer_log(TOKEN, Address::zero(), ALICE, 1000)],
+    );
+    deploy_receipt.contract_address = Some(TOKEN);
+    let send = mock_ethers_transaction(ALICE, Some(TOKEN), 1);
+    let send_receipt = mock_receipt(&send, vec![mock_transfer_log(TOKEN, ALICE, BOB, 300)]);
+    vec![
+        (1, vec![(deploy, deploy_receipt)]),
+        (2, vec![(send, send_receipt)]),
+    ]
+}
+
+/// Writes the `chain_blocks` straight into a store.
+fn index_blocks(store: &dyn Store) -> Vec<H256> {
+    let context = IndexerContext::default();
+    let contracts = HashMap::from([(TOKEN, token_contract())]);
+    chain_blocks()
+        .into_iter()
+        .map(|(number, transactions_with_receipts)| {
+            let txs = transactions_with_receipts.iter().map(|(tx, _)| tx.clone());
+            let block = mock_ethers_block(number, txs.collect());
+            store
+                .write_block(
+                    &context,
+                    &block_row(&block).unwrap(),
+                    &transactions_with_receipts,
+                    &contracts,
+                )
+                .unwrap();
+            block.hash.unwrap()
+        })
+        .collect()
+}
+
+/// A node serving the `chain_blocks`, and the token's code and metadata.
+fn mock_node() -> MockNode {
+    let mut node = MockNode::default();
+    for (number, transactions_with_receipts) in chain_blocks() {
+        let (txs, receipts) = transactions_with_receipts.into_iter().unzip();
+        node.add_block(mock_ethers_block(number, txs), receipts);
+    }
+    let contract = token_contract();
+    let metadata = contract.token.unwrap();
+    node.set_code(TOKEN, contract.code);
+    for (signature, result) in [
+        ("name()", Token::String(metadata.name.unwrap())),
+        ("symbol()", Token::String(metadata.symbol.unwrap())),
+        ("decimals()", Token::Uint(metadata.decimals.unwrap().into())),
+    ] {
+        let selector = keccak256(signature)[..4].to_vec();
+        node.set_call(TOKEN, selector.into(), encode(&[result]).into());
+    }
+    node
+}
+
+/// Runs the indexer into `store` until it has indexed `last_block`.
+async fn run_indexer_until(store: &SharedStore, url: String, last_block: i64) {
+    let chain = ChainConfig::single(Some(url), Some(1));
+    let indexer = tokio::spawn(run_indexer(store.clone(), IndexerContext::default(), chain));
+    let indexed = async {
+        while store.latest_block_number().unwrap() < Some(last_block) {
+            sleep(Duration::from_millis(20)).await;
+        }
+    };
+    let result = timeout(Duration::from_secs(10), indexed).await;
+    indexer.abort();
+    result.expect("The indexer didn't reach the last block");
+}
+
+/// Everything the API returns about the indexed blocks, as JSON.
+async fn api_snapshot(store: SharedStore, block_hashes: &[H256]) -> serde_json::Value {
+    let signatures = Arc::new(SignatureDb::bundled());
+    let mut snapshot = serde_json::Map::new();
+    let mut add = |key: String, value: serde_json::Value| snapshot.insert(key, value);
+
+    let blocks = get_recent_blocks(State(store.clone()), Query(PageQuery::default()))
+        .await
+        .unwrap()
+        .0;
+    add("blocks".into(), serde_json::to_value(blocks).unwrap());
+    for hash in block_hashes {
+        let hash = format!("{:#x}", hash);
+        let txs = get_transactions_for_block(
+            State(store.clone()),
+            State(signatures.clone()),
+            Path(hash.clone()),
+            Query(PageQuery::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        for tx in &txs.items {
+            let detail = get_transaction_details(
+                State(store.clone()),
+                State(SharedAbiRegistry::default()),
+                State(signatures.clone()),
+                Path(tx.hash.clone()),
+            )
+            .await
+            .unwrap()
+            .0;
+            add(tx.hash.clone(), serde_json::to_value(detail).unwrap());
+        }
+        add(hash, serde_json::to_value(txs).unwrap());
+    }
+    for owner in [ALICE, BOB] {
+        let owner = format!("{:#x}", owner);
+        let account = get_account_details(State(store.clone()), Path(owner.clone())).await;
+        add(
+            format!("account {}", owner),
+            serde_json::to_value(account.ok().map(|a| a.0)).unwrap(),
+        );
+        let balances = get_account_balances(
+            State(store.clone()),
+            Path(owner.clone()),
+            Query(BalancesQuery { block: None }),
+            Query(PageQuery::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        add(
+            format!("balances {}", owner),
+            serde_json::to_value(balances).unwrap(),
+        );
+        let series = get_balance_series(
+            State(store.clone()),
+            Path((owner.clone(), format!("{:#x}", TOKEN))),
+            Query(BalanceSeriesQuery {
+                from: None,
+                to: None,
+            }),
+            Query(PageQuery::default()),
+        )
+        .await
+        .unwrap()
+        .0;
+        add(
+            format!("series {}", owner),
+            serde_json::to_value(series).unwrap(),
+        );
+    }
+    let contract = get_contract_details(State(store.clone()), Path(format!("{:#x}", TOKEN))).await;
+    add(
+        "contract".into(),
+        serde_json::to_value(contract.ok().map(|c| c.0)).unwrap(),
+    );
+    let queries = [
+        "test".to_string(),
+        "TS".to_string(),
+        "2".to_string(),
+        format!("{:#x}", block_hashes[0]),
+        format!("{:#x}", ALICE),
+        format!("{:#x}", TOKEN),
+    ];
+    for query in queries {
+        let results = search(
+            State(store.clone()),
+            Query(SearchQuery { q: query.clone() }),
+        )
+        .await
+        .unwrap()
+        .0;
+        add(
+            format!("search {}", query),
+            serde_json::to_value(results).unwrap(),
+        );
+    }
+    serde_json::Value::Object(snapshot)
+}
+
+/// Indexes the same blocks into PostgreSQL and `other`, and checks that the API can't tell them
+/// apart, before and after a rollback.
+async fn assert_matches_postgres(other: SharedStore) {
+    let db = TestDb::new();
+    let postgres = db.store();
+
+    let hashes = index_blocks(postgres.as_ref());
+    assert_eq!(index_blocks(other.as_ref()), hashes);
+
+    let indexed = api_snapshot(postgres.clone(), &hashes).await;
+    assert_eq!(api_snapshot(other.clone(), &hashes).await, indexed);
+    assert_eq!(
+        indexed[format!("balances {:#x}", BOB)]["balances"]["items"][0]["amount"],
+        "300"
+    );
+
+    // Rolling back block 2 returns Bob's tokens to Alice in both stores.
+    let context = IndexerContext::default();
+    postgres.rollback_block(&context, hashes[1]).unwrap();
+    other.rollback_block(&context, hashes[1]).unwrap();
+    let rolled_back = api_snapshot(postgres, &hashes[..1]).await;
+    assert_eq!(api_snapshot(other.clone(), &hashes[..1]).await, rolled_back);
+    assert_eq!(
+        rolled_back[format!("account {:#x}", ALICE)]["tx_sent_count"],
+        1
+    );
+    assert_eq!(other.latest_block_number().unwrap(), Some(1));
+}
+
+#[tokio::test]
+async fn test_memory_store_matches_postgres() {
+    assert_matches_postgres(Arc::new(MemoryStore::new())).await;
+}
+
+#[tokio::test]
+async fn test_sqlite_store_matches_postgres() {
+    assert_matches_postgres(Arc::new(
+        SqliteStore::open(":memory:", &PoolConfig::default()).unwrap(),
+    ))
+    .await;
+}
+
+#[test]
+fn test_replicated_store_reads_from_replicas_that_keep_up() {
+    let context = IndexerContext::default();
+    let primary: SharedStore = Arc::new(MemoryStore::new());
+    let replica: SharedStore = Arc::new(MemoryStore::new());
+    let hashes = index_blocks(primary.as_ref());
+    index_blocks(replica.as_ref());
+    replica.rollback_block(&context, hashes[1]).unwrap();
+
+    // Replicas are only read from once their lag is known.
+    let store = ReplicatedStore::new(primary.clone(), vec![replica.clone()], 1);
+    assert_eq!(store.latest_block_number().unwrap(), Some(2));
+    assert_eq!(store.check_lag().unwrap(), vec![Some(1)]);
+    assert_eq!(store.latest_block_number().unwrap(), Some(1));
+
+    // A replica further behind than allowed is skipped.
+    let strict = ReplicatedStore::new(primary.clone(), vec![replica], 0);
+    assert_eq!(strict.check_lag().unwrap(), vec![Some(1)]);
+    assert_eq!(strict.latest_block_number().unwrap(), Some(2));
+
+    // Writes always go to the primary.
+    store.rollback_block(&context, hashes[1]).unwrap();
+    assert_eq!(primary.latest_block_number().unwrap(), Some(1));
+}
+
+/// A contract first paid by a plain transfer, before its code was probed, is flagged once a
+/// later transaction finds its code.
+#[test]
+fn test_contract_first_seen_through_plain_transfer() {
+    let db = TestDb::new();
+    let stores: [SharedStore; 3] = [
+        db.store(),
+        Arc::new(MemoryStore::new()),
+        Arc::new(SqliteStore::open(":memory:", &PoolConfig::default()).unwrap()),
+    ];
+    let context = IndexerContext::default();
+    for store in stores {
+        let mut payment = mock_ethers_transaction(ALICE, Some(TOKEN), 0);
+        payment.input = Bytes::new();
+        let block = mock_ethers_block(1, vec![payment.clone()]);
+        let receipt = mock_receipt(&payment, vec![]);
+        let transactions = [(payment, receipt)];
+        let row = block_row(&block).unwrap();
+        store
+            .write_block(&context, &row, &transactions, &HashMap::new())
+            .unwrap();
+        let account = store.account(DbAddress(TOKEN)).unwrap().unwrap();
+        assert!(!account.is_contract);
+        assert!(store.known_contracts(&[TOKEN]).unwrap().is_empty());
+        assert_eq!(store.code_checked(&[ALICE, TOKEN]).unwrap().len(), 1);
+
+        let mut payment = mock_ethers_transaction(ALICE, Some(TOKEN), 1);
+        payment.input = Bytes::new();
+        let block = mock_ethers_block(2, vec![payment.clone()]);
+        let receipt = mock_receipt(&payment, vec![]);
+        let contracts = HashMap::from([(
+            TOKEN,
+            DeployedContract {
+                code: Bytes::from(vec![0x60, 0x80]),
+                proxy: None,
+                token: None,
+            },
+        )]);
+        store
+            .write_block(
+                &context,
+                &block_row(&block).unwrap(),
+                &[(payment, receipt)],
+                &contracts,
+            )
+            .unwrap();
+        let account = store.account(DbAddress(TOKEN)).unwrap().unwrap();
+        assert!(account.is_contract);
+        assert_eq!(store.known_contracts(&[TOKEN]).unwrap().len(), 1);
+    }
+}
+
+/// Rolling back a call to a contract leaves the activity of its creation, and rolling back the
+/// creation drops the contract and the accounts only its block had seen.
+#[test]
+fn test_rollback_reverts_created_contracts() {
+    let db = TestDb::new();
+    let stores: [SharedStore; 3] = [
+        db.store(),
+        Arc::new(MemoryStore::new()),
+        Arc::new(SqliteStore::open(":memory:", &PoolConfig::default()).unwrap()),
+    ];
+    let context = IndexerContext::default();
+    for store in stores {
+        let hashes = index_blocks(store.as_ref());
+        let token = || store.account(DbAddress(TOKEN)).unwrap();
+        assert_eq!(token().unwrap().last_active_block, Some(2));
+
+        store.rollback_block(&context, hashes[1]).unwrap();
+        let account = token().unwrap();
+        assert_eq!(account.first_seen_block, Some(1));
+        assert_eq!(account.last_active_block, Some(1));
+        assert_eq!(account.tx_received_count, 0);
+
+        store.rollback_block(&context, hashes[0]).unwrap();
+        assert!(token().is_none());
+        assert!(store.account(DbAddress(ALICE)).unwrap().is_none());
+        assert!(store.contract(DbAddress(TOKEN)).unwrap().is_none());
+    }
+}
+
+/// Exact symbols rank first even when more than `limit` tokens match, then shorter symbols, then
+/// tokens matched by name.
+#[test]
+fn test_search_tokens_ranks_before_limiting() {
+    let db = TestDb::new();
+    let stores: [SharedStore; 3] = [
+        db.store(),
+        Arc::new(MemoryStore::new()),
+        Arc::new(SqliteStore::open(":memory:", &PoolConfig::default()).unwrap()),
+    ];
+    let token = |symbol: &str, name: &str| DeployedContract {
+        code: Bytes::from(vec![0x60, 0x80]),
+        proxy: None,
+        token: Some(TokenMetadata {
+            name: Some(name.to_string()),
+            symbol: Some(symbol.to_string()),
+            decimals: Some(6),
+        }),
+    };
+    // Bridged copies have the lowest addresses, the canonical token the highest.
+    let mut contracts: HashMap<Address, DeployedContract> = (1..=12u8)
+        .map(|i| (Address::repeat_byte(i), token("USDC.e", "Bridged")))
+        .collect();
+    contracts.insert(Address::repeat_byte(0x20), token("xUSD", "USDC Vault"));
+    contracts.insert(Address::repeat_byte(0x21), token("USDCX", "Wrapped"));
+    contracts.insert(Address::repeat_byte(0xf0), token("usdc", "USD Coin"));
+
+    let calls: Vec<(EthersTransaction, TransactionReceipt)> = contracts
+        .keys()
+        .enumerate()
+        .map(|(index, address)| {
+            let tx = mock_ethers_transaction(ALICE, Some(*address), index as u64);
+            let receipt = mock_receipt(&tx, vec![]);
+            (tx, receipt)
+        })
+        .collect();
+    let block = mock_ethers_block(1, calls.iter().map(|(tx, _)| tx.clone()).collect());
+
+    let context = IndexerContext::default();
+    for store in stores {
+        store
+            .write_block(&context, &block_row(&block).unwrap(), &calls, &contracts)
+            .unwrap();
+        let found: Vec<Address> = store
+            .search_tokens("USDC", 3)
+            .unwrap()
+            .into_iter()
+            .map(|contract| contract.address.0)
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                Address::repeat_byte(0xf0),
+                Address::repeat_byte(0x21),
+                Address::repeat_byte(1)
+            ]
+        );
+        let last = store.search_tokens("usdc", 20).unwrap().pop().unwrap();
+        assert_eq!(last.address.0, Address::repeat_byte(0x20));
+    }
+}
+
+/// The indexer and the API run on a memory store alone, without PostgreSQL, and store what is
+/// written to it directly.
+#[tokio::test]
+async fn test_run_indexer_into_memory_store() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    run_indexer_until(&store, mock_node().serve().await, 2).await;
+
+    let written: SharedStore = Arc::new(MemoryStore::new());
+    let hashes = index_blocks(written.as_ref());
+    let indexed = api_snapshot(store, &hashes).await;
+    assert_eq!(api_snapshot(written, &hashes).await, indexed);
+    assert_eq!(
+        indexed[format!("balances {:#x}", BOB)]["balances"]["items"][0]["amount"],
+        "300"
+    );
+    assert_eq!(indexed["contract"]["token"]["symbol"], "TST");
+    assert_eq!(indexed["search TS"][0]["kind"], "token");
+}
+
+/// An account found without code is looked up again only when it is called with input.
+#[tokio::test]
+async fn test_run_indexer_skips_accounts_without_code() {
+    let mut node = MockNode::default();
+    for (nonce, input) in [(0, vec![]), (1, vec![]), (2, vec![0xa9, 0x05, 0x9c, 0xbb])] {
+        let mut payment = mock_ethers_transaction(ALICE, Some(BOB), nonce);
+        payment.input = Bytes::from(input);
+        let receipt = mock_receipt(&payment, vec![]);
+        node.add_block(mock_ethers_block(nonce + 1, vec![payment]), vec![receipt]);
+    }
+    let lookups = node.code_lookups();
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    run_indexer_until(&store, node.serve().await, 3).await;
+
+    assert_eq!(*lookups.lock().unwrap(), vec![BOB, BOB]);
+    assert_eq!(store.code_checked(&[ALICE, BOB]).unwrap().len(), 1);
+    assert!(!store.account(DbAddress(BOB)).unwrap().unwrap().is_contract);
+}
+
+/// A block replaced on the node is rolled back from the memory store and indexed again.
+#[tokio::test]
+async fn test_run_indexer_unwinds_reorgs_in_memory_store() {
+    let store: SharedStore = Arc::new(MemoryStore::new());
+    run_indexer_until(&store, mock_node().serve().await, 2).await;
+
+    // Block 2 is replaced by an empty one, on which block 3 is built.
+    let mut node = mock_node();
+    let mut replaced = mock_ethers_block(2, vec![]);
+    replaced.hash = Some(H256::repeat_byte(0x22));
+    let mut next = mock_ethers_block(3, vec![]);
+    next.parent_hash = replaced.hash.unwrap();
+    node.add_block(replaced, vec![]);
+    node.add_block(next, vec![]);
+    run_indexer_until(&store, node.serve().await, 3).await;
+
+    assert_eq!(store.block_hash(2).unwrap(), Some(H256::repeat_byte(0x22)));
+    let bob = get_account_balances(
+        State(store.clone()),
+        Path(format!("{:#x}", BOB)),
+        Query(BalancesQuery { block: None }),
+        Query(PageQuery::default()),
+    )
+    .await
+    .unwrap()
+    .0;
+    assert!(bob.balances.items.is_empty());
+    let alice = store.account(DbAddress(ALICE)).unwrap().unwrap();
+    assert_eq!(alice.tx_sent_count, 1);
+}
+
+struct NoopHandler;
+
+impl EventHandler for NoopHandler {
+    fn name(&self) -> &str {
+        "noop"
+    }
+
+    fn handle(
+        &self,
+        _connection: &mut PgConnection,
+        _log: &EthersLog,
+        _decoded: Option<&DecodedLog>,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stores_reject_event_handlers() {
+    let mut handlers = HandlerRegistry::default();
+    handlers.register(NoopHandler);
+    let context = IndexerContext {
+        handlers: Arc::new(handlers),
+        ..Default::default()
+    };
+
+    let stores: [SharedStore; 2] = [
+        Arc::new(MemoryStore::new()),
+        Arc::new(SqliteStore::open(":memory:", &PoolConfig::default()).unwrap()),
+    ];
+    for store in stores {
+        let block = mock_ethers_block(1, vec![]);
+        let error = store
+            .write_block(&context, &block_row(&block).unwrap(), &[], &HashMap::new())
+            .unwrap_err();
+        assert!(error.to_string().contains("Event handlers"));
+        assert_eq!(store.latest_block_number().unwrap(), None);
+    }
+}
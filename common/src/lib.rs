@@ -1,5 +1,5 @@
 use bigdecimal::BigDecimal;
-use chrono::{offset::Utc, DateTime};
+use chrono::{offset::Utc, DateTime, NaiveDate};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -24,11 +24,31 @@ pub struct Transaction {
     pub value: BigDecimal,
     pub gas_price: Option<BigDecimal>,
     pub gas_used: Option<BigDecimal>,
+    pub method_id: Option<String>,
+    /// Known signatures matching `method_id`; more than one means a selector collision.
+    pub method_name: Vec<String>,
+    /// EIP-2718 type, e.g. 2 for EIP-1559 and 126 (0x7e) for OP-stack deposits.
+    pub tx_type: Option<i16>,
+    /// What the sender paid, in wei: the gas at its effective price, plus the L1 data fee on
+    /// OP-stack chains.
+    pub fee: Option<BigDecimal>,
+    /// OP-stack: the L1 data fee, and the L1 gas and gas price it was computed from.
+    pub l1_fee: Option<BigDecimal>,
+    pub l1_gas_used: Option<BigDecimal>,
+    pub l1_gas_price: Option<BigDecimal>,
+    /// Arbitrum: the part of `gas_used` paying for L1 calldata.
+    pub gas_used_for_l1: Option<BigDecimal>,
+    /// Arbitrum: the L1 block the transaction was sequenced against.
+    pub l1_block_number: Option<i64>,
+    /// OP-stack deposits: the hash identifying the L1 event the deposit comes from.
+    pub deposit_source_hash: Option<String>,
+    /// OP-stack deposits: the ETH minted on L2, in wei.
+    pub deposit_mint: Option<BigDecimal>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Log {
-    pub id: i32,
+    pub id: i64,
     pub tx_hash: String,
     pub address: String,
     pub topic0: Option<String>,
@@ -36,37 +56,197 @@ pub struct Log {
     pub topic2: Option<String>,
     pub topic3: Option<String>,
     pub data: String,
+    /// Known signatures matching `topic0`; more than one means a selector collision.
+    pub event_name: Vec<String>,
+    pub decoded: Option<DecodedLog>,
+}
+
+/// A log decoded against a known contract ABI.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedLog {
+    pub name: String,
+    pub signature: String,
+    pub params: Vec<DecodedParam>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecodedParam {
+    pub name: String,
+    pub kind: String,
+    pub indexed: bool,
+    pub value: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenTransfer {
-    pub id: i32,
+    pub id: i64,
     pub tx_hash: String,
     pub token_address: String,
     pub from_address: String,
     pub to_address: String,
     pub value: Option<BigDecimal>,
     pub token_id: Option<BigDecimal>,
+    pub block_number: i64,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TokenBalance {
-    pub id: i32,
+    pub id: i64,
     pub owner_address: String,
     pub token_address: String,
     pub amount: BigDecimal,
     pub token_id: Option<BigDecimal>,
 }
 
+/// One page of a list. Passing `next_cursor` back as `?cursor=` fetches the next page; it's
+/// `None` on the last one.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+}
+
+/// A transaction with the first page of its logs and token transfers, the rest being served by
+/// `/tx/:hash/logs` and `/tx/:hash/transfers`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionDetail {
     pub transaction: Transaction,
-    pub logs: Vec<Log>,
-    pub token_transfers: Vec<TokenTransfer>,
+    pub logs: Page<Log>,
+    pub token_transfers: Page<TokenTransfer>,
+}
+
+/// A page of an account's token holdings as of a given block, which later pages keep.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AccountBalances {
+    pub address: String,
+    pub block_number: i64,
+    pub balances: Page<HistoricalBalance>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HistoricalBalance {
+    pub token_address: String,
+    /// Set for ERC721 tokens, whose `amount` is then 1.
+    pub token_id: Option<BigDecimal>,
+    pub amount: BigDecimal,
+}
+
+/// A token balance right after the transfers of a block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BalancePoint {
+    pub block_number: i64,
+    pub amount: BigDecimal,
+}
+
+/// Chain activity during a day (UTC), including blocks that have since been pruned.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DailyStats {
+    pub day: NaiveDate,
+    pub blocks: i64,
+    pub transactions: i64,
+    pub token_transfers: i64,
+    pub gas_used: BigDecimal,
+}
+
+/// A chain served by the API, under `/api/<name>`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Chain {
+    pub name: String,
+    /// The EIP-155 chain ID, when configured.
+    pub chain_id: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContractDetail {
+    pub address: String,
+    /// `address` in EIP-55 mixed-case checksum form, for display.
+    pub checksum_address: String,
+    /// Keccak-256 hash of the runtime bytecode, shared by identical deployments.
+    pub bytecode_hash: Option<String>,
+    pub bytecode: Option<String>,
+    pub bytecode_size: Option<i32>,
+    /// Set when the deployment transaction was indexed.
+    pub creation_tx: Option<String>,
+    pub creation_block: Option<i64>,
+    pub proxy: Option<ProxyInfo>,
+    /// The first implementation changes from `Upgraded` events, oldest first, the rest being
+    /// served by `/contract/:address/upgrades`.
+    pub upgrades: Page<ProxyUpgrade>,
+    /// Set for contracts that report a token name or symbol.
+    pub token: Option<TokenInfo>,
+}
+
+/// The metadata a token contract reports about itself.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<i16>,
+}
+
+/// A match of `/api/search`, tagged with its `kind`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SearchResult {
+    Block {
+        hash: String,
+        number: i64,
+    },
+    Transaction {
+        hash: String,
+        block_number: i64,
+    },
+    Account {
+        address: String,
+    },
+    Contract {
+        address: String,
+        token: Option<TokenInfo>,
+    },
+    /// A token found by a prefix of its symbol or name.
+    Token {
+        address: String,
+        token: TokenInfo,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyInfo {
+    /// One of `eip1967`, `eip1822`, `beacon` or `eip1167`.
+    pub kind: String,
+    pub implementation: Option<String>,
+    pub beacon: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyUpgrade {
+    pub tx_hash: String,
+    pub block_number: i64,
+    pub implementation: String,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AccountDetail {
     pub address: String,
-    pub token_balances: Vec<TokenBalance>,
+    /// `address` in EIP-55 mixed-case checksum form, for display.
+    pub checksum_address: String,
+    /// Block of the first indexed transaction involving the account.
+    pub first_seen_block: Option<i64>,
+    pub first_seen_tx: Option<String>,
+    /// Timestamp of `first_seen_block`.
+    pub first_seen_at: Option<DateTime<Utc>>,
+    pub last_active_block: Option<i64>,
+    pub tx_sent_count: i64,
+    pub tx_received_count: i64,
+    pub is_contract: bool,
+    /// The first token balances, the rest being served by `/account/:address/tokens`.
+    pub token_balances: Page<TokenBalance>,
+}
+
+/// The body of an API error response, e.g. for a malformed address or hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// Machine-readable error kind, such as `invalid_address`.
+    pub error: String,
+    pub message: String,
 }
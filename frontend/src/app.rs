@@ -1,7 +1,14 @@
-use crate::pages::{AccountDetailsPage, BlockDetailsPage, HomePage, TransactionDetailsPage};
+use crate::pages::{
+    result_path, AccountDetailsPage, BlockDetailsPage, HomePage, SearchPage, TransactionDetailsPage,
+};
+use common::{Chain, SearchResult};
 use gloo_net::http::Request;
-use leptos::{component, view, IntoView};
-use leptos_router::{Outlet, Redirect, Route, Router, Routes, TrailingSlash, A};
+use leptos::{
+    component, create_effect, create_resource, create_rw_signal, ev::SubmitEvent,
+    event_target_value, expect_context, provide_context, spawn_local, view, CollectView, IntoView,
+    RwSignal, Show, SignalGet, SignalGetUntracked, SignalSet,
+};
+use leptos_router::{use_navigate, Outlet, Redirect, Route, Router, Routes, TrailingSlash, A};
 use serde::Deserialize;
 
 const API_BASE_URL: &str = "http://127.0.0.1:3000/api";
@@ -11,20 +18,138 @@ pub(crate) async fn fetch_api<T: for<'de> Deserialize<'de>>(path: &str) -> Optio
     Request::get(&url).send().await.ok()?.json::<T>().await.ok()
 }
 
+/// Fetches a path of the API of a chain, e.g. `/blocks`.
+pub(crate) async fn fetch_chain_api<T: for<'de> Deserialize<'de>>(
+    chain: Option<String>,
+    path: &str,
+) -> Option<T> {
+    fetch_api(&format!("/{}{}", chain?, path)).await
+}
+
+/// Percent-encodes a value for a query string.
+pub(crate) fn encode_query(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The name of the chain picked in the navbar, `None` until the chains are loaded.
+#[derive(Clone, Copy)]
+struct SelectedChain(RwSignal<Option<String>>);
+
+/// The chain the pages show, to be tracked by their resources.
+pub(crate) fn use_chain() -> RwSignal<Option<String>> {
+    expect_context::<SelectedChain>().0
+}
+
+#[component]
+fn ChainSelector() -> impl IntoView {
+    let chain = use_chain();
+    let navigate = use_navigate();
+    let chains_resource = create_resource(
+        || (),
+        |()| async move { fetch_api::<Vec<Chain>>("/chains").await },
+    );
+
+    // Start on the first configured chain
+    create_effect(move |_| {
+        let first = chains_resource
+            .get()
+            .flatten()
+            .and_then(|chains| chains.into_iter().next());
+        if let (Some(first), None) = (first, chain.get_untracked()) {
+            chain.set(Some(first.name));
+        }
+    });
+
+    view! {
+        <select
+            class="chain-selector"
+            on:change=move |ev| {
+                chain.set(Some(event_target_value(&ev)));
+                // Pages of the previous chain don't exist on the new one
+                navigate("/", Default::default());
+            }
+        >
+            {move || chains_resource.get().flatten().unwrap_or_default().into_iter().map(|c| {
+                let selected = chain.get().as_deref() == Some(c.name.as_str());
+                let label = match c.chain_id {
+                    Some(id) => format!("{} ({})", c.name, id),
+                    None => c.name.clone(),
+                };
+                view! { <option value=c.name selected=selected>{label}</option> }
+            }).collect_view()}
+        </select>
+    }
+}
+
+#[component]
+fn SearchBox() -> impl IntoView {
+    let chain = use_chain();
+    let navigate = use_navigate();
+    let query = create_rw_signal(String::new());
+
+    let on_submit = move |ev: SubmitEvent| {
+        ev.prevent_default();
+        let q = query.get_untracked().trim().to_string();
+        if q.is_empty() {
+            return;
+        }
+        let navigate = navigate.clone();
+        spawn_local(async move {
+            let path = format!("/search?q={}", encode_query(&q));
+            let results = fetch_chain_api::<Vec<SearchResult>>(chain.get_untracked(), &path)
+                .await
+                .unwrap_or_default();
+            // Go straight to a single match, list the others
+            match results.as_slice() {
+                [result] => navigate(&result_path(result), Default::default()),
+                _ => navigate(&path, Default::default()),
+            }
+            query.set(String::new());
+        });
+    };
+
+    view! {
+        <form class="search-box" on:submit=on_submit>
+            <input
+                type="search"
+                placeholder="Block, transaction, address or token"
+                prop:value=move || query.get()
+                on:input=move |ev| query.set(event_target_value(&ev))
+            />
+        </form>
+    }
+}
+
 #[component]
 pub fn App() -> impl IntoView {
+    let chain = create_rw_signal(None);
+    provide_context(SelectedChain(chain));
+
     view! {
         <Router>
             <nav class="navbar">
                 <A href="/" class="nav-brand">"CloudWalk Indexer"</A>
+                <SearchBox/>
+                <ChainSelector/>
             </nav>
             <main class="container">
-                <Routes>
-                    <Route path="" view=HomePage/>
-                    <Route path="/block/:hash" view=BlockDetailsPage/>
-                    <Route path="/tx/:hash" view=TransactionDetailsPage/>
-                    <Route path="/account/:address" view=AccountDetailsPage/>
-                </Routes>
+                <Show when=move || chain.get().is_some() fallback=|| view! { <p>"Loading chains..."</p> }>
+                    <Routes>
+                        <Route path="" view=HomePage/>
+                        <Route path="/block/:id" view=BlockDetailsPage/>
+                        <Route path="/tx/:hash" view=TransactionDetailsPage/>
+                        <Route path="/account/:address" view=AccountDetailsPage/>
+                        <Route path="/search" view=SearchPage/>
+                    </Routes>
+                </Show>
             </main>
         </Router>
     }
@@ -1,15 +1,23 @@
-use crate::app::fetch_api;
-use common::AccountDetail;
+use crate::app::{fetch_chain_api, use_chain};
+use common::{AccountDetail, ContractDetail};
 use leptos::{component, create_resource, view, For, IntoView, SignalGet, SignalWith, Suspense};
 use leptos_router::{use_params_map, A};
 
 #[component]
 pub fn AccountDetailsPage() -> impl IntoView {
     let params = use_params_map();
-    let address = move || params.with(|p| p.get("address").cloned().unwrap_or_default());
+    let chain = use_chain();
+    let address = move || {
+        let address = params.with(|p| p.get("address").cloned().unwrap_or_default());
+        (chain.get(), address)
+    };
 
-    let account_resource = create_resource(address, |addr| async move {
-        fetch_api::<AccountDetail>(&format!("/account/{}", addr)).await
+    let account_resource = create_resource(address, |(chain, addr)| async move {
+        fetch_chain_api::<AccountDetail>(chain, &format!("/account/{}", addr)).await
+    });
+
+    let contract_resource = create_resource(address, |(chain, addr)| async move {
+        fetch_chain_api::<ContractDetail>(chain, &format!("/contract/{}", addr)).await
     });
 
     view! {
@@ -17,7 +25,81 @@ pub fn AccountDetailsPage() -> impl IntoView {
             {move || account_resource.get().map(|res| match res {
                 Some(detail) => view! {
                     <h1 class="title">"Account Details"</h1>
-                    <p class="address-header">{detail.address}</p>
+                    <p class="address-header">{detail.checksum_address}</p>
+                    <div class="detail-grid">
+                        <span>"Type:"</span>        <span>{if detail.is_contract { "Contract" } else { "Account" }}</span>
+                        <span>"First Seen:"</span>
+                        <span>
+                            {match (detail.first_seen_block, detail.first_seen_tx, detail.first_seen_at) {
+                                (Some(block), Some(tx), Some(at)) => view! {
+                                    "Block " {block} " (" {at.to_string()} ") in "
+                                    <A href=format!("/tx/{}", tx) class="link">{tx.clone()}</A>
+                                }.into_view(),
+                                _ => "Never".into_view(),
+                            }}
+                        </span>
+                        <span>"Last Active:"</span> <span>{detail.last_active_block.map_or("Never".to_string(), |b| format!("Block {}", b))}</span>
+                        <span>"Transactions:"</span> <span>{format!("{} sent, {} received", detail.tx_sent_count, detail.tx_received_count)}</span>
+                    </div>
+
+                    <Suspense fallback=|| ()>
+                        {move || contract_resource.get().flatten().map(|contract| view! {
+                            <h2 class="subtitle">"Contract"</h2>
+                            <div class="detail-grid">
+                                <span>"Bytecode Hash:"</span> <span>{contract.bytecode_hash.unwrap_or_else(|| "Unknown".to_string())}</span>
+                                <span>"Bytecode Size:"</span> <span>{contract.bytecode_size.map_or("Unknown".to_string(), |size| format!("{} bytes", size))}</span>
+                                <span>"Created In:"</span>
+                                <span>
+                                    {match contract.creation_tx {
+                                        Some(tx) => view! { <A href=format!("/tx/{}", tx) class="link">{tx.clone()}</A> }.into_view(),
+                                        None => "Unknown".into_view(),
+                                    }}
+                                </span>
+                                {contract.proxy.map(|proxy| view! {
+                                    <span>"Proxy:"</span> <span>{proxy.kind}</span>
+                                    <span>"Implementation:"</span>
+                                    <span>
+                                        {match proxy.implementation {
+                                            Some(implementation) => view! { <A href=format!("/account/{}", implementation) class="link">{implementation.clone()}</A> }.into_view(),
+                                            None => "Unknown".into_view(),
+                                        }}
+                                    </span>
+                                    {proxy.beacon.map(|beacon| view! {
+                                        <span>"Beacon:"</span>
+                                        <span><A href=format!("/account/{}", beacon) class="link">{beacon.clone()}</A></span>
+                                    })}
+                                })}
+                            </div>
+                            {(!contract.upgrades.items.is_empty()).then(|| view! {
+                                <h2 class="subtitle">"Upgrades"</h2>
+                                <div class="table-container">
+                                    <table>
+                                        <thead>
+                                            <tr>
+                                                <th>"Block"</th>
+                                                <th>"Implementation"</th>
+                                                <th>"Tx Hash"</th>
+                                            </tr>
+                                        </thead>
+                                        <tbody>
+                                            <For
+                                                each=move || contract.upgrades.items.clone()
+                                                key=|upgrade| upgrade.tx_hash.clone()
+                                                let:upgrade
+                                            >
+                                                <tr>
+                                                    <td>{upgrade.block_number}</td>
+                                                    <td><A href=format!("/account/{}", upgrade.implementation) class="link truncate">{upgrade.implementation}</A></td>
+                                                    <td><A href=format!("/tx/{}", upgrade.tx_hash) class="link truncate">{upgrade.tx_hash}</A></td>
+                                                </tr>
+                                            </For>
+                                        </tbody>
+                                    </table>
+                                </div>
+                                {contract.upgrades.next_cursor.is_some().then(|| view! { <p>"Only the first upgrades are shown."</p> })}
+                            })}
+                        })}
+                    </Suspense>
 
                     <h2 class="subtitle">"Token Balances"</h2>
                      <div class="table-container">
@@ -30,7 +112,7 @@ pub fn AccountDetailsPage() -> impl IntoView {
                             </thead>
                             <tbody>
                                 <For
-                                    each=move || detail.token_balances.clone()
+                                    each=move || detail.token_balances.items.clone()
                                     key=|balance| balance.id
                                     let:balance
                                 >
@@ -50,6 +132,7 @@ pub fn AccountDetailsPage() -> impl IntoView {
                             </tbody>
                         </table>
                     </div>
+                    {detail.token_balances.next_cursor.is_some().then(|| view! { <p>"Only the first token balances are shown."</p> })}
                 }.into_view(),
                 None => view!{ <p class="error">"Error: Account not found."</p> }.into_view()
             })}
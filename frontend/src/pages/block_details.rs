@@ -1,20 +1,44 @@
-use crate::app::fetch_api;
-use common::{Block, Transaction};
-use leptos::{component, create_resource, view, For, IntoView, SignalGet, SignalWith, Suspense};
+use crate::app::{fetch_chain_api, use_chain};
+use common::{Block, Page, Transaction};
+use leptos::{
+    component, create_effect, create_resource, create_rw_signal, view, For, IntoView, SignalGet,
+    SignalSet, SignalWith, Suspense,
+};
 use leptos_router::{use_params_map, A};
 
 #[component]
 pub fn BlockDetailsPage() -> impl IntoView {
     let params = use_params_map();
-    let hash = move || params.with(|p| p.get("hash").cloned().unwrap_or_default());
+    let chain = use_chain();
+    // A hash, a number or `latest`
+    let id = move || {
+        let id = params.with(|p| p.get("id").cloned().unwrap_or_default());
+        (chain.get(), id)
+    };
 
-    let block_resource = create_resource(hash, |h| async move {
-        fetch_api::<Block>(&format!("/block/{}", h)).await
+    let block_resource = create_resource(id, |(chain, id)| async move {
+        fetch_chain_api::<Block>(chain, &format!("/block/{}", id)).await
     });
 
-    let transactions_resource = create_resource(hash, |h| async move {
-        fetch_api::<Vec<Transaction>>(&format!("/block/{}/transactions", h)).await
+    // The cursor of the page of transactions shown, `None` for the first
+    let cursor = create_rw_signal(None::<String>);
+    create_effect(move |_| {
+        id();
+        cursor.set(None);
     });
+    // Fetched by the hash of the loaded block, so the transactions of `latest` are from the block
+    // shown even if another one was indexed in between.
+    let block_hash = move || block_resource.get().flatten().map(|block| block.hash);
+    let transactions_resource = create_resource(
+        move || (chain.get(), block_hash(), cursor.get()),
+        |(chain, hash, cursor)| async move {
+            let mut path = format!("/block/{}/transactions", hash?);
+            if let Some(cursor) = cursor {
+                path.push_str(&format!("?cursor={}", cursor));
+            }
+            fetch_chain_api::<Page<Transaction>>(chain, &path).await
+        },
+    );
 
     view! {
         <Suspense fallback=move || view!{<p>"Loading block data..."</p>}>
@@ -33,12 +57,16 @@ pub fn BlockDetailsPage() -> impl IntoView {
                         <h2 class="subtitle">"Transactions"</h2>
                     <Suspense fallback=move || view!{<p>"Loading transactions..."</p>}>
                         {move || transactions_resource.get().map(|tx_res| match tx_res {
-                            Some(transactions) => view! {
+                            Some(page) => {
+                                let transactions = page.items;
+                                let next_cursor = page.next_cursor;
+                                view! {
                                 <div class="table-container">
                                     <table>
                                         <thead>
                                             <tr>
                                                 <th>"Tx Hash"</th>
+                                                <th>"Method"</th>
                                                 <th>"From"</th>
                                                 <th>"To"</th>
                                                 <th>"Value"</th>
@@ -52,6 +80,12 @@ pub fn BlockDetailsPage() -> impl IntoView {
                                             >
                                                 <tr>
                                                     <td><A href=format!("/tx/{}", tx.hash) class="link truncate">{tx.hash.clone()}</A></td>
+                                                    <td title=tx.method_name.join(" | ")>{
+                                                        tx.method_name.first()
+                                                            .and_then(|m| m.split('(').next().map(str::to_string))
+                                                            .or(tx.method_id.clone())
+                                                            .unwrap_or_else(|| "Transfer".to_string())
+                                                    }</td>
                                                     <td><A href=format!("/account/{}", tx.from_address) class="link truncate">{tx.from_address.clone()}</A></td>
                                                     <td>{
                                                         if let Some(to) = tx.to_address {
@@ -66,7 +100,14 @@ pub fn BlockDetailsPage() -> impl IntoView {
                                         </tbody>
                                     </table>
                                 </div>
-                            }.into_view(),
+                                <div class="pagination">
+                                    <button disabled=move || cursor.get().is_none() on:click=move |_| cursor.set(None)>"First"</button>
+                                    {next_cursor.map(|next| view! {
+                                        <button on:click=move |_| cursor.set(Some(next.clone()))>"Next"</button>
+                                    })}
+                                </div>
+                                }.into_view()
+                            }
                             None => view! { <p class="error">"Could not load transactions for this block."</p> }.into_view()
                         })}
                     </Suspense>
@@ -1,20 +1,39 @@
-use crate::app::fetch_api;
-use common::Block;
-use leptos::{component, create_resource, view, For, IntoView, SignalGet, Suspense};
+use crate::app::{fetch_chain_api, use_chain};
+use common::{Block, Page};
+use leptos::{
+    component, create_effect, create_resource, create_rw_signal, view, For, IntoView, SignalGet,
+    SignalSet, Suspense,
+};
 use leptos_router::A;
 
 #[component]
 pub fn HomePage() -> impl IntoView {
+    let chain = use_chain();
+    // The cursor of the page shown, `None` for the newest blocks
+    let cursor = create_rw_signal(None::<String>);
+    create_effect(move |_| {
+        chain.get();
+        cursor.set(None);
+    });
     let blocks_resource = create_resource(
-        || (),
-        |()| async move { fetch_api::<Vec<Block>>("/blocks").await },
+        move || (chain.get(), cursor.get()),
+        |(chain, cursor)| async move {
+            let path = match cursor {
+                Some(cursor) => format!("/blocks?cursor={}", cursor),
+                None => "/blocks".to_string(),
+            };
+            fetch_chain_api::<Page<Block>>(chain, &path).await
+        },
     );
 
     view! {
         <h1 class="title">"Latest Blocks"</h1>
         <Suspense fallback=move || view! { <p>"Loading..."</p> }>
             {move || blocks_resource.get().map(|res| match res {
-                Some(blocks) => view! {
+                Some(page) => {
+                    let blocks = page.items;
+                    let next_cursor = page.next_cursor;
+                    view! {
                     <div class="table-container">
                         <table>
                             <thead>
@@ -41,7 +60,14 @@ pub fn HomePage() -> impl IntoView {
                             </tbody>
                         </table>
                     </div>
-                }.into_view(),
+                    <div class="pagination">
+                        <button disabled=move || cursor.get().is_none() on:click=move |_| cursor.set(None)>"Newest"</button>
+                        {next_cursor.map(|next| view! {
+                            <button on:click=move |_| cursor.set(Some(next.clone()))>"Older blocks"</button>
+                        })}
+                    </div>
+                    }.into_view()
+                }
                 None => view! { <p class="error">"Error: Could not fetch recent blocks."</p> }.into_view(),
             })}
         </Suspense>
@@ -1,9 +1,13 @@
 mod account_details;
 mod block_details;
 mod homepage;
+mod search;
 mod transaction_details;
 
 pub(crate) use self::{
-    account_details::AccountDetailsPage, block_details::BlockDetailsPage, homepage::HomePage,
+    account_details::AccountDetailsPage,
+    block_details::BlockDetailsPage,
+    homepage::HomePage,
+    search::{result_path, SearchPage},
     transaction_details::TransactionDetailsPage,
 };
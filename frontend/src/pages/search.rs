@@ -0,0 +1,85 @@
+use crate::app::{encode_query, fetch_chain_api, use_chain};
+use common::{SearchResult, TokenInfo};
+use leptos::{
+    component, create_resource, view, CollectView, IntoView, SignalGet, SignalWith, Suspense,
+};
+use leptos_router::{use_query_map, A};
+
+/// The page showing a search result.
+pub(crate) fn result_path(result: &SearchResult) -> String {
+    match result {
+        SearchResult::Block { hash, .. } => format!("/block/{}", hash),
+        SearchResult::Transaction { hash, .. } => format!("/tx/{}", hash),
+        SearchResult::Account { address }
+        | SearchResult::Contract { address, .. }
+        | SearchResult::Token { address, .. } => format!("/account/{}", address),
+    }
+}
+
+fn token_label(token: &TokenInfo) -> String {
+    match (&token.name, &token.symbol) {
+        (Some(name), Some(symbol)) => format!("{} ({})", name, symbol),
+        (Some(text), None) | (None, Some(text)) => text.clone(),
+        (None, None) => String::new(),
+    }
+}
+
+/// The kind and description of a search result.
+fn describe(result: &SearchResult) -> (&'static str, String) {
+    match result {
+        SearchResult::Block { number, .. } => ("Block", number.to_string()),
+        SearchResult::Transaction { hash, .. } => ("Transaction", hash.clone()),
+        SearchResult::Account { address } => ("Account", address.clone()),
+        SearchResult::Contract { address, token } => match token {
+            Some(token) => ("Token", format!("{} {}", token_label(token), address)),
+            None => ("Contract", address.clone()),
+        },
+        SearchResult::Token { address, token } => {
+            ("Token", format!("{} {}", token_label(token), address))
+        }
+    }
+}
+
+#[component]
+pub fn SearchPage() -> impl IntoView {
+    let query = use_query_map();
+    let chain = use_chain();
+    let q = move || {
+        let q = query.with(|q| q.get("q").cloned().unwrap_or_default());
+        (chain.get(), q)
+    };
+
+    let results_resource = create_resource(q, |(chain, q)| async move {
+        fetch_chain_api::<Vec<SearchResult>>(chain, &format!("/search?q={}", encode_query(&q)))
+            .await
+    });
+
+    view! {
+        <h1 class="title">"Search Results"</h1>
+        <Suspense fallback=move || view! { <p>"Searching..."</p> }>
+            {move || results_resource.get().map(|res| match res {
+                Some(results) if results.is_empty() => view! {
+                    <p>{format!("Nothing found for \"{}\".", q().1)}</p>
+                }.into_view(),
+                Some(results) => view! {
+                    <div class="table-container">
+                        <table>
+                            <tbody>
+                                {results.iter().map(|result| {
+                                    let (kind, description) = describe(result);
+                                    view! {
+                                        <tr>
+                                            <td>{kind}</td>
+                                            <td><A href=result_path(result) class="link">{description}</A></td>
+                                        </tr>
+                                    }
+                                }).collect_view()}
+                            </tbody>
+                        </table>
+                    </div>
+                }.into_view(),
+                None => view! { <p class="error">"Error: The search failed."</p> }.into_view(),
+            })}
+        </Suspense>
+    }
+}
@@ -1,4 +1,4 @@
-use crate::app::fetch_api;
+use crate::app::{fetch_chain_api, use_chain};
 use common::TransactionDetail;
 use leptos::{
     component, create_resource, view, CollectView, For, IntoView, SignalGet, SignalWith, Suspense,
@@ -8,10 +8,14 @@ use leptos_router::{use_params_map, A};
 #[component]
 pub fn TransactionDetailsPage() -> impl IntoView {
     let params = use_params_map();
-    let hash = move || params.with(|p| p.get("hash").cloned().unwrap_or_default());
+    let chain = use_chain();
+    let hash = move || {
+        let hash = params.with(|p| p.get("hash").cloned().unwrap_or_default());
+        (chain.get(), hash)
+    };
 
-    let tx_resource = create_resource(hash, |h| async move {
-        fetch_api::<TransactionDetail>(&format!("/tx/{}", h)).await
+    let tx_resource = create_resource(hash, |(chain, h)| async move {
+        fetch_chain_api::<TransactionDetail>(chain, &format!("/tx/{}", h)).await
     });
 
     view! {
@@ -19,8 +23,24 @@ pub fn TransactionDetailsPage() -> impl IntoView {
             {move || tx_resource.get().map(|res| match res {
                 Some(detail) => {
                     let tx = detail.transaction;
-                    let logs = detail.logs;
-                    let token_transfers = detail.token_transfers;
+                    let more_logs = detail.logs.next_cursor.is_some();
+                    let logs = detail.logs.items;
+                    let more_transfers = detail.token_transfers.next_cursor.is_some();
+                    let token_transfers = detail.token_transfers.items;
+                    // Only L2 transactions have these
+                    let l2_fields: Vec<(&str, String)> = [
+                        ("Type:", tx.tx_type.filter(|t| *t == 0x7e).map(|_| "Deposit".to_string())),
+                        ("L1 Fee:", tx.l1_fee.as_ref().map(ToString::to_string)),
+                        ("L1 Gas Used:", tx.l1_gas_used.as_ref().map(ToString::to_string)),
+                        ("L1 Gas Price:", tx.l1_gas_price.as_ref().map(ToString::to_string)),
+                        ("Gas Used for L1:", tx.gas_used_for_l1.as_ref().map(ToString::to_string)),
+                        ("L1 Block:", tx.l1_block_number.map(|n| n.to_string())),
+                        ("Deposit Source:", tx.deposit_source_hash.clone()),
+                        ("Minted:", tx.deposit_mint.as_ref().map(ToString::to_string)),
+                    ]
+                    .into_iter()
+                    .filter_map(|(label, value)| Some((label, value?)))
+                    .collect();
 
                     view! {
                         <h1 class="title">"Transaction Details"</h1>
@@ -35,9 +55,20 @@ pub fn TransactionDetailsPage() -> impl IntoView {
                                     view! { "Contract Creation" }.into_view()
                                 }
                             }</span>
+                            <span>"Method:"</span>      <span>{
+                                if tx.method_name.is_empty() {
+                                    tx.method_id.clone().unwrap_or_else(|| "Transfer".to_string())
+                                } else {
+                                    tx.method_name.join(" | ")
+                                }
+                            }</span>
                             <span>"Value:"</span>       <span>{tx.value.to_string()}</span>
                             <span>"Gas Used:"</span>    <span>{tx.gas_used.map(|g| g.to_string()).unwrap_or_default()}</span>
                             <span>"Gas Price:"</span>   <span>{tx.gas_price.map(|g| g.to_string()).unwrap_or_default()}</span>
+                            <span>"Fee:"</span>         <span>{tx.fee.map(|f| f.to_string()).unwrap_or_default()}</span>
+                            {l2_fields.into_iter().map(|(label, value)| view! {
+                                <span>{label}</span> <span>{value}</span>
+                            }).collect_view()}
                         </div>
 
                         <h2 class="subtitle">"Token Transfers"</h2>
@@ -79,6 +110,7 @@ pub fn TransactionDetailsPage() -> impl IntoView {
                                         </tbody>
                                     </table>
                                 </div>
+                                {more_transfers.then(|| view! { <p>"Only the first token transfers are shown."</p> })}
                             }.into_view()
                         }}
 
@@ -92,6 +124,23 @@ pub fn TransactionDetailsPage() -> impl IntoView {
                                         <span class="log-index">{i}</span>
                                         <span class="log-address"><A href=format!("/account/{}", log.address) class="link">{log.address}</A></span>
                                     </div>
+                                    {match log.decoded {
+                                        Some(decoded) => {
+                                            let params = decoded.params.iter()
+                                                .map(|p| format!("{}={}", p.name, p.value))
+                                                .collect::<Vec<_>>()
+                                                .join(", ");
+                                            view! {
+                                                <div class="log-decoded" title=decoded.signature>
+                                                    {format!("{}({})", decoded.name, params)}
+                                                </div>
+                                            }.into_view()
+                                        }
+                                        None if !log.event_name.is_empty() => view! {
+                                            <div class="log-decoded">{log.event_name.join(" | ")}</div>
+                                        }.into_view(),
+                                        None => ().into_view(),
+                                    }}
                                     <div class="log-topics">
                                         <p><strong>"Topics"</strong></p>
                                         {
@@ -110,6 +159,7 @@ pub fn TransactionDetailsPage() -> impl IntoView {
                                 </div>
                             }).collect_view()
                         }}
+                        {more_logs.then(|| view! { <p>"Only the first logs are shown."</p> })}
                     }.into_view()
                 },
                 None => view!{ <p class="error">"Error: Transaction not found."</p> }.into_view()